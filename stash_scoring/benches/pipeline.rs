@@ -0,0 +1,184 @@
+use std::sync::Arc;
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use stash_scoring::board::Position;
+use stash_scoring::engine::{EngineConfig, Score, SearchLimit};
+use stash_scoring::input::InputSchema;
+use stash_scoring::task_queue::{TaskClient, TaskWorker, Workload};
+
+const MOCK_ENGINE: &str = env!("CARGO_BIN_EXE_mock_engine");
+const THREAD_COUNTS: [usize; 4] = [1, 2, 4, 8];
+
+fn dataset(lines: usize) -> Arc<str> {
+    let data: String = (0..lines)
+        .map(|i| {
+            format!(
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 {} 0.5 {}\n",
+                i + 1,
+                i as i32 - 500
+            )
+        })
+        .collect();
+
+    Arc::from(data)
+}
+
+/// Pushes the dataset through the queue with workers answering immediately,
+/// measuring the cost of the queue itself and its contention.
+fn run_queue(data: &Arc<str>, threads: usize) -> usize {
+    let mut client: TaskClient = TaskClient::new();
+    let mut handles = Vec::new();
+
+    for _ in 0..threads {
+        let queue = client.queue_ref().clone();
+        let local = queue.add_worker();
+
+        handles.push(thread::spawn(move || loop {
+            let finished = queue.is_workload_finished();
+
+            if let Some(workload) = queue.query_workload_for(&local) {
+                queue.add_response(workload.index(), Some(workload.to_string()));
+            } else if finished {
+                queue.remove_worker();
+                break;
+            }
+        }));
+    }
+
+    client.add_workloads(Workload::split_lines(data.clone()));
+    client.stop_workload();
+
+    let mut responses = 0;
+
+    while client.query_response(true).is_some() {
+        responses += 1;
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    responses
+}
+
+/// Scores the dataset with instantly-replying engines, measuring the
+/// overhead of the UCI communication on top of the queue.
+fn run_engines(data: &Arc<str>, threads: usize) -> usize {
+    let mut client: TaskClient = TaskClient::new();
+    let mut handles = Vec::new();
+    let limit = SearchLimit {
+        depth: Some(1),
+        ..Default::default()
+    };
+    let engine = EngineConfig {
+        name: String::from("mock"),
+        command: String::from(MOCK_ENGINE),
+        ..Default::default()
+    };
+
+    for _ in 0..threads {
+        let mut worker = TaskWorker::new(client.queue_ref(), &engine);
+        let limit = limit.clone();
+
+        handles.push(thread::spawn(move || {
+            while let Some(workload) = worker.query_workload().unwrap() {
+                let fen = workload.rsplitn(3, ' ').nth(2).unwrap();
+
+                worker.engine_mut().setup_position(fen, &[]).unwrap();
+
+                let result = worker.engine_mut().run_search(&limit).unwrap();
+
+                worker.fill_response(&workload, format!("{} {}\n", fen, result.score.folded()));
+            }
+        }));
+    }
+
+    client.add_workloads(Workload::split_lines(data.clone()));
+    client.stop_workload();
+
+    let mut responses = 0;
+
+    while client.query_response(true).is_some() {
+        responses += 1;
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    responses
+}
+
+fn bench_queue(c: &mut Criterion) {
+    let data = dataset(100_000);
+    let mut group = c.benchmark_group("queue");
+
+    group.throughput(Throughput::Elements(100_000));
+
+    for threads in THREAD_COUNTS {
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, &t| {
+            b.iter(|| run_queue(&data, t))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_engines(c: &mut Criterion) {
+    let data = dataset(2_000);
+    let mut group = c.benchmark_group("mock_engine");
+
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(2_000));
+
+    for threads in THREAD_COUNTS {
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, &t| {
+            b.iter(|| run_engines(&data, t))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_parsers(c: &mut Criterion) {
+    let data = dataset(10_000);
+    let schema: InputSchema = "fen,wdl,eval".parse().unwrap();
+    let mut group = c.benchmark_group("parser");
+
+    group.throughput(Throughput::Elements(10_000));
+    group.bench_function("input_line", |b| {
+        b.iter(|| {
+            data.lines()
+                .filter(|line| schema.split(line).is_ok())
+                .count()
+        })
+    });
+
+    let records: Vec<_> = data
+        .lines()
+        .map(|line| schema.split(line).unwrap())
+        .collect();
+
+    group.bench_function("fen", |b| {
+        b.iter(|| {
+            records
+                .iter()
+                .filter(|record| Position::from_fen(&record.fen, false).is_ok())
+                .count()
+        })
+    });
+    group.bench_function("eval", |b| {
+        b.iter(|| {
+            records
+                .iter()
+                .filter(|record| record.eval.unwrap().parse::<Score>().is_ok())
+                .count()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_queue, bench_engines, bench_parsers);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use stash_scoring::reader::InputReader;
+
+const LINES: usize = 100_000;
+
+fn dataset_path() -> PathBuf {
+    let path = std::env::temp_dir().join("stash_scoring_reader_bench.txt");
+
+    if !path.exists() {
+        let mut file = BufWriter::new(File::create(&path).unwrap());
+
+        for i in 0..LINES {
+            writeln!(
+                file,
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 {} 0.5",
+                i
+            )
+            .unwrap();
+        }
+    }
+
+    path
+}
+
+fn read_all(path: &str, mmap: bool) -> usize {
+    let mut reader = InputReader::open(path, mmap).unwrap();
+    let mut bytes = 0;
+
+    while let Some(line) = reader.next_line().unwrap() {
+        bytes += line.len();
+    }
+
+    bytes
+}
+
+fn bench_readers(c: &mut Criterion) {
+    let path = dataset_path();
+    let path = path.to_str().unwrap();
+    let mut group = c.benchmark_group("reader");
+
+    group.throughput(Throughput::Elements(LINES as u64));
+    group.bench_function("buffered", |b| b.iter(|| read_all(path, false)));
+
+    #[cfg(feature = "mmap")]
+    group.bench_function("mmap", |b| b.iter(|| read_all(path, true)));
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_readers);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use stash_scoring::task_queue::{Task, TaskQueue, Workload};
+
+const LINES: usize = 10_000;
+
+fn dataset() -> String {
+    (0..LINES)
+        .map(|i| {
+            format!(
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 {} 0.5\n",
+                i
+            )
+        })
+        .collect()
+}
+
+fn drain(queue: &TaskQueue) -> usize {
+    let mut bytes = 0;
+
+    while let Some(workload) = queue.query_workload() {
+        bytes += workload.len();
+    }
+
+    bytes
+}
+
+fn bench_workloads(c: &mut Criterion) {
+    let data = dataset();
+    let mut group = c.benchmark_group("workload");
+
+    group.throughput(Throughput::Elements(LINES as u64));
+
+    // One allocation per line, as done when reading line by line.
+    group.bench_function("owned_lines", |b| {
+        b.iter(|| {
+            let queue = TaskQueue::new();
+
+            for (index, line) in data.split_inclusive('\n').enumerate() {
+                queue.add_workload(Task::new(index, Workload::from(line.to_string())));
+            }
+
+            drain(&queue)
+        })
+    });
+
+    // One allocation for the whole chunk, shared by all its lines.
+    group.bench_function("shared_chunk", |b| {
+        b.iter(|| {
+            let queue = TaskQueue::new();
+
+            for (index, workload) in Workload::split_lines(Arc::from(data.as_str())).enumerate() {
+                queue.add_workload(Task::new(index, workload));
+            }
+
+            drain(&queue)
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_workloads);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+use std::process::Command;
+
+fn main() {
+    // Embed the git description of the sources in the binaries, so that the
+    // manifests of generated datasets identify the tool version exactly.
+    let describe = Command::new("git")
+        .args(["describe", "--always", "--dirty", "--tags"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map_or_else(|| String::from("unknown"), |s| s.trim().to_string());
+
+    println!("cargo:rustc-env=STASH_TOOLS_GIT_DESCRIBE={}", describe);
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/index");
+}
@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use stash_scoring::board::Position;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(line) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    for chess960 in [false, true] {
+        if let Ok(pos) = Position::from_epd(line, chess960) {
+            assert_eq!(
+                Position::from_epd(&pos.to_fen(), chess960)
+                    .unwrap()
+                    .to_fen(),
+                pos.to_fen()
+            );
+        }
+    }
+});
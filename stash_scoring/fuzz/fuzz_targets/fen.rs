@@ -0,0 +1,41 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use stash_scoring::board::{MoveNotation, Position};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(fen) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    for chess960 in [false, true] {
+        let Ok(pos) = Position::from_fen(fen, chess960) else {
+            continue;
+        };
+
+        // Written FENs must be read back as the same position.
+        let written = pos.to_fen();
+        let reread = Position::from_fen(&written, chess960).unwrap();
+
+        assert_eq!(reread.to_fen(), written);
+        let _ = pos.canonical_fen();
+
+        if pos.check_legality().is_err() {
+            continue;
+        }
+
+        for mv in pos.legal_moves() {
+            let uci = pos.write_move(mv, MoveNotation::Uci);
+            let san = pos.write_move(mv, MoveNotation::San);
+
+            assert_eq!(pos.parse_move(&uci).unwrap(), mv);
+            assert_eq!(pos.parse_move(&san).unwrap(), mv);
+
+            let mut child = pos.clone();
+
+            child.play(mv);
+            let _ = child.canonical_fen();
+        }
+    }
+});
@@ -0,0 +1,42 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use stash_scoring::engine::{EngineProfile, Score, ScoreFormat, SearchInfo};
+
+fn check_score(score: Score) {
+    for score in [score, score.parent(), score.flipped()] {
+        let _ = score.folded();
+        let _ = score.expected_result(1.0);
+
+        // Scores written to datasets must be read back unchanged, except for
+        // folded mates.
+        for format in [ScoreFormat::Pound, ScoreFormat::Letter] {
+            let written = score.display(format).to_string();
+
+            assert_eq!(written.parse::<Score>(), Ok(score));
+        }
+
+        let _ = score.display(ScoreFormat::Folded).to_string();
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(line) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    for profile in [EngineProfile::Generic, EngineProfile::Lc0] {
+        if let Ok(SearchInfo {
+            score: Some(score), ..
+        }) = SearchInfo::parse(line, profile)
+        {
+            check_score(score);
+        }
+    }
+
+    // Evals read back from scored datasets.
+    if let Ok(score) = line.parse::<Score>() {
+        check_score(score);
+    }
+});
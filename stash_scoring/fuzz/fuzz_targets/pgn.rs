@@ -0,0 +1,36 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use stash_scoring::board::Color;
+use stash_scoring::pgn::PgnReader;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = PgnReader::new(data);
+
+    while let Ok(Some(game)) = reader.next_game() {
+        let Ok(game) = game else {
+            continue;
+        };
+
+        let _ = (game.elo(Color::White), game.elo(Color::Black));
+        let _ = (game.time_control(), game.wdl());
+
+        // Replay the moves as the extraction tool does, up to the first
+        // illegal one.
+        let Ok(mut pos) = game.start_position() else {
+            continue;
+        };
+
+        if pos.check_legality().is_err() {
+            continue;
+        }
+
+        for san in &game.moves {
+            match pos.parse_san(san) {
+                Ok(mv) => pos.play(mv),
+                Err(_) => break,
+            }
+        }
+    }
+});
@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::engine::Score;
+use crate::rng::Rng;
+
+/// A position to search again, with the score it got the first time.
+#[derive(Clone, Debug)]
+pub struct AuditJob {
+    pub fen: String,
+    pub moves: Vec<String>,
+    pub score: Score,
+    /// The number of the engine which searched it the first time.
+    pub engine: usize,
+}
+
+/// Picks a random share of the scored positions, which are searched again by
+/// another engine than the first one, and collects how much both scores
+/// disagree. Disagreements reveal nondeterministic engines (e.g. SMP
+/// searches), broken machines or engines, while the run is still going on.
+pub struct Audit {
+    rate: f64,
+    jobs: Mutex<VecDeque<AuditJob>>,
+    /// The absolute differences between both scores, with mates folded.
+    differences: Mutex<Vec<u32>>,
+}
+
+impl Audit {
+    /// Creates an audit of the given share of the positions.
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            jobs: Mutex::new(VecDeque::new()),
+            differences: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Whether the position with this index in the input is audited. The
+    /// choice only depends on the index, so that the same positions are
+    /// audited whatever the number of engines.
+    pub fn is_sampled(&self, index: usize) -> bool {
+        Rng::new(index as u64).next_f64() < self.rate
+    }
+
+    pub fn push(&self, job: AuditJob) {
+        self.jobs.lock().unwrap().push_back(job);
+    }
+
+    /// Takes a position first searched by another engine than the given
+    /// one, or by any engine if `any_engine` is set, e.g. when there is no
+    /// other engine left.
+    pub fn take_for(&self, engine: usize, any_engine: bool) -> Option<AuditJob> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let position = jobs
+            .iter()
+            .position(|job| any_engine || job.engine != engine)?;
+
+        jobs.remove(position)
+    }
+
+    /// Records the score of the second search of a position.
+    pub fn record(&self, job: &AuditJob, score: Score) {
+        let difference = job.score.folded().abs_diff(score.folded());
+
+        self.differences.lock().unwrap().push(difference);
+    }
+
+    /// Summarizes the disagreements recorded so far.
+    pub fn report(&self) -> AuditReport {
+        let mut differences = self.differences.lock().unwrap().clone();
+
+        differences.sort_unstable();
+
+        let percentile = |p: usize| match differences.len() {
+            0 => 0,
+            len => differences[(len - 1) * p / 100],
+        };
+
+        AuditReport {
+            audited: differences.len(),
+            identical: differences.iter().take_while(|&&d| d == 0).count(),
+            median: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: differences.last().copied().unwrap_or(0),
+        }
+    }
+}
+
+/// The distribution of the absolute score differences found by an audit,
+/// in centipawns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuditReport {
+    pub audited: usize,
+    pub identical: usize,
+    pub median: u32,
+    pub p90: u32,
+    pub p99: u32,
+    pub max: u32,
+}
+
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} positions audited, {} with identical scores, score differences: median {}, 90th percentile {}, 99th percentile {}, max {}",
+            self.audited, self.identical, self.median, self.p90, self.p99, self.max
+        )
+    }
+}
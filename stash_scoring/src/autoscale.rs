@@ -0,0 +1,128 @@
+use std::fs;
+use std::time::{Duration, Instant};
+
+/// The throughput gain an extra worker must bring to be kept, relatively to
+/// the throughput without it.
+const MIN_GAIN: f64 = 0.05;
+
+/// The number of responses per worker a measurement window must hold, so
+/// that slow searches still give meaningful throughputs.
+const MIN_RESPONSES_PER_WORKER: usize = 8;
+
+/// What to do with the workers after a measurement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scaling {
+    Hold,
+    /// Start one more worker.
+    Grow,
+    /// Stop the last worker started.
+    Shrink,
+}
+
+/// Chooses the number of workers of a run from its throughput: starting with
+/// a single worker, one more is added after each measurement window as long
+/// as this increases the throughput, and the last one is removed once it
+/// does not (e.g. because of context switching), or when memory runs low.
+/// The count then stays the same until the end of the run.
+pub struct ThreadScaler {
+    max_workers: usize,
+    workers: usize,
+    window: Duration,
+    window_start: Instant,
+    window_responses: usize,
+    /// The throughput measured with one worker less, if any.
+    previous: Option<f64>,
+    /// The steady-state throughput, once the count of workers is settled.
+    settled: Option<f64>,
+}
+
+impl ThreadScaler {
+    /// Creates a scaler for up to `max_workers` workers, measuring the
+    /// throughput over windows of at least the given duration.
+    pub fn new(max_workers: usize, window: Duration, now: Instant) -> Self {
+        Self {
+            max_workers: max_workers.max(1),
+            workers: 1,
+            window,
+            window_start: now,
+            window_responses: 0,
+            previous: None,
+            settled: None,
+        }
+    }
+
+    /// The number of workers which should be running.
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// The throughput with the final number of workers, in responses per
+    /// second, once it is settled.
+    pub fn settled_throughput(&self) -> Option<f64> {
+        self.settled
+    }
+
+    /// Starts a new measurement window, e.g. once a new worker is ready, so
+    /// that its startup time is not counted.
+    pub fn start_window(&mut self, now: Instant) {
+        self.window_start = now;
+        self.window_responses = 0;
+    }
+
+    /// Records new responses, returning how the workers should be scaled.
+    pub fn record(&mut self, responses: usize, now: Instant, memory_low: bool) -> Scaling {
+        if self.settled.is_some() {
+            return Scaling::Hold;
+        }
+
+        self.window_responses += responses;
+
+        let elapsed = now.saturating_duration_since(self.window_start);
+
+        if elapsed < self.window || self.window_responses < MIN_RESPONSES_PER_WORKER * self.workers
+        {
+            return Scaling::Hold;
+        }
+
+        let throughput = self.window_responses as f64 / elapsed.as_secs_f64();
+
+        self.start_window(now);
+
+        match self.previous {
+            Some(previous) if memory_low || throughput < previous * (1.0 + MIN_GAIN) => {
+                self.workers -= 1;
+                self.settled = Some(previous);
+                Scaling::Shrink
+            }
+            _ if memory_low || self.workers >= self.max_workers => {
+                self.settled = Some(throughput);
+                Scaling::Hold
+            }
+            _ => {
+                self.previous = Some(throughput);
+                self.workers += 1;
+                Scaling::Grow
+            }
+        }
+    }
+}
+
+/// Whether less than a tenth of the memory of the system is available, in
+/// which case more engines could make it swap. Always false on systems
+/// without `/proc/meminfo`.
+pub fn memory_low() -> bool {
+    let Ok(meminfo) = fs::read_to_string("/proc/meminfo") else {
+        return false;
+    };
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.split_whitespace().next()?.parse::<u64>().ok())
+    };
+
+    match (field("MemAvailable"), field("MemTotal")) {
+        (Some(available), Some(total)) => available < total / 10,
+        _ => false,
+    }
+}
@@ -0,0 +1,274 @@
+//! A minimal UCI engine, replying instantly to every command. It is used for
+//! measuring the overhead of the scoring pipeline without any search cost, and
+//! for testing the tools against scripted engine behaviours.
+//!
+//! When the MOCK_ENGINE_SCRIPT environment variable points to a file, the
+//! replies are taken from it instead. The script is made of sections starting
+//! with the command they answer, which are replayed in order for successive
+//! occurrences of this command, the last one being repeated indefinitely:
+//!
+//! ```text
+//! [go]
+//! info depth 1 score cp 12 pv e2e4
+//! bestmove e2e4
+//! [go]
+//! !sleep 100
+//! info depth 1 score mate 3 pv d2d4
+//! bestmove d2d4
+//! ```
+//!
+//! Besides regular output lines, a section may contain the `!sleep <ms>`,
+//! `!stall` (stop answering anything), `!exit <code>`, `!stderr <text>`
+//! (write a line to the standard error) and `!legal` directives. The latter
+//! plays a legal move in the last position sent to the engine, picked
+//! according to the MoveIndex option, so that the mock engine can play whole
+//! games. The reply it expects is picked the same way.
+//!
+//! Replies to `go ponder` and `go infinite` are held back until the next
+//! `ponderhit` or `stop` command.
+//!
+//! When the MOCK_ENGINE_LOG environment variable is set, every received
+//! command is appended to the file it points to.
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::prelude::*;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use stash_scoring::board::Position;
+
+/// The position and options sent by the GUI.
+struct EngineState {
+    pos: Option<Position>,
+    chess960: bool,
+    move_index: usize,
+}
+
+impl EngineState {
+    fn handle(&mut self, line: &str) {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens[..] {
+            ["setoption", "name", "UCI_Chess960", "value", value] => {
+                self.chess960 = value == "true";
+            }
+            ["setoption", "name", "MoveIndex", "value", value] => {
+                self.move_index = value.parse().unwrap_or(0);
+            }
+            ["position", ..] => self.set_position(&tokens[1..]),
+            _ => (),
+        }
+    }
+
+    fn set_position(&mut self, tokens: &[&str]) {
+        let moves_idx = tokens
+            .iter()
+            .position(|&t| t == "moves")
+            .unwrap_or(tokens.len());
+        let fen = match tokens.first() {
+            Some(&"startpos") => Position::STARTPOS.to_string(),
+            _ => tokens[1..moves_idx].join(" "),
+        };
+        let mut pos = Position::from_fen(&fen, self.chess960).ok();
+
+        for uci in tokens.iter().skip(moves_idx + 1) {
+            pos = pos.and_then(|mut pos| {
+                let mv = pos.parse_uci(uci).ok()?;
+
+                pos.play(mv);
+                Some(pos)
+            });
+        }
+
+        self.pos = pos;
+    }
+
+    /// Returns the move picked in the current position, along with the
+    /// reply picked in the resulting position, if any.
+    fn legal_move(&self) -> (String, Option<String>) {
+        let Some(pos) = &self.pos else {
+            return (String::from("0000"), None);
+        };
+        let pick = |pos: &Position| {
+            let moves = pos.legal_moves();
+
+            match moves.len() {
+                0 => None,
+                n => Some(moves[self.move_index % n]),
+            }
+        };
+        let Some(mv) = pick(pos) else {
+            return (String::from("0000"), None);
+        };
+        let mut next = pos.clone();
+
+        next.play(mv);
+
+        (
+            mv.to_uci(self.chess960),
+            pick(&next).map(|reply| reply.to_uci(self.chess960)),
+        )
+    }
+}
+
+#[derive(Default)]
+struct Script {
+    sections: HashMap<String, Vec<Vec<String>>>,
+    replayed: HashMap<String, usize>,
+}
+
+impl Script {
+    fn parse(content: &str) -> Self {
+        let mut script = Self::default();
+        let mut current = None;
+
+        for line in content.lines() {
+            if let Some(command) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let sections = script.sections.entry(command.to_string()).or_default();
+
+                sections.push(Vec::new());
+                current = Some(command.to_string());
+            } else if let Some(command) = &current {
+                let section = script
+                    .sections
+                    .get_mut(command)
+                    .unwrap()
+                    .last_mut()
+                    .unwrap();
+
+                section.push(line.to_string());
+            }
+        }
+
+        script
+    }
+
+    /// Returns the reply to the next occurrence of the command, if scripted.
+    fn reply(&mut self, command: &str) -> Option<Vec<String>> {
+        let sections = self.sections.get(command)?;
+        let count = self.replayed.entry(command.to_string()).or_default();
+        let section = sections[(*count).min(sections.len() - 1)].clone();
+
+        *count += 1;
+        Some(section)
+    }
+}
+
+fn default_reply(command: &str) -> &'static [&'static str] {
+    match command {
+        "uci" => &[
+            "id name MockEngine",
+            "option name Threads type spin default 1 min 1 max 1024",
+            "option name Hash type spin default 16 min 1 max 1024",
+            "option name MultiPV type spin default 1 min 1 max 256",
+            "option name Ponder type check default false",
+            "option name UCI_Chess960 type check default false",
+            "option name MoveIndex type spin default 0 min 0 max 1000000",
+            "uciok",
+        ],
+        "isready" => &["readyok"],
+        "go" => &[
+            "info depth 1 seldepth 1 score cp 0 nodes 1 time 0 pv e2e4",
+            "bestmove e2e4",
+        ],
+        _ => &[],
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut script = match std::env::var("MOCK_ENGINE_SCRIPT") {
+        Ok(path) => Script::parse(&fs::read_to_string(path)?),
+        Err(_) => Script::default(),
+    };
+    let mut state = EngineState {
+        pos: None,
+        chess960: false,
+        move_index: 0,
+    };
+    let mut log = match std::env::var("MOCK_ENGINE_LOG") {
+        Ok(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+        Err(_) => None,
+    };
+    let mut deferred = None;
+    let stdin = io::stdin();
+    let mut stdout = io::stdout().lock();
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+
+        if let Some(log) = &mut log {
+            writeln!(log, "{}", line)?;
+        }
+
+        let Some(command) = line.split_whitespace().next() else {
+            continue;
+        };
+
+        if command == "quit" {
+            break;
+        }
+
+        state.handle(&line);
+
+        let reply = match command {
+            "ponderhit" | "stop" => deferred.take().unwrap_or_default(),
+            _ => script.reply(command).unwrap_or_else(|| {
+                default_reply(command)
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect()
+            }),
+        };
+
+        if let Some("ponder" | "infinite") = line.split_whitespace().nth(1) {
+            deferred = Some(reply);
+            continue;
+        }
+
+        for reply_line in reply {
+            let mut tokens = reply_line.split_whitespace();
+
+            match tokens.next() {
+                Some("!sleep") => {
+                    let ms = tokens.next().and_then(|t| t.parse().ok()).unwrap_or(0);
+
+                    stdout.flush()?;
+                    thread::sleep(Duration::from_millis(ms));
+                }
+                Some("!stall") => {
+                    stdout.flush()?;
+
+                    loop {
+                        thread::park();
+                    }
+                }
+                Some("!exit") => {
+                    stdout.flush()?;
+                    process::exit(tokens.next().and_then(|t| t.parse().ok()).unwrap_or(1));
+                }
+                Some("!stderr") => {
+                    eprintln!("{}", tokens.collect::<Vec<&str>>().join(" "));
+                }
+                Some("!legal") => match state.legal_move() {
+                    (mv, Some(reply)) => {
+                        writeln!(stdout, "info depth 1 score cp 0 pv {} {}", mv, reply)?;
+                        writeln!(stdout, "bestmove {} ponder {}", mv, reply)?;
+                    }
+                    (mv, None) => {
+                        writeln!(stdout, "info depth 1 score cp 0 pv {}", mv)?;
+                        writeln!(stdout, "bestmove {}", mv)?;
+                    }
+                },
+                _ => writeln!(stdout, "{}", reply_line)?,
+            }
+        }
+
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
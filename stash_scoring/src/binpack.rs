@@ -0,0 +1,641 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::board::{
+    bishop_attacks, fen_placement, king_attacks, knight_attacks, pawn_attacks, rook_attacks,
+    squares, Color, Move, MoveKind, Piece, PieceType, Position, Square, KING_SIDE, QUEEN_SIDE,
+};
+use crate::engine::Score;
+use crate::formats::ScoredPosition;
+
+/// The size of the first position of a binpack chain: the compressed
+/// position, its move, score, ply and result, and its halfmove clock.
+const STEM_SIZE: usize = 32;
+
+/// The number of bits of each block of the variable-length score deltas of
+/// binpack chains.
+const SCORE_BLOCK_SIZE: usize = 4;
+
+/// The mate scores of Stockfish, as an offset in plies from this value.
+const VALUE_MATE: i32 = 32000;
+const MAX_MATE_PLY: i32 = 246;
+
+/// A position of a Stockfish training dataset, with the move played from
+/// it, and its score and game result from the side to move's point of view.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrainingEntry {
+    pub pos: Position,
+    pub mv: Move,
+    /// The score, in the internal units of the engine which wrote it.
+    pub score: i16,
+    pub ply: u16,
+    /// 1 for a win, 0 for a draw and -1 for a loss.
+    pub result: i8,
+}
+
+impl TrainingEntry {
+    /// The game result from White's point of view, as a WDL label.
+    pub fn wdl(&self) -> f64 {
+        let result = match self.pos.side_to_move() {
+            Color::White => self.result,
+            Color::Black => -self.result,
+        };
+
+        f64::from(result + 1) / 2.0
+    }
+
+    /// The score, with Stockfish mate scores converted into mates in moves.
+    /// Returns None for scores beyond the mate scores, e.g. the VALUE_NONE
+    /// (32002) of positions written without a score.
+    pub fn score(&self) -> Option<Score> {
+        let score = i32::from(self.score);
+        let plies = VALUE_MATE - score.abs();
+
+        match plies {
+            ..0 => None,
+            0..=MAX_MATE_PLY => Some(Score::Mate((plies + 1) / 2 * score.signum())),
+            _ => Some(Score::Cp(score)),
+        }
+    }
+
+    pub fn to_scored(&self) -> Result<ScoredPosition, String> {
+        let score = self
+            .score()
+            .ok_or_else(|| format!("no score for '{}'", self.pos.to_fen()))?;
+
+        Ok(ScoredPosition {
+            pos: self.pos.clone(),
+            wdl: self.wdl(),
+            score,
+        })
+    }
+}
+
+/// Reads the bits of a binpack movetext, most significant bits first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, count: usize) -> Result<u16, String> {
+        let mut value = 0;
+
+        for _ in 0..count {
+            let byte = self.data.get(self.bit / 8).ok_or("truncated movetext")?;
+
+            value = (value << 1) | u16::from((byte >> (7 - self.bit % 8)) & 1);
+            self.bit += 1;
+        }
+
+        Ok(value)
+    }
+
+    /// Reads a number written as blocks of bits, each one followed by a bit
+    /// telling whether another block follows.
+    fn read_vle(&mut self) -> Result<u16, String> {
+        let mut value = 0u16;
+        let mut offset = 0;
+
+        loop {
+            let block = self.read(SCORE_BLOCK_SIZE + 1)?;
+
+            value |= (block & ((1 << SCORE_BLOCK_SIZE) - 1))
+                .checked_shl(offset)
+                .unwrap_or(0);
+
+            if block >> SCORE_BLOCK_SIZE == 0 {
+                return Ok(value);
+            }
+
+            offset += SCORE_BLOCK_SIZE as u32;
+        }
+    }
+
+    /// The number of bytes read so far, the last one being partly read.
+    fn bytes_read(&self) -> usize {
+        self.bit.div_ceil(8)
+    }
+}
+
+/// Converts the zigzag-like encoding of signed numbers of binpack files.
+fn unsigned_to_signed(value: u16) -> i16 {
+    let mut value = value.rotate_right(1);
+
+    if value & 0x8000 != 0 {
+        value ^= 0x7fff;
+    }
+
+    value as i16
+}
+
+/// The number of bits needed to write numbers up to `max`.
+fn used_bits(max: usize) -> usize {
+    (usize::BITS - max.leading_zeros()) as usize
+}
+
+/// Returns the square of the `n`-th square of a bitboard, in square order.
+fn nth_square(mut bb: u64, n: usize) -> Result<Square, String> {
+    for _ in 0..n {
+        bb &= bb.wrapping_sub(1);
+    }
+
+    match bb {
+        0 => Err(String::from("invalid move index")),
+        _ => Ok(Square::from_index(bb.trailing_zeros() as usize)),
+    }
+}
+
+/// Decodes the 24-byte compressed position of a binpack stem: the occupancy
+/// bitboard, then a 4-bit code per piece in square order, some codes also
+/// giving the castling rights, the en passant square and the side to move.
+fn decompress_position(bytes: &[u8], halfmove_clock: u16, ply: u16) -> Result<Position, String> {
+    let occupancy = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+    let mut board: [Option<Piece>; 64] = [None; 64];
+    let mut side_to_move = Color::White;
+    let mut castling = String::new();
+    let mut ep_square = None;
+
+    if occupancy.count_ones() > 32 {
+        return Err(String::from("too many pieces"));
+    }
+
+    for (idx, square) in squares(occupancy).enumerate() {
+        let code = (bytes[8 + idx / 2] >> (4 * (idx % 2))) & 0xf;
+        let piece = match code {
+            0..=11 => Piece::new(
+                match code % 2 {
+                    0 => Color::White,
+                    _ => Color::Black,
+                },
+                PieceType::ALL[usize::from(code / 2)],
+            ),
+            12 => {
+                let color = match square.rank() {
+                    3 => Color::White,
+                    _ => Color::Black,
+                };
+                let behind = match color {
+                    Color::White => 2,
+                    Color::Black => 5,
+                };
+
+                ep_square = Some(Square::new(square.file(), behind));
+                Piece::new(color, PieceType::Pawn)
+            }
+            13 | 14 => {
+                let (color, right) = match (code, square.file()) {
+                    (13, 0) => (Color::White, 'Q'),
+                    (13, _) => (Color::White, 'K'),
+                    (_, 0) => (Color::Black, 'q'),
+                    _ => (Color::Black, 'k'),
+                };
+
+                castling.push(right);
+                Piece::new(color, PieceType::Rook)
+            }
+            _ => {
+                side_to_move = Color::Black;
+                Piece::new(Color::Black, PieceType::King)
+            }
+        };
+
+        board[square.index()] = Some(piece);
+    }
+
+    // The castling rights are listed in the FEN order.
+    let castling: String = "KQkq".chars().filter(|c| castling.contains(*c)).collect();
+    let fen = format!(
+        "{} {} {} {} {} {}",
+        fen_placement(&board),
+        match side_to_move {
+            Color::White => "w",
+            Color::Black => "b",
+        },
+        match castling.is_empty() {
+            true => "-",
+            false => &castling,
+        },
+        ep_square.map_or(String::from("-"), |square| square.to_string()),
+        halfmove_clock,
+        ply / 2 + 1
+    );
+
+    Position::from_fen(&fen, false).map_err(|err| err.to_string())
+}
+
+/// Decodes a move written with its type, squares and promotion, castling
+/// moves being written as the king capturing its rook.
+fn decompress_move(packed: u16) -> Move {
+    let from = Square::from_index(usize::from((packed >> 8) & 63));
+    let to = Square::from_index(usize::from((packed >> 2) & 63));
+    let mut mv = Move::new(from, to);
+
+    match packed >> 14 {
+        1 => mv.promotion = Some(PieceType::ALL[usize::from(packed & 3) + 1]),
+        2 => mv.kind = MoveKind::Castling,
+        3 => mv.kind = MoveKind::EnPassant,
+        _ => (),
+    }
+
+    mv
+}
+
+/// Decodes a move of a binpack movetext, written as the index of the moving
+/// piece among the pieces of the side to move, then as the index of the move
+/// among the pseudo-legal moves of the piece.
+fn read_move(pos: &Position, bits: &mut BitReader) -> Result<Move, String> {
+    let us = pos.side_to_move();
+    let ours = pos.color_pieces(us);
+    let theirs = pos.color_pieces(us.flip());
+    let occupancy = pos.occupancy();
+    let piece_index = bits.read(used_bits(ours.count_ones() as usize - 1))?;
+    let from = nth_square(ours, usize::from(piece_index))?;
+    let kind = pos.piece_at(from).unwrap().kind;
+    let mut read_index = |targets: u64| -> Result<Square, String> {
+        let index = bits.read(used_bits(targets.count_ones() as usize - 1))?;
+
+        nth_square(targets, usize::from(index))
+    };
+
+    match kind {
+        PieceType::Pawn => {
+            let (forward, start_rank, last_rank): (i8, u8, u8) = match us {
+                Color::White => (8, 1, 6),
+                Color::Black => (-8, 6, 1),
+            };
+            // Like in the writers of binpack files, the en passant square is
+            // only set when the capture is legal.
+            let ep_square = pos.ep_square().filter(|_| {
+                pos.legal_moves()
+                    .iter()
+                    .any(|mv| mv.kind == MoveKind::EnPassant)
+            });
+            let mut targets =
+                pawn_attacks(us, from) & (theirs | ep_square.map_or(0, |square| square.bitboard()));
+            let push = Square::from_index((from.index() as i8 + forward) as usize);
+
+            if occupancy & push.bitboard() == 0 {
+                targets |= push.bitboard();
+
+                let double_push = Square::from_index((push.index() as i8 + forward) as usize);
+
+                if from.rank() == start_rank && occupancy & double_push.bitboard() == 0 {
+                    targets |= double_push.bitboard();
+                }
+            }
+
+            if from.rank() == last_rank {
+                let count = targets.count_ones() as usize * 4;
+                let index = usize::from(bits.read(used_bits(count - 1))?);
+                let mut mv = Move::new(from, nth_square(targets, index / 4)?);
+
+                mv.promotion = Some(PieceType::ALL[index % 4 + 1]);
+                return Ok(mv);
+            }
+
+            let to = read_index(targets)?;
+            let mut mv = Move::new(from, to);
+
+            if Some(to) == ep_square {
+                mv.kind = MoveKind::EnPassant;
+            }
+
+            Ok(mv)
+        }
+        PieceType::King => {
+            let targets = king_attacks(from) & !ours;
+            let rooks: Vec<Square> = [QUEEN_SIDE, KING_SIDE]
+                .into_iter()
+                .filter_map(|side| pos.castling_rook(us, side))
+                .collect();
+            let count = targets.count_ones() as usize + rooks.len();
+            let index = usize::from(bits.read(used_bits(count - 1))?);
+
+            // Castling moves come last, the long one first.
+            match index.checked_sub(targets.count_ones() as usize) {
+                Some(castling) => {
+                    let rook = rooks.get(castling).ok_or("invalid move index")?;
+
+                    Ok(Move {
+                        kind: MoveKind::Castling,
+                        ..Move::new(from, *rook)
+                    })
+                }
+                None => Ok(Move::new(from, nth_square(targets, index)?)),
+            }
+        }
+        _ => {
+            let attacks = match kind {
+                PieceType::Knight => knight_attacks(from),
+                PieceType::Bishop => bishop_attacks(from, occupancy),
+                PieceType::Rook => rook_attacks(from, occupancy),
+                _ => bishop_attacks(from, occupancy) | rook_attacks(from, occupancy),
+            };
+
+            Ok(Move::new(from, read_index(attacks & !ours)?))
+        }
+    }
+}
+
+/// The positions of a binpack chain which are still to be read.
+struct Chain {
+    entry: TrainingEntry,
+    remaining_plies: u16,
+    /// The position of the movetext in the chunk, and the bit read next.
+    movetext: usize,
+    bit: usize,
+    /// The score of the previous position, from the point of view of the
+    /// side to move.
+    last_score: i16,
+}
+
+/// Reads the positions of a Stockfish .binpack file. The file is made of
+/// chunks ('BINP', then the chunk size in little endian), holding chains of
+/// positions: a first position written in full, followed by the moves played
+/// from it along with the score of each reached position.
+pub struct BinpackReader<R> {
+    reader: R,
+    chunk: Vec<u8>,
+    offset: usize,
+    chain: Option<Chain>,
+    bytes_read: u64,
+}
+
+impl BinpackReader<BufReader<File>> {
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> BinpackReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            chunk: Vec::new(),
+            offset: 0,
+            chain: None,
+            bytes_read: 0,
+        }
+    }
+
+    /// The number of bytes of the file read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Reads the next chunk, returning false at the end of the file.
+    fn read_chunk(&mut self) -> io::Result<bool> {
+        let mut header = [0; 8];
+
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+
+        self.reader.read_exact(&mut header)?;
+
+        if &header[..4] != b"BINP" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid binpack chunk header",
+            ));
+        }
+
+        let size = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+
+        self.chunk.resize(size, 0);
+        self.reader.read_exact(&mut self.chunk)?;
+        self.bytes_read += (header.len() + size) as u64;
+        self.offset = 0;
+        Ok(true)
+    }
+
+    /// Reads the first position of a chain.
+    fn read_stem(&mut self) -> Result<TrainingEntry, String> {
+        let stem = self
+            .chunk
+            .get(self.offset..self.offset + STEM_SIZE + 2)
+            .ok_or("truncated chain")?;
+        let word = |idx: usize| u16::from_be_bytes([stem[idx], stem[idx + 1]]);
+        let ply_and_result = word(28);
+        let ply = ply_and_result & 0x3fff;
+        let entry = TrainingEntry {
+            pos: decompress_position(&stem[..24], word(30), ply)?,
+            mv: decompress_move(word(24)),
+            score: unsigned_to_signed(word(26)),
+            ply,
+            result: unsigned_to_signed(ply_and_result >> 14) as i8,
+        };
+
+        self.chain = Some(Chain {
+            entry: entry.clone(),
+            remaining_plies: word(STEM_SIZE),
+            movetext: self.offset + STEM_SIZE + 2,
+            bit: 0,
+            last_score: entry.score.wrapping_neg(),
+        });
+
+        Ok(entry)
+    }
+
+    /// Reads the next position of the current chain.
+    fn read_ply(&mut self, mut chain: Chain) -> Result<TrainingEntry, String> {
+        let mut bits = BitReader {
+            data: &self.chunk[chain.movetext..],
+            bit: chain.bit,
+        };
+        let mut pos = chain.entry.pos.clone();
+
+        if !pos.legal_moves().contains(&chain.entry.mv) {
+            return Err(format!("illegal move in '{}'", pos.to_fen()));
+        }
+
+        pos.play(chain.entry.mv);
+
+        let mv = read_move(&pos, &mut bits)?;
+        let score = chain
+            .last_score
+            .wrapping_add(unsigned_to_signed(bits.read_vle()?));
+        let entry = TrainingEntry {
+            pos,
+            mv,
+            score,
+            ply: chain.entry.ply + 1,
+            result: -chain.entry.result,
+        };
+
+        chain.remaining_plies -= 1;
+
+        match chain.remaining_plies {
+            0 => self.offset = chain.movetext + bits.bytes_read(),
+            _ => {
+                chain.bit = bits.bit;
+                chain.last_score = score.wrapping_neg();
+                chain.entry = entry.clone();
+                self.chain = Some(chain);
+            }
+        }
+
+        Ok(entry)
+    }
+
+    /// Reads the next position. Invalid data makes the rest of its chunk
+    /// unreadable, so it is skipped after the error is returned.
+    pub fn next_entry(&mut self) -> io::Result<Option<Result<TrainingEntry, String>>> {
+        let result = match self.chain.take() {
+            Some(chain) => self.read_ply(chain),
+            None => {
+                if self.offset >= self.chunk.len() && !self.read_chunk()? {
+                    return Ok(None);
+                }
+
+                let result = self.read_stem();
+
+                if let Some(chain) = &self.chain {
+                    if chain.remaining_plies == 0 {
+                        self.offset = chain.movetext;
+                        self.chain = None;
+                    }
+                }
+
+                result
+            }
+        };
+
+        if result.is_err() {
+            self.chain = None;
+            self.offset = self.chunk.len();
+        }
+
+        Ok(Some(result))
+    }
+}
+
+/// Reads the positions of a Stockfish .plain file, written as blocks of
+/// 'fen', 'move' (in UCI notation), 'score', 'ply' and 'result' lines, each
+/// block ending with an 'e' line.
+pub struct PlainReader<R> {
+    reader: R,
+    line: String,
+    bytes_read: u64,
+}
+
+impl PlainReader<BufReader<File>> {
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> PlainReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            bytes_read: 0,
+        }
+    }
+
+    /// The number of bytes of the file read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Reads the next position. An invalid block only invalidates its own
+    /// position.
+    pub fn next_entry(&mut self) -> io::Result<Option<Result<TrainingEntry, String>>> {
+        let mut fields: Vec<(String, String)> = Vec::new();
+
+        loop {
+            self.line.clear();
+
+            let len = self.reader.read_line(&mut self.line)?;
+
+            self.bytes_read += len as u64;
+
+            let line = self.line.trim();
+
+            match (len, line) {
+                (0, _) if fields.is_empty() => return Ok(None),
+                (0, _) => return Ok(Some(Err(String::from("truncated position")))),
+                (_, "") => continue,
+                (_, "e") => break,
+                (_, line) => {
+                    let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+
+                    fields.push((key.to_string(), value.trim().to_string()));
+                }
+            }
+        }
+
+        let field = |key: &str| {
+            fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str())
+                .ok_or_else(|| format!("missing '{}' field", key))
+        };
+        let parse = |key: &str| -> Result<i64, String> {
+            let value = field(key)?;
+
+            value
+                .parse()
+                .map_err(|_| format!("invalid {} '{}'", key, value))
+        };
+
+        Ok(Some((|| {
+            let pos = Position::from_fen(field("fen")?, false).map_err(|err| err.to_string())?;
+            let mv = pos
+                .parse_uci(field("move")?)
+                .map_err(|err| err.to_string())?;
+
+            Ok(TrainingEntry {
+                mv,
+                score: i16::try_from(parse("score")?).map_err(|err| err.to_string())?,
+                ply: u16::try_from(parse("ply")?).map_err(|err| err.to_string())?,
+                result: match parse("result")? {
+                    result @ -1..=1 => result as i8,
+                    result => return Err(format!("invalid result '{}'", result)),
+                },
+                pos,
+            })
+        })()))
+    }
+}
+
+/// A Stockfish training dataset, in one of the supported formats.
+pub enum TrainingDataReader {
+    Binpack(Box<BinpackReader<BufReader<File>>>),
+    Plain(PlainReader<BufReader<File>>),
+}
+
+impl TrainingDataReader {
+    /// Opens a dataset whose format is given by its extension, '.binpack'
+    /// or '.plain'. Returns None for other files.
+    pub fn open(path: &str) -> io::Result<Option<Self>> {
+        let extension = Path::new(path).extension().and_then(|ext| ext.to_str());
+
+        Ok(match extension {
+            Some("binpack") => Some(Self::Binpack(Box::new(BinpackReader::open(path)?))),
+            Some("plain") => Some(Self::Plain(PlainReader::open(path)?)),
+            _ => None,
+        })
+    }
+
+    /// The number of bytes of the file read so far.
+    pub fn bytes_read(&self) -> u64 {
+        match self {
+            Self::Binpack(reader) => reader.bytes_read(),
+            Self::Plain(reader) => reader.bytes_read(),
+        }
+    }
+
+    pub fn next_entry(&mut self) -> io::Result<Option<Result<TrainingEntry, String>>> {
+        match self {
+            Self::Binpack(reader) => reader.next_entry(),
+            Self::Plain(reader) => reader.next_entry(),
+        }
+    }
+}
@@ -0,0 +1,1426 @@
+use std::fmt;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+use crate::input::tokenize;
+use crate::rng::Rng;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn flip(self) -> Self {
+        match self {
+            Self::White => Self::Black,
+            Self::Black => Self::White,
+        }
+    }
+
+    /// The rank (0-based) on which the pieces of this color start.
+    pub fn back_rank(self) -> u8 {
+        match self {
+            Self::White => 0,
+            Self::Black => 7,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PieceType {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl PieceType {
+    pub const ALL: [PieceType; 6] = [
+        Self::Pawn,
+        Self::Knight,
+        Self::Bishop,
+        Self::Rook,
+        Self::Queen,
+        Self::King,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        match c.to_ascii_lowercase() {
+            'p' => Some(Self::Pawn),
+            'n' => Some(Self::Knight),
+            'b' => Some(Self::Bishop),
+            'r' => Some(Self::Rook),
+            'q' => Some(Self::Queen),
+            'k' => Some(Self::King),
+            _ => None,
+        }
+    }
+
+    /// The lowercase letter used for this piece type in FENs and UCI moves.
+    pub fn to_char(self) -> char {
+        match self {
+            Self::Pawn => 'p',
+            Self::Knight => 'n',
+            Self::Bishop => 'b',
+            Self::Rook => 'r',
+            Self::Queen => 'q',
+            Self::King => 'k',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Piece {
+    pub color: Color,
+    pub kind: PieceType,
+}
+
+impl Piece {
+    pub fn new(color: Color, kind: PieceType) -> Self {
+        Self { color, kind }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        let kind = PieceType::from_char(c)?;
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        Some(Self { color, kind })
+    }
+
+    pub fn to_char(self) -> char {
+        match self.color {
+            Color::White => self.kind.to_char().to_ascii_uppercase(),
+            Color::Black => self.kind.to_char(),
+        }
+    }
+}
+
+/// A square of the board, from 0 (a1) to 63 (h8).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Square(u8);
+
+impl Square {
+    pub fn new(file: u8, rank: u8) -> Self {
+        debug_assert!(file < 8 && rank < 8);
+        Self(rank * 8 + file)
+    }
+
+    pub fn from_index(index: usize) -> Self {
+        debug_assert!(index < 64);
+        Self(index as u8)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn file(self) -> u8 {
+        self.0 % 8
+    }
+
+    pub fn rank(self) -> u8 {
+        self.0 / 8
+    }
+
+    pub fn bitboard(self) -> u64 {
+        1u64 << self.0
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            (b'a' + self.file()) as char,
+            (b'1' + self.rank()) as char
+        )
+    }
+}
+
+impl FromStr for Square {
+    type Err = FenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Ok(Self::new(file - b'a', rank - b'1')),
+            _ => Err(FenError(format!("invalid square '{}'", s))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FenError(pub String);
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for FenError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MoveError(pub String);
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+/// Index of the castling sides in castling-related arrays.
+pub const KING_SIDE: usize = 0;
+pub const QUEEN_SIDE: usize = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MoveKind {
+    Normal,
+    EnPassant,
+    /// Castling moves are encoded as the king capturing its own rook, so that
+    /// standard chess and Chess960 are handled the same way.
+    Castling,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: Option<PieceType>,
+    pub kind: MoveKind,
+}
+
+impl Move {
+    pub fn new(from: Square, to: Square) -> Self {
+        Self {
+            from,
+            to,
+            promotion: None,
+            kind: MoveKind::Normal,
+        }
+    }
+
+    /// Returns the destination square of the king for castling moves, and
+    /// the destination square of the moving piece otherwise.
+    pub fn king_to(self) -> Square {
+        match self.kind {
+            MoveKind::Castling if self.to.file() > self.from.file() => {
+                Square::new(6, self.from.rank())
+            }
+            MoveKind::Castling => Square::new(2, self.from.rank()),
+            _ => self.to,
+        }
+    }
+
+    /// Writes the move in UCI notation. Castling moves are written as the
+    /// king capturing its rook in Chess960 mode, and as a two-square king
+    /// move otherwise.
+    pub fn to_uci(self, chess960: bool) -> String {
+        let to = match chess960 {
+            true => self.to,
+            false => self.king_to(),
+        };
+        let mut uci = format!("{}{}", self.from, to);
+
+        if let Some(promotion) = self.promotion {
+            uci.push(promotion.to_char());
+        }
+
+        uci
+    }
+}
+
+/// A notation for writing moves.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveNotation {
+    /// Standard Algebraic Notation, e.g. `Nf3` or `O-O`.
+    San,
+    /// UCI notation, e.g. `g1f3` or `e1g1`.
+    Uci,
+}
+
+/// The random values of the Zobrist keys of positions: 768 values for pieces
+/// on squares, then 4 for castling rights, 8 for en passant files and 1 for
+/// Black to move.
+const ZOBRIST: [u64; 781] = zobrist_values();
+
+/// Generates the Zobrist values from a fixed seed.
+const fn zobrist_values() -> [u64; 781] {
+    let mut values = [0; 781];
+    let mut rng = Rng::new(0);
+    let mut idx = 0;
+
+    while idx < values.len() {
+        values[idx] = rng.next_u64();
+        idx += 1;
+    }
+
+    values
+}
+
+fn piece_key(piece: Piece, square: Square) -> u64 {
+    ZOBRIST[64 * (6 * piece.color.index() + piece.kind.index()) + square.index()]
+}
+
+/// A chess position, supporting both standard chess and Chess960.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    squares: [Option<Piece>; 64],
+    by_color: [u64; 2],
+    by_kind: [u64; 6],
+    side_to_move: Color,
+    /// The squares of the rooks which can still castle, indexed by color and
+    /// castling side.
+    castling_rooks: [[Option<Square>; 2]; 2],
+    ep_square: Option<Square>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    chess960: bool,
+    /// The Zobrist key of the position, updated along with it.
+    key: u64,
+}
+
+impl Position {
+    pub const STARTPOS: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    /// Parses a position from a FEN string. Castling rights may be written
+    /// with the standard `KQkq` letters or, when `chess960` is set, with
+    /// Shredder-FEN (rook files) or X-FEN notation. The move counters are
+    /// optional.
+    pub fn from_fen(fen: &str, chess960: bool) -> Result<Self, FenError> {
+        let mut fields = fen.split_whitespace();
+        let mut pos = Self {
+            squares: [None; 64],
+            by_color: [0; 2],
+            by_kind: [0; 6],
+            side_to_move: Color::White,
+            castling_rooks: [[None; 2]; 2],
+            ep_square: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            chess960,
+            key: 0,
+        };
+
+        let placement = fields.next().ok_or_else(|| FenError("empty FEN".into()))?;
+        pos.parse_placement(placement)?;
+
+        pos.side_to_move = match fields.next() {
+            Some("w") => Color::White,
+            Some("b") => Color::Black,
+            other => {
+                return Err(FenError(format!(
+                    "invalid side to move '{}'",
+                    other.unwrap_or_default()
+                )))
+            }
+        };
+
+        let castling = fields
+            .next()
+            .ok_or_else(|| FenError("missing castling field".into()))?;
+        pos.parse_castling(castling)?;
+
+        pos.ep_square = match fields.next() {
+            Some("-") => None,
+            Some(s) => {
+                let square = s.parse::<Square>()?;
+                let expected_rank = match pos.side_to_move {
+                    Color::White => 5,
+                    Color::Black => 2,
+                };
+
+                if square.rank() != expected_rank {
+                    return Err(FenError(format!("invalid en passant square '{}'", s)));
+                }
+
+                Some(square)
+            }
+            None => return Err(FenError("missing en passant field".into())),
+        };
+
+        if let Some(halfmove) = fields.next() {
+            pos.halfmove_clock = halfmove
+                .parse()
+                .map_err(|_| FenError(format!("invalid halfmove clock '{}'", halfmove)))?;
+
+            let fullmove = fields
+                .next()
+                .ok_or_else(|| FenError("missing fullmove number".into()))?;
+            pos.fullmove_number = fullmove
+                .parse()
+                .map_err(|_| FenError(format!("invalid fullmove number '{}'", fullmove)))?;
+        }
+
+        if let Some(extra) = fields.next() {
+            return Err(FenError(format!("unexpected trailing field '{}'", extra)));
+        }
+
+        pos.key ^= pos.state_key();
+        Ok(pos)
+    }
+
+    /// Parses a position from an EPD line, or from a FEN. EPD operations
+    /// following the four position fields are ignored, while move counters
+    /// are used when both are present.
+    pub fn from_epd(line: &str, chess960: bool) -> Result<Self, FenError> {
+        let tokens: Vec<&str> = tokenize(line).collect();
+        let has_counters =
+            tokens.len() >= 6 && tokens[4..6].iter().all(|t| t.parse::<u32>().is_ok());
+        let fen_len = if has_counters { 6 } else { 4.min(tokens.len()) };
+
+        Self::from_fen(&tokens[..fen_len].join(" "), chess960)
+    }
+
+    /// Returns the Chess960 starting position with the given Scharnagl
+    /// numbers (0 to 959, 518 being the standard setup) for the White and
+    /// Black pieces. Different numbers give a double Fischer random (DFRC)
+    /// position.
+    pub fn from_scharnagl(white: usize, black: usize) -> Self {
+        let back_rank = |number: usize| {
+            const KNIGHTS: [(usize, usize); 10] = [
+                (0, 1),
+                (0, 2),
+                (0, 3),
+                (0, 4),
+                (1, 2),
+                (1, 3),
+                (1, 4),
+                (2, 3),
+                (2, 4),
+                (3, 4),
+            ];
+
+            assert!(number < 960, "invalid Scharnagl number {}", number);
+
+            let mut rank = [None; 8];
+
+            rank[2 * (number % 4) + 1] = Some('b');
+            rank[2 * (number / 4 % 4)] = Some('b');
+
+            let mut empty: Vec<usize> = (0..8).filter(|&f| rank[f].is_none()).collect();
+
+            rank[empty.remove(number / 16 % 6)] = Some('q');
+
+            let (first, second) = KNIGHTS[number / 96];
+
+            rank[empty[first]] = Some('n');
+            rank[empty[second]] = Some('n');
+
+            for (piece, file) in ['r', 'k', 'r']
+                .into_iter()
+                .zip((0..8).filter(|&f| rank[f].is_none()).collect::<Vec<_>>())
+            {
+                rank[file] = Some(piece);
+            }
+
+            let rank: String = rank.into_iter().flatten().collect();
+            let rooks: String = rank
+                .char_indices()
+                .filter(|&(_, piece)| piece == 'r')
+                .map(|(file, _)| (b'a' + file as u8) as char)
+                .rev()
+                .collect();
+
+            (rank, rooks)
+        };
+        let (white_rank, white_rooks) = back_rank(white);
+        let (black_rank, black_rooks) = back_rank(black);
+        let fen = format!(
+            "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {}{} - 0 1",
+            black_rank,
+            white_rank.to_ascii_uppercase(),
+            white_rooks.to_ascii_uppercase(),
+            black_rooks
+        );
+
+        Self::from_fen(&fen, true).unwrap()
+    }
+
+    fn parse_placement(&mut self, placement: &str) -> Result<(), FenError> {
+        let ranks: Vec<&str> = placement.split('/').collect();
+
+        if ranks.len() != 8 {
+            return Err(FenError(format!("expected 8 ranks, got {}", ranks.len())));
+        }
+
+        for (i, rank_str) in ranks.iter().enumerate() {
+            let rank = 7 - i as u8;
+            let mut file = 0u8;
+
+            for c in rank_str.chars() {
+                if let Some(skip) = c.to_digit(10).filter(|d| (1..=8).contains(d)) {
+                    file += skip as u8;
+                } else {
+                    let piece = Piece::from_char(c)
+                        .ok_or_else(|| FenError(format!("invalid piece '{}'", c)))?;
+
+                    if file >= 8 {
+                        return Err(FenError(format!("rank {} is too long", rank + 1)));
+                    }
+
+                    self.put_piece(Square::new(file, rank), piece);
+                    file += 1;
+                }
+
+                if file > 8 {
+                    return Err(FenError(format!("rank {} is too long", rank + 1)));
+                }
+            }
+
+            if file != 8 {
+                return Err(FenError(format!("rank {} is too short", rank + 1)));
+            }
+        }
+
+        for color in [Color::White, Color::Black] {
+            let kings = self.pieces(color, PieceType::King).count_ones();
+
+            if kings != 1 {
+                return Err(FenError(format!(
+                    "expected one {:?} king, got {}",
+                    color, kings
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_castling(&mut self, castling: &str) -> Result<(), FenError> {
+        if castling == "-" {
+            return Ok(());
+        }
+
+        for c in castling.chars() {
+            let color = if c.is_ascii_uppercase() {
+                Color::White
+            } else {
+                Color::Black
+            };
+            let rank = color.back_rank();
+            let king = self.king_square(color);
+
+            if king.rank() != rank {
+                return Err(FenError(format!("castling right '{}' without king", c)));
+            }
+
+            let rook = Piece::new(color, PieceType::Rook);
+            let rook_files =
+                (0..8u8).filter(|&f| self.piece_at(Square::new(f, rank)) == Some(rook));
+            let file = match c.to_ascii_lowercase() {
+                // Standard and X-FEN letters refer to the outermost rook.
+                'k' => rook_files.filter(|&f| f > king.file()).max(),
+                'q' => rook_files.filter(|&f| f < king.file()).min(),
+                f @ 'a'..='h' if self.chess960 => {
+                    let file = f as u8 - b'a';
+                    rook_files.into_iter().find(|&rf| rf == file)
+                }
+                _ => return Err(FenError(format!("invalid castling right '{}'", c))),
+            };
+            let file =
+                file.ok_or_else(|| FenError(format!("castling right '{}' without rook", c)))?;
+            let side = if file > king.file() {
+                KING_SIDE
+            } else {
+                QUEEN_SIDE
+            };
+
+            if !self.chess960 && (king.file() != 4 || (file != 0 && file != 7)) {
+                return Err(FenError(format!(
+                    "castling right '{}' requires Chess960 mode",
+                    c
+                )));
+            }
+
+            self.castling_rooks[color.index()][side] = Some(Square::new(file, rank));
+        }
+
+        Ok(())
+    }
+
+    fn put_piece(&mut self, square: Square, piece: Piece) {
+        self.key ^= piece_key(piece, square);
+        self.squares[square.index()] = Some(piece);
+        self.by_color[piece.color.index()] |= square.bitboard();
+        self.by_kind[piece.kind.index()] |= square.bitboard();
+    }
+
+    fn remove_piece(&mut self, square: Square) -> Option<Piece> {
+        let piece = self.squares[square.index()].take()?;
+
+        self.key ^= piece_key(piece, square);
+        self.by_color[piece.color.index()] &= !square.bitboard();
+        self.by_kind[piece.kind.index()] &= !square.bitboard();
+        Some(piece)
+    }
+
+    pub fn piece_at(&self, square: Square) -> Option<Piece> {
+        self.squares[square.index()]
+    }
+
+    pub fn side_to_move(&self) -> Color {
+        self.side_to_move
+    }
+
+    pub fn ep_square(&self) -> Option<Square> {
+        self.ep_square
+    }
+
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    pub fn fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    pub fn castling_rook(&self, color: Color, side: usize) -> Option<Square> {
+        self.castling_rooks[color.index()][side]
+    }
+
+    /// Returns the bitboard of the pieces of the given color and type.
+    pub fn pieces(&self, color: Color, kind: PieceType) -> u64 {
+        self.by_color[color.index()] & self.by_kind[kind.index()]
+    }
+
+    pub fn color_pieces(&self, color: Color) -> u64 {
+        self.by_color[color.index()]
+    }
+
+    pub fn kind_pieces(&self, kind: PieceType) -> u64 {
+        self.by_kind[kind.index()]
+    }
+
+    pub fn occupancy(&self) -> u64 {
+        self.by_color[0] | self.by_color[1]
+    }
+
+    pub fn king_square(&self, color: Color) -> Square {
+        Square::from_index(self.pieces(color, PieceType::King).trailing_zeros() as usize)
+    }
+
+    /// Returns the bitboard of the pieces of the given color attacking the
+    /// square, with the given board occupancy for sliders.
+    pub fn attackers_to(&self, square: Square, by: Color, occupancy: u64) -> u64 {
+        let bishops = self.kind_pieces(PieceType::Bishop) | self.kind_pieces(PieceType::Queen);
+        let rooks = self.kind_pieces(PieceType::Rook) | self.kind_pieces(PieceType::Queen);
+        let attackers = (pawn_attacks(by.flip(), square) & self.kind_pieces(PieceType::Pawn))
+            | (knight_attacks(square) & self.kind_pieces(PieceType::Knight))
+            | (king_attacks(square) & self.kind_pieces(PieceType::King))
+            | (bishop_attacks(square, occupancy) & bishops)
+            | (rook_attacks(square, occupancy) & rooks);
+
+        attackers & self.color_pieces(by)
+    }
+
+    pub fn is_attacked(&self, square: Square, by: Color) -> bool {
+        self.attackers_to(square, by, self.occupancy()) != 0
+    }
+
+    /// Returns whether the side to move is in check.
+    pub fn in_check(&self) -> bool {
+        self.is_attacked(
+            self.king_square(self.side_to_move),
+            self.side_to_move.flip(),
+        )
+    }
+
+    /// Returns whether neither side can ever checkmate the other, i.e. when
+    /// only kings remain, with at most a single minor piece or with bishops
+    /// all on squares of the same color.
+    pub fn has_insufficient_material(&self) -> bool {
+        const DARK_SQUARES: u64 = 0xAA55_AA55_AA55_AA55;
+
+        let heavy = self.kind_pieces(PieceType::Pawn)
+            | self.kind_pieces(PieceType::Rook)
+            | self.kind_pieces(PieceType::Queen);
+        let knights = self.kind_pieces(PieceType::Knight);
+        let bishops = self.kind_pieces(PieceType::Bishop);
+
+        if heavy != 0 {
+            return false;
+        }
+
+        (knights | bishops).count_ones() <= 1
+            || (knights == 0 && (bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0))
+    }
+
+    /// Checks that the position could arise in a game, beyond the syntax
+    /// checks done when parsing the FEN.
+    pub fn check_legality(&self) -> Result<(), FenError> {
+        const BACK_RANKS: u64 = 0xFF000000000000FF;
+
+        if self.kind_pieces(PieceType::Pawn) & BACK_RANKS != 0 {
+            return Err(FenError("pawns on the first or last rank".into()));
+        }
+
+        for color in [Color::White, Color::Black] {
+            if self.color_pieces(color).count_ones() > 16 {
+                return Err(FenError(format!("too many {:?} pieces", color)));
+            }
+
+            if self.pieces(color, PieceType::Pawn).count_ones() > 8 {
+                return Err(FenError(format!("too many {:?} pawns", color)));
+            }
+        }
+
+        let them = self.side_to_move.flip();
+
+        if self.is_attacked(self.king_square(them), self.side_to_move) {
+            return Err(FenError("the side not to move is in check".into()));
+        }
+
+        if let Some(ep) = self.ep_square {
+            let (pushed, origin) = match self.side_to_move {
+                Color::White => (Square::new(ep.file(), 4), Square::new(ep.file(), 6)),
+                Color::Black => (Square::new(ep.file(), 3), Square::new(ep.file(), 1)),
+            };
+
+            if self.piece_at(pushed) != Some(Piece::new(them, PieceType::Pawn))
+                || self.piece_at(ep).is_some()
+                || self.piece_at(origin).is_some()
+            {
+                return Err(FenError(format!("impossible en passant square {}", ep)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns all legal moves in the position.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let us = self.side_to_move;
+        let mut moves = self.pseudo_legal_moves();
+
+        moves.retain(|&mv| {
+            let mut pos = self.clone();
+
+            pos.play(mv);
+            !pos.is_attacked(pos.king_square(us), us.flip())
+        });
+        moves
+    }
+
+    /// Returns the moves following the movement rules of the pieces, without
+    /// checking whether they leave the king in check.
+    fn pseudo_legal_moves(&self) -> Vec<Move> {
+        let us = self.side_to_move;
+        let occupancy = self.occupancy();
+        let targets = !self.color_pieces(us);
+        let mut moves = Vec::with_capacity(64);
+
+        for from in squares(self.color_pieces(us)) {
+            let kind = self.piece_at(from).unwrap().kind;
+            let attacks = match kind {
+                PieceType::Pawn => {
+                    self.pawn_moves(from, &mut moves);
+                    continue;
+                }
+                PieceType::Knight => knight_attacks(from),
+                PieceType::Bishop => bishop_attacks(from, occupancy),
+                PieceType::Rook => rook_attacks(from, occupancy),
+                PieceType::Queen => bishop_attacks(from, occupancy) | rook_attacks(from, occupancy),
+                PieceType::King => king_attacks(from),
+            };
+
+            moves.extend(squares(attacks & targets).map(|to| Move::new(from, to)));
+        }
+
+        self.castling_moves(&mut moves);
+        moves
+    }
+
+    fn pawn_moves(&self, from: Square, moves: &mut Vec<Move>) {
+        let us = self.side_to_move;
+        let (forward, start_rank, last_rank) = match us {
+            Color::White => (1i8, 1, 7),
+            Color::Black => (-1i8, 6, 0),
+        };
+        let push = |sq: Square, ranks: i8| Square::new(sq.file(), (sq.rank() as i8 + ranks) as u8);
+        let mut add = |to: Square, kind: MoveKind| {
+            if to.rank() == last_rank {
+                for promotion in [
+                    PieceType::Queen,
+                    PieceType::Rook,
+                    PieceType::Bishop,
+                    PieceType::Knight,
+                ] {
+                    moves.push(Move {
+                        from,
+                        to,
+                        promotion: Some(promotion),
+                        kind,
+                    });
+                }
+            } else {
+                moves.push(Move {
+                    from,
+                    to,
+                    promotion: None,
+                    kind,
+                });
+            }
+        };
+
+        let single = push(from, forward);
+
+        if self.piece_at(single).is_none() {
+            add(single, MoveKind::Normal);
+
+            if from.rank() == start_rank && self.piece_at(push(from, 2 * forward)).is_none() {
+                add(push(from, 2 * forward), MoveKind::Normal);
+            }
+        }
+
+        let attacks = pawn_attacks(us, from);
+
+        for to in squares(attacks & self.color_pieces(us.flip())) {
+            add(to, MoveKind::Normal);
+        }
+
+        if let Some(ep) = self.ep_square.filter(|ep| attacks & ep.bitboard() != 0) {
+            add(ep, MoveKind::EnPassant);
+        }
+    }
+
+    fn castling_moves(&self, moves: &mut Vec<Move>) {
+        let us = self.side_to_move;
+        let king = self.king_square(us);
+        let rank = us.back_rank();
+
+        for side in [KING_SIDE, QUEEN_SIDE] {
+            let Some(rook) = self.castling_rooks[us.index()][side] else {
+                continue;
+            };
+            let (king_to, rook_to) = match side {
+                KING_SIDE => (Square::new(6, rank), Square::new(5, rank)),
+                _ => (Square::new(2, rank), Square::new(3, rank)),
+            };
+            let blockers = self.occupancy() & !king.bitboard() & !rook.bitboard();
+            let king_path = rank_span(king, king_to);
+
+            if (king_path | rank_span(rook, rook_to)) & blockers != 0 {
+                continue;
+            }
+
+            if squares(king_path).any(|sq| self.is_attacked(sq, us.flip())) {
+                continue;
+            }
+
+            moves.push(Move {
+                from: king,
+                to: rook,
+                promotion: None,
+                kind: MoveKind::Castling,
+            });
+        }
+    }
+
+    /// Returns whether the move captures a piece.
+    pub fn is_capture(&self, mv: Move) -> bool {
+        match mv.kind {
+            MoveKind::Normal => self.piece_at(mv.to).is_some(),
+            MoveKind::EnPassant => true,
+            MoveKind::Castling => false,
+        }
+    }
+
+    /// Plays the move, which is expected to be legal.
+    pub fn play(&mut self, mv: Move) {
+        let us = self.side_to_move;
+        let them = us.flip();
+
+        self.key ^= self.state_key();
+
+        let piece = self.remove_piece(mv.from).expect("no piece to move");
+
+        self.halfmove_clock += 1;
+        self.ep_square = None;
+
+        match mv.kind {
+            MoveKind::Castling => {
+                let rook = self.remove_piece(mv.to).expect("no rook to castle with");
+                let rook_file = if mv.to.file() > mv.from.file() { 5 } else { 3 };
+
+                self.put_piece(mv.king_to(), piece);
+                self.put_piece(Square::new(rook_file, mv.from.rank()), rook);
+            }
+            MoveKind::EnPassant => {
+                self.remove_piece(Square::new(mv.to.file(), mv.from.rank()));
+                self.put_piece(mv.to, piece);
+                self.halfmove_clock = 0;
+            }
+            MoveKind::Normal => {
+                if self.remove_piece(mv.to).is_some() {
+                    self.halfmove_clock = 0;
+                }
+
+                let kind = mv.promotion.unwrap_or(piece.kind);
+
+                self.put_piece(mv.to, Piece::new(us, kind));
+            }
+        }
+
+        if piece.kind == PieceType::Pawn {
+            self.halfmove_clock = 0;
+
+            // The en passant square is only set when a capture is possible,
+            // like most engines and GUIs do.
+            if mv.from.rank().abs_diff(mv.to.rank()) == 2 {
+                let ep = Square::new(mv.from.file(), (mv.from.rank() + mv.to.rank()) / 2);
+
+                if pawn_attacks(us, ep) & self.pieces(them, PieceType::Pawn) != 0 {
+                    self.ep_square = Some(ep);
+                }
+            }
+        }
+
+        if piece.kind == PieceType::King {
+            self.castling_rooks[us.index()] = [None; 2];
+        }
+
+        for rooks in &mut self.castling_rooks {
+            for rook in rooks.iter_mut() {
+                if *rook == Some(mv.from) || *rook == Some(mv.to) {
+                    *rook = None;
+                }
+            }
+        }
+
+        if us == Color::Black {
+            self.fullmove_number += 1;
+        }
+
+        self.side_to_move = them;
+        self.key ^= self.state_key();
+    }
+
+    /// Returns the Zobrist key of the position, identifying it regardless of
+    /// its move counters, e.g. for detecting repetitions. Like in the
+    /// Polyglot format, the en passant square is only part of the key when a
+    /// pawn of the side to move stands next to the pushed pawn.
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    /// Returns the part of the Zobrist key which does not depend on the
+    /// pieces: the side to move, the castling rights and the en passant file.
+    fn state_key(&self) -> u64 {
+        let mut key = 0;
+
+        for color in [Color::White, Color::Black] {
+            for side in [KING_SIDE, QUEEN_SIDE] {
+                if self.castling_rooks[color.index()][side].is_some() {
+                    key ^= ZOBRIST[768 + 2 * color.index() + side];
+                }
+            }
+        }
+
+        let us = self.side_to_move;
+
+        if let Some(ep) = self.ep_square {
+            if pawn_attacks(us.flip(), ep) & self.pieces(us, PieceType::Pawn) != 0 {
+                key ^= ZOBRIST[772 + ep.file() as usize];
+            }
+        }
+
+        if us == Color::Black {
+            key ^= ZOBRIST[780];
+        }
+
+        key
+    }
+
+    /// Parses a legal move written in UCI notation. Castling moves are
+    /// accepted both as king-captures-rook and as two-square king moves.
+    pub fn parse_uci(&self, uci: &str) -> Result<Move, MoveError> {
+        let moves = self.legal_moves();
+
+        moves
+            .iter()
+            .find(|mv| mv.to_uci(self.chess960) == uci)
+            .or_else(|| {
+                moves
+                    .iter()
+                    .find(|mv| mv.kind == MoveKind::Castling && mv.to_uci(!self.chess960) == uci)
+            })
+            .copied()
+            .ok_or_else(|| MoveError(format!("illegal move '{}' in '{}'", uci, self.to_fen())))
+    }
+
+    /// Parses a legal move written in Standard Algebraic Notation. Check and
+    /// annotation suffixes are ignored.
+    pub fn parse_san(&self, san: &str) -> Result<Move, MoveError> {
+        let error =
+            |message: &str| MoveError(format!("{} '{}' in '{}'", message, san, self.to_fen()));
+        let text = san.trim_end_matches(['+', '#', '!', '?']);
+        let moves = self.legal_moves();
+
+        if let Some(side) = match text {
+            "O-O" | "0-0" => Some(KING_SIDE),
+            "O-O-O" | "0-0-0" => Some(QUEEN_SIDE),
+            _ => None,
+        } {
+            let rook = self.castling_rooks[self.side_to_move.index()][side];
+
+            return moves
+                .into_iter()
+                .find(|mv| mv.kind == MoveKind::Castling && Some(mv.to) == rook)
+                .ok_or_else(|| error("illegal castling move"));
+        }
+
+        let (kind, rest) = match text.chars().next() {
+            Some(c @ ('N' | 'B' | 'R' | 'Q' | 'K')) => {
+                (PieceType::from_char(c).unwrap(), &text[1..])
+            }
+            _ => (PieceType::Pawn, text),
+        };
+        let (rest, promotion) = match rest.char_indices().last() {
+            Some((idx, c))
+                if kind == PieceType::Pawn && c.is_ascii_alphabetic() && c.is_ascii_uppercase() =>
+            {
+                let promotion =
+                    PieceType::from_char(c).ok_or_else(|| error("invalid promotion in"))?;
+
+                (rest[..idx].trim_end_matches('='), Some(promotion))
+            }
+            _ => (rest, None),
+        };
+
+        if rest.len() < 2 || !rest.is_ascii() {
+            return Err(error("invalid move"));
+        }
+
+        let to = rest[rest.len() - 2..]
+            .parse::<Square>()
+            .map_err(|_| error("invalid move"))?;
+        let hint = rest[..rest.len() - 2].trim_end_matches('x');
+        let (mut hint_file, mut hint_rank) = (None, None);
+
+        for c in hint.chars() {
+            match c {
+                'a'..='h' => hint_file = Some(c as u8 - b'a'),
+                '1'..='8' => hint_rank = Some(c as u8 - b'1'),
+                _ => return Err(error("invalid move")),
+            }
+        }
+
+        let mut candidates = moves.into_iter().filter(|mv| {
+            mv.kind != MoveKind::Castling
+                && mv.to == to
+                && mv.promotion == promotion
+                && self.piece_at(mv.from).map(|p| p.kind) == Some(kind)
+                && hint_file.is_none_or(|f| mv.from.file() == f)
+                && hint_rank.is_none_or(|r| mv.from.rank() == r)
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(mv), None) => Ok(mv),
+            (None, _) => Err(error("illegal move")),
+            (Some(_), Some(_)) => Err(error("ambiguous move")),
+        }
+    }
+
+    /// Parses a legal move written either in UCI notation or in SAN.
+    pub fn parse_move(&self, text: &str) -> Result<Move, MoveError> {
+        self.parse_uci(text)
+            .or_else(|_| self.parse_san(text))
+            .map_err(|_| MoveError(format!("illegal move '{}' in '{}'", text, self.to_fen())))
+    }
+
+    /// Writes a legal move in the given notation.
+    pub fn write_move(&self, mv: Move, notation: MoveNotation) -> String {
+        match notation {
+            MoveNotation::San => self.san(mv),
+            MoveNotation::Uci => mv.to_uci(self.chess960),
+        }
+    }
+
+    /// Converts a move from SAN to UCI notation.
+    pub fn san_to_uci(&self, san: &str) -> Result<String, MoveError> {
+        Ok(self.parse_san(san)?.to_uci(self.chess960))
+    }
+
+    /// Converts a move from UCI notation to SAN.
+    pub fn uci_to_san(&self, uci: &str) -> Result<String, MoveError> {
+        Ok(self.san(self.parse_uci(uci)?))
+    }
+
+    /// Converts a sequence of moves played from this position, written in SAN
+    /// or UCI notation (possibly mixed), to the given notation.
+    pub fn convert_moves(
+        &self,
+        moves: &[&str],
+        notation: MoveNotation,
+    ) -> Result<Vec<String>, MoveError> {
+        let mut pos = self.clone();
+        let mut converted = Vec::with_capacity(moves.len());
+
+        for text in moves {
+            let mv = pos.parse_move(text)?;
+
+            converted.push(pos.write_move(mv, notation));
+            pos.play(mv);
+        }
+
+        Ok(converted)
+    }
+
+    /// Checks that a sequence of moves written in UCI notation, e.g. a
+    /// principal variation returned by an engine, can be played from this
+    /// position.
+    pub fn check_uci_line(&self, moves: &[&str]) -> Result<(), MoveError> {
+        let mut pos = self.clone();
+
+        for (ply, uci) in moves.iter().enumerate() {
+            let mv = pos
+                .parse_uci(uci)
+                .map_err(|err| MoveError(format!("move {}: {}", ply + 1, err)))?;
+
+            pos.play(mv);
+        }
+
+        Ok(())
+    }
+
+    /// Writes a legal move in Standard Algebraic Notation.
+    pub fn san(&self, mv: Move) -> String {
+        let mut san = match mv.kind {
+            MoveKind::Castling if mv.to.file() > mv.from.file() => String::from("O-O"),
+            MoveKind::Castling => String::from("O-O-O"),
+            _ => {
+                let kind = self.piece_at(mv.from).unwrap().kind;
+                let capture = self.is_capture(mv);
+                let mut san = String::new();
+
+                if kind == PieceType::Pawn {
+                    if capture {
+                        san.push((b'a' + mv.from.file()) as char);
+                    }
+                } else {
+                    san.push(kind.to_char().to_ascii_uppercase());
+
+                    // Disambiguate with the file if possible, then with the
+                    // rank, then with both.
+                    let others: Vec<Square> = self
+                        .legal_moves()
+                        .into_iter()
+                        .filter(|other| {
+                            other.kind != MoveKind::Castling
+                                && other.to == mv.to
+                                && other.from != mv.from
+                                && self.piece_at(other.from).map(|p| p.kind) == Some(kind)
+                        })
+                        .map(|other| other.from)
+                        .collect();
+
+                    if !others.is_empty() {
+                        let same_file = others.iter().any(|sq| sq.file() == mv.from.file());
+                        let same_rank = others.iter().any(|sq| sq.rank() == mv.from.rank());
+
+                        if !same_file {
+                            san.push((b'a' + mv.from.file()) as char);
+                        } else if !same_rank {
+                            san.push((b'1' + mv.from.rank()) as char);
+                        } else {
+                            san.push_str(&mv.from.to_string());
+                        }
+                    }
+                }
+
+                if capture {
+                    san.push('x');
+                }
+
+                san.push_str(&mv.to.to_string());
+
+                if let Some(promotion) = mv.promotion {
+                    san.push('=');
+                    san.push(promotion.to_char().to_ascii_uppercase());
+                }
+
+                san
+            }
+        };
+
+        let mut next = self.clone();
+
+        next.play(mv);
+
+        if next.in_check() {
+            san.push(match next.legal_moves().is_empty() {
+                true => '#',
+                false => '+',
+            });
+        }
+
+        san
+    }
+
+    /// Writes the castling rights, using X-FEN notation in Chess960 mode: the
+    /// standard letters are kept when the rook is the outermost one on its
+    /// side, and the rook file is used otherwise.
+    fn castling_string(&self, shredder: bool) -> String {
+        let mut s = String::new();
+
+        for color in [Color::White, Color::Black] {
+            for side in [KING_SIDE, QUEEN_SIDE] {
+                let Some(rook) = self.castling_rooks[color.index()][side] else {
+                    continue;
+                };
+                let rank = color.back_rank();
+                let piece = Some(Piece::new(color, PieceType::Rook));
+                let outermost = match side {
+                    KING_SIDE => {
+                        (rook.file() + 1..8).all(|f| self.piece_at(Square::new(f, rank)) != piece)
+                    }
+                    _ => (0..rook.file()).all(|f| self.piece_at(Square::new(f, rank)) != piece),
+                };
+                let c = if shredder || !outermost {
+                    (b'a' + rook.file()) as char
+                } else if side == KING_SIDE {
+                    'k'
+                } else {
+                    'q'
+                };
+
+                s.push(match color {
+                    Color::White => c.to_ascii_uppercase(),
+                    Color::Black => c,
+                });
+            }
+        }
+
+        if s.is_empty() {
+            s.push('-');
+        }
+
+        s
+    }
+
+    fn fen_with_castling(&self, castling: String) -> String {
+        let fen = fen_placement(&self.squares);
+        let side = match self.side_to_move {
+            Color::White => 'w',
+            Color::Black => 'b',
+        };
+        let ep = self
+            .ep_square
+            .map_or_else(|| "-".to_string(), |sq| sq.to_string());
+
+        format!(
+            "{} {} {} {} {} {}",
+            fen, side, castling, ep, self.halfmove_clock, self.fullmove_number
+        )
+    }
+
+    /// Returns the FEN of the position, using X-FEN castling notation for
+    /// Chess960 positions.
+    pub fn to_fen(&self) -> String {
+        self.fen_with_castling(self.castling_string(false))
+    }
+
+    /// Returns the FEN of the position, using Shredder-FEN castling notation
+    /// (rook files) regardless of the variant.
+    pub fn to_shredder_fen(&self) -> String {
+        self.fen_with_castling(self.castling_string(true))
+    }
+
+    /// Returns the first four fields of the FEN, with the en passant square
+    /// only kept when an en passant capture is legal. Positions which only
+    /// differ by their move counters or an unusable en passant square have
+    /// the same canonical FEN, so it should be used to detect duplicates.
+    pub fn canonical_fen(&self) -> String {
+        let mut pos = self.clone();
+
+        if self.ep_square.is_some()
+            && !self
+                .legal_moves()
+                .iter()
+                .any(|mv| mv.kind == MoveKind::EnPassant)
+        {
+            pos.ep_square = None;
+        }
+
+        let fen = pos.to_fen();
+        let fields: Vec<&str> = fen.split(' ').take(4).collect();
+
+        fields.join(" ")
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_fen())
+    }
+}
+
+/// Parses a FEN and returns the canonical FEN of the position.
+pub fn canonical_fen(fen: &str, chess960: bool) -> Result<String, FenError> {
+    Ok(Position::from_fen(fen, chess960)?.canonical_fen())
+}
+
+/// Writes the piece placement field of a FEN, from the pieces indexed by
+/// square.
+pub fn fen_placement(board: &[Option<Piece>; 64]) -> String {
+    let mut fen = String::new();
+
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+
+        for file in 0..8 {
+            match board[Square::new(file, rank).index()] {
+                Some(piece) => {
+                    if empty > 0 {
+                        fen.push((b'0' + empty) as char);
+                        empty = 0;
+                    }
+
+                    fen.push(piece.to_char());
+                }
+                None => empty += 1,
+            }
+        }
+
+        if empty > 0 {
+            fen.push((b'0' + empty) as char);
+        }
+
+        if rank > 0 {
+            fen.push('/');
+        }
+    }
+
+    fen
+}
+
+/// Iterates over the squares of a bitboard.
+pub fn squares(mut bb: u64) -> impl Iterator<Item = Square> {
+    std::iter::from_fn(move || {
+        if bb == 0 {
+            return None;
+        }
+
+        let square = Square::from_index(bb.trailing_zeros() as usize);
+
+        bb &= bb - 1;
+        Some(square)
+    })
+}
+
+/// Returns the squares between two squares of the same rank, both included.
+fn rank_span(a: Square, b: Square) -> u64 {
+    let (lo, hi) = (a.file().min(b.file()), a.file().max(b.file()));
+
+    (lo..=hi).fold(0, |bb, file| bb | Square::new(file, a.rank()).bitboard())
+}
+
+/// Returns the squares reachable from the square with the given (file, rank)
+/// steps.
+fn step_attacks(square: Square, steps: &[(i8, i8)]) -> u64 {
+    let (file, rank) = (square.file() as i8, square.rank() as i8);
+
+    steps
+        .iter()
+        .map(|&(df, dr)| (file + df, rank + dr))
+        .filter(|&(f, r)| (0..8).contains(&f) && (0..8).contains(&r))
+        .fold(0, |bb, (f, r)| {
+            bb | Square::new(f as u8, r as u8).bitboard()
+        })
+}
+
+/// Returns the squares reachable from the square by sliding in the given
+/// directions, stopping at the first occupied square of each ray.
+fn slider_attacks(square: Square, occupancy: u64, directions: &[(i8, i8)]) -> u64 {
+    let mut attacks = 0;
+
+    for &(df, dr) in directions {
+        let (mut f, mut r) = (square.file() as i8 + df, square.rank() as i8 + dr);
+
+        while (0..8).contains(&f) && (0..8).contains(&r) {
+            let bb = Square::new(f as u8, r as u8).bitboard();
+
+            attacks |= bb;
+
+            if occupancy & bb != 0 {
+                break;
+            }
+
+            f += df;
+            r += dr;
+        }
+    }
+
+    attacks
+}
+
+pub fn pawn_attacks(color: Color, square: Square) -> u64 {
+    match color {
+        Color::White => step_attacks(square, &[(-1, 1), (1, 1)]),
+        Color::Black => step_attacks(square, &[(-1, -1), (1, -1)]),
+    }
+}
+
+pub fn knight_attacks(square: Square) -> u64 {
+    step_attacks(
+        square,
+        &[
+            (1, 2),
+            (2, 1),
+            (2, -1),
+            (1, -2),
+            (-1, -2),
+            (-2, -1),
+            (-2, 1),
+            (-1, 2),
+        ],
+    )
+}
+
+pub fn king_attacks(square: Square) -> u64 {
+    step_attacks(
+        square,
+        &[
+            (1, 1),
+            (1, 0),
+            (1, -1),
+            (0, -1),
+            (-1, -1),
+            (-1, 0),
+            (-1, 1),
+            (0, 1),
+        ],
+    )
+}
+
+pub fn bishop_attacks(square: Square, occupancy: u64) -> u64 {
+    slider_attacks(square, occupancy, &[(1, 1), (1, -1), (-1, -1), (-1, 1)])
+}
+
+pub fn rook_attacks(square: Square, occupancy: u64) -> u64 {
+    slider_attacks(square, occupancy, &[(1, 0), (0, -1), (-1, 0), (0, 1)])
+}
@@ -0,0 +1,202 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use clap::Args;
+
+/// Builds an engine from a commit of its git repository, and prints the path
+/// of the binary. The repository is cloned once in the cache directory and
+/// updated on later builds, and binaries are kept there by commit, so that a
+/// commit is only built once for given build settings.
+///
+/// The engine is built by running make in the directory holding the makefile
+/// of the repository (its root or its 'src' directory), with EXE set to the
+/// binary path.
+#[derive(Args)]
+pub struct BuildEngineArgs {
+    /// The commit to build, as a hash, a branch or a tag name. Branches are
+    /// taken from the remote repository when it has them.
+    #[arg(short, long)]
+    commit: String,
+
+    /// The git repository of the engine.
+    #[arg(
+        short,
+        long,
+        default_value = "https://github.com/mhouppin/stash-bot.git"
+    )]
+    repo: String,
+
+    /// The ARCH flag passed to make, if any.
+    #[arg(short, long)]
+    arch: Option<String>,
+
+    /// An additional argument passed to make (e.g. 'CC=clang'). You can use
+    /// this flag as many times as you need.
+    #[arg(short, long)]
+    make_arg: Vec<String>,
+
+    /// The directory holding the repository clone and the built binaries.
+    #[arg(long, default_value = "engines")]
+    cache_dir: String,
+}
+
+/// Runs a command, turning a failure exit status into an error holding its
+/// standard error.
+pub(crate) fn run_command(command: &mut Command) -> io::Result<Vec<u8>> {
+    let output = command.output()?;
+
+    check_output(command, output)
+}
+
+/// Runs a command like [`run_command`], writing the given data to its
+/// standard input, e.g. so that secrets do not show in its arguments.
+pub(crate) fn run_command_with_input(command: &mut Command, input: &[u8]) -> io::Result<Vec<u8>> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Dropping the pipe closes it, so that the command sees the end of its
+    // input.
+    child.stdin.take().unwrap().write_all(input)?;
+
+    let output = child.wait_with_output()?;
+
+    check_output(command, output)
+}
+
+/// Returns the standard output of a finished command, or an error holding
+/// its standard error if it failed.
+fn check_output(command: &Command, output: Output) -> io::Result<Vec<u8>> {
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{:?} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Runs make in the directory holding the makefile of the sources, building
+/// the engine binary at the given (absolute) path.
+pub(crate) fn make_engine(sources: &Path, binary: &Path, make_args: &[String]) -> io::Result<()> {
+    let makefile_dir = [sources.to_path_buf(), sources.join("src")]
+        .into_iter()
+        .find(|dir| dir.join("makefile").exists() || dir.join("Makefile").exists())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no makefile found in {}", sources.display()),
+            )
+        })?;
+
+    run_command(
+        Command::new("make")
+            .arg("-C")
+            .arg(&makefile_dir)
+            .arg(format!("EXE={}", binary.display()))
+            .args(make_args),
+    )?;
+
+    match binary.exists() {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("the build did not produce {}", binary.display()),
+        )),
+    }
+}
+
+/// Runs a git command in the repository, returning its trimmed output.
+fn git(repo: &Path, args: &[&str]) -> io::Result<String> {
+    let output = run_command(Command::new("git").arg("-C").arg(repo).args(args))?;
+
+    Ok(String::from_utf8_lossy(&output).trim().to_string())
+}
+
+/// Builds the requested commit unless it already was, and returns the binary
+/// path.
+fn build(args: &BuildEngineArgs) -> io::Result<PathBuf> {
+    fs::create_dir_all(&args.cache_dir)?;
+
+    let cache_dir = fs::canonicalize(&args.cache_dir)?;
+    let repo = cache_dir.join("repo");
+
+    if repo.join(".git").exists() {
+        git(&repo, &["remote", "set-url", "origin", &args.repo])?;
+        git(&repo, &["fetch", "--quiet", "--tags", "--force", "origin"])?;
+    } else {
+        run_command(
+            Command::new("git")
+                .args(["clone", "--quiet", &args.repo])
+                .arg(&repo),
+        )?;
+    }
+
+    // Prefer the remote branch, as the local one is not updated by fetches.
+    let sha = [format!("origin/{}", args.commit), args.commit.clone()]
+        .iter()
+        .find_map(|rev| {
+            git(
+                &repo,
+                &[
+                    "rev-parse",
+                    "--verify",
+                    "--quiet",
+                    &format!("{}^{{commit}}", rev),
+                ],
+            )
+            .ok()
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("unknown commit '{}' in {}", args.commit, args.repo),
+            )
+        })?;
+    let mut name = sha[..12].to_string();
+
+    if let Some(arch) = &args.arch {
+        name = format!("{}-{}", name, arch);
+    }
+
+    // Builds with different make arguments are kept apart, under a hash of
+    // the arguments.
+    if !args.make_arg.is_empty() {
+        let settings = args.make_arg.join(" ");
+        let tag = settings.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+        name = format!("{}-{:08x}", name, tag as u32);
+    }
+
+    let binary = cache_dir.join(format!("stash-{}", name));
+
+    if binary.exists() {
+        return Ok(binary);
+    }
+
+    git(&repo, &["checkout", "--quiet", "--force", "--detach", &sha])?;
+    git(&repo, &["clean", "--quiet", "-d", "--force", "-x"])?;
+
+    let mut make_args = args.make_arg.clone();
+
+    if let Some(arch) = &args.arch {
+        make_args.push(format!("ARCH={}", arch));
+    }
+
+    make_engine(&repo, &binary, &make_args)?;
+    Ok(binary)
+}
+
+pub fn run(args: &BuildEngineArgs) -> io::Result<()> {
+    println!("{}", build(args)?.display());
+    Ok(())
+}
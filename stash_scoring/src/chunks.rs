@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// The file a producer creates in its chunk directory once it has written
+/// its last chunk, so that the next stage of the pipeline knows when to
+/// stop.
+pub const END_MARKER: &str = "END";
+
+/// The interval between two scans of a watched directory without any new
+/// chunk.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Watches a directory filled with chunk files by another stage of a
+/// pipeline, e.g. PGN chunks written by a match and read by the extractor.
+/// Chunks are the files with a given extension: producers write them under a
+/// temporary name first, so that they are only seen once complete.
+pub struct ChunkWatcher {
+    dir: PathBuf,
+    extension: String,
+    seen: HashSet<PathBuf>,
+}
+
+impl ChunkWatcher {
+    pub fn new(dir: &Path, extension: &str) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            extension: extension.to_string(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Waits for a chunk not returned yet, taking the chunks in the order of
+    /// their names. Returns None once the producer has marked the directory
+    /// as finished and all chunks have been returned, or when `cancelled`
+    /// returns true.
+    pub fn next_chunk(&mut self, cancelled: impl Fn() -> bool) -> io::Result<Option<PathBuf>> {
+        while !cancelled() {
+            // The marker is checked first, so that no chunk written before it
+            // can be missed.
+            let finished = self.dir.join(END_MARKER).exists();
+            let mut chunks: Vec<PathBuf> = fs::read_dir(&self.dir)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<_>>()?;
+
+            chunks.retain(|path| {
+                path.extension() == Some(OsStr::new(&self.extension)) && !self.seen.contains(path)
+            });
+
+            if let Some(chunk) = chunks.into_iter().min() {
+                self.seen.insert(chunk.clone());
+                return Ok(Some(chunk));
+            }
+
+            if finished {
+                break;
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        Ok(None)
+    }
+}
+
+/// Marks a chunk directory as finished, once the last chunk is written.
+pub fn finish_chunks(dir: &Path) -> io::Result<()> {
+    File::create(dir.join(END_MARKER)).map(drop)
+}
+
+/// Creates the chunk directory of a producer starting from scratch, refusing
+/// a directory which is not empty: the chunks of an earlier run would be
+/// overwritten by the new ones, and its end marker would stop the consumers
+/// early.
+pub fn create_chunk_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    if fs::read_dir(dir)?.next().is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("the chunk directory '{}' is not empty", dir.display()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Creates the chunk directory of a stage which resumes an earlier run, e.g.
+/// one skipping the chunks it already wrote. The end marker of the earlier
+/// run is removed, so that consumers wait for the new one.
+pub fn reopen_chunk_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    match fs::remove_file(dir.join(END_MARKER)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,299 @@
+use std::io;
+
+use clap::Args;
+
+use stash_scoring::board::{PieceType, Position};
+use stash_scoring::engine::Score;
+use stash_scoring::input::{InputColumn, InputSchema};
+use stash_scoring::reader::InputReader;
+
+/// Compares the eval distributions of two scored datasets, e.g. before and
+/// after changing the scoring engine or its search limit. For all positions
+/// and for each game phase, the means and standard deviations of the evals
+/// are reported along with the two-sample Kolmogorov-Smirnov statistic,
+/// followed by histograms of both distributions.
+///
+/// Evals are compared from the side to move's point of view, as written by
+/// the scoring tool. The game phase is derived from the remaining material:
+/// knights and bishops count for 1, rooks for 2 and queens for 4, out of 24
+/// in the starting position. Positions with at least 20 are counted in the
+/// opening, those with at most 8 in the endgame.
+#[derive(Args)]
+pub struct CompareDistArgs {
+    /// The first (reference) dataset.
+    #[arg(short = 'a', long)]
+    first_file: String,
+
+    /// The second dataset, compared with the first one.
+    #[arg(short = 'b', long)]
+    second_file: String,
+
+    /// The layout of the dataset lines, using the same syntax as the
+    /// --input-columns flag of the scoring tool. An 'eval' column is required.
+    #[arg(long, default_value = "fen,wdl,eval")]
+    input_columns: InputSchema,
+
+    /// The width of the histogram bins, in centipawns.
+    #[arg(long, default_value_t = 100)]
+    bin_width: i32,
+
+    /// Evals (including mates) are clamped to +/- this value for computing
+    /// means, standard deviations and histograms, so that mate scores do not
+    /// dominate them. The KS statistic uses unclamped evals.
+    #[arg(long, default_value_t = 1000)]
+    clamp: i32,
+
+    /// Accept Chess960 castling rights in FENs.
+    #[arg(long)]
+    chess960: bool,
+}
+
+/// The largest folded score kept apart in the distributions. Mate scores are
+/// folded just below it.
+const MAX_FOLDED: i32 = 32000;
+
+const PHASE_NAMES: [&str; 4] = ["all", "opening", "middlegame", "endgame"];
+
+/// Returns the game phase of the position: 1 for the opening, 2 for the
+/// middlegame and 3 for the endgame.
+fn game_phase(pos: &Position) -> usize {
+    let material: u32 = [
+        (PieceType::Knight, 1),
+        (PieceType::Bishop, 1),
+        (PieceType::Rook, 2),
+        (PieceType::Queen, 4),
+    ]
+    .into_iter()
+    .map(|(kind, weight)| weight * pos.kind_pieces(kind).count_ones())
+    .sum();
+
+    match material {
+        20.. => 1,
+        9..=19 => 2,
+        _ => 3,
+    }
+}
+
+/// The distribution of the evals of a dataset, counted for every folded score
+/// so that the KS statistic is exact without keeping all evals.
+struct Distribution {
+    counts: Vec<u64>,
+    positions: u64,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl Distribution {
+    fn new() -> Self {
+        Self {
+            counts: vec![0; 2 * MAX_FOLDED as usize + 1],
+            positions: 0,
+            sum: 0.0,
+            sum_squares: 0.0,
+        }
+    }
+
+    fn add(&mut self, score: Score, clamp: i32) {
+        let folded = score.folded().clamp(-MAX_FOLDED, MAX_FOLDED);
+        let clamped = folded.clamp(-clamp, clamp) as f64;
+
+        self.counts[(folded + MAX_FOLDED) as usize] += 1;
+        self.positions += 1;
+        self.sum += clamped;
+        self.sum_squares += clamped * clamped;
+    }
+
+    fn mean(&self) -> f64 {
+        self.sum / self.positions as f64
+    }
+
+    fn stddev(&self) -> f64 {
+        let mean = self.mean();
+
+        (self.sum_squares / self.positions as f64 - mean * mean)
+            .max(0.0)
+            .sqrt()
+    }
+
+    /// Counts the evals falling in [low, high), after clamping them to
+    /// [-clamp, clamp]: the bins at both ends also hold the clamped evals.
+    fn count_between(&self, low: i32, high: i32, clamp: i32) -> u64 {
+        let index = |value: i32| (value.clamp(-MAX_FOLDED, MAX_FOLDED) + MAX_FOLDED) as usize;
+        let start = if low <= -clamp { 0 } else { index(low) };
+        let end = if high >= clamp {
+            self.counts.len()
+        } else {
+            index(high)
+        };
+
+        self.counts[start..end.max(start)].iter().sum()
+    }
+}
+
+/// Returns the two-sample Kolmogorov-Smirnov statistic of the distributions,
+/// with its asymptotic p-value.
+fn ks_test(a: &Distribution, b: &Distribution) -> (f64, f64) {
+    let (mut cdf_a, mut cdf_b) = (0.0, 0.0);
+    let mut d: f64 = 0.0;
+
+    for (count_a, count_b) in a.counts.iter().zip(&b.counts) {
+        cdf_a += *count_a as f64 / a.positions as f64;
+        cdf_b += *count_b as f64 / b.positions as f64;
+        d = d.max((cdf_a - cdf_b).abs());
+    }
+
+    // The Kolmogorov distribution, with the correction of Stephens (1970) for
+    // small samples.
+    let n = (a.positions * b.positions) as f64 / (a.positions + b.positions) as f64;
+    let lambda = (n.sqrt() + 0.12 + 0.11 / n.sqrt()) * d;
+    let p_value = match lambda < 0.2 {
+        true => 1.0,
+        false => (1..=100)
+            .map(|k| {
+                let sign = if k % 2 == 1 { 1.0 } else { -1.0 };
+
+                2.0 * sign * (-2.0 * (k * k) as f64 * lambda * lambda).exp()
+            })
+            .sum::<f64>()
+            .clamp(0.0, 1.0),
+    };
+
+    (d, p_value)
+}
+
+/// Reads the evals of a dataset, by game phase. Lines which cannot be parsed
+/// are counted and skipped.
+fn read_distributions(args: &CompareDistArgs, path: &str) -> io::Result<[Distribution; 4]> {
+    let mut reader = InputReader::open(path, false)?;
+    let mut distributions = [(); 4].map(|_| Distribution::new());
+    let mut invalid = 0;
+
+    while let Some(line) = reader.next_line()? {
+        let parsed = std::str::from_utf8(line)
+            .map_err(|err| err.to_string())
+            .and_then(|line| {
+                args.input_columns
+                    .split(line)
+                    .map_err(|err| err.to_string())
+            })
+            .and_then(|record| {
+                let pos = Position::from_fen(&record.fen, args.chess960)
+                    .map_err(|err| err.to_string())?;
+                let eval = record.eval.unwrap();
+                let score = eval
+                    .parse::<Score>()
+                    .map_err(|_| format!("unparsable eval '{}'", eval))?;
+
+                Ok((game_phase(&pos), score))
+            });
+
+        match parsed {
+            Ok((phase, score)) => {
+                distributions[0].add(score, args.clamp);
+                distributions[phase].add(score, args.clamp);
+            }
+            Err(_) => invalid += 1,
+        }
+    }
+
+    if invalid > 0 {
+        eprintln!("Skipped {} invalid lines in {}", invalid, path);
+    }
+
+    Ok(distributions)
+}
+
+pub fn run(args: &CompareDistArgs) -> io::Result<()> {
+    if !args.input_columns.contains(InputColumn::Eval) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "an 'eval' column is required",
+        ));
+    }
+
+    if args.bin_width <= 0 || args.clamp <= 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--bin-width and --clamp must be positive",
+        ));
+    }
+
+    let first = read_distributions(args, &args.first_file)?;
+    let second = read_distributions(args, &args.second_file)?;
+
+    println!("A: {}", args.first_file);
+    println!("B: {}", args.second_file);
+    println!();
+    println!(
+        "{:<12}{:>10}{:>10}{:>9}{:>9}{:>9}{:>9}{:>9}{:>9}{:>8}{:>10}",
+        "Phase",
+        "Pos. A",
+        "Pos. B",
+        "Mean A",
+        "Mean B",
+        "Delta",
+        "SD A",
+        "SD B",
+        "Delta",
+        "KS",
+        "p-value"
+    );
+
+    for (phase, (a, b)) in first.iter().zip(&second).enumerate() {
+        if a.positions == 0 || b.positions == 0 {
+            println!(
+                "{:<12}{:>10}{:>10}",
+                PHASE_NAMES[phase], a.positions, b.positions
+            );
+            continue;
+        }
+
+        let (d, p_value) = ks_test(a, b);
+
+        println!(
+            "{:<12}{:>10}{:>10}{:>9.1}{:>9.1}{:>+9.1}{:>9.1}{:>9.1}{:>+9.1}{:>8.4}{:>10.2e}",
+            PHASE_NAMES[phase],
+            a.positions,
+            b.positions,
+            a.mean(),
+            b.mean(),
+            b.mean() - a.mean(),
+            a.stddev(),
+            b.stddev(),
+            b.stddev() - a.stddev(),
+            d,
+            p_value
+        );
+    }
+
+    for (phase, (a, b)) in first.iter().zip(&second).enumerate() {
+        if a.positions == 0 || b.positions == 0 {
+            continue;
+        }
+
+        println!();
+        println!("Histogram ({}):", PHASE_NAMES[phase]);
+        println!("{:>16}{:>9}{:>9}{:>9}", "Eval", "A", "B", "Delta");
+
+        let mut low = -args.clamp;
+
+        while low < args.clamp {
+            let high = (low + args.bin_width).min(args.clamp);
+            let share = |dist: &Distribution| {
+                100.0 * dist.count_between(low, high, args.clamp) as f64 / dist.positions as f64
+            };
+            let (share_a, share_b) = (share(a), share(b));
+
+            println!(
+                "{:>16}{:>8.2}%{:>8.2}%{:>+8.2}%",
+                format!("[{}, {})", low, high),
+                share_a,
+                share_b,
+                share_b - share_a
+            );
+            low = high;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+use clap::Args;
+use serde_json::json;
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::{EngineConfig, EngineProfile, Score, ScoreFormat, SearchLimit};
+use stash_scoring::input::InputSchema;
+use stash_scoring::pool::EnginePool;
+use stash_scoring::registry::EngineRegistry;
+
+/// Searches each position of a file several times, optionally with several
+/// engine configurations (e.g. other hash sizes or thread counts), and
+/// reports the positions whose best move or score is not always the same, as
+/// a nondeterminism detector for engine development. A JSON report is
+/// written at the end.
+#[derive(Args)]
+pub struct ConsistencyArgs {
+    /// The path of the engine to check, or the name of an engine of the
+    /// registry (see --engine-registry).
+    #[arg(short, long)]
+    engine_path: String,
+
+    /// The family of the engine. Defaults to 'generic', or to the profile of
+    /// the engine in the registry.
+    #[arg(short, long, value_enum)]
+    profile: Option<EngineProfile>,
+
+    /// An UCI option which should be passed to the engine at startup, as
+    /// 'Name=Value'. You can use this flag as many times as you need.
+    #[arg(short, long)]
+    config: Vec<String>,
+
+    /// Another configuration to search the positions with, as
+    /// comma-separated UCI options overriding the --config ones, e.g.
+    /// 'Hash=64,Threads=4'. You can use this flag as many times as you need.
+    #[arg(long)]
+    variant: Vec<String>,
+
+    /// The file holding the positions to search.
+    #[arg(short, long)]
+    input_file: String,
+
+    /// The layout of the input lines, using the same syntax as the
+    /// --input-columns flag of the scoring tool.
+    #[arg(long, default_value = "fen,extra*")]
+    input_columns: InputSchema,
+
+    /// Read positions as Chess960 ones.
+    #[arg(long)]
+    chess960: bool,
+
+    /// The number of times each position is searched with each
+    /// configuration.
+    #[arg(short, long, default_value_t = 3)]
+    repeats: usize,
+
+    /// The largest score difference between the searches of a position, in
+    /// centipawns, which is not reported.
+    #[arg(long, default_value_t = 0)]
+    tolerance: u32,
+
+    /// The number of engine instances searching the positions.
+    #[arg(short, long, default_value_t = 1)]
+    threads: usize,
+
+    #[command(flatten)]
+    limit: SearchLimit,
+
+    /// The file to write the JSON report to. Defaults to the standard output.
+    #[arg(long)]
+    report_file: Option<String>,
+
+    /// The maximal number of inconsistent positions listed in the report.
+    #[arg(long, default_value_t = 100)]
+    max_positions: usize,
+}
+
+/// A search of a position, with the configuration it was done with.
+struct Outcome {
+    configuration: usize,
+    best_move: String,
+    score: Score,
+}
+
+fn read_positions(args: &ConsistencyArgs) -> io::Result<Vec<Position>> {
+    let mut positions = Vec::new();
+
+    for (idx, line) in BufReader::new(File::open(&args.input_file)?)
+        .lines()
+        .enumerate()
+    {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let pos = args
+            .input_columns
+            .split(&line)
+            .map_err(|err| err.to_string())
+            .and_then(|record| {
+                Position::from_fen(&record.fen, args.chess960).map_err(|err| err.to_string())
+            });
+
+        match pos {
+            Ok(pos) => positions.push(pos),
+            Err(err) => eprintln!("Skipping line {}: {}", idx + 1, err),
+        }
+    }
+
+    Ok(positions)
+}
+
+/// Runs the check, returning whether all the positions got consistent
+/// results.
+pub fn run(args: &ConsistencyArgs, registry: &EngineRegistry) -> io::Result<bool> {
+    args.limit
+        .check_template(false)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    let mut engine = match registry.get(&args.engine_path) {
+        Some(engine) if !args.engine_path.contains('/') => engine.clone(),
+        _ => EngineConfig {
+            name: args.engine_path.clone(),
+            command: args.engine_path.clone(),
+            ..Default::default()
+        },
+    };
+
+    engine.profile = args.profile.unwrap_or(engine.profile);
+    engine.options.extend_from_slice(&args.config);
+
+    if args.chess960 {
+        engine.options.insert(0, String::from("UCI_Chess960=true"));
+    }
+
+    let positions = read_positions(args)?;
+    let mut configurations = vec![String::from("base")];
+
+    configurations.extend(args.variant.iter().cloned());
+
+    let mut outcomes: Vec<Vec<Outcome>> = positions.iter().map(|_| Vec::new()).collect();
+
+    for (configuration, variant) in configurations.iter().enumerate() {
+        let mut config = engine.clone();
+
+        if configuration > 0 {
+            config.options.extend(
+                variant
+                    .split(',')
+                    .filter(|option| !option.trim().is_empty())
+                    .map(|option| option.trim().to_string()),
+            );
+        }
+
+        let pool = EnginePool::new(&config, args.threads.max(1))?;
+
+        for repeat in 0..args.repeats {
+            eprintln!(
+                "Searching {} positions with the '{}' configuration ({}/{})",
+                positions.len(),
+                variant,
+                repeat + 1,
+                args.repeats
+            );
+
+            let results = pool.search_batch(&positions, &args.limit);
+
+            for ((result, outcomes), pos) in results.into_iter().zip(&mut outcomes).zip(&positions)
+            {
+                let result = result.map_err(|err| {
+                    io::Error::new(
+                        err.kind(),
+                        format!("cannot search '{}': {}", pos.to_fen(), err),
+                    )
+                })?;
+
+                outcomes.push(Outcome {
+                    configuration,
+                    best_move: result.best_move,
+                    score: result.score,
+                });
+            }
+        }
+    }
+
+    let mut inconsistent = 0;
+    let mut reports = Vec::new();
+
+    for (pos, outcomes) in positions.iter().zip(&outcomes) {
+        let mut best_moves: BTreeMap<&str, usize> = BTreeMap::new();
+
+        for outcome in outcomes {
+            *best_moves.entry(&outcome.best_move).or_default() += 1;
+        }
+
+        let scores = outcomes.iter().map(|outcome| outcome.score.folded());
+        let spread = match (scores.clone().min(), scores.max()) {
+            (Some(min), Some(max)) => max.abs_diff(min),
+            _ => 0,
+        };
+
+        if best_moves.len() <= 1 && spread <= args.tolerance {
+            continue;
+        }
+
+        inconsistent += 1;
+
+        if reports.len() < args.max_positions {
+            let searches: Vec<_> = outcomes
+                .iter()
+                .map(|outcome| {
+                    json!({
+                        "configuration": configurations[outcome.configuration],
+                        "bestmove": outcome.best_move,
+                        "score": outcome.score.display(ScoreFormat::Pound).to_string(),
+                    })
+                })
+                .collect();
+
+            reports.push(json!({
+                "fen": pos.to_fen(),
+                "bestmoves": best_moves,
+                "score_spread": spread,
+                "searches": searches,
+            }));
+        }
+    }
+
+    let report = json!({
+        "file": args.input_file,
+        "consistent": inconsistent == 0,
+        "positions": positions.len(),
+        "configurations": configurations,
+        "repeats": args.repeats,
+        "tolerance": args.tolerance,
+        "inconsistent_positions": inconsistent,
+        "inconsistencies": reports,
+    });
+    let report = serde_json::to_string_pretty(&report)? + "\n";
+
+    match &args.report_file {
+        Some(path) => File::create(path)?.write_all(report.as_bytes())?,
+        None => io::stdout().write_all(report.as_bytes())?,
+    }
+
+    Ok(inconsistent == 0)
+}
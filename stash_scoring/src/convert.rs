@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::thread;
+
+use clap::Args;
+
+use stash_scoring::binpack::{BinpackReader, PlainReader, TrainingDataReader};
+use stash_scoring::engine::ScoreFormat;
+use stash_scoring::formats::{DatasetFormat, ScoredPosition};
+
+/// The number of records converted at once, spread over the threads.
+const BATCH_SIZE: usize = 64 * 1024;
+
+/// Converts scored datasets between the supported formats, without any
+/// engine: the tool's own text format, EPD, JSON lines, CSV, and the binary
+/// records of the bullet trainer. Stockfish .binpack and .plain datasets can
+/// be read too. Records which cannot be read are reported and skipped. The
+/// output keeps the order of the input.
+#[derive(Args)]
+pub struct ConvertArgs {
+    /// The dataset to convert.
+    #[arg(short, long)]
+    input_file: String,
+
+    /// The format of the input dataset.
+    #[arg(long, value_enum)]
+    from: DatasetFormat,
+
+    /// The file to write the converted dataset to. Defaults to the standard
+    /// output.
+    #[arg(short, long)]
+    output_file: Option<String>,
+
+    /// The format of the converted dataset.
+    #[arg(long, value_enum)]
+    to: DatasetFormat,
+
+    /// How mate scores are written in the text and CSV formats.
+    #[arg(short, long, value_enum, default_value_t = ScoreFormat::Pound)]
+    score_format: ScoreFormat,
+
+    /// Read positions as Chess960 ones.
+    #[arg(long)]
+    chess960: bool,
+
+    /// The number of threads converting the records.
+    #[arg(short, long, default_value_t = 1)]
+    threads: usize,
+}
+
+/// A record of the input dataset, decoded while reading for Stockfish
+/// datasets.
+enum Record {
+    Raw(Vec<u8>),
+    Decoded(Box<Result<ScoredPosition, String>>),
+}
+
+/// Reads the records of a dataset one by one, as lines for text formats.
+enum RecordReader {
+    Lines(BufReader<File>),
+    Binary(BufReader<File>, usize),
+    TrainingData(TrainingDataReader),
+}
+
+impl RecordReader {
+    /// Reads the next record, without its line end for text formats.
+    fn next_record(&mut self) -> io::Result<Option<Record>> {
+        match self {
+            Self::Lines(reader) => {
+                let mut line = Vec::new();
+
+                if reader.read_until(b'\n', &mut line)? == 0 {
+                    return Ok(None);
+                }
+
+                while line.last().is_some_and(|&b| b == b'\n' || b == b'\r') {
+                    line.pop();
+                }
+
+                Ok(Some(Record::Raw(line)))
+            }
+            Self::Binary(reader, size) => {
+                let mut record = Vec::with_capacity(*size);
+
+                reader.take(*size as u64).read_to_end(&mut record)?;
+
+                // A truncated last record is reported when converted.
+                Ok((!record.is_empty()).then_some(Record::Raw(record)))
+            }
+            Self::TrainingData(reader) => Ok(reader
+                .next_entry()?
+                .map(|entry| Record::Decoded(Box::new(entry.and_then(|entry| entry.to_scored()))))),
+        }
+    }
+}
+
+/// Converts a single record, returning the bytes to write for it, if any.
+fn convert_record(record: &Record, args: &ConvertArgs) -> Result<Option<Vec<u8>>, String> {
+    let record = match record {
+        Record::Raw(record) => record,
+        Record::Decoded(entry) => {
+            let scored = entry.as_ref().as_ref().map_err(String::clone)?;
+
+            return Ok(Some(write_record(scored, args)));
+        }
+    };
+    let scored = match args.from.record_size() {
+        Some(_) => ScoredPosition::from_bullet(record)?,
+        None => {
+            let line = std::str::from_utf8(record).map_err(|err| err.to_string())?;
+
+            // CSV headers and blank lines are not records.
+            if line.trim().is_empty() || Some(line.trim()) == args.from.header() {
+                return Ok(None);
+            }
+
+            ScoredPosition::parse_line(line, args.from, args.chess960)?
+        }
+    };
+
+    Ok(Some(write_record(&scored, args)))
+}
+
+/// Returns the bytes to write for a position in the output format.
+fn write_record(scored: &ScoredPosition, args: &ConvertArgs) -> Vec<u8> {
+    match args.to.is_text() {
+        true => format!("{}\n", scored.to_line(args.to, args.score_format)).into_bytes(),
+        false => scored.to_bullet().to_vec(),
+    }
+}
+
+pub fn run(args: &ConvertArgs) -> io::Result<()> {
+    if !args.to.is_writable() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Stockfish datasets can only be read",
+        ));
+    }
+
+    let file = BufReader::new(File::open(&args.input_file)?);
+    let mut reader = match (args.from, args.from.record_size()) {
+        (DatasetFormat::Binpack, _) => RecordReader::TrainingData(TrainingDataReader::Binpack(
+            Box::new(BinpackReader::new(file)),
+        )),
+        (DatasetFormat::Plain, _) => {
+            RecordReader::TrainingData(TrainingDataReader::Plain(PlainReader::new(file)))
+        }
+        (_, Some(size)) => RecordReader::Binary(file, size),
+        (_, None) => RecordReader::Lines(file),
+    };
+    let mut output: Box<dyn Write> = match &args.output_file {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let threads = args.threads.max(1);
+    let mut records_read = 0;
+    let mut converted = 0;
+    let mut invalid = 0;
+
+    if let Some(header) = args.to.header() {
+        writeln!(output, "{}", header)?;
+    }
+
+    loop {
+        let mut batch = Vec::new();
+
+        while batch.len() < BATCH_SIZE {
+            match reader.next_record()? {
+                Some(record) => batch.push(record),
+                None => break,
+            }
+        }
+
+        if batch.is_empty() {
+            break;
+        }
+
+        let chunk_size = batch.len().div_ceil(threads);
+        let results: Vec<Vec<Result<Option<Vec<u8>>, String>>> = thread::scope(|scope| {
+            let handles: Vec<_> = batch
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|record| convert_record(record, args))
+                            .collect()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        for (index, result) in results.into_iter().flatten().enumerate() {
+            match result {
+                Ok(Some(bytes)) => {
+                    output.write_all(&bytes)?;
+                    converted += 1;
+                }
+                Ok(None) => (),
+                Err(err) => {
+                    let kind = match args.from.is_text() {
+                        true => "line",
+                        false => "record",
+                    };
+
+                    eprintln!("Skipping {} {}: {}", kind, records_read + index + 1, err);
+                    invalid += 1;
+                }
+            }
+        }
+
+        records_read += batch.len();
+    }
+
+    output.flush()?;
+
+    if invalid > 0 {
+        eprintln!("{} invalid records skipped", invalid);
+    }
+
+    eprintln!("{} positions converted", converted);
+    Ok(())
+}
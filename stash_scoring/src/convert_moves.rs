@@ -0,0 +1,99 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+use clap::Args;
+
+use stash_scoring::board::{MoveNotation, Position};
+use stash_scoring::input::tokenize;
+use stash_scoring::reader::BufferedLines;
+
+/// Converts move sequences between SAN and UCI notation. Each input line holds
+/// the moves of a game, played from the standard position, or from a given
+/// position when written as '<FEN> moves <MOVES>'. Input moves may use either
+/// notation, and move numbers are ignored. Lines with an illegal move are
+/// reported and skipped.
+#[derive(Args)]
+pub struct ConvertMovesArgs {
+    /// The file containing the move sequences.
+    #[arg(short, long)]
+    input_file: String,
+
+    /// The file to write the converted sequences to. Defaults to the standard
+    /// output.
+    #[arg(short, long)]
+    output_file: Option<String>,
+
+    /// The notation of the converted moves.
+    #[arg(long, value_enum)]
+    to: MoveNotation,
+
+    /// Read positions as Chess960 ones, and write castling moves as the king
+    /// capturing its rook in UCI notation.
+    #[arg(long)]
+    chess960: bool,
+}
+
+/// Converts a single line, returning the converted line.
+fn convert_line(line: &str, args: &ConvertMovesArgs) -> Result<String, String> {
+    let tokens: Vec<&str> = tokenize(line).collect();
+    let (fen, moves) = match tokens.iter().position(|&t| t == "moves") {
+        Some(idx) => (Some(tokens[..idx].join(" ")), &tokens[idx + 1..]),
+        None => (None, &tokens[..]),
+    };
+    let pos = Position::from_fen(fen.as_deref().unwrap_or(Position::STARTPOS), args.chess960)
+        .map_err(|err| err.to_string())?;
+    let moves: Vec<&str> = moves
+        .iter()
+        .copied()
+        .filter(|t| !t.starts_with(|c: char| c.is_ascii_digit()) || !t.ends_with('.'))
+        .collect();
+    let converted = pos
+        .convert_moves(&moves, args.to)
+        .map_err(|err| err.to_string())?
+        .join(" ");
+
+    Ok(match fen {
+        Some(fen) => format!("{} moves {}", fen, converted)
+            .trim_end()
+            .to_string(),
+        None => converted,
+    })
+}
+
+pub fn run(args: &ConvertMovesArgs) -> io::Result<()> {
+    let mut lines = BufferedLines::open(&args.input_file)?;
+    let mut output: Box<dyn Write> = match &args.output_file {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let mut line_number = 0;
+    let mut invalid = 0;
+
+    while let Some(line) = lines.next_line()? {
+        line_number += 1;
+
+        let line = String::from_utf8_lossy(line);
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match convert_line(&line, args) {
+            Ok(converted) => writeln!(output, "{}", converted)?,
+            Err(err) => {
+                eprintln!("Skipping line {}: {}", line_number, err);
+                invalid += 1;
+            }
+        }
+    }
+
+    output.flush()?;
+
+    if invalid > 0 {
+        eprintln!("{} invalid lines skipped", invalid);
+    }
+
+    Ok(())
+}
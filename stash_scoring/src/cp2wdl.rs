@@ -0,0 +1,79 @@
+use std::io;
+
+use clap::Args;
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::Score;
+use stash_scoring::wdl::{WdlModel, WdlModelKind};
+
+/// Converts centipawn scores into win/draw/loss probabilities, printing one
+/// <SCORE WIN DRAW LOSS EXPECTED> line per score, from the side to move's
+/// point of view. With --inverse, expected results are converted back into
+/// centipawn scores instead.
+#[derive(Args)]
+pub struct Cp2WdlArgs {
+    /// The scores to convert, in centipawns or as mate scores ('#3', 'M-2').
+    /// With --inverse, the expected results to convert, between 0 and 1.
+    #[arg(required = true, allow_hyphen_values = true)]
+    values: Vec<String>,
+
+    /// The conversion model.
+    #[arg(long, value_enum, default_value_t = WdlModelKind::Logistic)]
+    model: WdlModelKind,
+
+    /// The scale of the logistic model, in centipawns.
+    #[arg(long, default_value_t = 400.0)]
+    sigmoid_k: f64,
+
+    /// The position the scores are given in, whose material is used by the
+    /// material model. Defaults to the starting position.
+    #[arg(long)]
+    fen: Option<String>,
+
+    /// Convert expected results into centipawn scores.
+    #[arg(long)]
+    inverse: bool,
+}
+
+pub fn run(args: &Cp2WdlArgs) -> io::Result<()> {
+    let invalid = |err: String| io::Error::new(io::ErrorKind::InvalidInput, err);
+
+    if args.sigmoid_k <= 0.0 {
+        return Err(invalid(String::from("--sigmoid-k must be positive")));
+    }
+
+    let pos = Position::from_fen(args.fen.as_deref().unwrap_or(Position::STARTPOS), false)
+        .map_err(|err| invalid(err.to_string()))?;
+    let model = WdlModel::new(args.model, args.sigmoid_k);
+
+    for value in &args.values {
+        if args.inverse {
+            let expected: f64 = value
+                .parse()
+                .ok()
+                .filter(|expected| (0.0..=1.0).contains(expected))
+                .ok_or_else(|| invalid(format!("invalid expected result '{}'", value)))?;
+
+            // Adding zero turns the -0.0 of rounded bisection results into 0.0.
+            let cp = (model.centipawns(expected, &pos) * 10.0).round() / 10.0 + 0.0;
+
+            println!("{} {:.1}", value, cp);
+        } else {
+            let score: Score = value
+                .parse()
+                .map_err(|_| invalid(format!("invalid score '{}'", value)))?;
+            let wdl = model.wdl(score, &pos);
+
+            println!(
+                "{} {:.4} {:.4} {:.4} {:.4}",
+                value,
+                wdl.win,
+                wdl.draw,
+                wdl.loss,
+                wdl.expected_result()
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::io;
+use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::time::Instant;
+
+use stash_scoring::game::Termination;
+use stash_scoring::tournament::{Sprt, Standings};
+
+/// The live status of a match, redrawn below the game results when the
+/// standard output is a terminal. Otherwise, only the game results are
+/// printed.
+pub struct Dashboard {
+    live: bool,
+    /// The number of lines of the last drawn status, to be erased before the
+    /// next output.
+    drawn_lines: usize,
+    start: Instant,
+    total_games: usize,
+    games: usize,
+    crashes: usize,
+    time_forfeits: usize,
+    illegal_moves: usize,
+}
+
+impl Dashboard {
+    pub fn new(total_games: usize) -> Self {
+        Self {
+            live: io::stdout().is_terminal(),
+            drawn_lines: 0,
+            start: Instant::now(),
+            total_games,
+            games: 0,
+            crashes: 0,
+            time_forfeits: 0,
+            illegal_moves: 0,
+        }
+    }
+
+    /// Counts a finished game.
+    pub fn add_game(&mut self, termination: Termination) {
+        self.games += 1;
+
+        match termination {
+            Termination::EngineFailure => self.crashes += 1,
+            Termination::TimeForfeit => self.time_forfeits += 1,
+            Termination::IllegalMove => self.illegal_moves += 1,
+            _ => (),
+        }
+    }
+
+    /// Prints a line above the status.
+    pub fn log(&mut self, line: &str) -> io::Result<()> {
+        self.erase()?;
+        println!("{}", line);
+        Ok(())
+    }
+
+    /// Formats the failure counters.
+    pub fn failures(&self) -> String {
+        format!(
+            "Crashes: {}, time forfeits: {}, illegal moves: {}",
+            self.crashes, self.time_forfeits, self.illegal_moves
+        )
+    }
+
+    /// Redraws the status, with the Elo estimates of each pairing and the
+    /// state of the SPRT if any.
+    pub fn draw(&mut self, standings: &Standings, sprt: Option<&Sprt>) -> io::Result<()> {
+        if !self.live {
+            return Ok(());
+        }
+
+        let elapsed = self.start.elapsed().as_secs();
+        let mut status = format!(
+            "--- {}/{} games played, {:02}:{:02}:{:02} elapsed ---\n",
+            self.games,
+            self.total_games,
+            elapsed / 3600,
+            elapsed / 60 % 60,
+            elapsed % 60
+        );
+
+        status.push_str(&standings.pair_elos());
+
+        if let Some(sprt) = sprt {
+            status.push_str(&sprt.summary(&standings.pentanomial(0, 1)));
+            status.push('\n');
+        }
+
+        status.push_str(&self.failures());
+        status.push('\n');
+
+        self.erase()?;
+        print!("{}", status);
+        self.drawn_lines = status.lines().count();
+        io::stdout().flush()
+    }
+
+    /// Erases the last drawn status.
+    pub fn erase(&mut self) -> io::Result<()> {
+        if self.drawn_lines > 0 {
+            print!("\x1b[{}A\x1b[J", self.drawn_lines);
+            self.drawn_lines = 0;
+        }
+
+        io::stdout().flush()
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::sync::{Mutex, RwLock};
+
+use sha2::{Digest, Sha256};
+
+use crate::board::Position;
+
+/// The set of positions already written to an output, so that duplicates can
+/// be dropped while scoring instead of in a separate pass. Positions are
+/// identified by a 64-bit hash of their canonical FEN (and move, if any),
+/// which makes collisions negligible for datasets of billions of positions.
+///
+/// The set can be backed by a file holding the hashes of all the positions
+/// written, so that duplicates are also dropped across runs, e.g. when a
+/// dataset is scored in several parts.
+pub struct OutputDedup {
+    keys: RwLock<HashSet<u64>>,
+    file: Option<Mutex<BufWriter<File>>>,
+}
+
+impl OutputDedup {
+    /// Creates an empty in-memory set.
+    pub fn new() -> Self {
+        Self {
+            keys: RwLock::new(HashSet::new()),
+            file: None,
+        }
+    }
+
+    /// Opens the file backing the set, creating it if needed, and loads the
+    /// hashes it holds. A hash left incomplete by an interrupted run is
+    /// ignored.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut content = Vec::new();
+
+        file.read_to_end(&mut content)?;
+
+        let complete = content.len() - content.len() % 8;
+
+        // Appending after a partial hash would misalign all the next ones.
+        file.set_len(complete as u64)?;
+
+        let keys = content[..complete]
+            .chunks_exact(8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+
+        Ok(Self {
+            keys: RwLock::new(keys),
+            file: Some(Mutex::new(BufWriter::new(file))),
+        })
+    }
+
+    /// The hash identifying a position, and the move played from it if any.
+    pub fn key(pos: &Position, mv: Option<&str>) -> u64 {
+        let mut hasher = Sha256::new();
+
+        hasher.update(pos.canonical_fen().as_bytes());
+
+        if let Some(mv) = mv {
+            hasher.update(b" ");
+            hasher.update(mv.as_bytes());
+        }
+
+        u64::from_le_bytes(hasher.finalize()[..8].try_into().unwrap())
+    }
+
+    /// Whether a position with this hash was already written.
+    pub fn contains(&self, key: u64) -> bool {
+        self.keys.read().unwrap().contains(&key)
+    }
+
+    /// Records a position about to be written, returning false if it is a
+    /// duplicate which should be dropped instead.
+    pub fn insert(&self, key: u64) -> io::Result<bool> {
+        if !self.keys.write().unwrap().insert(key) {
+            return Ok(false);
+        }
+
+        if let Some(file) = &self.file {
+            file.lock().unwrap().write_all(&key.to_le_bytes())?;
+        }
+
+        Ok(true)
+    }
+
+    /// The number of positions in the set.
+    pub fn len(&self) -> usize {
+        self.keys.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes the new hashes to the backing file, if any.
+    pub fn flush(&self) -> io::Result<()> {
+        match &self.file {
+            Some(file) => file.lock().unwrap().flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for OutputDedup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
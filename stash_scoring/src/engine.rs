@@ -1,10 +1,22 @@
+use std::fmt;
+use std::fs::File;
 use std::io;
 use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvError, RecvTimeoutError};
+use std::sync::{Arc, Mutex, OnceLock, RwLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use clap::Args;
+use clap::{Args, ValueEnum};
+use regex::Regex;
 
-#[derive(Args, Clone)]
+use crate::events::{Event, EventBus};
+
+#[derive(Args, Clone, Default)]
 #[group(required = true, multiple = true)]
 pub struct SearchLimit {
     /// The maximal depth for searches.
@@ -14,12 +26,168 @@ pub struct SearchLimit {
     /// The maximal node count for searches.
     #[arg(short, long)]
     pub nodes: Option<u64>,
+
+    /// A template for the go command, for engines needing a nonstandard
+    /// syntax or extra tokens, e.g. 'go nodes {nodes} extra'. '{depth}' and
+    /// '{nodes}' are replaced by the limits above, and '{wtime}', '{btime}',
+    /// '{winc}' and '{binc}' by the clocks of timed games, in milliseconds,
+    /// and '{moves}' by the root moves searches are restricted to, a
+    /// 'searchmoves' keyword before it being dropped if there are none.
+    /// Ponder searches insert 'ponder' after 'go'.
+    #[arg(long, value_parser = parse_go_template)]
+    pub go_template: Option<String>,
+
+    /// The root moves the search is restricted to, in UCI notation, sent
+    /// with 'searchmoves'. All the legal moves are searched if empty.
+    #[arg(skip)]
+    pub searchmoves: Vec<String>,
+}
+
+/// The placeholders of go templates.
+const GO_PLACEHOLDERS: [&str; 7] = ["depth", "nodes", "wtime", "btime", "winc", "binc", "moves"];
+
+/// Replaces the placeholders of a go template with their values, failing on
+/// unknown placeholders and on the ones without a value.
+fn expand_go_template(
+    template: &str,
+    value: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut command = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let end = start
+            + rest[start..]
+                .find('}')
+                .ok_or("unclosed '{' in the go template")?;
+        let name = &rest[start + 1..end];
+
+        if !GO_PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "unknown placeholder '{{{}}}' in the go template",
+                name
+            ));
+        }
+
+        let value = value(name)
+            .ok_or_else(|| format!("the go template uses '{{{}}}', which is not set", name))?;
+        // An empty move list is sent without its keyword, which would
+        // otherwise leave a 'searchmoves' token without any move.
+        let prefix = match value.is_empty() {
+            true => rest[..start]
+                .trim_end()
+                .strip_suffix("searchmoves")
+                .filter(|prefix| prefix.is_empty() || prefix.ends_with(char::is_whitespace))
+                .unwrap_or(&rest[..start]),
+            false => &rest[..start],
+        };
+
+        command.push_str(prefix);
+        command.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+
+    command.push_str(rest);
+    Ok(command)
+}
+
+/// Checks the syntax of a go template given on the command line.
+pub fn parse_go_template(template: &str) -> Result<String, String> {
+    expand_go_template(template, |_| Some(String::new()))?;
+
+    match template.split_whitespace().next() {
+        Some("go") => Ok(template.to_string()),
+        _ => Err(String::from("the go template must start with 'go'")),
+    }
+}
+
+/// The remaining time and increment of White and Black, sent along with the
+/// search limits in timed games.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchClocks {
+    pub time: [Duration; 2],
+    pub increment: [Duration; 2],
 }
 
 impl SearchLimit {
+    /// Checks that the go template, if any, only uses the limits which are
+    /// set, and the clocks if searches are timed.
+    pub fn check_template(&self, timed: bool) -> Result<(), String> {
+        let Some(template) = &self.go_template else {
+            return Ok(());
+        };
+        let clocks = timed.then_some(SearchClocks {
+            time: [Duration::ZERO; 2],
+            increment: [Duration::ZERO; 2],
+        });
+
+        expand_go_template(template, |name| self.template_value(name, clocks.as_ref())).map(drop)
+    }
+
+    /// The value of a placeholder of the go template.
+    fn template_value(&self, name: &str, clocks: Option<&SearchClocks>) -> Option<String> {
+        let millis = |duration: Duration| duration.as_millis().to_string();
+
+        match name {
+            "depth" => self.depth.map(|depth| depth.to_string()),
+            "nodes" => self.nodes.map(|nodes| nodes.to_string()),
+            "wtime" => clocks.map(|clocks| millis(clocks.time[0])),
+            "btime" => clocks.map(|clocks| millis(clocks.time[1])),
+            "winc" => clocks.map(|clocks| millis(clocks.increment[0])),
+            "binc" => clocks.map(|clocks| millis(clocks.increment[1])),
+            "moves" => Some(self.searchmoves.join(" ")),
+            _ => None,
+        }
+    }
+
     pub fn go_command(&self) -> String {
+        self.timed_go_command(None)
+    }
+
+    /// Builds the go command, including the state of the clocks if given.
+    pub fn timed_go_command(&self, clocks: Option<&SearchClocks>) -> String {
+        self.build_go_command(clocks, false)
+    }
+
+    /// Builds the command starting a ponder search.
+    pub fn ponder_command(&self, clocks: Option<&SearchClocks>) -> String {
+        self.build_go_command(clocks, true)
+    }
+
+    fn build_go_command(&self, clocks: Option<&SearchClocks>, ponder: bool) -> String {
+        if let Some(template) = &self.go_template {
+            // Placeholders without a value were rejected by check_template().
+            let command = expand_go_template(template, |name| {
+                Some(self.template_value(name, clocks).unwrap_or_default())
+            })
+            .unwrap_or_default();
+            let command = command.trim();
+
+            return match ponder {
+                true => format!(
+                    "go ponder{}\n",
+                    command.strip_prefix("go").unwrap_or(command)
+                ),
+                false => format!("{}\n", command),
+            };
+        }
+
         let mut command = String::from("go");
 
+        if ponder {
+            command.push_str(" ponder");
+        }
+
+        if let Some(clocks) = clocks {
+            command.push_str(&format!(
+                " wtime {} btime {} winc {} binc {}",
+                clocks.time[0].as_millis(),
+                clocks.time[1].as_millis(),
+                clocks.increment[0].as_millis(),
+                clocks.increment[1].as_millis()
+            ));
+        }
+
         if let Some(depth) = self.depth {
             command.push_str(format!(" depth {}", depth).as_str());
         }
@@ -28,96 +196,1222 @@ impl SearchLimit {
             command.push_str(format!(" nodes {}", nodes).as_str());
         }
 
+        if !self.searchmoves.is_empty() {
+            command.push_str(&format!(" searchmoves {}", self.searchmoves.join(" ")));
+        }
+
         command.push('\n');
         command
     }
 }
 
+/// A search score, as reported by the engine from the side to move's point of
+/// view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    /// A regular evaluation, in centipawns.
+    Cp(i32),
+    /// A forced mate, in moves. Negative values mean that the side to move is
+    /// getting mated.
+    Mate(i32),
+}
+
+impl Score {
+    /// Returns the score folded into a single number, with mates being
+    /// represented as an offset from +/-32000.
+    pub fn folded(&self) -> i32 {
+        match *self {
+            Self::Cp(cp) => cp,
+            Self::Mate(mate) if mate <= 0 => mate.saturating_sub(32000),
+            Self::Mate(mate) => 32000i32.saturating_sub(mate),
+        }
+    }
+
+    /// Parses the two tokens following `score` in an info line.
+    pub fn parse(
+        kind: Option<&str>,
+        value: Option<&str>,
+        profile: EngineProfile,
+    ) -> io::Result<Self> {
+        let value = value
+            .and_then(|v| v.parse::<i32>().ok())
+            .ok_or(io::Error::from(io::ErrorKind::InvalidInput))?;
+
+        match kind {
+            Some("cp") => Ok(Self::Cp(profile.interpret_score(value))),
+            Some("mate") => Ok(Self::Mate(value)),
+            _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+        }
+    }
+
+    /// Converts the score of the position reached after a move into the score
+    /// of this move, from the point of view of the side which played it.
+    pub fn parent(&self) -> Self {
+        match *self {
+            Self::Cp(cp) => Self::Cp(cp.saturating_neg()),
+            Self::Mate(mate) if mate <= 0 => Self::Mate(1i32.saturating_sub(mate)),
+            Self::Mate(mate) => Self::Mate(-mate),
+        }
+    }
+
+    /// Returns the same score, seen from the other side's point of view.
+    pub fn flipped(&self) -> Self {
+        match *self {
+            Self::Cp(cp) => Self::Cp(cp.saturating_neg()),
+            Self::Mate(mate) => Self::Mate(mate.saturating_neg()),
+        }
+    }
+
+    pub fn is_mate(&self) -> bool {
+        matches!(self, Self::Mate(_))
+    }
+
+    /// Maps the score to an expected game result between 0 (loss) and 1
+    /// (win), using a logistic curve of scale `k` in centipawns.
+    pub fn expected_result(&self, k: f64) -> f64 {
+        1.0 / (1.0 + (-(self.folded() as f64) / k).exp())
+    }
+
+    pub fn display(&self, format: ScoreFormat) -> ScoreDisplay {
+        ScoreDisplay {
+            score: *self,
+            format,
+        }
+    }
+}
+
+impl FromStr for Score {
+    type Err = std::num::ParseIntError;
+
+    /// Parses a score written in any of the supported score formats. Folded
+    /// mate scores are read back as centipawn scores.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(mate) = s.strip_prefix('#') {
+            Ok(Self::Mate(mate.parse()?))
+        } else if let Some(mate) = s.strip_prefix("-M") {
+            Ok(Self::Mate(format!("-{}", mate).parse()?))
+        } else if let Some(mate) = s.strip_prefix('M') {
+            Ok(Self::Mate(mate.parse()?))
+        } else {
+            Ok(Self::Cp(s.parse()?))
+        }
+    }
+}
+
+/// The available notations for writing mate scores in output files.
+/// Centipawn scores are always written as plain integers.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScoreFormat {
+    /// Mates are written as `#5` or `#-3`.
+    Pound,
+    /// Mates are written as `M5` or `-M3`.
+    Letter,
+    /// Mates are folded into the score as an offset from +/-32000 (legacy
+    /// behaviour).
+    Folded,
+}
+
+pub struct ScoreDisplay {
+    score: Score,
+    format: ScoreFormat,
+}
+
+impl fmt::Display for ScoreDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.score, self.format) {
+            (Score::Cp(cp), _) => write!(f, "{}", cp),
+            (score, ScoreFormat::Folded) => write!(f, "{}", score.folded()),
+            (Score::Mate(mate), ScoreFormat::Pound) => write!(f, "#{}", mate),
+            (Score::Mate(mate), ScoreFormat::Letter) if mate <= 0 => {
+                write!(f, "-M{}", mate.unsigned_abs())
+            }
+            (Score::Mate(mate), ScoreFormat::Letter) => write!(f, "M{}", mate),
+        }
+    }
+}
+
+/// A root move and its score, as reported in a MultiPV line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RootMove {
+    pub mv: String,
+    pub score: Score,
+}
+
+/// The outcome of a search.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchResult {
+    /// The last score reported by the engine.
+    pub score: Score,
+    /// The best move returned by the engine, in UCI notation.
+    pub best_move: String,
+    /// The reply the engine expects to the best move, if it reported one.
+    pub ponder_move: Option<String>,
+    /// The last reported line for each MultiPV index, in order.
+    pub root_moves: Vec<RootMove>,
+    /// The depth of the last line reporting the score, if the engine gave it.
+    pub depth: Option<u16>,
+    /// The last node count reported by the engine, if any.
+    pub nodes: Option<u64>,
+    /// The principal variation of the line reporting the score, in UCI
+    /// notation.
+    pub pv: Vec<String>,
+}
+
+/// The fields of an info line used by the tool.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchInfo<'a> {
+    /// The MultiPV index of the line, starting from 1.
+    pub multipv: usize,
+    pub depth: Option<u16>,
+    pub nodes: Option<u64>,
+    pub score: Option<Score>,
+    /// The principal variation, in UCI notation.
+    pub pv: Vec<&'a str>,
+}
+
+impl<'a> SearchInfo<'a> {
+    /// Parses an info line sent by the engine during a search.
+    pub fn parse(line: &'a str, profile: EngineProfile) -> io::Result<Self> {
+        let mut tokens = line.split(char::is_whitespace);
+
+        if tokens.next() != Some("info") {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        let mut multipv = 1;
+        let mut depth = None;
+        let mut nodes = None;
+        let mut score = None;
+        let mut pv = Vec::new();
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "score" => {
+                    score = Some(Score::parse(tokens.next(), tokens.next(), profile)?);
+                }
+                "depth" => {
+                    depth = Some(
+                        tokens
+                            .next()
+                            .and_then(|v| v.parse::<u16>().ok())
+                            .ok_or(io::Error::from(io::ErrorKind::InvalidInput))?,
+                    );
+                }
+                "nodes" => {
+                    nodes = Some(
+                        tokens
+                            .next()
+                            .and_then(|v| v.parse::<u64>().ok())
+                            .ok_or(io::Error::from(io::ErrorKind::InvalidInput))?,
+                    );
+                }
+                "multipv" => {
+                    multipv = tokens
+                        .next()
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .filter(|&v| v > 0)
+                        .ok_or(io::Error::from(io::ErrorKind::InvalidInput))?;
+                }
+                "wdl" => {
+                    let _ = tokens.nth(2);
+                }
+                "upperbound" => (),
+                "lowerbound" => (),
+                "pv" => {
+                    pv = tokens.filter(|mv| !mv.is_empty()).collect();
+                    break;
+                }
+                "string" => break,
+                _ => {
+                    let _ = tokens.next();
+                }
+            }
+        }
+
+        Ok(Self {
+            multipv,
+            depth,
+            nodes,
+            score,
+            pv,
+        })
+    }
+}
+
+/// Options which can make scores depend on something else than the position,
+/// with their neutral values for labeling: playing strength limits,
+/// contempt, opening books and analysis settings. A value of 'max' stands for
+/// the maximum of a spin option.
+pub const CANONICAL_OPTIONS: &[(&str, &str)] = &[
+    ("UCI_Chess960", "false"),
+    ("MultiPV", "1"),
+    ("OwnBook", "false"),
+    ("UCI_LimitStrength", "false"),
+    ("Skill Level", "max"),
+    ("Contempt", "0"),
+    ("Analysis Contempt", "Off"),
+];
+
+/// Describes the quirks of a family of engines, so that engines which do not
+/// behave like Stash can be used without extra configuration.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EngineProfile {
+    /// Any UCI engine reporting centipawn scores (Stash, Stockfish, ...).
+    #[default]
+    Generic,
+    /// Leela-type engines, which need a long time to load their network and
+    /// report value-head scores.
+    Lc0,
+}
+
+impl EngineProfile {
+    /// The maximal time the engine is allowed to take for completing the UCI
+    /// handshake and applying its options, if any.
+    pub fn startup_timeout(&self) -> Option<Duration> {
+        match self {
+            Self::Generic => None,
+            Self::Lc0 => Some(Duration::from_secs(300)),
+        }
+    }
+
+    /// UCI options sent to the engine before the user-provided ones.
+    pub fn required_options(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::Generic => &[],
+            Self::Lc0 => &[
+                ("VerboseMoveStats", "false"),
+                ("LogLiveStats", "false"),
+                ("ScoreType", "Q"),
+            ],
+        }
+    }
+
+    /// The options after which the engine is waited for before sending it
+    /// anything else, as they load files which may take a while.
+    pub fn synchronized_options(&self) -> &'static [&'static str] {
+        match self {
+            Self::Generic => &["EvalFile", "EvalFileSmall", "SyzygyPath"],
+            Self::Lc0 => &["WeightsFile", "Backend", "SyzygyPath"],
+        }
+    }
+
+    /// Regular expressions matching the lines by which the engine reports
+    /// that it cannot work properly, e.g. because its network failed to load.
+    pub fn startup_errors(&self) -> &'static [&'static str] {
+        match self {
+            Self::Generic => &[
+                r"^info string ERROR\b",
+                r"(?i)^info string .*\b(failed|unable|could not|cannot) (to )?(load|open|find)\b",
+            ],
+            // Lc0 reports its failures as plain 'error' lines.
+            Self::Lc0 => &[
+                r"^info string ERROR\b",
+                r"(?i)^info string .*\b(failed|unable|could not|cannot) (to )?(load|open|find)\b",
+                r"^error\b",
+            ],
+        }
+    }
+
+    /// Converts a `score cp` value reported by the engine into centipawns.
+    pub fn interpret_score(&self, value: i32) -> i32 {
+        match self {
+            Self::Generic => value,
+            Self::Lc0 => {
+                // With ScoreType=Q, Lc0 reports the value head's expected
+                // score in [-1, 1] scaled by 10000. We convert it back to
+                // centipawns with the formula Lc0 itself uses for its default
+                // centipawn score type.
+                let q = (value as f64 / 10000.0).clamp(-0.999, 0.999);
+                let cp = 90.0 * (1.5637541897 * q).tan();
+
+                cp.round() as i32
+            }
+        }
+    }
+}
+
+/// The type of a UCI option, with the values it accepts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptionType {
+    Check,
+    Spin { min: i64, max: i64 },
+    Combo { choices: Vec<String> },
+    Button,
+    String,
+}
+
+/// An option declared by the engine during the UCI handshake.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UciOption {
+    pub name: String,
+    pub kind: OptionType,
+    pub default: Option<String>,
+}
+
+impl UciOption {
+    /// Parses an option declaration, e.g.
+    /// `option name Hash type spin default 16 min 1 max 33554432`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let type_idx = tokens.iter().position(|&t| t == "type")?;
+
+        if tokens.get(..2) != Some(&["option", "name"]) || type_idx < 3 {
+            return None;
+        }
+
+        // Names may contain spaces, and so may the values of the fields
+        // following the type.
+        let mut fields: Vec<(&str, Vec<&str>)> = Vec::new();
+
+        for &token in &tokens[type_idx..] {
+            match token {
+                "type" | "default" | "min" | "max" | "var" => fields.push((token, Vec::new())),
+                _ => fields.last_mut()?.1.push(token),
+            }
+        }
+
+        let field = |key| {
+            fields
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, value)| value.join(" "))
+        };
+        let bound = |key| field(key)?.parse::<i64>().ok();
+        let kind = match field("type")?.as_str() {
+            "check" => OptionType::Check,
+            "spin" => OptionType::Spin {
+                min: bound("min")?,
+                max: bound("max")?,
+            },
+            "combo" => OptionType::Combo {
+                choices: fields
+                    .iter()
+                    .filter(|(k, _)| *k == "var")
+                    .map(|(_, value)| value.join(" "))
+                    .collect(),
+            },
+            "button" => OptionType::Button,
+            "string" => OptionType::String,
+            _ => return None,
+        };
+
+        Some(Self {
+            name: tokens[2..type_idx].join(" "),
+            kind,
+            default: field("default"),
+        })
+    }
+
+    /// Checks a value given for the option, returning it as it should be
+    /// sent to the engine: check values may be written as `true`/`false`,
+    /// `1`/`0`, `yes`/`no` or `on`/`off`, and combo values in any case.
+    /// Buttons take no value.
+    pub fn coerce(&self, value: &str) -> Result<String, String> {
+        let invalid = |expected: String| {
+            format!(
+                "invalid value '{}' for option '{}', expected {}",
+                value, self.name, expected
+            )
+        };
+
+        match &self.kind {
+            OptionType::Check => match value.to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" | "on" => Ok(String::from("true")),
+                "false" | "0" | "no" | "off" => Ok(String::from("false")),
+                _ => Err(invalid(String::from("'true' or 'false'"))),
+            },
+            OptionType::Spin { min, max } => value
+                .parse::<i64>()
+                .ok()
+                .filter(|v| (min..=max).contains(&v))
+                .map(|v| v.to_string())
+                .ok_or_else(|| invalid(format!("an integer between {} and {}", min, max))),
+            OptionType::Combo { choices } => choices
+                .iter()
+                .find(|choice| choice.eq_ignore_ascii_case(value))
+                .cloned()
+                .ok_or_else(|| invalid(format!("one of '{}'", choices.join("', '")))),
+            OptionType::Button => match value.is_empty() {
+                true => Ok(String::new()),
+                false => Err(invalid(String::from("no value"))),
+            },
+            OptionType::String => Ok(value.to_string()),
+        }
+    }
+}
+
+/// How to start an engine for games: its command, display name, family and
+/// UCI options. The default configuration starts no command, and only serves
+/// as a base for the fields which are not set.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EngineConfig {
+    pub name: String,
+    pub command: String,
+    /// The arguments the engine is started with.
+    pub args: Vec<String>,
+    /// The directory the engine is started from.
+    pub working_dir: Option<String>,
+    /// Whether the engine is started with an empty environment instead of
+    /// inheriting ours.
+    pub clear_env: bool,
+    /// Environment variables set for the engine, as (name, value) pairs.
+    pub env: Vec<(String, String)>,
+    pub profile: EngineProfile,
+    /// UCI options, as 'Name=Value' strings.
+    pub options: Vec<String>,
+    /// Options after which the engine must be waited for, besides the ones
+    /// of its profile.
+    pub sync_options: Vec<String>,
+    /// Whether the options of `CANONICAL_OPTIONS` are set to their neutral
+    /// values, unless given in `options`.
+    pub canonical_options: bool,
+    /// Regular expressions matching the lines by which the engine reports
+    /// a failure at startup, e.g. a network file it could not load, besides
+    /// the ones of its profile.
+    pub startup_errors: Vec<String>,
+}
+
+impl EngineConfig {
+    /// Returns the path of the engine binary. Relative paths are resolved
+    /// from the working directory of the engine, if any.
+    pub fn binary_path(&self) -> PathBuf {
+        match &self.working_dir {
+            Some(dir) if self.command.contains('/') => Path::new(dir).join(&self.command),
+            _ => PathBuf::from(&self.command),
+        }
+    }
+
+    /// Returns the command starting the engine process.
+    pub fn process_command(&self) -> Command {
+        let mut command = Command::new(self.binary_path());
+
+        command.args(&self.args);
+
+        if let Some(dir) = &self.working_dir {
+            command.current_dir(dir);
+        }
+
+        if self.clear_env {
+            command.env_clear();
+        }
+
+        command.envs(self.env.iter().map(|(name, value)| (name, value)));
+        command
+    }
+
+    /// Starts the engine and sends it its options.
+    pub fn start(&self) -> io::Result<UciEngine> {
+        self.start_reporting_to(None)
+    }
+
+    /// Starts the engine, reporting its warnings to the event bus if any.
+    pub fn start_reporting_to(&self, events: Option<Arc<EventBus>>) -> io::Result<UciEngine> {
+        let mut engine = UciEngine::from_command(self.process_command(), self.profile)?;
+
+        if let Some(events) = events {
+            engine.report_to(events);
+        }
+
+        engine.synchronize_after(&self.sync_options);
+        engine.set_canonical_options(self.canonical_options);
+        engine.fail_on(&self.startup_errors)?;
+        engine.init_protocol(&self.options)?;
+        Ok(engine)
+    }
+}
+
+impl FromStr for EngineConfig {
+    type Err = String;
+
+    /// Parses a comma-separated list of 'key=value' settings, with the keys
+    /// 'cmd' (required), 'name', 'arg' (an argument of the engine), 'dir',
+    /// 'clearenv' (true to start the engine with an empty environment),
+    /// 'env.<NAME>' (an environment variable), 'profile', 'option.<NAME>',
+    /// 'sync' (an option after which the engine must be waited for) and
+    /// 'error' (a regular expression matching startup failure reports), e.g.
+    /// `cmd=./stash,name=Stash,option.Hash=16`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config = Self::default().with_settings(s)?;
+
+        if config.command.is_empty() {
+            return Err(format!("missing 'cmd' in '{}'", s));
+        }
+
+        Ok(config)
+    }
+}
+
+impl EngineConfig {
+    /// Overrides parts of the configuration with a comma-separated list of
+    /// 'key=value' settings, using the same keys as when parsing a full
+    /// configuration. Options are added after the existing ones.
+    pub fn with_settings(mut self, s: &str) -> Result<Self, String> {
+        let mut name = None;
+
+        for setting in s.split(',') {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("expected 'key=value', got '{}'", setting))?;
+
+            match key {
+                "cmd" => self.command = value.to_string(),
+                "name" => name = Some(value.to_string()),
+                "arg" => self.args.push(value.to_string()),
+                "dir" => self.working_dir = Some(value.to_string()),
+                "clearenv" => {
+                    self.clear_env = value
+                        .parse()
+                        .map_err(|_| format!("expected 'true' or 'false', got '{}'", value))?
+                }
+                "profile" => self.profile = EngineProfile::from_str(value, true)?,
+                "sync" => self.sync_options.push(value.to_string()),
+                "error" => self.startup_errors.push(value.to_string()),
+                _ => {
+                    if let Some(option) = key.strip_prefix("option.") {
+                        self.options.push(format!("{}={}", option, value));
+                    } else if let Some(var) = key.strip_prefix("env.") {
+                        self.env.push((var.to_string(), value.to_string()));
+                    } else {
+                        return Err(format!("unknown engine setting '{}'", key));
+                    }
+                }
+            }
+        }
+
+        if let Some(name) = name {
+            self.name = name;
+        } else if self.name.is_empty() {
+            self.name = self.command.clone();
+        }
+
+        Ok(self)
+    }
+}
+
+/// Whether the UCI traffic of all engines is echoed to stderr.
+static DEBUG_UCI: AtomicBool = AtomicBool::new(false);
+
+/// The number of engines started so far, which identify engines in the
+/// debug output.
+static ENGINE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The reference time of the debug output timestamps.
+static DEBUG_EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// The directory the standard error of engines is written to, if any.
+static STDERR_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Writes the standard error of the engines started from now on to
+/// 'engine-<N>.stderr' files of the given directory, N being the engine
+/// number, instead of sharing the one of the tool.
+pub fn capture_engine_stderr(dir: Option<&Path>) {
+    *STDERR_DIR.write().unwrap() = dir.map(Path::to_path_buf);
+}
+
+/// Enables or disables echoing the UCI traffic of all engines to stderr,
+/// each line being prefixed with the time since the first engine start or
+/// the first call to this function, the engine number and its direction.
+pub fn set_debug_uci(enabled: bool) {
+    debug_epoch();
+    DEBUG_UCI.store(enabled, Ordering::Relaxed);
+}
+
+/// Toggles the UCI traffic debug output. This only flips an atomic flag, so
+/// that it can be called from a signal handler.
+pub fn toggle_debug_uci() {
+    DEBUG_UCI.fetch_xor(true, Ordering::Relaxed);
+}
+
+fn debug_epoch() -> Instant {
+    *DEBUG_EPOCH.get_or_init(Instant::now)
+}
+
+/// Echoes a line sent to ('>') or received from ('<') an engine, if enabled.
+fn debug_line(engine_id: usize, direction: char, line: &str) {
+    if DEBUG_UCI.load(Ordering::Relaxed) {
+        eprintln!(
+            "[{:12.6}] #{} {} {}",
+            debug_epoch().elapsed().as_secs_f64(),
+            engine_id,
+            direction,
+            line.trim_end()
+        );
+    }
+}
+
+fn engine_closed() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "engine closed its output")
+}
+
+/// Reads the engine output line by line from a new thread, until the output
+/// is closed or a read fails.
+fn spawn_line_reader(stdout: ChildStdout, engine_id: usize) -> Receiver<io::Result<String>> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut stdout = io::BufReader::new(stdout);
+
+        loop {
+            let mut buf = String::new();
+            let line = match stdout.read_line(&mut buf) {
+                Ok(0) => break,
+                Ok(_) => {
+                    debug_line(engine_id, '<', &buf);
+                    Ok(buf)
+                }
+                Err(err) => Err(err),
+            };
+            let failed = line.is_err();
+
+            if sender.send(line).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Stops the current search of an engine from another thread, e.g. when the
+/// user interrupts a run. The search result is still returned to the thread
+/// which started the search.
+#[derive(Clone)]
+pub struct StopHandle {
+    engine_id: usize,
+    stdin: Weak<Mutex<ChildStdin>>,
+}
+
+impl StopHandle {
+    /// Sends 'stop' to the engine, unless it was dropped. Engines ignore this
+    /// command when they are not searching.
+    pub fn stop(&self) -> io::Result<()> {
+        let Some(stdin) = self.stdin.upgrade() else {
+            return Ok(());
+        };
+        let mut stdin = stdin.lock().unwrap();
+
+        debug_line(self.engine_id, '>', "stop\n");
+        stdin.write_all(b"stop\n")
+    }
+}
+
 pub struct UciEngine {
-    _proc: Child,
-    stdin: ChildStdin,
-    stdout: io::BufReader<ChildStdout>,
+    id: usize,
+    proc: Arc<Mutex<Child>>,
+    /// The engine input, shared with the stop handles of the engine.
+    stdin: Arc<Mutex<ChildStdin>>,
+    /// The lines of the engine output, read by a dedicated thread so that
+    /// reads can time out.
+    lines: Receiver<io::Result<String>>,
+    /// The part of the current command not sent yet: only whole lines are
+    /// sent, so that they cannot be interleaved with the commands of stop
+    /// handles.
+    unsent: Vec<u8>,
+    profile: EngineProfile,
+    options: Vec<UciOption>,
+    sync_options: Vec<String>,
+    canonical_options: bool,
+    startup_errors: Vec<Regex>,
+    events: Option<Arc<EventBus>>,
 }
 
 impl UciEngine {
-    pub fn try_new(path: &str) -> io::Result<UciEngine> {
-        let mut proc = Command::new(path)
+    pub fn try_new(path: &str, profile: EngineProfile) -> io::Result<UciEngine> {
+        Self::try_new_in(path, None, profile)
+    }
+
+    /// Starts the engine from the given working directory. Relative engine
+    /// paths are then resolved from this directory.
+    pub fn try_new_in(
+        path: &str,
+        working_dir: Option<&str>,
+        profile: EngineProfile,
+    ) -> io::Result<UciEngine> {
+        let config = EngineConfig {
+            name: path.to_string(),
+            command: path.to_string(),
+            working_dir: working_dir.map(String::from),
+            profile,
+            ..Default::default()
+        };
+
+        Self::from_command(config.process_command(), profile)
+    }
+
+    /// Starts the engine with a custom command, e.g. one setting its
+    /// arguments or environment. Its standard input and output are replaced
+    /// with pipes.
+    pub fn from_command(mut command: Command, profile: EngineProfile) -> io::Result<UciEngine> {
+        let id = ENGINE_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if let Some(dir) = STDERR_DIR.read().unwrap().as_ref() {
+            command.stderr(File::create(dir.join(format!("engine-{}.stderr", id)))?);
+        }
+
+        let mut proc = command
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .spawn()?;
-
         let stdin = proc.stdin.take().unwrap();
-        let stdout = io::BufReader::new(proc.stdout.take().unwrap());
+        let lines = spawn_line_reader(proc.stdout.take().unwrap(), id);
+
+        debug_epoch();
 
         Ok(UciEngine {
-            _proc: proc,
-            stdin,
-            stdout,
+            id,
+            proc: Arc::new(Mutex::new(proc)),
+            stdin: Arc::new(Mutex::new(stdin)),
+            lines,
+            unsent: Vec::new(),
+            profile,
+            options: Vec::new(),
+            sync_options: Vec::new(),
+            canonical_options: false,
+            startup_errors: profile
+                .startup_errors()
+                .iter()
+                .map(|pattern| Regex::new(pattern).unwrap())
+                .collect(),
+            events: None,
         })
     }
 
+    pub fn profile(&self) -> EngineProfile {
+        self.profile
+    }
+
+    /// The number identifying the engine in the debug output and events.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Reports the warnings about the engine as events, instead of printing
+    /// them, as long as the bus has subscribers.
+    pub fn report_to(&mut self, events: Arc<EventBus>) {
+        self.events = Some(events);
+    }
+
+    fn warn(&self, message: String) {
+        let reported = self.events.as_ref().is_some_and(|events| {
+            events.emit(|| Event::EngineWarning {
+                engine: self.id,
+                message: message.clone(),
+            })
+        });
+
+        if !reported {
+            eprintln!("Warning: {}", message);
+        }
+    }
+
+    /// Makes the handshake wait for the engine after setting these options,
+    /// besides the ones of its profile. Options are otherwise all sent before
+    /// waiting for the engine once.
+    pub fn synchronize_after(&mut self, options: &[String]) {
+        self.sync_options.extend_from_slice(options);
+    }
+
+    /// Makes the handshake set the declared options of `CANONICAL_OPTIONS` to
+    /// their neutral values, except the ones given in its configuration.
+    pub fn set_canonical_options(&mut self, enabled: bool) {
+        self.canonical_options = enabled;
+    }
+
+    /// Makes the engine fail when it outputs a line matching one of these
+    /// regular expressions while starting or getting ready, besides the ones
+    /// of its profile.
+    pub fn fail_on(&mut self, patterns: &[String]) -> io::Result<()> {
+        for pattern in patterns {
+            let regex = Regex::new(pattern)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+
+            self.startup_errors.push(regex);
+        }
+
+        Ok(())
+    }
+
+    /// Fails if the line reports an error of the engine.
+    fn check_failure(&self, line: &str) -> io::Result<()> {
+        match self.startup_errors.iter().any(|regex| regex.is_match(line)) {
+            true => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the engine reported an error: '{}'", line.trim_end()),
+            )),
+            false => Ok(()),
+        }
+    }
+
+    /// The options declared by the engine during the handshake.
+    pub fn options(&self) -> &[UciOption] {
+        &self.options
+    }
+
     pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
-        self.stdin.write_all(data)
+        self.unsent.extend_from_slice(data);
+
+        let Some(end) = self.unsent.iter().rposition(|&b| b == b'\n') else {
+            return Ok(());
+        };
+        let lines: Vec<u8> = self.unsent.drain(..=end).collect();
+
+        for line in lines.split_inclusive(|&b| b == b'\n') {
+            debug_line(self.id, '>', &String::from_utf8_lossy(line));
+        }
+
+        self.stdin.lock().unwrap().write_all(&lines)
+    }
+
+    /// Returns a handle for stopping the searches of the engine from another
+    /// thread.
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle {
+            engine_id: self.id,
+            stdin: Arc::downgrade(&self.stdin),
+        }
     }
 
+    /// Reads the next line of the engine output, waiting as long as needed.
     pub fn read_line(&mut self) -> io::Result<String> {
-        let mut buf = String::new();
+        match self.lines.recv() {
+            Ok(line) => line,
+            Err(RecvError) => Err(engine_closed()),
+        }
+    }
 
-        self.stdout.read_line(&mut buf)?;
-        Ok(buf)
+    /// Reads the next line of the engine output, failing with a `TimedOut`
+    /// error if none is received in time. The line is not lost on timeout:
+    /// it is returned by the next read.
+    pub fn read_line_timeout(&mut self, timeout: Duration) -> io::Result<String> {
+        match self.lines.recv_timeout(timeout) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("engine did not answer within {:?}", timeout),
+            )),
+            Err(RecvTimeoutError::Disconnected) => Err(engine_closed()),
+        }
+    }
+
+    /// Runs the given closure, killing the engine if it does not complete
+    /// before the timeout expires. The action is named in the error message.
+    fn with_deadline<T>(
+        &mut self,
+        timeout: Option<Duration>,
+        action: &str,
+        f: impl FnOnce(&mut Self) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let Some(timeout) = timeout else {
+            return f(self);
+        };
+
+        let (sender, receiver) = mpsc::channel::<()>();
+        let proc = self.proc.clone();
+        let watchdog = thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(timeout) {
+                let _ = proc.lock().unwrap().kill();
+                return true;
+            }
+
+            false
+        });
+
+        let result = f(self);
+
+        drop(sender);
+
+        if watchdog.join().unwrap() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("engine did not {} within {:?}", action, timeout),
+            ));
+        }
+
+        result
     }
 
     pub fn ready(&mut self) -> io::Result<()> {
         self.write(b"isready\n")?;
 
         loop {
-            if let Some("readyok") = self.read_line()?.split(char::is_whitespace).next() {
+            let line = self.read_line()?;
+
+            if let Some("readyok") = line.split(char::is_whitespace).next() {
                 break;
             }
+
+            self.check_failure(&line)?;
         }
 
         Ok(())
     }
 
-    pub fn init_protocol(&mut self, config: &Vec<String>) -> io::Result<()> {
-        self.write(b"uci\n")?;
+    /// Checks that the engine still answers, killing it if it does not
+    /// answer 'isready' in time, e.g. while it is idle.
+    pub fn ping(&mut self, timeout: Duration) -> io::Result<()> {
+        self.with_deadline(Some(timeout), "answer isready", |engine| engine.ready())
+    }
+
+    pub fn init_protocol(&mut self, config: &[String]) -> io::Result<()> {
+        self.with_deadline(self.profile.startup_timeout(), "start", |engine| {
+            engine.handshake(config)
+        })
+    }
 
-        // TODO: additionally collect existing options in the engine and warn
-        // in case of invalid/non-existent parameters in the config
+    fn handshake(&mut self, config: &[String]) -> io::Result<()> {
+        self.write(b"uci\n")?;
+        self.options.clear();
 
         loop {
-            if let Some("uciok") = self.read_line()?.split(char::is_whitespace).next() {
-                break;
+            let line = self.read_line()?;
+
+            match line.split(char::is_whitespace).next() {
+                Some("uciok") => break,
+                Some("option") => self.options.extend(UciOption::parse(&line)),
+                _ => self.check_failure(&line)?,
             }
         }
 
+        for (name, value) in self.profile.required_options() {
+            self.configure(name, value)?;
+        }
+
+        if self.canonical_options {
+            self.configure_canonical(config)?;
+        }
+
         for parameter in config {
             if let Some((name, value)) = parameter.split_once('=') {
-                self.write(b"setoption name ")?;
-                self.write(name.as_bytes())?;
-                self.write(b" value ")?;
-                self.write(value.as_bytes())?;
-                self.write(b"\n")?;
-                self.ready()?;
+                self.configure(name, value)?;
             }
         }
 
+        // Some engines only load their network once they are asked whether
+        // they are ready, so make sure this happens within the deadline too.
+        self.ready()
+    }
+
+    /// Sets the declared options of `CANONICAL_OPTIONS` which are not given
+    /// in the configuration to their neutral values. Options already set to
+    /// these values by default, or whose declaration does not allow them,
+    /// are left alone.
+    fn configure_canonical(&mut self, config: &[String]) -> io::Result<()> {
+        for (name, value) in CANONICAL_OPTIONS {
+            let configured = config.iter().any(|parameter| {
+                parameter
+                    .split_once('=')
+                    .is_some_and(|(option, _)| option.eq_ignore_ascii_case(name))
+            });
+            let Some(option) = self
+                .options
+                .iter()
+                .find(|option| option.name.eq_ignore_ascii_case(name))
+            else {
+                continue;
+            };
+            let value = match (value, &option.kind) {
+                (&"max", OptionType::Spin { max, .. }) => max.to_string(),
+                _ => value.to_string(),
+            };
+            let neutral = option.coerce(&value).ok();
+
+            if configured || neutral.is_none() || neutral == option.default {
+                continue;
+            }
+
+            let name = option.name.clone();
+
+            self.configure(&name, &value)?;
+        }
+
         Ok(())
     }
 
-    pub fn setup_position(&mut self, fen: &str) -> io::Result<()> {
+    /// Lists the options of the configuration overriding the neutral value of
+    /// a canonical option, comparing the values as declared by the engine, so
+    /// that e.g. the maximal value of a spin option matches 'max'. Options the
+    /// engine did not declare have no effect, and are not listed.
+    pub fn canonical_conflicts(&self, config: &[String]) -> Vec<String> {
+        config
+            .iter()
+            .filter(|parameter| {
+                let Some((name, value)) = parameter.split_once('=') else {
+                    return false;
+                };
+                let Some((_, neutral)) = CANONICAL_OPTIONS
+                    .iter()
+                    .find(|(option, _)| option.eq_ignore_ascii_case(name))
+                else {
+                    return false;
+                };
+                let Some(option) = self
+                    .options
+                    .iter()
+                    .find(|option| option.name.eq_ignore_ascii_case(name))
+                else {
+                    return false;
+                };
+                let neutral = match (neutral, &option.kind) {
+                    (&"max", OptionType::Spin { max, .. }) => max.to_string(),
+                    _ => neutral.to_string(),
+                };
+
+                option.coerce(value).ok() != option.coerce(&neutral).ok()
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Sets an option from the configuration, checking its value against the
+    /// declaration of the option. Options the engine did not declare are
+    /// still sent, with a warning. The engine is only waited for after the
+    /// options which need it.
+    fn configure(&mut self, name: &str, value: &str) -> io::Result<()> {
+        let (name, value) = match self
+            .options
+            .iter()
+            .find(|option| option.name.eq_ignore_ascii_case(name))
+        {
+            Some(option) => {
+                let value = option
+                    .coerce(value)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+                (
+                    option.name.clone(),
+                    (option.kind != OptionType::Button).then_some(value),
+                )
+            }
+            None => {
+                self.warn(format!("the engine did not declare the option '{}'", name));
+                (name.to_string(), Some(value.to_string()))
+            }
+        };
+
+        self.send_option(&name, value.as_deref())?;
+
+        let needs_sync = self
+            .profile
+            .synchronized_options()
+            .iter()
+            .copied()
+            .chain(self.sync_options.iter().map(String::as_str))
+            .any(|option| option.eq_ignore_ascii_case(&name));
+
+        match needs_sync {
+            true => self.ready(),
+            false => Ok(()),
+        }
+    }
+
+    /// Sends an option to the engine without waiting for it. Buttons are
+    /// pressed by omitting the value.
+    pub fn send_option(&mut self, name: &str, value: Option<&str>) -> io::Result<()> {
+        self.write(b"setoption name ")?;
+        self.write(name.as_bytes())?;
+
+        if let Some(value) = value {
+            self.write(b" value ")?;
+            self.write(value.as_bytes())?;
+        }
+
+        self.write(b"\n")
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send_option(name, Some(value))?;
+        self.ready()
+    }
+
+    /// Sets up the position for a search, resetting the engine state first.
+    pub fn setup_position(&mut self, fen: &str, moves: &[&str]) -> io::Result<()> {
+        self.new_game()?;
+        self.set_position(fen, moves)
+    }
+
+    pub fn new_game(&mut self) -> io::Result<()> {
         self.write(b"ucinewgame\n")?;
-        self.ready()?;
+        self.ready()
+    }
+
+    /// Sets up the position without resetting the engine state, e.g. between
+    /// two moves of the same game.
+    pub fn set_position(&mut self, fen: &str, moves: &[&str]) -> io::Result<()> {
         self.write(b"position fen ")?;
         self.write(fen.as_bytes())?;
+
+        if !moves.is_empty() {
+            self.write(b" moves")?;
+
+            for mv in moves {
+                self.write(b" ")?;
+                self.write(mv.as_bytes())?;
+            }
+        }
+
         self.write(b"\n")?;
         self.ready()
     }
 
-    pub fn run_search(&mut self, limit: &SearchLimit) -> io::Result<i16> {
+    pub fn run_search(&mut self, limit: &SearchLimit) -> io::Result<SearchResult> {
         self.write(limit.go_command().as_bytes())?;
+        self.read_search_result()
+    }
+
+    /// Runs a search on the clock, killing the engine if it does not return
+    /// its move before the deadline.
+    pub fn run_timed_search(
+        &mut self,
+        limit: &SearchLimit,
+        clocks: &SearchClocks,
+        deadline: Duration,
+    ) -> io::Result<SearchResult> {
+        self.with_deadline(Some(deadline), "move", |engine| {
+            engine.write(limit.timed_go_command(Some(clocks)).as_bytes())?;
+            engine.read_search_result()
+        })
+    }
+
+    /// Starts searching the position sent last in ponder mode, i.e. while
+    /// the opponent is thinking. The search is completed by either
+    /// `ponder_hit` or `stop_ponder`.
+    pub fn start_ponder(
+        &mut self,
+        limit: &SearchLimit,
+        clocks: Option<&SearchClocks>,
+    ) -> io::Result<()> {
+        self.write(limit.ponder_command(clocks).as_bytes())
+    }
+
+    /// Tells the pondering engine that the opponent played the expected move,
+    /// and waits for the end of its search, killing the engine if it does
+    /// not return its move before the deadline.
+    pub fn ponder_hit(&mut self, deadline: Option<Duration>) -> io::Result<SearchResult> {
+        self.with_deadline(deadline, "move", |engine| {
+            engine.write(b"ponderhit\n")?;
+            engine.read_search_result()
+        })
+    }
+
+    /// Stops a ponder search, discarding its result.
+    pub fn stop_ponder(&mut self) -> io::Result<()> {
+        self.write(b"stop\n")?;
+
+        loop {
+            if let Some("bestmove") = self.read_line()?.split(char::is_whitespace).next() {
+                return Ok(());
+            }
+        }
+    }
 
+    fn read_search_result(&mut self) -> io::Result<SearchResult> {
         let mut score = None;
+        let mut depth = None;
+        let mut nodes = None;
+        let mut root_moves: Vec<Option<RootMove>> = Vec::new();
+        let mut pv = Vec::new();
+        let best_move;
+        let ponder_move;
 
         loop {
             let line = self.read_line()?;
@@ -125,44 +1419,112 @@ impl UciEngine {
 
             match tokens.next() {
                 Some("info") => (),
-                Some("bestmove") => break,
+                Some("bestmove") => {
+                    best_move = tokens.next().unwrap_or_default().to_string();
+                    ponder_move = match tokens.next() {
+                        Some("ponder") => {
+                            tokens.next().filter(|mv| !mv.is_empty()).map(String::from)
+                        }
+                        _ => None,
+                    };
+                    break;
+                }
+                Some("") => continue,
                 _ => return Err(io::Error::from(io::ErrorKind::InvalidInput)),
             }
 
-            while let Some(token) = tokens.next() {
-                match token {
-                    "score" => match (tokens.next(), tokens.next()) {
-                        (Some("cp"), Some(v)) => {
-                            score = Some(
-                                v.parse()
-                                    .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?,
-                            );
-                        }
-                        (Some("mate"), Some(v)) => {
-                            let mate = v
-                                .parse::<i16>()
-                                .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
-                            score = Some(if mate <= 0 {
-                                mate - 32000
-                            } else {
-                                32000 - mate
-                            });
-                        }
-                        _ => return Err(io::Error::from(io::ErrorKind::InvalidInput)),
-                    },
-                    "wdl" => {
-                        let _ = tokens.nth(2);
-                    }
-                    "upperbound" => (),
-                    "lowerbound" => (),
-                    "pv" => break,
-                    _ => {
-                        let _ = tokens.next();
-                    }
+            let info = SearchInfo::parse(&line, self.profile)?;
+
+            nodes = info.nodes.or(nodes);
+
+            // With MultiPV, the best move's score is the one of the first line.
+            if info.multipv == 1 && info.score.is_some() {
+                score = info.score;
+                depth = info.depth;
+                pv = info.pv.iter().map(|mv| mv.to_string()).collect();
+            }
+
+            if let (Some(score), Some(mv)) = (info.score, info.pv.first()) {
+                if root_moves.len() < info.multipv {
+                    root_moves.resize(info.multipv, None);
                 }
+
+                root_moves[info.multipv - 1] = Some(RootMove {
+                    mv: mv.to_string(),
+                    score,
+                });
             }
         }
 
-        score.ok_or(io::Error::from(io::ErrorKind::UnexpectedEof))
+        let score = score.ok_or(io::Error::from(io::ErrorKind::UnexpectedEof))?;
+
+        Ok(SearchResult {
+            score,
+            best_move,
+            ponder_move,
+            root_moves: root_moves.into_iter().flatten().collect(),
+            depth,
+            nodes,
+            pv,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_scores_in_each_format() {
+        let cases = [
+            (Score::Cp(-250), ["-250", "-250", "-250"]),
+            (Score::Mate(5), ["#5", "M5", "31995"]),
+            (Score::Mate(-3), ["#-3", "-M3", "-32003"]),
+            (Score::Mate(0), ["#0", "-M0", "-32000"]),
+        ];
+        let formats = [ScoreFormat::Pound, ScoreFormat::Letter, ScoreFormat::Folded];
+
+        for (score, expected) in cases {
+            for (format, expected) in formats.into_iter().zip(expected) {
+                assert_eq!(score.display(format).to_string(), expected);
+            }
+        }
+
+        assert!(Score::Mate(0).is_mate());
+        assert!(!Score::Cp(32000).is_mate());
+    }
+
+    #[test]
+    fn parses_extreme_scores() {
+        let parse = |kind, value, profile| Score::parse(Some(kind), Some(value), profile).ok();
+
+        for (kind, value, expected) in [
+            ("cp", "100000", Score::Cp(100000)),
+            ("cp", "2147483647", Score::Cp(i32::MAX)),
+            ("cp", "-2147483648", Score::Cp(i32::MIN)),
+            ("mate", "0", Score::Mate(0)),
+            ("mate", "40000", Score::Mate(40000)),
+            ("mate", "-2147483648", Score::Mate(i32::MIN)),
+        ] {
+            assert_eq!(parse(kind, value, EngineProfile::Generic), Some(expected));
+        }
+
+        assert_eq!(parse("cp", "2147483648", EngineProfile::Generic), None);
+        assert_eq!(parse("mate", "-2147483649", EngineProfile::Generic), None);
+        assert_eq!(parse("cp", "12.5", EngineProfile::Generic), None);
+        assert_eq!(parse("wdl", "42", EngineProfile::Generic), None);
+        assert!(parse("cp", "2147483647", EngineProfile::Lc0).is_some());
+    }
+
+    #[test]
+    fn writes_extreme_scores() {
+        assert_eq!(Score::Mate(i32::MIN).folded(), i32::MIN);
+        assert_eq!(Score::Mate(i32::MAX).folded(), 32000 - i32::MAX);
+        assert_eq!(
+            Score::Mate(i32::MIN)
+                .display(ScoreFormat::Letter)
+                .to_string(),
+            "-M2147483648"
+        );
     }
 }
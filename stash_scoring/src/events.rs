@@ -0,0 +1,79 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::RwLock;
+
+use crate::engine::Score;
+
+/// What happened during a run, for embedders (GUIs, web services, ...) to
+/// observe it without parsing the output of the tools.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A position was scored, with the index of its task.
+    PositionScored {
+        index: usize,
+        fen: String,
+        score: Score,
+    },
+    /// The engine of a worker was restarted, e.g. after a crash, with the
+    /// numbers of the previous engine and of its replacement.
+    WorkerRestarted {
+        previous: usize,
+        engine: usize,
+        reason: String,
+    },
+    /// An engine was sent something it may not handle, e.g. an option it
+    /// did not declare.
+    EngineWarning { engine: usize, message: String },
+    /// The number of tasks done out of those queued so far, along with the
+    /// share of the input read.
+    Progress {
+        done: usize,
+        queued: usize,
+        input_progress: f64,
+    },
+    /// The number of workers chosen by the automatic scaling, along with the
+    /// throughput it gives, in tasks per second.
+    WorkersSettled { workers: usize, throughput: f64 },
+}
+
+/// Delivers events to all the receivers subscribed to it. Events are only
+/// built and sent when there are subscribers.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: RwLock<Vec<Sender<Event>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a receiver getting all the events emitted from now on.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.subscribers.write().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        !self.subscribers.read().unwrap().is_empty()
+    }
+
+    /// Sends the event built by the closure to all subscribers, returning
+    /// whether any subscriber received it.
+    pub fn emit(&self, event: impl FnOnce() -> Event) -> bool {
+        let subscribers = self.subscribers.read().unwrap();
+
+        if subscribers.is_empty() {
+            return false;
+        }
+
+        let event = event();
+
+        subscribers
+            .iter()
+            .filter(|subscriber| subscriber.send(event.clone()).is_ok())
+            .count()
+            > 0
+    }
+}
@@ -0,0 +1,341 @@
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+use crate::board::{fen_placement, squares, Color, Piece, PieceType, Position, Square};
+use crate::engine::{Score, ScoreFormat};
+use crate::input::tokenize;
+
+/// The dataset formats scored positions can be converted between.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DatasetFormat {
+    /// `<FEN> <WDL> <EVAL>` lines, as written by the scoring tool.
+    Text,
+    /// EPD lines, with the move counters in 'hmvc' and 'fmvn', the score in
+    /// 'ce' (or 'dm' for mates) and the result in 'c9', e.g.
+    /// `<POSITION> hmvc 0; fmvn 1; ce 35; c9 "1/2-1/2";`.
+    Epd,
+    /// JSON objects, one per line, e.g.
+    /// `{"fen": "<FEN>", "wdl": 0.5, "eval": 35}`, with a 'mate' field
+    /// instead of 'eval' for mate scores.
+    Jsonl,
+    /// Comma-separated values, with a `fen,wdl,eval` header.
+    Csv,
+    /// The 32-byte ChessBoard records of bulletformat, used by the bullet
+    /// trainer. Positions are stored from the side to move's point of view,
+    /// without castling rights, en passant square and move counters, and
+    /// results are rounded to wins, draws and losses: records read back
+    /// from this format are White to move.
+    Bullet,
+    /// Stockfish .binpack files, chains of positions with the moves played
+    /// between them. Scores are kept in the internal units of the engine
+    /// which wrote them. Input only.
+    Binpack,
+    /// Stockfish .plain files, blocks of 'fen', 'move', 'score', 'ply' and
+    /// 'result' lines. Input only.
+    Plain,
+}
+
+impl DatasetFormat {
+    /// Whether the format stores one position per line of text, instead of
+    /// fixed-size binary records.
+    pub fn is_text(&self) -> bool {
+        matches!(self, Self::Text | Self::Epd | Self::Jsonl | Self::Csv)
+    }
+
+    /// Whether datasets can be written in the format, and not only read.
+    pub fn is_writable(&self) -> bool {
+        !matches!(self, Self::Binpack | Self::Plain)
+    }
+
+    /// The size of a binary record, if the format is a binary one.
+    pub fn record_size(&self) -> Option<usize> {
+        match self {
+            Self::Bullet => Some(BULLET_RECORD_SIZE),
+            _ => None,
+        }
+    }
+
+    /// The line written before the records, if any.
+    pub fn header(&self) -> Option<&'static str> {
+        match self {
+            Self::Csv => Some("fen,wdl,eval"),
+            _ => None,
+        }
+    }
+}
+
+/// A scored position of a dataset, with the game result from White's point
+/// of view, and the score from the side to move's point of view.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoredPosition {
+    pub pos: Position,
+    pub wdl: f64,
+    pub score: Score,
+}
+
+const BULLET_RECORD_SIZE: usize = 32;
+
+fn parse_wdl(wdl: &str) -> Result<f64, String> {
+    let value = match wdl {
+        "1-0" => 1.0,
+        "0-1" => 0.0,
+        "1/2-1/2" => 0.5,
+        _ => wdl
+            .parse::<f64>()
+            .map_err(|_| format!("invalid WDL value '{}'", wdl))?,
+    };
+
+    match (0.0..=1.0).contains(&value) {
+        true => Ok(value),
+        false => Err(format!("WDL value '{}' is outside of [0, 1]", wdl)),
+    }
+}
+
+fn parse_score(score: &str) -> Result<Score, String> {
+    score
+        .parse()
+        .map_err(|_| format!("invalid score '{}'", score))
+}
+
+impl ScoredPosition {
+    /// Parses a line of a text format. The header of CSV files must be
+    /// skipped beforehand.
+    pub fn parse_line(line: &str, format: DatasetFormat, chess960: bool) -> Result<Self, String> {
+        match format {
+            DatasetFormat::Text => {
+                let tokens: Vec<&str> = tokenize(line).collect();
+
+                if tokens.len() < 3 {
+                    return Err(format!("not enough columns in line '{}'", line.trim()));
+                }
+
+                let (fen, labels) = tokens.split_at(tokens.len() - 2);
+
+                Self::from_fields(&fen.join(" "), labels[0], labels[1], chess960)
+            }
+            DatasetFormat::Csv => {
+                let fields: Vec<&str> = line.trim().split(',').collect();
+
+                match fields[..] {
+                    [fen, wdl, score] => Self::from_fields(fen, wdl, score, chess960),
+                    _ => Err(format!("expected 3 fields in line '{}'", line.trim())),
+                }
+            }
+            DatasetFormat::Epd => Self::parse_epd(line, chess960),
+            DatasetFormat::Jsonl => Self::parse_json(line, chess960),
+            DatasetFormat::Bullet | DatasetFormat::Binpack | DatasetFormat::Plain => Err(format!(
+                "{} records are not text lines",
+                format.to_possible_value().unwrap().get_name()
+            )),
+        }
+    }
+
+    fn from_fields(fen: &str, wdl: &str, score: &str, chess960: bool) -> Result<Self, String> {
+        Ok(Self {
+            pos: Position::from_fen(fen, chess960).map_err(|err| err.to_string())?,
+            wdl: parse_wdl(wdl)?,
+            score: parse_score(score)?,
+        })
+    }
+
+    fn parse_epd(line: &str, chess960: bool) -> Result<Self, String> {
+        let tokens: Vec<&str> = tokenize(line).collect();
+
+        if tokens.len() < 4 {
+            return Err(format!("not enough fields in line '{}'", line.trim()));
+        }
+
+        let mut counters = [String::from("0"), String::from("1")];
+        let mut wdl = None;
+        let mut score = None;
+
+        for operation in tokens[4..].join(" ").split(';') {
+            let Some((opcode, operand)) = operation.trim().split_once(' ') else {
+                continue;
+            };
+            let operand = operand.trim().trim_matches('"');
+
+            match opcode {
+                "hmvc" => counters[0] = operand.to_string(),
+                "fmvn" => counters[1] = operand.to_string(),
+                "c9" => wdl = Some(parse_wdl(operand)?),
+                "ce" => score = Some(parse_score(operand)?),
+                "dm" => {
+                    let mate = operand
+                        .parse()
+                        .map_err(|_| format!("invalid mate distance '{}'", operand))?;
+
+                    score = Some(Score::Mate(mate));
+                }
+                _ => (),
+            }
+        }
+
+        let fen = format!("{} {} {}", tokens[..4].join(" "), counters[0], counters[1]);
+
+        Ok(Self {
+            pos: Position::from_fen(&fen, chess960).map_err(|err| err.to_string())?,
+            wdl: wdl.ok_or("missing result operation 'c9'")?,
+            score: score.ok_or("missing score operation 'ce' or 'dm'")?,
+        })
+    }
+
+    fn parse_json(line: &str, chess960: bool) -> Result<Self, String> {
+        let object: Value = serde_json::from_str(line).map_err(|err| err.to_string())?;
+        let fen = object["fen"].as_str().ok_or("missing 'fen' field")?;
+        let wdl = match &object["wdl"] {
+            Value::Number(wdl) => parse_wdl(&wdl.to_string())?,
+            Value::String(wdl) => parse_wdl(wdl)?,
+            _ => return Err(String::from("missing 'wdl' field")),
+        };
+        let score = match (&object["eval"], &object["mate"]) {
+            (_, Value::Number(mate)) => mate.as_i64().map(|mate| Score::Mate(mate as i32)),
+            (Value::Number(cp), _) => cp.as_i64().map(|cp| Score::Cp(cp as i32)),
+            (Value::String(score), _) => Some(parse_score(score)?),
+            _ => None,
+        }
+        .ok_or("missing 'eval' or 'mate' field")?;
+
+        Ok(Self {
+            pos: Position::from_fen(fen, chess960).map_err(|err| err.to_string())?,
+            wdl,
+            score,
+        })
+    }
+
+    /// Writes the position as a line of a text format, without its line end.
+    pub fn to_line(&self, format: DatasetFormat, score_format: ScoreFormat) -> String {
+        let fen = self.pos.to_fen();
+        let score = self.score.display(score_format);
+
+        match format {
+            DatasetFormat::Text => format!("{} {} {}", fen, self.wdl, score),
+            DatasetFormat::Csv => format!("{},{},{}", fen, self.wdl, score),
+            DatasetFormat::Epd => {
+                let fields: Vec<&str> = fen.split(' ').collect();
+                let result = match self.wdl {
+                    1.0 => String::from("1-0"),
+                    0.0 => String::from("0-1"),
+                    0.5 => String::from("1/2-1/2"),
+                    wdl => wdl.to_string(),
+                };
+                let score = match self.score {
+                    Score::Cp(cp) => format!("ce {}", cp),
+                    Score::Mate(mate) => format!("dm {}", mate),
+                };
+
+                format!(
+                    "{} hmvc {}; fmvn {}; {}; c9 \"{}\";",
+                    fields[..4].join(" "),
+                    fields[4],
+                    fields[5],
+                    score,
+                    result
+                )
+            }
+            DatasetFormat::Jsonl => {
+                let object = match self.score {
+                    Score::Cp(cp) => json!({ "fen": fen, "wdl": self.wdl, "eval": cp }),
+                    Score::Mate(mate) => json!({ "fen": fen, "wdl": self.wdl, "mate": mate }),
+                };
+
+                object.to_string()
+            }
+            DatasetFormat::Bullet => unreachable!("bullet records are binary"),
+            DatasetFormat::Binpack | DatasetFormat::Plain => {
+                unreachable!("Stockfish datasets cannot be written")
+            }
+        }
+    }
+
+    /// Encodes the position as a bulletformat ChessBoard record: the
+    /// occupancy bitboard, the pieces in square order as 4-bit codes (the
+    /// opponent's pieces with the high bit set), the score (mates folded and
+    /// clamped to 16 bits), the result in half points, the king square of
+    /// the side to move and the other king square, flipped. All of them are
+    /// from the side to move's point of view, the board being flipped
+    /// vertically for Black.
+    pub fn to_bullet(&self) -> [u8; BULLET_RECORD_SIZE] {
+        let us = self.pos.side_to_move();
+        let flip = |sq: Square| match us {
+            Color::White => sq.index(),
+            Color::Black => sq.index() ^ 56,
+        };
+        let mut board: [Option<(bool, PieceType)>; 64] = [None; 64];
+
+        for square in squares(self.pos.occupancy()) {
+            let piece = self.pos.piece_at(square).unwrap();
+
+            board[flip(square)] = Some((piece.color != us, piece.kind));
+        }
+
+        let mut record = [0u8; BULLET_RECORD_SIZE];
+        let mut occupancy = 0u64;
+        let mut count = 0;
+
+        for (square, piece) in board.iter().enumerate() {
+            let Some((theirs, kind)) = piece else {
+                continue;
+            };
+            let code = (u8::from(*theirs) << 3) | kind.index() as u8;
+
+            occupancy |= 1 << square;
+            record[8 + count / 2] |= code << (4 * (count % 2));
+            count += 1;
+        }
+
+        let result = match us {
+            Color::White => self.wdl,
+            Color::Black => 1.0 - self.wdl,
+        };
+        let score = self.score.folded().clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+
+        record[..8].copy_from_slice(&occupancy.to_le_bytes());
+        record[24..26].copy_from_slice(&score.to_le_bytes());
+        record[26] = (2.0 * result).round() as u8;
+        record[27] = flip(self.pos.king_square(us)) as u8;
+        record[28] = (flip(self.pos.king_square(us.flip())) ^ 56) as u8;
+        record
+    }
+
+    /// Decodes a bulletformat ChessBoard record, as a White to move
+    /// position.
+    pub fn from_bullet(record: &[u8]) -> Result<Self, String> {
+        if record.len() != BULLET_RECORD_SIZE {
+            return Err(format!("truncated record of {} bytes", record.len()));
+        }
+
+        let occupancy = u64::from_le_bytes(record[..8].try_into().unwrap());
+        let mut board: [Option<Piece>; 64] = [None; 64];
+
+        if occupancy.count_ones() > 32 {
+            return Err(String::from("too many pieces"));
+        }
+
+        for (count, square) in squares(occupancy).enumerate() {
+            let code = (record[8 + count / 2] >> (4 * (count % 2))) & 0xf;
+            let color = match code >> 3 {
+                0 => Color::White,
+                _ => Color::Black,
+            };
+            let kind = *PieceType::ALL
+                .get(usize::from(code & 7))
+                .ok_or_else(|| format!("invalid piece code {}", code))?;
+
+            board[square.index()] = Some(Piece::new(color, kind));
+        }
+
+        let fen = format!("{} w - - 0 1", fen_placement(&board));
+        let score = i16::from_le_bytes([record[24], record[25]]);
+
+        if record[26] > 2 {
+            return Err(format!("invalid result {}", record[26]));
+        }
+
+        Ok(Self {
+            pos: Position::from_fen(&fen, false).map_err(|err| err.to_string())?,
+            wdl: f64::from(record[26]) / 2.0,
+            score: Score::Cp(i32::from(score)),
+        })
+    }
+}
@@ -0,0 +1,507 @@
+use std::io;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::board::{Color, Move, Position};
+use crate::engine::{RootMove, Score, SearchClocks, SearchLimit, SearchResult, UciEngine};
+use crate::rng::Rng;
+
+/// The result of a finished game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWin,
+    BlackWin,
+    Draw,
+}
+
+impl GameResult {
+    /// Returns the result of a game lost by the given side.
+    pub fn loss_for(color: Color) -> Self {
+        match color {
+            Color::White => Self::BlackWin,
+            Color::Black => Self::WhiteWin,
+        }
+    }
+
+    /// Returns the result in PGN notation.
+    pub fn to_pgn(self) -> &'static str {
+        match self {
+            Self::WhiteWin => "1-0",
+            Self::BlackWin => "0-1",
+            Self::Draw => "1/2-1/2",
+        }
+    }
+
+    /// Returns the points scored by the given side (1 for a win, 0.5 for a
+    /// draw and 0 for a loss).
+    pub fn points(self, color: Color) -> f64 {
+        match (self, color) {
+            (Self::Draw, _) => 0.5,
+            (Self::WhiteWin, Color::White) | (Self::BlackWin, Color::Black) => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Why a game ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Repetition,
+    FiftyMoves,
+    /// Neither side has enough material left to checkmate.
+    InsufficientMaterial,
+    /// An engine played an illegal move.
+    IllegalMove,
+    /// An engine crashed or stopped answering.
+    EngineFailure,
+    /// An engine ran out of time.
+    TimeForfeit,
+}
+
+impl Termination {
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Checkmate => "checkmate",
+            Self::Stalemate => "stalemate",
+            Self::Repetition => "threefold repetition",
+            Self::FiftyMoves => "fifty-move rule",
+            Self::InsufficientMaterial => "insufficient material",
+            Self::IllegalMove => "illegal move",
+            Self::EngineFailure => "engine failure",
+            Self::TimeForfeit => "time forfeit",
+        }
+    }
+}
+
+/// A time control with an increment, written as `<base>+<increment>` in
+/// seconds, e.g. `10+0.1`. The increment can be omitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (base, increment) = s.split_once('+').unwrap_or((s, "0"));
+        let seconds = |value: &str| {
+            value
+                .parse::<f64>()
+                .ok()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .ok_or_else(|| format!("invalid time control '{}'", s))
+        };
+        let base = seconds(base)?;
+
+        if base.is_zero() {
+            return Err(format!("the base time of '{}' must be positive", s));
+        }
+
+        Ok(Self {
+            base,
+            increment: seconds(increment)?,
+        })
+    }
+}
+
+impl TimeControl {
+    /// Returns the time control in PGN notation.
+    pub fn to_pgn(&self) -> String {
+        format!(
+            "{}+{}",
+            self.base.as_secs_f64(),
+            self.increment.as_secs_f64()
+        )
+    }
+}
+
+/// The limits of the searches of a game: fixed limits, and optionally a time
+/// control for both sides.
+#[derive(Clone)]
+pub struct GameLimits {
+    pub search: SearchLimit,
+    pub time_control: Option<TimeControl>,
+    /// The extra time an engine may use beyond its remaining time before it
+    /// loses on time, to make up for communication delays.
+    pub time_margin: Duration,
+    /// Let engines think on the opponent's time, on the reply they expect.
+    pub ponder: bool,
+    /// The node limits of White and Black, replacing the one of `search`,
+    /// e.g. for node-odds games.
+    pub side_nodes: [Option<u64>; 2],
+    /// Play the first moves of the game at random, for more varied games.
+    pub sampling: Option<MoveSampling>,
+}
+
+impl GameLimits {
+    /// The limit of the searches of the given side.
+    pub fn search_for(&self, color: Color) -> SearchLimit {
+        match self.side_nodes[color.index()] {
+            Some(nodes) => SearchLimit {
+                nodes: Some(nodes),
+                ..self.search.clone()
+            },
+            None => self.search.clone(),
+        }
+    }
+}
+
+/// Picks the moves of the first plies of a game at random among the root
+/// moves reported with MultiPV, following a softmax of their scores.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveSampling {
+    /// The temperatures of the first plies of the game, in centipawns.
+    /// Higher temperatures make the weaker moves more likely.
+    pub temperatures: Vec<f64>,
+    /// The number of root moves searched with MultiPV for these plies.
+    pub multipv: usize,
+    /// The seed of the random choices, which should differ between games.
+    pub seed: u64,
+}
+
+impl MoveSampling {
+    /// Picks one of the root moves with the temperature of the given ply,
+    /// returning None once the sampled plies are over.
+    pub fn pick<'a>(
+        &self,
+        root_moves: &'a [RootMove],
+        ply: usize,
+        rng: &mut Rng,
+    ) -> Option<&'a RootMove> {
+        let temperature = *self.temperatures.get(ply)?;
+        let best = root_moves.iter().map(|rm| rm.score.folded()).max()?;
+
+        if temperature <= 0.0 {
+            return None;
+        }
+
+        // Scores are taken relative to the best one, to avoid overflows.
+        let weights: Vec<f64> = root_moves
+            .iter()
+            .map(|rm| ((rm.score.folded() - best) as f64 / temperature).exp())
+            .collect();
+        let mut target = rng.next_f64() * weights.iter().sum::<f64>();
+
+        for (rm, weight) in root_moves.iter().zip(&weights) {
+            if target < *weight {
+                return Some(rm);
+            }
+
+            target -= weight;
+        }
+
+        root_moves.last()
+    }
+}
+
+/// A finished game.
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    pub start: Position,
+    pub moves: Vec<Move>,
+    /// The score reported for each move by the engine which played it, from
+    /// White's point of view.
+    pub evals: Vec<Option<Score>>,
+    /// The time left on the clock of the side which played each move, after
+    /// the increment.
+    pub clocks: Vec<Option<Duration>>,
+    pub result: GameResult,
+    pub termination: Termination,
+}
+
+impl GameRecord {
+    /// Returns the average of the centipawn scores reported for the moves,
+    /// from White's point of view, ignoring mate scores.
+    pub fn average_eval(&self) -> Option<f64> {
+        let evals: Vec<i32> = self
+            .evals
+            .iter()
+            .filter_map(|eval| match eval {
+                Some(Score::Cp(cp)) => Some(*cp),
+                _ => None,
+            })
+            .collect();
+
+        match evals.is_empty() {
+            true => None,
+            false => Some(evals.iter().map(|&cp| f64::from(cp)).sum::<f64>() / evals.len() as f64),
+        }
+    }
+}
+
+/// A game in progress, tracking what is needed for detecting its end.
+#[derive(Clone, Debug)]
+pub struct Game {
+    start: Position,
+    pos: Position,
+    moves: Vec<Move>,
+    evals: Vec<Option<Score>>,
+    clocks: Vec<Option<Duration>>,
+    uci_moves: Vec<String>,
+    /// The keys of the positions since the last irreversible move.
+    history: Vec<u64>,
+}
+
+impl Game {
+    pub fn new(start: Position) -> Self {
+        Self {
+            history: vec![start.key()],
+            pos: start.clone(),
+            start,
+            moves: Vec::new(),
+            evals: Vec::new(),
+            clocks: Vec::new(),
+            uci_moves: Vec::new(),
+        }
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.pos
+    }
+
+    pub fn start(&self) -> &Position {
+        &self.start
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// Returns the moves played so far, in UCI notation.
+    pub fn uci_moves(&self) -> Vec<&str> {
+        self.uci_moves.iter().map(String::as_str).collect()
+    }
+
+    pub fn play(&mut self, mv: Move) {
+        self.play_annotated(mv, None, None);
+    }
+
+    /// Plays a move, recording the score reported for it from White's point
+    /// of view and the clock of the side which played it.
+    pub fn play_annotated(&mut self, mv: Move, eval: Option<Score>, clock: Option<Duration>) {
+        self.evals.push(eval);
+        self.clocks.push(clock);
+        self.uci_moves.push(mv.to_uci(self.pos.is_chess960()));
+        self.moves.push(mv);
+        self.pos.play(mv);
+
+        if self.pos.halfmove_clock() == 0 {
+            self.history.clear();
+        }
+
+        self.history.push(self.pos.key());
+    }
+
+    /// Returns the result of the game if it is over according to the rules.
+    pub fn outcome(&self) -> Option<(GameResult, Termination)> {
+        if self.pos.legal_moves().is_empty() {
+            return Some(match self.pos.in_check() {
+                true => (
+                    GameResult::loss_for(self.pos.side_to_move()),
+                    Termination::Checkmate,
+                ),
+                false => (GameResult::Draw, Termination::Stalemate),
+            });
+        }
+
+        if self.pos.has_insufficient_material() {
+            return Some((GameResult::Draw, Termination::InsufficientMaterial));
+        }
+
+        if self.pos.halfmove_clock() >= 100 {
+            return Some((GameResult::Draw, Termination::FiftyMoves));
+        }
+
+        let current = self.history.last().unwrap();
+
+        if self.history.iter().filter(|&key| key == current).count() >= 3 {
+            return Some((GameResult::Draw, Termination::Repetition));
+        }
+
+        None
+    }
+
+    pub fn finish(self, result: GameResult, termination: Termination) -> GameRecord {
+        GameRecord {
+            start: self.start,
+            moves: self.moves,
+            evals: self.evals,
+            clocks: self.clocks,
+            result,
+            termination,
+        }
+    }
+}
+
+/// Asks the engine for its move in the current position of the game. With a
+/// time control, the search time is deducted from the side's clock. If the
+/// engine was pondering on the given move, its search goes on when this move
+/// was played, and is stopped otherwise.
+fn engine_move(
+    engine: &mut UciEngine,
+    game: &Game,
+    limits: &GameLimits,
+    clocks: &mut Option<SearchClocks>,
+    pondered: Option<&str>,
+) -> Result<SearchResult, Termination> {
+    let ponder_hit = pondered.is_some_and(|mv| game.uci_moves().last() == Some(&mv));
+
+    if pondered.is_some() && !ponder_hit {
+        engine
+            .stop_ponder()
+            .map_err(|_| Termination::EngineFailure)?;
+    }
+
+    if !ponder_hit {
+        engine
+            .set_position(&game.start().to_fen(), &game.uci_moves())
+            .map_err(|_| Termination::EngineFailure)?;
+    }
+
+    // The time spent pondering is not deducted from the clock: it only
+    // starts running once the engine knows the opponent's move.
+    let us = game.position().side_to_move().index();
+    let deadline = clocks.map(|clocks| clocks.time[us] + limits.time_margin);
+    let search = limits.search_for(game.position().side_to_move());
+    let start = Instant::now();
+    let result = match (ponder_hit, &clocks, deadline) {
+        (true, _, _) => engine.ponder_hit(deadline),
+        (false, Some(clocks), Some(deadline)) => engine.run_timed_search(&search, clocks, deadline),
+        _ => engine.run_search(&search),
+    };
+    let elapsed = start.elapsed();
+
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::TimedOut => Err(Termination::TimeForfeit),
+        Err(_) => Err(Termination::EngineFailure),
+        Ok(_) if deadline.is_some_and(|deadline| elapsed > deadline) => {
+            Err(Termination::TimeForfeit)
+        }
+        Ok(result) => {
+            if let Some(clocks) = clocks {
+                clocks.time[us] = clocks.time[us].saturating_sub(elapsed) + clocks.increment[us];
+            }
+
+            Ok(result)
+        }
+    }
+}
+
+/// Starts pondering on the reply the engine expects to its last move, if it
+/// reported a legal one. Returns the move the engine is pondering on.
+fn start_ponder(
+    engine: &mut UciEngine,
+    game: &Game,
+    limits: &GameLimits,
+    clocks: Option<&SearchClocks>,
+    ponder_move: Option<String>,
+) -> Option<String> {
+    let ponder_move = ponder_move.filter(|mv| game.position().parse_uci(mv).is_ok())?;
+    let mut moves = game.uci_moves();
+
+    // The engine which just moved ponders with its own limit.
+    let search = limits.search_for(game.position().side_to_move().flip());
+
+    moves.push(&ponder_move);
+    engine
+        .set_position(&game.start().to_fen(), &moves)
+        .and_then(|_| engine.start_ponder(&search, clocks))
+        .ok()?;
+
+    Some(ponder_move)
+}
+
+/// Plays a game between two engines, indexed by color, from the given
+/// position.
+pub fn play_game(
+    engines: [&mut UciEngine; 2],
+    start: &Position,
+    limits: &GameLimits,
+) -> GameRecord {
+    let mut game = Game::new(start.clone());
+    let [white, black] = engines;
+    let mut clocks = limits.time_control.map(|tc| SearchClocks {
+        time: [tc.base; 2],
+        increment: [tc.increment; 2],
+    });
+    let mut pondering: [Option<String>; 2] = [None, None];
+    let mut rng = Rng::new(limits.sampling.as_ref().map_or(0, |sampling| sampling.seed));
+
+    for engine in [&mut *white, &mut *black] {
+        if engine.new_game().is_err() {
+            // The failing engine is identified when it is asked for a move.
+            break;
+        }
+    }
+
+    let (result, termination) = loop {
+        if let Some(outcome) = game.outcome() {
+            break outcome;
+        }
+
+        let us = game.position().side_to_move();
+        let engine = match us {
+            Color::White => &mut *white,
+            Color::Black => &mut *black,
+        };
+        let ply = game.moves().len();
+
+        // Engines search several root moves while moves are sampled, each of
+        // them being switched back once its last sampled move is played.
+        if let Some(sampling) = &limits.sampling {
+            if ply < sampling.temperatures.len() + 2 {
+                let multipv = match ply < sampling.temperatures.len() {
+                    true => sampling.multipv,
+                    false => 1,
+                };
+
+                if engine.set_option("MultiPV", &multipv.to_string()).is_err() {
+                    break (GameResult::loss_for(us), Termination::EngineFailure);
+                }
+            }
+        }
+
+        let pondered = pondering[us.index()].take();
+        let result = match engine_move(engine, &game, limits, &mut clocks, pondered.as_deref()) {
+            Ok(result) => result,
+            Err(termination) => break (GameResult::loss_for(us), termination),
+        };
+        let (best_move, score) = match limits
+            .sampling
+            .as_ref()
+            .and_then(|sampling| sampling.pick(&result.root_moves, ply, &mut rng))
+        {
+            Some(root_move) => (root_move.mv.as_str(), root_move.score),
+            None => (result.best_move.as_str(), result.score),
+        };
+        let eval = match us {
+            Color::White => score,
+            Color::Black => score.flipped(),
+        };
+        let clock = clocks.map(|clocks| clocks.time[us.index()]);
+
+        match game.position().parse_uci(best_move) {
+            Ok(mv) => game.play_annotated(mv, Some(eval), clock),
+            Err(_) => break (GameResult::loss_for(us), Termination::IllegalMove),
+        }
+
+        if limits.ponder {
+            pondering[us.index()] =
+                start_ponder(engine, &game, limits, clocks.as_ref(), result.ponder_move);
+        }
+    };
+
+    // Make sure that the moves of searches still running don't leak into the
+    // next game.
+    for (engine, pondered) in [white, black].into_iter().zip(pondering) {
+        if pondered.is_some() {
+            let _ = engine.stop_ponder();
+        }
+    }
+
+    game.finish(result, termination)
+}
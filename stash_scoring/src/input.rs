@@ -0,0 +1,405 @@
+use std::fmt;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+use crate::board::Position;
+use crate::engine::Score;
+
+/// A column of an input dataset line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputColumn {
+    /// The position, in Forsyth-Edwards Notation.
+    Fen,
+    /// The game result, from White's point of view.
+    Wdl,
+    /// A move in UCI notation, to be played before scoring the position.
+    Move,
+    /// A search score, as written by the scoring tool.
+    Eval,
+    /// A single column carried through unchanged to the output.
+    Extra,
+    /// All remaining columns, carried through unchanged to the output.
+    ExtraRest,
+}
+
+/// Describes the layout of the lines of an input dataset, e.g.
+/// `fen,wdl,extra*`, or `epd` for EPD lines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputSchema {
+    columns: Vec<InputColumn>,
+    /// Whether lines are EPD records, whose move counters and game result
+    /// are given as operations.
+    epd: bool,
+}
+
+/// The common layouts of input lines, which can be detected from the lines
+/// themselves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// A plain FEN.
+    Fen,
+    /// A FEN followed by the game result.
+    FenWdl,
+    /// A FEN followed by the game result and a score, e.g. a scored dataset.
+    FenWdlEval,
+    /// An EPD record, with the game result in an optional 'c9' operation.
+    /// Records without one are written with '-' as their WDL value.
+    Epd,
+}
+
+/// The number of lines inspected to detect the format of an input file.
+pub const SNIFFED_LINES: usize = 16;
+
+impl InputFormat {
+    /// The name of the format, as given to --input-format.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Fen => "fen",
+            Self::FenWdl => "fen-wdl",
+            Self::FenWdlEval => "fen-wdl-eval",
+            Self::Epd => "epd",
+        }
+    }
+
+    /// The schema used for parsing lines of this format.
+    pub fn schema(&self) -> InputSchema {
+        match self {
+            Self::Fen => "fen".parse().unwrap(),
+            Self::FenWdl => "fen,wdl".parse().unwrap(),
+            Self::FenWdlEval => "fen,wdl,eval".parse().unwrap(),
+            Self::Epd => "epd".parse().unwrap(),
+        }
+    }
+
+    /// Detects the format of the given lines, as the layout most of them
+    /// can be parsed with: a FEN of 4 or 6 fields, followed by a game result
+    /// and a score for scored datasets, or EPD operations. A few invalid
+    /// lines do not prevent the detection. When several layouts fit as many
+    /// lines, as with `<FEN> 0 1` lines, a plain FEN is preferred to a
+    /// scored one.
+    pub fn sniff<'a>(
+        lines: impl IntoIterator<Item = &'a str>,
+        chess960: bool,
+    ) -> Result<Self, InputError> {
+        let formats = [Self::Fen, Self::FenWdl, Self::FenWdlEval, Self::Epd];
+        let mut fitting = [0usize; 4];
+
+        for line in lines {
+            let tokens: Vec<&str> = tokenize(line).collect();
+
+            if tokens.is_empty() {
+                continue;
+            }
+
+            for (format, count) in formats.iter().zip(&mut fitting) {
+                *count += usize::from(format.fits(&tokens, chess960));
+            }
+        }
+
+        // The first of the formats fitting the most lines.
+        let (format, count) = formats
+            .into_iter()
+            .zip(fitting)
+            .rev()
+            .max_by_key(|&(_, count)| count)
+            .unwrap();
+
+        match count {
+            0 => Err(InputError(
+                "cannot detect the format of the input lines".into(),
+            )),
+            _ => Ok(format),
+        }
+    }
+
+    /// Whether the tokens of a line can be parsed with this format.
+    fn fits(&self, tokens: &[&str], chess960: bool) -> bool {
+        let is_fen = |tokens: &[&str]| {
+            let counters = match tokens.len() {
+                4 => true,
+                6 => tokens[4..].iter().all(|t| t.parse::<u32>().is_ok()),
+                _ => false,
+            };
+
+            counters && Position::from_fen(&tokens.join(" "), chess960).is_ok()
+        };
+        let is_wdl = |token: &str| {
+            token
+                .parse::<f64>()
+                .is_ok_and(|wdl| (0.0..=1.0).contains(&wdl))
+        };
+        let is_score = |token: &str| token.parse::<Score>().is_ok();
+        let count = tokens.len();
+
+        match self {
+            Self::Fen => is_fen(tokens),
+            Self::FenWdl => count > 1 && is_wdl(tokens[count - 1]) && is_fen(&tokens[..count - 1]),
+            Self::FenWdlEval => {
+                count > 2
+                    && is_score(tokens[count - 1])
+                    && is_wdl(tokens[count - 2])
+                    && is_fen(&tokens[..count - 2])
+            }
+            Self::Epd => {
+                count > 4
+                    && tokens[4].parse::<u32>().is_err()
+                    && tokens[4..].join(" ").trim_end().ends_with(';')
+                    && is_fen(&tokens[..4])
+            }
+        }
+    }
+}
+
+/// The fields of a single input line, split according to an [`InputSchema`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputRecord<'a> {
+    pub fen: String,
+    pub wdl: Option<&'a str>,
+    pub mv: Option<&'a str>,
+    pub eval: Option<&'a str>,
+    pub extras: Vec<&'a str>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputError(String);
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InputError {}
+
+/// Splits a dataset line into its tokens. Any run of whitespace characters
+/// separates two tokens, so that tab-separated lines, repeated spaces and the
+/// carriage returns of CRLF line ends are all accepted. Byte order marks,
+/// which some editors write at the start of files, are ignored.
+pub fn tokenize(line: &str) -> impl Iterator<Item = &str> {
+    line.split(|c: char| c.is_whitespace() || c == '\u{feff}')
+        .filter(|token| !token.is_empty())
+}
+
+impl FromStr for InputSchema {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "epd" {
+            return Ok(Self {
+                columns: vec![InputColumn::Fen, InputColumn::Wdl],
+                epd: true,
+            });
+        }
+
+        let mut columns = Vec::new();
+
+        for name in s.split(',').map(str::trim) {
+            if columns.last() == Some(&InputColumn::ExtraRest) {
+                return Err(InputError("'extra*' must be the last column".into()));
+            }
+
+            columns.push(match name {
+                "fen" => InputColumn::Fen,
+                "wdl" => InputColumn::Wdl,
+                "move" => InputColumn::Move,
+                "eval" => InputColumn::Eval,
+                "extra" => InputColumn::Extra,
+                "extra*" => InputColumn::ExtraRest,
+                _ => return Err(InputError(format!("unknown column '{}'", name))),
+            });
+        }
+
+        let count = |column| columns.iter().filter(|&&c| c == column).count();
+
+        if count(InputColumn::Fen) != 1 {
+            return Err(InputError(
+                "the schema must contain exactly one 'fen' column".into(),
+            ));
+        }
+
+        if [InputColumn::Wdl, InputColumn::Move, InputColumn::Eval]
+            .into_iter()
+            .any(|column| count(column) > 1)
+        {
+            return Err(InputError(
+                "the 'wdl', 'move' and 'eval' columns can only be used once".into(),
+            ));
+        }
+
+        Ok(Self {
+            columns,
+            epd: false,
+        })
+    }
+}
+
+impl Default for InputSchema {
+    fn default() -> Self {
+        Self {
+            columns: vec![InputColumn::Fen, InputColumn::Wdl],
+            epd: false,
+        }
+    }
+}
+
+impl InputSchema {
+    pub fn contains(&self, column: InputColumn) -> bool {
+        self.columns.contains(&column)
+    }
+
+    /// Whether the line holds a FEN with its move counters, followed by
+    /// tokens fitting the columns declared after it, so that e.g. an integer
+    /// WDL and an integer extra column after a FEN without counters are not
+    /// mistaken for them.
+    fn fits_full_fen(&self, tokens: &[&str]) -> bool {
+        // Columns before the FEN are single tokens.
+        let fen_start = self
+            .columns
+            .iter()
+            .position(|&c| c == InputColumn::Fen)
+            .unwrap();
+        let Some(counters) = tokens.get(fen_start + 4..fen_start + 6) else {
+            return false;
+        };
+        let mut rest = tokens[fen_start + 6..].iter();
+
+        counters.iter().all(|t| t.parse::<u32>().is_ok())
+            && self.columns[fen_start + 1..]
+                .iter()
+                .take_while(|&&c| c != InputColumn::ExtraRest)
+                .all(|&column| {
+                    rest.next().is_some_and(|token| match column {
+                        InputColumn::Wdl => {
+                            *token == "-"
+                                || token
+                                    .parse::<f64>()
+                                    .is_ok_and(|wdl| (0.0..=1.0).contains(&wdl))
+                        }
+                        InputColumn::Eval => token.parse::<Score>().is_ok(),
+                        InputColumn::Move => (4..=5).contains(&token.len()),
+                        _ => true,
+                    })
+                })
+    }
+
+    /// Splits an input line into its fields.
+    pub fn split<'a>(&self, line: &'a str) -> Result<InputRecord<'a>, InputError> {
+        if self.epd {
+            return split_epd(line);
+        }
+
+        let tokens: Vec<&str> = tokenize(line).collect();
+        let has_rest = self.columns.contains(&InputColumn::ExtraRest);
+        let single_columns = self
+            .columns
+            .iter()
+            .filter(|&&c| c != InputColumn::Fen && c != InputColumn::ExtraRest)
+            .count();
+
+        // Without a variable number of columns, the FEN simply spans all tokens
+        // not used by other columns. Otherwise, it must be delimited from its
+        // own content: the move counters are only part of it if the tokens
+        // after them still fit the columns declared after the FEN.
+        let fen_len = match has_rest {
+            true if self.fits_full_fen(&tokens) => 6,
+            true => 4,
+            false => tokens.len().saturating_sub(single_columns),
+        };
+
+        if fen_len < 4 || tokens.len() < fen_len + single_columns {
+            return Err(InputError(format!(
+                "not enough columns in line '{}'",
+                line.trim()
+            )));
+        }
+
+        let mut record = InputRecord {
+            fen: String::new(),
+            wdl: None,
+            mv: None,
+            eval: None,
+            extras: Vec::new(),
+        };
+        let mut idx = 0;
+
+        for column in &self.columns {
+            match column {
+                InputColumn::Fen => {
+                    record.fen = tokens[idx..idx + fen_len].join(" ");
+                    idx += fen_len;
+                }
+                InputColumn::Wdl => {
+                    // '-' stands for an unknown result, as written for EPD
+                    // records without one.
+                    record.wdl = Some(tokens[idx]).filter(|&wdl| wdl != "-");
+                    idx += 1;
+                }
+                InputColumn::Move => {
+                    record.mv = Some(tokens[idx]);
+                    idx += 1;
+                }
+                InputColumn::Eval => {
+                    record.eval = Some(tokens[idx]);
+                    idx += 1;
+                }
+                InputColumn::Extra => {
+                    record.extras.push(tokens[idx]);
+                    idx += 1;
+                }
+                InputColumn::ExtraRest => {
+                    record.extras.extend_from_slice(&tokens[idx..]);
+                    idx = tokens.len();
+                }
+            }
+        }
+
+        Ok(record)
+    }
+}
+
+/// Splits an EPD record, whose FEN is made of its first four fields and of
+/// the move counters given by its 'hmvc' and 'fmvn' operations, if any. The
+/// game result is taken from its 'c9' operation, if any, which may be
+/// written as a number or as `1-0`, `0-1` or `1/2-1/2`.
+fn split_epd(line: &str) -> Result<InputRecord<'_>, InputError> {
+    let tokens: Vec<&str> = tokenize(line).collect();
+
+    if tokens.len() < 4 {
+        return Err(InputError(format!(
+            "not enough fields in EPD record '{}'",
+            line.trim()
+        )));
+    }
+
+    let mut counters = ["0", "1"];
+    let mut wdl = None;
+
+    for operation in tokens[4..].split_inclusive(|token| token.ends_with(';')) {
+        let operand = operation
+            .get(1)
+            .map(|operand| operand.trim_end_matches(';').trim_matches('"'));
+
+        match (operation[0], operand) {
+            ("hmvc", Some(operand)) => counters[0] = operand,
+            ("fmvn", Some(operand)) => counters[1] = operand,
+            ("c9", Some(operand)) => {
+                wdl = Some(match operand {
+                    "1-0" => "1",
+                    "0-1" => "0",
+                    "1/2-1/2" => "0.5",
+                    _ => operand,
+                })
+            }
+            _ => (),
+        }
+    }
+
+    Ok(InputRecord {
+        fen: format!("{} {} {}", tokens[..4].join(" "), counters[0], counters[1]),
+        wdl,
+        mv: None,
+        eval: None,
+        extras: Vec::new(),
+    })
+}
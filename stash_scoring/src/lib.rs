@@ -0,0 +1,28 @@
+pub mod audit;
+pub mod autoscale;
+pub mod binpack;
+pub mod board;
+pub mod chunks;
+pub mod dedup;
+pub mod engine;
+pub mod events;
+pub mod formats;
+pub mod game;
+pub mod input;
+pub mod manifest;
+pub mod memory;
+pub mod output;
+pub mod pgn;
+pub mod polyglot;
+pub mod pool;
+pub mod reader;
+pub mod reference;
+pub mod registry;
+pub mod rng;
+pub mod sampling;
+pub mod score_cache;
+pub mod task_queue;
+pub mod throttle;
+pub mod tournament;
+pub mod wdl;
+pub mod workspace;
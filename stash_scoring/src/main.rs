@@ -1,16 +1,72 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use regex::Regex;
 
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::{stdout, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-pub mod engine;
-pub mod task_queue;
+mod build_engine;
+mod compare_dist;
+mod consistency;
+mod convert;
+mod convert_moves;
+mod cp2wdl;
+mod dashboard;
+mod make_book;
+mod match_runner;
+mod openbench;
+mod pgn_extract;
+mod rebalance;
+mod serve_http;
+mod sweep;
+mod ttd;
+mod verify;
 
-use crate::engine::SearchLimit;
-use crate::task_queue::{TaskClient, TaskWorker};
+use stash_scoring::audit::{Audit, AuditJob};
+use stash_scoring::autoscale::{memory_low, Scaling, ThreadScaler};
+use stash_scoring::board::{Color, Position};
+use stash_scoring::chunks::{finish_chunks, reopen_chunk_dir, ChunkWatcher};
+use stash_scoring::dedup::OutputDedup;
+use stash_scoring::engine::{
+    capture_engine_stderr, set_debug_uci, EngineConfig, EngineProfile, Score, ScoreFormat,
+    SearchLimit, SearchResult,
+};
+use stash_scoring::events::{Event, EventBus};
+use stash_scoring::input::{InputColumn, InputFormat, InputSchema, SNIFFED_LINES};
+use stash_scoring::manifest::{find_executable, sha256_file, Manifest};
+use stash_scoring::memory::MemoryGuard;
+use stash_scoring::output::{OutputFile, OutputPolicy};
+use stash_scoring::reader::InputReader;
+use stash_scoring::reference::ReferenceDiff;
+use stash_scoring::registry::EngineRegistry;
+use stash_scoring::score_cache::{cache_context, ScoreCache};
+use stash_scoring::task_queue::{TaskClient, TaskWorker, Workload};
+use stash_scoring::throttle::{idle, Throttle};
+use stash_scoring::wdl::{WdlModel, WdlModelKind};
+use stash_scoring::workspace::{CleanupPolicy, Workspace};
+
+use crate::build_engine::BuildEngineArgs;
+use crate::compare_dist::CompareDistArgs;
+use crate::consistency::ConsistencyArgs;
+use crate::convert::ConvertArgs;
+use crate::convert_moves::ConvertMovesArgs;
+use crate::cp2wdl::Cp2WdlArgs;
+use crate::make_book::MakeBookArgs;
+use crate::match_runner::MatchArgs;
+use crate::openbench::OpenBenchArgs;
+use crate::pgn_extract::PgnExtractArgs;
+use crate::rebalance::RebalanceArgs;
+use crate::serve_http::ServeHttpArgs;
+use crate::sweep::SweepArgs;
+use crate::ttd::TtdArgs;
+use crate::verify::VerifyArgs;
 
 /// This tool allows for scoring chess positions coming from a text-based
 /// dataset file.
@@ -18,129 +74,1968 @@ use crate::task_queue::{TaskClient, TaskWorker};
 /// The expected input format of the dataset is <FEN WDL>, with FEN being a
 /// chess position written in Forsyth-Edwards Notation, and WDL being a decimal
 /// number representing the game result from White's point of view (1.0 for a
-/// White win, 0.0 for a Black win, and 0.5 for draw).
+/// White win, 0.0 for a Black win, and 0.5 for draw). Soft labels, i.e. any
+/// value between 0.0 and 1.0, are accepted too. Bare FENs, already scored
+/// <FEN WDL EVAL> lines and EPD records are also accepted: the format of the
+/// input is detected from its first lines, unless given with --input-format.
+///
+/// Additional columns (game id, ply, ...) can be described with the
+/// --input-columns flag, and are then written unchanged after the EVAL column.
+/// A 'move' column can also be given, in which case the position reached after
+/// playing this move is searched, and EVAL is the score of the move from the
+/// point of view of the side playing it (<FEN MOVE EVAL> for 'fen,move').
 ///
 /// The output format is <FEN WDL EVAL>, with EVAL being the returned search
-/// score from the engine, from the side to move's point of view.
+/// score from the engine, from the side to move's point of view. Mate scores
+/// are written according to the chosen score format.
+///
+/// When --blend-lambda is used, a TARGET column is added after EVAL, holding
+/// lambda * sigmoid(EVAL / K) + (1 - lambda) * WDL from White's point of view,
+/// for trainers which expect a single blended target.
+///
+/// When --tag-engine is used, a TAG column is added after EVAL (and TARGET),
+/// telling which machine and engine instance the score comes from.
+///
+/// When --multipv is used, EVAL is replaced by the list of root moves reported
+/// by the engine with their scores, in the <MOVE:EVAL MOVE:EVAL ...> format,
+/// for generating policy targets.
+///
+/// Additional dataset tools are available as subcommands.
 #[derive(Parser)]
-#[command(author, version, about, long_about, verbatim_doc_comment)]
+#[command(
+    author,
+    version,
+    about,
+    long_about,
+    verbatim_doc_comment,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
-    /// The path of the engine to use for scoring
-    #[arg(short, long)]
-    engine_path: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    score: ScoreArgs,
+
+    /// Print all the UCI traffic with the engines to stderr, prefixed with
+    /// the time since startup and the engine number. On Unix, this output
+    /// can also be toggled at any time by sending SIGUSR1 to the tool.
+    #[arg(long, global = true)]
+    debug_uci: bool,
+
+    /// The registry of named engine configurations, which engines can be
+    /// referred to from. Defaults to ~/.config/stash_tools/engines.toml, if
+    /// it exists.
+    #[arg(long, global = true)]
+    engine_registry: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check that a dataset is well-formed, and report any corrupted line.
+    Verify(VerifyArgs),
+    /// Extract labelled positions from the games of a PGN file.
+    PgnExtract(PgnExtractArgs),
+    /// Downsample overrepresented game results to target ratios.
+    Rebalance(RebalanceArgs),
+    /// Play a tournament between engines.
+    Match(MatchArgs),
+    /// Convert scored datasets between formats (text, EPD, JSONL, CSV, bullet).
+    Convert(ConvertArgs),
+    /// Convert move sequences between SAN and UCI notation.
+    ConvertMoves(ConvertMovesArgs),
+    /// Build an engine from a commit of its git repository.
+    BuildEngine(BuildEngineArgs),
+    /// Compare the eval distributions of two scored datasets.
+    CompareDist(CompareDistArgs),
+    /// Convert centipawn scores into win/draw/loss probabilities.
+    #[command(name = "cp2wdl")]
+    Cp2Wdl(Cp2WdlArgs),
+    /// Play games for an OpenBench server as one of its workers.
+    Openbench(OpenBenchArgs),
+    /// Serve scoring requests over HTTP, as an evaluation service.
+    ServeHttp(ServeHttpArgs),
+    /// Search positions several times, and report the ones whose best move
+    /// or score varies.
+    Consistency(ConsistencyArgs),
+    /// Score positions at several search limits, e.g. for scaling studies.
+    Sweep(SweepArgs),
+    /// Measure the time engines take to reach a depth, across thread counts.
+    Ttd(TtdArgs),
+    /// Build a Polyglot opening book from the openings of played games.
+    MakeBook(MakeBookArgs),
+}
+
+#[derive(Args, Clone)]
+struct ScoreArgs {
+    /// The path of the engine to use for scoring, or the name of an engine of
+    /// the registry (see --engine-registry). The other engine flags then add
+    /// to or override its configuration.
+    #[arg(short, long, required = true)]
+    engine_path: Option<String>,
+
+    /// The SHA-256 hash the engine binary must have, so that jobs scoring
+    /// parts of a dataset on several machines refuse to run with another
+    /// build of the engine instead of mixing their scores.
+    #[arg(long)]
+    engine_sha256: Option<String>,
+
+    /// The directory the engine is started from, e.g. for engines loading
+    /// their network file from a relative path. A relative engine path is
+    /// then resolved from this directory.
+    #[arg(long)]
+    engine_cwd: Option<String>,
+
+    /// An argument passed to the engine on its command line. You can use this
+    /// flag as many times as you need.
+    #[arg(long, allow_hyphen_values = true)]
+    engine_arg: Vec<String>,
+
+    /// Start the engine with an empty environment, so that its behavior does
+    /// not depend on the shell the scoring run was started from. Variables
+    /// it needs can be set with --engine-env.
+    #[arg(long)]
+    clear_engine_env: bool,
+
+    /// An environment variable set for the engine, as 'NAME=VALUE', e.g. for
+    /// thread pinning or locale settings. You can use this flag as many times
+    /// as you need.
+    #[arg(long, value_parser = parse_env_var)]
+    engine_env: Vec<(String, String)>,
 
-    /// An UCI option which should be passed to the engine at startup.
-    /// You can use this flag as many times as you need.
+    /// The family of the engine, used for handling its startup time, required
+    /// options and score reporting. Defaults to 'generic', or to the profile
+    /// of the engine in the registry.
+    #[arg(short, long, value_enum)]
+    profile: Option<EngineProfile>,
+
+    /// An UCI option which should be passed to the engine at startup, as
+    /// 'Name=Value'. Values are checked against the type of the option
+    /// declared by the engine. You can use this flag as many times as you
+    /// need.
     #[arg(short, long)]
     config: Vec<String>,
 
-    /// The file containing the positions to score.
-    #[arg(short, long)]
-    input_file: String,
+    /// Do not force neutral values for the engine options which can make
+    /// scores inconsistent (UCI_Chess960 unless --chess960 is used, MultiPV
+    /// unless --multipv is used, UCI_LimitStrength, Skill Level, Contempt,
+    /// OwnBook, ...). Options given with --config always take precedence.
+    #[arg(long)]
+    no_canonical_options: bool,
+
+    /// An UCI option after which the engine is waited for before sending it
+    /// other options, e.g. one loading a network file. Options are otherwise
+    /// all sent at once. Options of the engine profile which load files
+    /// (EvalFile, WeightsFile, SyzygyPath, ...) are always waited for. You can
+    /// use this flag as many times as you need.
+    #[arg(long)]
+    sync_option: Vec<String>,
+
+    /// A regular expression matching the lines by which the engine reports a
+    /// failure at startup, e.g. a network file it could not load. Scoring is
+    /// then aborted instead of producing meaningless scores. Error reports
+    /// such as 'info string ERROR' are always detected. You can use this flag
+    /// as many times as you need.
+    #[arg(long, value_parser = parse_regex)]
+    startup_error: Vec<String>,
+
+    /// Score Chess960 positions. This enables the UCI_Chess960 option of the
+    /// engine, and allows for Shredder-FEN and X-FEN castling rights in the
+    /// input file.
+    #[arg(long)]
+    chess960: bool,
+
+    /// The file containing the positions to score. Stockfish '.binpack' and
+    /// '.plain' datasets are read as FENs followed by their WDL labels, and
+    /// '.zst' files are decompressed, which requires the 'zstd' feature.
+    #[arg(short, long, required = true)]
+    input_file: Option<String>,
+
+    /// Read the input file by mapping it in memory instead of through a
+    /// buffered reader. Requires the 'mmap' feature.
+    #[arg(long)]
+    mmap: bool,
+
+    /// The layout of the input lines, as a comma-separated list of columns
+    /// among 'fen', 'wdl', 'move' (an UCI move to score), 'extra' (a single
+    /// extra column) and 'extra*' (all remaining columns, only allowed last),
+    /// or 'epd' for EPD records. By default, the layout is detected from the
+    /// first lines of the input, see --input-format.
+    #[arg(long, conflicts_with = "input_format")]
+    input_columns: Option<InputSchema>,
+
+    /// The format of the input lines, instead of detecting it from the first
+    /// lines of the input. Lines which cannot be parsed with the format are
+    /// skipped. Bare FENs ('fen') are written back as '<FEN> <EVAL>' lines,
+    /// without any result.
+    #[arg(long, value_enum)]
+    input_format: Option<InputFormat>,
 
     /// The output file for scored positions. Note that it will overwrite any
-    /// already existing file with the given name.
-    #[arg(short, long)]
-    output_file: String,
+    /// already existing file with the given name, unless --append is used.
+    /// The positions are written to a temporary '<OUTPUT_FILE>.tmp' file
+    /// first, which is only renamed once all positions have been scored. An
+    /// interrupted run keeps the positions scored so far in the temporary
+    /// file.
+    #[arg(short, long, required_unless_present = "smoke")]
+    output_file: Option<String>,
+
+    /// Append the scored positions to the output file instead of replacing
+    /// it, e.g. for chunks of a dataset scored by concurrent jobs. The lines
+    /// are written in place, and never interleave with the lines of other
+    /// runs appending to the same file. The run is refused if the layout of
+    /// the output lines (columns, score format, WDL settings) or the engine
+    /// build differ from the ones recorded in the manifest of the file, or if
+    /// its first line cannot be read with the current layout when it has no
+    /// manifest.
+    #[arg(long)]
+    append: bool,
+
+    /// Watch the input directory for the position chunks written by a
+    /// 'pgn-extract --watch' run, scoring each one to a shard of the output
+    /// directory as they appear, so that a single long-running pipeline feeds
+    /// a training loop. Chunks which already have a shard are skipped, and an
+    /// 'END' file is written to the output directory once the extractor is
+    /// done. An interrupted shard is removed, to be scored again by the next
+    /// run.
+    #[arg(long, conflicts_with_all = ["append", "smoke"])]
+    watch: bool,
+
+    /// A previous output of the tool, whose scores are reused for the
+    /// positions it already holds instead of searching them again, e.g. when
+    /// a dataset was extended. Positions (and moves) are matched exactly, so
+    /// the file must have been written with the same input columns and
+    /// engine settings. Not supported with --multipv.
+    #[arg(long)]
+    reuse_scores: Option<String>,
+
+    /// A previous output of the tool to compare the new scores with, e.g. to
+    /// check that an engine change does not alter its evaluations. Positions
+    /// are matched like with --reuse-scores, and a summary of the score
+    /// differences is printed at the end of the run. Not supported with
+    /// --multipv.
+    #[arg(long)]
+    reference: Option<String>,
+
+    /// The score difference with --reference, in centipawns, from which a
+    /// position is written to --disagreements-file.
+    #[arg(long, default_value_t = 50, requires = "reference")]
+    disagreement_threshold: u32,
+
+    /// The file the positions disagreeing with --reference are written to
+    /// as they are scored, as '<FEN> [<MOVE>] <REFERENCE> <SCORE>
+    /// <DIFFERENCE>' lines. Defaults to the output file with a
+    /// '.disagreements' suffix.
+    #[arg(long, requires = "reference")]
+    disagreements_file: Option<String>,
+
+    /// A cache file for search scores, shared between runs. Positions already
+    /// scored with the same engine binary, options and search limit are
+    /// taken from it instead of being searched, and new scores are added to
+    /// it. Not supported with --multipv.
+    #[arg(long)]
+    score_cache: Option<String>,
+
+    /// Drop the positions (and moves) already written to the output, based
+    /// on their canonical FEN, instead of deduplicating the output in a
+    /// separate pass. Duplicates are not searched again.
+    #[arg(long)]
+    dedup_output: bool,
+
+    /// A file holding the hashes of the positions written with
+    /// --dedup-output, so that duplicates are also dropped across runs, e.g.
+    /// when a dataset is scored in several parts.
+    #[arg(long, requires = "dedup_output")]
+    dedup_file: Option<String>,
+
+    /// The size of the output buffer, in kilobytes.
+    #[arg(long, default_value_t = 64)]
+    output_buffer_kb: usize,
+
+    /// Flush the output buffer at least every this many seconds, so that
+    /// progress is visible in the output file.
+    #[arg(long)]
+    flush_interval: Option<f64>,
+
+    /// Sync the output file to disk every time this many positions have been
+    /// written.
+    #[arg(long)]
+    fsync_every: Option<usize>,
+
+    /// Compress the output file with Zstandard, on this many threads so that
+    /// compression keeps up with large runs. The output is then written as
+    /// is, without adding a '.zst' extension. Requires the 'zstd' feature.
+    #[arg(long, conflicts_with = "append")]
+    compress_threads: Option<u32>,
+
+    /// Do not write the '<OUTPUT_FILE>.manifest.json' file, recording the
+    /// command line, the tool version, the SHA-256 hashes of the engine, input
+    /// and output files, and the time of the run.
+    #[arg(long)]
+    no_manifest: bool,
+
+    /// Create a directory for the files of the run under this directory: its
+    /// log ('run.log'), the standard error of each engine
+    /// ('engine-<N>.stderr'), the input lines which were skipped with the
+    /// reason ('rejects.tsv') and a copy of the manifest ('manifest.json').
+    /// Each run gets its own directory, named after the output file, the
+    /// start time and the process id.
+    #[arg(long)]
+    workspace: Option<String>,
+
+    /// When the directory created with --workspace is deleted.
+    #[arg(long, value_enum, default_value_t = CleanupPolicy::OnSuccess, requires = "workspace")]
+    workspace_cleanup: CleanupPolicy,
 
     /// The number of threads/engine instances to use for scoring.
     #[arg(short, long, default_value_t = 1)]
     threads: usize,
 
+    /// Start with a single engine instance, and add more while this increases
+    /// the scoring throughput, up to --threads instances. The last instance
+    /// added is stopped if it made the throughput drop, e.g. because of
+    /// context switching, or if memory runs low. The chosen count is reported
+    /// once settled.
+    #[arg(long)]
+    auto_threads: bool,
+
+    /// The memory the tool and its engines may use, in gigabytes. Once it is
+    /// exceeded, a warning is printed, no more engines are started and the
+    /// input is only queued as fast as it is scored, so that the run slows
+    /// down instead of being killed for lack of memory. Linux only.
+    #[arg(long)]
+    max_rss_gb: Option<f64>,
+
+    /// Check that engines waiting for positions still answer every time
+    /// they have been idle for this many seconds, e.g. when the input comes
+    /// from a slow pipe, and restart those which do not answer. 0 disables
+    /// the checks.
+    #[arg(long, default_value_t = 30.0)]
+    heartbeat: f64,
+
+    /// Idle between searches so that engines only search this fraction of
+    /// the time, between 0 and 1, e.g. for a background job on a shared
+    /// machine. Engines are never paused in the middle of a search.
+    #[arg(long)]
+    duty_cycle: Option<f64>,
+
+    /// Idle between searches so that the engines search at most this many
+    /// nodes per second in total, shared equally between the running engine
+    /// instances, whose count may change with --auto-threads. Only searches
+    /// reporting their node count are throttled.
+    #[arg(long)]
+    max_nps: Option<f64>,
+
     #[command(flatten)]
     limit: SearchLimit,
 
+    /// Skip positions whose search did not reach this depth, which can happen
+    /// with node limits in very sharp positions, instead of writing a
+    /// low-quality score. Searches which do not report their depth are
+    /// skipped too.
+    #[arg(long)]
+    min_depth: Option<u16>,
+
+    /// Search positions which did not reach --min-depth again, up to this
+    /// many times, before skipping them. Each retry doubles the node limit
+    /// and raises the depth limit by two plies, to --min-depth at least.
+    #[arg(long, default_value_t = 0, requires = "min_depth")]
+    shallow_retries: usize,
+
+    /// Search this many root moves per position with the MultiPV option, and
+    /// write all of them with their scores instead of a single evaluation.
+    #[arg(long)]
+    multipv: Option<usize>,
+
+    /// The number of decimals used for writing WDL values. By default, values
+    /// are written with as many decimals as needed to represent them exactly.
+    #[arg(long)]
+    wdl_precision: Option<usize>,
+
+    /// Write a blended training target after the evaluation, mixing the
+    /// expected result derived from the evaluation (with this weight) and the
+    /// game result. Requires a 'wdl' input column.
+    #[arg(long)]
+    blend_lambda: Option<f64>,
+
+    /// Write a tag after the evaluation (and the blended target), telling
+    /// where the score comes from, for tracing anomalies back to a machine or
+    /// an engine instance: '<HOST>:<ENGINE>:<N>' for the search of the N-th
+    /// engine started by the run, with ENGINE being the name of the engine
+    /// configuration, or '<HOST>:<ENGINE>:reused' and '<HOST>:<ENGINE>:cache'
+    /// for scores taken from --reuse-scores and --score-cache.
+    #[arg(long)]
+    tag_engine: bool,
+
+    /// Write the principal variation of the search after the evaluation (the
+    /// blended target and the tag), as comma-separated UCI moves, or '-' for
+    /// scores not searched by the run. PVs are checked to be legal move
+    /// sequences, and positions with illegal ones are skipped and counted,
+    /// to catch engines returning corrupt PVs.
+    #[arg(long)]
+    with_pv: bool,
+
+    /// Write the number of nodes searched for each position after the
+    /// evaluation (the blended target, the tag and the PV), e.g. for
+    /// weighting samples by search effort, or '-' for scores not searched by
+    /// the run and engines not reporting it.
+    #[arg(long)]
+    with_nodes: bool,
+
+    /// Search this share of the positions, between 0 and 1, a second time
+    /// with another engine instance than the first one when possible, and
+    /// report the distribution of the score differences at the end of the
+    /// run, e.g. to catch nondeterministic or faulty engines. The second
+    /// scores are not written to the output.
+    #[arg(long)]
+    audit_rate: Option<f64>,
+
+    /// The model used to convert evaluations into expected results for
+    /// --blend-lambda.
+    #[arg(long, value_enum, default_value_t = WdlModelKind::Logistic)]
+    wdl_model: WdlModelKind,
+
+    /// The scale of the logistic WDL model, in centipawns.
+    #[arg(long, default_value_t = 400.0)]
+    sigmoid_k: f64,
+
+    /// How mate scores should be written in the output file.
+    #[arg(short, long, value_enum, default_value_t = ScoreFormat::Pound)]
+    score_format: ScoreFormat,
+
     /// How frequently should progress be reported, in terms of scored positions.
     #[arg(short, long, default_value_t = 1000)]
     report_every: usize,
+
+    /// Make two runs over the same input with the same engine produce
+    /// byte-identical outputs: positions are written in input order, and each
+    /// engine instance searches with a single thread. Any remaining source of
+    /// nondeterminism is reported at startup.
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Score only the first N positions of the input with a single engine,
+    /// print them with the details of their searches (score, depth, nodes,
+    /// best move) and exit, so that the engine, its options and the input
+    /// format can be checked before a long run. No output file is written.
+    #[arg(long, value_name = "N")]
+    smoke: Option<usize>,
 }
 
 fn main() -> std::io::Result<()> {
     let cli = Cli::parse();
-    let mut client = TaskClient::new();
-    let ifile = File::open(cli.input_file.as_str())?;
-    let mut ofile = File::create(cli.output_file.as_str())?;
-    let mut reader = BufReader::new(ifile);
+
+    set_debug_uci(cli.debug_uci);
+
+    #[cfg(unix)]
+    install_debug_toggle();
+
+    let registry = EngineRegistry::load(cli.engine_registry.as_deref())?;
+
+    match cli.command {
+        Some(Command::Verify(args)) => {
+            if !verify::run(&args)? {
+                std::process::exit(1);
+            }
+
+            Ok(())
+        }
+        Some(Command::PgnExtract(args)) => pgn_extract::run(&args),
+        Some(Command::Rebalance(args)) => rebalance::run(&args),
+        Some(Command::Match(args)) => match_runner::run(&args, &registry),
+        Some(Command::Convert(args)) => convert::run(&args),
+        Some(Command::ConvertMoves(args)) => convert_moves::run(&args),
+        Some(Command::Openbench(args)) => openbench::run(&args),
+        Some(Command::BuildEngine(args)) => build_engine::run(&args),
+        Some(Command::CompareDist(args)) => compare_dist::run(&args),
+        Some(Command::Cp2Wdl(args)) => cp2wdl::run(&args),
+        Some(Command::ServeHttp(args)) => serve_http::run(&args, &registry),
+        Some(Command::Consistency(args)) => {
+            if !consistency::run(&args, &registry)? {
+                std::process::exit(1);
+            }
+
+            Ok(())
+        }
+        Some(Command::Sweep(args)) => sweep::run(&args, &registry),
+        Some(Command::Ttd(args)) => ttd::run(&args, &registry),
+        Some(Command::MakeBook(args)) => make_book::run(&args),
+        None if cli.score.watch => watch_chunks(cli.score, &registry),
+        None => score(cli.score, &registry),
+    }
+}
+
+/// Lets SIGUSR1 toggle the UCI traffic debug output.
+#[cfg(unix)]
+fn install_debug_toggle() {
+    extern "C" fn on_signal(_: libc::c_int) {
+        stash_scoring::engine::toggle_debug_uci();
+    }
+
+    // SAFETY: the handler only flips an atomic flag, which is
+    // async-signal-safe.
+    unsafe {
+        libc::signal(
+            libc::SIGUSR1,
+            on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+/// Set on SIGINT, for scoring runs to stop their engines and keep the scores
+/// obtained so far.
+#[cfg(unix)]
+static INTERRUPTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Cancels the scoring run on SIGINT. A second SIGINT exits right away, e.g.
+/// if an engine does not stop.
+#[cfg(unix)]
+fn watch_interrupts(token: stash_scoring::task_queue::CancellationToken) {
+    extern "C" fn on_signal(_: libc::c_int) {
+        if INTERRUPTED.swap(true, Ordering::Relaxed) {
+            // SAFETY: _exit is async-signal-safe.
+            unsafe { libc::_exit(130) };
+        }
+    }
+
+    // The token of the current run, as successive runs score the chunks of a
+    // watched directory.
+    static TOKEN: std::sync::Mutex<Option<stash_scoring::task_queue::CancellationToken>> =
+        std::sync::Mutex::new(None);
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+
+    *TOKEN.lock().unwrap() = Some(token);
+
+    INSTALL.call_once(|| {
+        // SAFETY: the handler only uses an atomic flag and _exit, which are
+        // async-signal-safe.
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+
+        // The handler cannot stop the engines itself, so a thread waits for
+        // it.
+        thread::spawn(|| {
+            while !INTERRUPTED.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(50));
+            }
+
+            eprintln!("\nInterrupted, stopping the engines");
+
+            if let Some(token) = TOKEN.lock().unwrap().as_ref() {
+                token.cancel();
+            }
+        });
+    });
+}
+
+/// Whether the tool was interrupted with SIGINT.
+fn interrupted() -> bool {
+    #[cfg(unix)]
+    return INTERRUPTED.load(Ordering::Relaxed);
+
+    #[cfg(not(unix))]
+    false
+}
+
+/// The number of bytes read at once from the input file. Lines are queued by
+/// chunks of this size, sharing the same buffer.
+const INPUT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The minimal duration over which the throughput is measured with
+/// --auto-threads, before trying another count of engine instances.
+const AUTO_THREADS_WINDOW: Duration = Duration::from_secs(10);
+
+/// The number of queued positions above which the input is no longer read
+/// once the memory limit of --max-rss-gb is exceeded.
+const THROTTLED_QUEUE_SIZE: usize = 1024;
+
+const GIB: f64 = (1u64 << 30) as f64;
+
+/// A scored position, with its hash when duplicates are dropped, as sent by
+/// the scoring threads.
+type ScoredLine = (Option<u64>, String);
+
+/// A scoring thread, with the flag stopping it after its current search.
+type ScoringThread = (Arc<AtomicBool>, thread::JoinHandle<std::io::Result<()>>);
+
+/// Parses a game result, which can be a soft label anywhere between a Black
+/// win (0.0) and a White win (1.0).
+fn parse_wdl(wdl: &str) -> Result<f64, String> {
+    match wdl.parse::<f64>() {
+        Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+        Ok(_) => Err(format!("WDL value '{}' is outside of [0, 1]", wdl)),
+        Err(_) => Err(format!("unparsable WDL value '{}'", wdl)),
+    }
+}
+
+/// The key of a scored position in the previous scores of --reuse-scores.
+fn score_key(pos: &Position, mv: Option<&str>) -> String {
+    match mv {
+        Some(mv) => format!("{} {}", pos.canonical_fen(), mv),
+        None => pos.canonical_fen(),
+    }
+}
+
+/// Returns the layout of the output lines written from input lines with the
+/// given layout, as a schema string.
+fn output_columns(schema: &InputSchema) -> String {
+    let mut columns = vec!["fen"];
+
+    if schema.contains(InputColumn::Wdl) {
+        columns.push("wdl");
+    }
+
+    if schema.contains(InputColumn::Move) {
+        columns.push("move");
+    }
+
+    // Blended targets and extra columns come after the score.
+    columns.extend(["eval", "extra*"]);
+    columns.join(",")
+}
+
+/// Returns the settings defining the layout of the output lines, and the
+/// build of the engine they were scored with, which must match between runs
+/// appending to the same file.
+fn output_layout(
+    cli: &ScoreArgs,
+    schema: &InputSchema,
+    engine_hash: Option<&str>,
+) -> serde_json::Value {
+    serde_json::json!({
+        "columns": output_columns(schema),
+        "engine_sha256": engine_hash,
+        "chess960": cli.chess960,
+        "score_format": cli.score_format.to_possible_value().unwrap().get_name(),
+        "multipv": cli.multipv,
+        "wdl_precision": cli.wdl_precision,
+        "blend_lambda": cli.blend_lambda,
+        "tag_engine": cli.tag_engine,
+        "with_pv": cli.with_pv,
+        "with_nodes": cli.with_nodes,
+    })
+}
+
+/// Checks that the lines of an existing output can be appended to with the
+/// given layout, using its manifest if any, or its first line otherwise.
+fn check_appended_output(
+    path: &str,
+    layout: &serde_json::Value,
+    schema: &InputSchema,
+    chess960: bool,
+    multipv: bool,
+) -> std::io::Result<()> {
+    let mismatch = |err: String| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("cannot append to '{}': {}", path, err),
+        )
+    };
+    let manifest_path = format!("{}.manifest.json", path);
+
+    if Path::new(&manifest_path).exists() {
+        let recorded = Manifest::read_layout(Path::new(&manifest_path))?;
+
+        if recorded.is_null() {
+            return Err(mismatch(String::from("its manifest records no layout")));
+        }
+
+        for (key, value) in layout.as_object().unwrap() {
+            if recorded[key] != *value {
+                return Err(mismatch(format!(
+                    "it was written with {} {}, not {}",
+                    key, recorded[key], value
+                )));
+            }
+        }
+
+        return Ok(());
+    }
+
+    let Ok(file) = File::open(path) else {
+        return Ok(());
+    };
+    let Some(line) = BufReader::new(file).lines().next().transpose()? else {
+        return Ok(());
+    };
+    let output_schema: InputSchema = output_columns(schema).parse().unwrap();
+    let record = output_schema
+        .split(&line)
+        .map_err(|err| mismatch(err.to_string()))?;
+
+    Position::from_fen(&record.fen, chess960).map_err(|err| mismatch(err.to_string()))?;
+
+    if let Some(wdl) = record.wdl {
+        parse_wdl(wdl).map_err(mismatch)?;
+    }
+
+    // With --multipv, scores are pairs of moves and scores instead.
+    let eval = record.eval.unwrap();
+
+    if !multipv && eval.parse::<Score>().is_err() {
+        return Err(mismatch(format!("unparsable eval '{}'", eval)));
+    }
+
+    Ok(())
+}
+
+/// Reads the scores of a previous output of the tool, written from input
+/// lines with the given layout.
+fn read_previous_scores(
+    path: &str,
+    schema: &InputSchema,
+    chess960: bool,
+) -> std::io::Result<HashMap<String, Score>> {
+    let output_schema: InputSchema = output_columns(schema).parse().unwrap();
+    let mut scores = HashMap::new();
+
+    for (idx, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let invalid = |err: String| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path, idx + 1, err),
+            )
+        };
+        let record = output_schema
+            .split(&line)
+            .map_err(|err| invalid(err.to_string()))?;
+        let eval = record.eval.unwrap();
+        let score = eval
+            .parse::<Score>()
+            .map_err(|_| invalid(format!("unparsable eval '{}'", eval)))?;
+        let pos =
+            Position::from_fen(&record.fen, chess960).map_err(|err| invalid(err.to_string()))?;
+
+        scores.insert(score_key(&pos, record.mv), score);
+    }
+
+    Ok(scores)
+}
+
+/// Where the score of a position comes from.
+#[derive(Clone, Copy)]
+enum ScoreSource {
+    /// The search of the engine with this number.
+    Engine(usize),
+    Reused,
+    Cache,
+}
+
+/// The name of the machine the tool runs on.
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("localhost"))
+}
+
+/// Makes a name usable as a part of a tag, which is a single column whose
+/// parts are separated by colons.
+fn tag_token(name: &str) -> String {
+    name.replace(|c: char| c.is_whitespace() || c == ':', "_")
+}
+
+/// Parses an environment variable given as 'NAME=VALUE'.
+fn parse_env_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected 'NAME=VALUE', got '{}'", s)),
+    }
+}
+
+fn parse_regex(s: &str) -> Result<String, String> {
+    Regex::new(s)
+        .map(|_| s.to_string())
+        .map_err(|err| err.to_string())
+}
+
+/// Lists the settings which can still make the output of a deterministic run
+/// vary between runs.
+fn nondeterminism_sources(engine: &EngineConfig) -> Vec<String> {
+    let mut sources = Vec::new();
+
+    if engine.profile == EngineProfile::Lc0 {
+        sources.push(String::from(
+            "Lc0 network backends are not guaranteed to return identical results across runs",
+        ));
+    }
+
+    if !engine.clear_env {
+        sources.push(String::from(
+            "the engine inherits the environment of this shell, use --clear-engine-env to isolate it",
+        ));
+    }
+
+    // Strength-limiting options usually pick moves at random, with a seed
+    // depending on the time.
+    for parameter in &engine.options {
+        if let Some((name, _)) = parameter.split_once('=') {
+            if ["Skill Level", "UCI_LimitStrength", "UCI_Elo"]
+                .iter()
+                .any(|option| name.eq_ignore_ascii_case(option))
+            {
+                sources.push(format!("the '{}' option may introduce randomness", name));
+            }
+        }
+    }
+
+    sources
+}
+
+/// Builds the configuration of the scoring engine from the command line, on
+/// top of its registry configuration if the engine is given by its name.
+fn engine_config(cli: &ScoreArgs, registry: &EngineRegistry) -> EngineConfig {
+    // These are only optional when a subcommand is used.
+    let engine_path = cli.engine_path.clone().unwrap();
+    let mut engine = match registry.get(&engine_path) {
+        Some(engine) if !engine_path.contains('/') => engine.clone(),
+        _ => EngineConfig {
+            name: engine_path.clone(),
+            command: engine_path,
+            ..Default::default()
+        },
+    };
+
+    engine.args.extend_from_slice(&cli.engine_arg);
+    engine.working_dir = cli.engine_cwd.clone().or(engine.working_dir);
+    engine.clear_env |= cli.clear_engine_env;
+    engine.env.extend_from_slice(&cli.engine_env);
+    engine.profile = cli.profile.unwrap_or(engine.profile);
+    engine.options.extend_from_slice(&cli.config);
+    engine.sync_options.extend_from_slice(&cli.sync_option);
+    engine.startup_errors.extend_from_slice(&cli.startup_error);
+    engine.canonical_options = !cli.no_canonical_options;
+    engine
+}
+
+fn score(cli: ScoreArgs, registry: &EngineRegistry) -> std::io::Result<()> {
+    let Some(parent) = &cli.workspace else {
+        return run_scoring(cli, registry, None);
+    };
+    let name = cli
+        .output_file
+        .as_deref()
+        .and_then(|path| Path::new(path).file_name())
+        .map_or(String::from("smoke"), |name| {
+            name.to_string_lossy().into_owned()
+        });
+    let workspace = Arc::new(Workspace::create(
+        Path::new(parent),
+        &name,
+        cli.workspace_cleanup,
+    )?);
+
+    eprintln!("Writing the run files to '{}'", workspace.dir().display());
+    workspace.log(&format!(
+        "command line: {}",
+        std::env::args().collect::<Vec<String>>().join(" ")
+    ))?;
+    capture_engine_stderr(Some(workspace.dir()));
+
+    let result = run_scoring(cli, registry, Some(Arc::clone(&workspace)));
+
+    capture_engine_stderr(None);
+
+    if let Err(err) = &result {
+        workspace.log(&format!("error: {}", err))?;
+    }
+
+    workspace.finish(result.is_ok())?;
+    result
+}
+
+/// Scores the position chunks of the input directory as they appear, see
+/// --watch.
+fn watch_chunks(cli: ScoreArgs, registry: &EngineRegistry) -> std::io::Result<()> {
+    let output_dir = PathBuf::from(cli.output_file.as_deref().unwrap());
+    let mut watcher = ChunkWatcher::new(Path::new(cli.input_file.as_deref().unwrap()), "txt");
+
+    reopen_chunk_dir(&output_dir)?;
+
+    while let Some(chunk) = watcher.next_chunk(interrupted)? {
+        let shard = output_dir.join(chunk.file_name().unwrap());
+
+        if shard.exists() {
+            continue;
+        }
+
+        eprintln!("Scoring '{}'", chunk.display());
+
+        let args = ScoreArgs {
+            input_file: Some(chunk.to_string_lossy().into_owned()),
+            output_file: Some(shard.to_string_lossy().into_owned()),
+            ..cli.clone()
+        };
+
+        if let Err(err) = score(args, registry) {
+            let mut tmp_name = shard.clone().into_os_string();
+
+            tmp_name.push(".tmp");
+            let _ = std::fs::remove_file(&shard);
+            let _ = std::fs::remove_file(tmp_name);
+            return Err(err);
+        }
+    }
+
+    if interrupted() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Interrupted,
+            "interrupted while watching for chunks",
+        ));
+    }
+
+    finish_chunks(&output_dir)
+}
+
+/// Searches an audited position again, and records the difference with its
+/// first score.
+fn run_audit(
+    worker: &mut TaskWorker<Workload, ScoredLine>,
+    audit: &Audit,
+    job: &AuditJob,
+    limit: &SearchLimit,
+    throttle: &Throttle,
+) {
+    let moves: Vec<&str> = job.moves.iter().map(String::as_str).collect();
+    let searched_at = Instant::now();
+    let result = worker
+        .engine_mut()
+        .setup_position(&job.fen, &moves)
+        .and_then(|_| worker.engine_mut().run_search(limit));
+
+    match result {
+        Ok(result) => {
+            audit.record(job, result.score);
+
+            if !throttle.is_unbounded() {
+                let idle_time = throttle.idle_time(searched_at.elapsed(), result.nodes);
+
+                idle(idle_time, || worker.is_cancelled());
+            }
+        }
+        Err(_) if worker.is_cancelled() => (),
+        Err(err) => eprintln!("\nWarning: cannot audit '{}': {}", job.fen, err),
+    }
+}
+
+/// Records a rejected input line in the workspace of the run, if any.
+fn reject(workspace: Option<&Workspace>, line: &str, reason: &str) {
+    if let Some(workspace) = workspace {
+        workspace.reject(line, reason).unwrap();
+    }
+}
+
+fn run_scoring(
+    cli: ScoreArgs,
+    registry: &EngineRegistry,
+    workspace: Option<Arc<Workspace>>,
+) -> std::io::Result<()> {
+    let mut manifest = Manifest::new();
+    let mut client: TaskClient<Workload, ScoredLine> = match cli.deterministic {
+        true => TaskClient::ordered(),
+        false => TaskClient::new(),
+    };
+    let mut engine = engine_config(&cli, registry);
+    let mut reader = InputReader::open(cli.input_file.as_deref().unwrap(), cli.mmap)?;
+    let schema = input_schema(&cli)?;
+    let policy = OutputPolicy {
+        buffer_size: cli.output_buffer_kb * 1024,
+        flush_interval: cli.flush_interval.map(Duration::from_secs_f64),
+        fsync_every: cli.fsync_every,
+        compress_threads: cli.compress_threads,
+    };
+
+    // The options given by the user are checked against the declarations of
+    // the first engine started, before the ones of the tool are added.
+    let user_options = engine.options.clone();
+    let check_canonical_options = AtomicBool::new(engine.canonical_options);
+
+    if cli.chess960 {
+        engine.options.insert(0, String::from("UCI_Chess960=true"));
+    }
+
+    if let Some(multipv) = cli.multipv {
+        engine.options.insert(0, format!("MultiPV={}", multipv));
+    }
+
+    if let Some(lambda) = cli.blend_lambda {
+        let error = if !(0.0..=1.0).contains(&lambda) {
+            Some("--blend-lambda must be between 0 and 1")
+        } else if cli.sigmoid_k <= 0.0 {
+            Some("--sigmoid-k must be positive")
+        } else if !schema.contains(InputColumn::Wdl) {
+            Some("--blend-lambda requires a 'wdl' input column")
+        } else {
+            None
+        };
+
+        if let Some(error) = error {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, error));
+        }
+    }
+
+    let throttle = Throttle {
+        duty_cycle: cli.duty_cycle,
+        max_nps: cli.max_nps,
+    };
+    // The node rate is split between the running workers, which --auto-threads
+    // may start or retire.
+    let active_workers = Arc::new(AtomicUsize::new(0));
+
+    if throttle.duty_cycle.is_some_and(|d| !(d > 0.0 && d <= 1.0)) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--duty-cycle must be greater than 0 and at most 1",
+        ));
+    }
+
+    if throttle
+        .max_nps
+        .is_some_and(|nps| nps <= 0.0 || nps.is_nan())
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--max-nps must be positive",
+        ));
+    }
+
+    cli.limit
+        .check_template(false)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+
+    if cli
+        .audit_rate
+        .is_some_and(|rate| !(0.0..=1.0).contains(&rate))
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--audit-rate must be between 0 and 1",
+        ));
+    }
+
+    let heartbeat = match Duration::try_from_secs_f64(cli.heartbeat) {
+        Ok(interval) => Some(interval).filter(|interval| !interval.is_zero()),
+        Err(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--heartbeat must be a positive number of seconds, or 0",
+            ));
+        }
+    };
+
+    if cli.deterministic {
+        let threads_override = engine.options.iter().any(|parameter| {
+            parameter
+                .split_once('=')
+                .is_some_and(|(name, value)| name.eq_ignore_ascii_case("Threads") && value != "1")
+        });
+
+        if threads_override {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--deterministic requires engines to search with a single thread",
+            ));
+        }
+
+        engine.options.insert(0, String::from("Threads=1"));
+
+        for source in nondeterminism_sources(&engine) {
+            eprintln!("Warning: {}", source);
+        }
+    }
+
+    if let Some(count) = cli.smoke {
+        return smoke_test(&cli, &engine, &schema, &mut reader, count);
+    }
+
+    let output_file = cli.output_file.as_deref().unwrap();
+    let engine_binary = engine.binary_path().to_string_lossy().into_owned();
+    let engine_hash = find_executable(&engine_binary)
+        .map(|path| sha256_file(&path))
+        .transpose()?;
+
+    if let Some(pinned) = &cli.engine_sha256 {
+        match &engine_hash {
+            Some(hash) if hash.eq_ignore_ascii_case(pinned) => (),
+            Some(hash) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "the engine binary '{}' has the SHA-256 hash {}, not the pinned {}",
+                        engine_binary, hash, pinned
+                    ),
+                ));
+            }
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("cannot find the engine binary '{}'", engine_binary),
+                ));
+            }
+        }
+    }
+
+    let layout = output_layout(&cli, &schema, engine_hash.as_deref());
+    let mut ofile = match cli.append {
+        true => {
+            check_appended_output(
+                output_file,
+                &layout,
+                &schema,
+                cli.chess960,
+                cli.multipv.is_some(),
+            )?;
+            OutputFile::append(output_file, policy)?
+        }
+        false => OutputFile::create(output_file, policy)?,
+    };
+
+    manifest.set_layout(layout);
+
     let mut thread_list = Vec::new();
+    let previous_scores = match &cli.reuse_scores {
+        Some(_) if cli.multipv.is_some() => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--reuse-scores cannot be used with --multipv",
+            ));
+        }
+        Some(path) => read_previous_scores(path, &schema, cli.chess960)?,
+        None => HashMap::new(),
+    };
+    let previous_scores = Arc::new(previous_scores);
+    let reused = Arc::new(AtomicUsize::new(0));
+    let illegal_pvs = Arc::new(AtomicUsize::new(0));
+    let cache = match &cli.score_cache {
+        Some(_) if cli.multipv.is_some() => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--score-cache cannot be used with --multipv",
+            ));
+        }
+        Some(path) => {
+            let binary = find_executable(&engine_binary).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("cannot find the engine binary '{}'", engine_binary),
+                )
+            })?;
+            let context = cache_context(&binary, &engine.options, &cli.limit)?;
+
+            Some(Arc::new(ScoreCache::open(path, &context)?))
+        }
+        None => None,
+    };
+    let cache_hits = Arc::new(AtomicUsize::new(0));
+    let reference = match &cli.reference {
+        Some(_) if cli.multipv.is_some() => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--reference cannot be used with --multipv",
+            ));
+        }
+        Some(path) => {
+            let scores = read_previous_scores(path, &schema, cli.chess960)?;
+            let disagreements = match &cli.disagreements_file {
+                Some(path) => path.clone(),
+                None => format!("{}.disagreements", output_file),
+            };
 
+            Some(Arc::new(ReferenceDiff::new(
+                scores,
+                cli.disagreement_threshold,
+                cli.score_format,
+                Box::new(File::create(disagreements)?),
+            )))
+        }
+        None => None,
+    };
+    let dedup = match (cli.dedup_output, &cli.dedup_file) {
+        (true, Some(path)) => Some(Arc::new(OutputDedup::open(path)?)),
+        (true, None) => Some(Arc::new(OutputDedup::new())),
+        (false, _) => None,
+    };
+    let mut duplicates: usize = 0;
     let mut queries: usize = 0;
     let mut responses: usize = 0;
     let start = Instant::now();
+    let queue = Arc::clone(client.queue_ref());
+    // The host and the engine configuration, completed by the engine number.
+    let tag_prefix = cli
+        .tag_engine
+        .then(|| format!("{}:{}", tag_token(&hostname()), tag_token(&engine.name)));
+    let audit = cli.audit_rate.map(|rate| Arc::new(Audit::new(rate)));
+    let spawn_worker = || -> std::io::Result<ScoringThread> {
+        let mut worker = TaskWorker::try_new(&queue, &engine)?;
+
+        if check_canonical_options.swap(false, Ordering::Relaxed) {
+            for parameter in worker.engine_mut().canonical_conflicts(&user_options) {
+                eprintln!(
+                    "Warning: '{}' overrides the neutral value of this option, scores may be \
+                     inconsistent",
+                    parameter
+                );
+            }
+        }
+
+        worker.set_heartbeat(heartbeat);
 
-    for _ in 0..cli.threads {
-        let mut worker = TaskWorker::new(client.queue_ref(), cli.engine_path.as_str(), &cli.config);
         let limit = cli.limit.clone();
+        let min_depth = cli.min_depth;
+        let shallow_retries = cli.shallow_retries;
+        let score_format = cli.score_format;
+        let schema = schema.clone();
+        let chess960 = cli.chess960;
+        let has_wdl = schema.contains(InputColumn::Wdl);
+        let wdl_precision = cli.wdl_precision;
+        let blend_lambda = cli.blend_lambda;
+        let wdl_model = WdlModel::new(cli.wdl_model, cli.sigmoid_k);
+        let multipv = cli.multipv.is_some();
+        let previous_scores = Arc::clone(&previous_scores);
+        let reused = Arc::clone(&reused);
+        let with_pv = cli.with_pv;
+        let with_nodes = cli.with_nodes;
+        let illegal_pvs = Arc::clone(&illegal_pvs);
+        let cache = cache.clone();
+        let cache_hits = Arc::clone(&cache_hits);
+        let dedup = dedup.clone();
+        let workspace = workspace.clone();
+        let tag_prefix = tag_prefix.clone();
+        let audit = audit.clone();
+        let reference = reference.clone();
+
+        let active_workers = Arc::clone(&active_workers);
+        let retired = Arc::new(AtomicBool::new(false));
+        let retire = Arc::clone(&retired);
+
+        active_workers.fetch_add(1, Ordering::Relaxed);
+
+        let thread = thread::spawn(move || -> std::io::Result<()> {
+            while !retired.load(Ordering::Relaxed) {
+                if let Some(audit) = &audit {
+                    let engine_id = worker.engine_mut().id();
 
-        thread_list.push(thread::spawn(move || {
-            while let Some(workload) = worker.query_workload() {
-                let last_space_idx = workload.rfind(' ').unwrap();
-                let (fen, value) = workload.split_at(last_space_idx);
-                let value = value.trim().parse::<f32>().unwrap();
+                    if let Some(job) = audit.take_for(engine_id, false) {
+                        let throttle = throttle.shared_by(active_workers.load(Ordering::Relaxed));
 
-                worker.engine_mut().setup_position(fen).unwrap();
+                        run_audit(&mut worker, audit, &job, &limit, &throttle);
+                        continue;
+                    }
+                }
 
-                let score = worker.engine_mut().run_search(&limit).unwrap();
-                let scored_fen = format!("{} {} {}\n", fen, value, score);
-                worker.fill_response(scored_fen);
+                let Some(workload) = worker.query_workload()? else {
+                    break;
+                };
+                let record = match schema.split(&workload) {
+                    Ok(record) => record,
+                    Err(err) => {
+                        eprintln!("\nSkipping line '{}': {}", workload.trim(), err);
+                        reject(workspace.as_deref(), &workload, &err.to_string());
+                        worker.skip_workload(&workload);
+                        continue;
+                    }
+                };
+                let value = match record.wdl.map(parse_wdl).transpose() {
+                    Ok(value) => value,
+                    Err(err) => {
+                        eprintln!("\nSkipping line '{}': {}", workload.trim(), err);
+                        reject(workspace.as_deref(), &workload, &err);
+                        worker.skip_workload(&workload);
+                        continue;
+                    }
+                };
+                let moves: Vec<&str> = record.mv.into_iter().collect();
+
+                let pos = match Position::from_fen(&record.fen, chess960) {
+                    Ok(pos) => pos,
+                    Err(err) => {
+                        eprintln!("\nSkipping invalid FEN '{}': {}", record.fen, err);
+                        reject(workspace.as_deref(), &workload, &err.to_string());
+                        worker.skip_workload(&workload);
+                        continue;
+                    }
+                };
+
+                let key = dedup.as_ref().map(|_| OutputDedup::key(&pos, record.mv));
+
+                // Positions already written are not searched again.
+                if dedup
+                    .as_ref()
+                    .zip(key)
+                    .is_some_and(|(dedup, key)| dedup.contains(key))
+                {
+                    worker.skip_workload(&workload);
+                    continue;
+                }
+
+                let previous_score = previous_scores.get(&score_key(&pos, record.mv)).copied();
+                let cached_score = cache.as_ref().and_then(|cache| cache.get(&pos, record.mv));
+                let (score, root_moves, pv, nodes, source) = match (previous_score, cached_score) {
+                    (Some(score), _) => {
+                        reused.fetch_add(1, Ordering::Relaxed);
+                        (score, Vec::new(), Vec::new(), None, ScoreSource::Reused)
+                    }
+                    (None, Some(score)) => {
+                        cache_hits.fetch_add(1, Ordering::Relaxed);
+                        (score, Vec::new(), Vec::new(), None, ScoreSource::Cache)
+                    }
+                    (None, None) => {
+                        let searched_at = Instant::now();
+                        let mut limit = limit.clone();
+                        let mut result = worker
+                            .engine_mut()
+                            .setup_position(&record.fen, &moves)
+                            .and_then(|_| worker.engine_mut().run_search(&limit));
+                        let mut retries = 0;
+                        let is_shallow = |result: &SearchResult| {
+                            min_depth.is_some_and(|min| result.depth.is_none_or(|d| d < min))
+                        };
+
+                        while result.as_ref().is_ok_and(is_shallow) && retries < shallow_retries {
+                            limit.nodes = limit.nodes.map(|nodes| nodes.saturating_mul(2));
+                            limit.depth = limit
+                                .depth
+                                .zip(min_depth)
+                                .map(|(depth, min)| depth.saturating_add(2).max(min));
+                            retries += 1;
+                            result = worker
+                                .engine_mut()
+                                .setup_position(&record.fen, &moves)
+                                .and_then(|_| worker.engine_mut().run_search(&limit));
+                        }
+
+                        // Stopped searches may not even report a score.
+                        if worker.is_cancelled() {
+                            break;
+                        }
+
+                        let result = result?;
+
+                        if is_shallow(&result) {
+                            let reason = format!(
+                                "the search stopped at depth {}",
+                                result.depth.map_or(String::from("?"), |d| d.to_string())
+                            );
+
+                            eprintln!("\nSkipping '{}': {}", record.fen, reason);
+                            reject(workspace.as_deref(), &workload, &reason);
+                            worker.skip_workload(&workload);
+                            continue;
+                        }
+
+                        if with_pv {
+                            // The PV is played after the move of the input line, if any.
+                            let line: Vec<&str> = moves
+                                .iter()
+                                .copied()
+                                .chain(result.pv.iter().map(String::as_str))
+                                .collect();
+
+                            if let Err(err) = pos.check_uci_line(&line) {
+                                let reason = format!("the engine returned an illegal PV, {}", err);
+
+                                eprintln!("\nSkipping '{}': {}", record.fen, reason);
+                                reject(workspace.as_deref(), &workload, &reason);
+                                illegal_pvs.fetch_add(1, Ordering::Relaxed);
+                                worker.skip_workload(&workload);
+                                continue;
+                            }
+                        }
+
+                        let score = match record.mv {
+                            Some(_) => result.score.parent(),
+                            None => result.score,
+                        };
+
+                        if let Some(cache) = &cache {
+                            cache.insert(&pos, record.mv, score)?;
+                        }
+
+                        if !throttle.is_unbounded() {
+                            let idle_time = throttle
+                                .shared_by(active_workers.load(Ordering::Relaxed))
+                                .idle_time(searched_at.elapsed(), result.nodes);
+
+                            idle(idle_time, || worker.is_cancelled());
+                        }
+
+                        let engine_id = worker.engine_mut().id();
+
+                        if let Some(audit) = audit
+                            .as_ref()
+                            .filter(|audit| audit.is_sampled(workload.index()))
+                        {
+                            audit.push(AuditJob {
+                                fen: record.fen.to_string(),
+                                moves: moves.iter().map(|mv| mv.to_string()).collect(),
+                                score: result.score,
+                                engine: engine_id,
+                            });
+                        }
+
+                        (
+                            score,
+                            result.root_moves,
+                            result.pv,
+                            result.nodes,
+                            ScoreSource::Engine(engine_id),
+                        )
+                    }
+                };
+
+                if let Some(reference) = &reference {
+                    let position = match record.mv {
+                        Some(mv) => format!("{} {}", record.fen, mv),
+                        None => record.fen.to_string(),
+                    };
+
+                    reference.compare(&score_key(&pos, record.mv), &position, score)?;
+                }
+
+                let mut scored_fen = record.fen.clone();
+
+                match (value, wdl_precision) {
+                    (Some(value), Some(precision)) => {
+                        scored_fen.push_str(&format!(" {:.*}", precision, value))
+                    }
+                    (Some(value), None) => scored_fen.push_str(&format!(" {}", value)),
+                    // EPD records without a result keep their WDL column.
+                    (None, _) if has_wdl => scored_fen.push_str(" -"),
+                    (None, _) => (),
+                }
+
+                if let Some(mv) = record.mv {
+                    scored_fen.push_str(&format!(" {}", mv));
+                }
+
+                if multipv {
+                    for root_move in &root_moves {
+                        let score = match record.mv {
+                            Some(_) => root_move.score.parent(),
+                            None => root_move.score,
+                        };
+
+                        scored_fen.push_str(&format!(
+                            " {}:{}",
+                            root_move.mv,
+                            score.display(score_format)
+                        ));
+                    }
+                } else {
+                    scored_fen.push_str(&format!(" {}", score.display(score_format)));
+                }
+
+                if let (Some(lambda), Some(value)) = (blend_lambda, value) {
+                    // The score is given from the point of view of the side to
+                    // move in the FEN, even for move scores.
+                    let expected = match pos.side_to_move() {
+                        Color::White => wdl_model.expected_result(score, &pos),
+                        Color::Black => 1.0 - wdl_model.expected_result(score, &pos),
+                    };
+                    let target = lambda * expected + (1.0 - lambda) * value;
+
+                    match wdl_precision {
+                        Some(precision) => {
+                            scored_fen.push_str(&format!(" {:.*}", precision, target))
+                        }
+                        None => scored_fen.push_str(&format!(" {}", target)),
+                    }
+                }
+
+                if let Some(prefix) = &tag_prefix {
+                    match source {
+                        ScoreSource::Engine(id) => {
+                            scored_fen.push_str(&format!(" {}:{}", prefix, id))
+                        }
+                        ScoreSource::Reused => scored_fen.push_str(&format!(" {}:reused", prefix)),
+                        ScoreSource::Cache => scored_fen.push_str(&format!(" {}:cache", prefix)),
+                    }
+                }
+
+                if with_pv {
+                    match pv.is_empty() {
+                        true => scored_fen.push_str(" -"),
+                        false => scored_fen.push_str(&format!(" {}", pv.join(","))),
+                    }
+                }
+
+                if with_nodes {
+                    match nodes {
+                        Some(nodes) => scored_fen.push_str(&format!(" {}", nodes)),
+                        None => scored_fen.push_str(" -"),
+                    }
+                }
+
+                for extra in record.extras {
+                    scored_fen.push(' ');
+                    scored_fen.push_str(extra);
+                }
+
+                scored_fen.push('\n');
+                worker.events().emit(|| Event::PositionScored {
+                    index: workload.index(),
+                    fen: record.fen.to_string(),
+                    score,
+                });
+                worker.fill_response(&workload, (key, scored_fen));
             }
 
-            worker.remove_worker();
-        }));
+            // Once the input is exhausted, the positions left to audit are
+            // searched by the remaining engines, even their own ones.
+            if let Some(audit) = audit.as_ref().filter(|_| !retired.load(Ordering::Relaxed)) {
+                while let Some(job) = audit.take_for(0, true) {
+                    if worker.is_cancelled() {
+                        break;
+                    }
+
+                    let throttle = throttle.shared_by(active_workers.load(Ordering::Relaxed));
+
+                    run_audit(&mut worker, audit, &job, &limit, &throttle);
+                }
+            }
+
+            Ok(())
+        });
+
+        Ok((retire, thread))
+    };
+    let mut scaler = cli
+        .auto_threads
+        .then(|| ThreadScaler::new(cli.threads, AUTO_THREADS_WINDOW, Instant::now()));
+
+    let memory_guard = cli.max_rss_gb.map(|gb| MemoryGuard::new((gb * GIB) as u64));
+    let memory_warned = Cell::new(false);
+    let over_memory = || {
+        let Some(guard) = &memory_guard else {
+            return false;
+        };
+        let over = guard.exceeded();
+
+        if over && !memory_warned.replace(true) {
+            eprintln!(
+                "\nWarning: the tool and its engines use {:.2} GB of memory, more than --max-rss-gb: \
+                 no more engines are started, and the input is only read as the queue empties",
+                guard.rss().unwrap_or(0) as f64 / GIB
+            );
+        }
+
+        over
+    };
+
+    for i in 0..scaler.as_ref().map_or(cli.threads, ThreadScaler::workers) {
+        if i > 0 && over_memory() {
+            break;
+        }
+
+        thread_list.push(spawn_worker()?);
     }
 
-    loop {
-        let mut buf = String::new();
-        let read_size = reader.read_line(&mut buf)?;
+    let mut scale_workers = |responses: usize| -> std::io::Result<()> {
+        let Some(active) = &mut scaler else {
+            return Ok(());
+        };
+
+        match active.record(responses, Instant::now(), memory_low() || over_memory()) {
+            Scaling::Hold => (),
+            Scaling::Grow => {
+                thread_list.push(spawn_worker()?);
+                active.start_window(Instant::now());
+            }
+            // The last worker stops after its current search.
+            Scaling::Shrink => {
+                thread_list[active.workers()]
+                    .0
+                    .store(true, Ordering::Relaxed);
+                active_workers.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        if let Some(throughput) = active.settled_throughput() {
+            let workers = active.workers();
+
+            println!(
+                "\nSettled on {} engine instances, {:.1} positions per second",
+                workers, throughput
+            );
+            queue.events().emit(|| Event::WorkersSettled {
+                workers,
+                throughput,
+            });
+            // The count of workers no longer changes.
+            scaler = None;
+        }
+
+        Ok(())
+    };
+
+    #[cfg(unix)]
+    watch_interrupts(client.queue_ref().cancellation_token().clone());
 
-        if read_size == 0 {
+    loop {
+        if client.queue_ref().is_cancelled() {
             break;
         }
 
-        client.add_workload(buf);
-        queries += 1;
+        // Under memory pressure, more of the input is only read once most of
+        // the queued positions are scored.
+        let throttled = !client.queue_ref().no_active_workers()
+            && client.queue_ref().queued_workloads() > THROTTLED_QUEUE_SIZE
+            && over_memory();
 
-        if let Some(scored_fen) = client.query_response(false) {
-            ofile.write_all(scored_fen.as_bytes())?;
-            responses += 1;
+        if throttled {
+            thread::sleep(Duration::from_millis(10));
+        } else {
+            let Some(chunk) = reader.next_chunk(INPUT_CHUNK_SIZE)? else {
+                break;
+            };
+
+            queries += client.add_workloads(Workload::split_lines(chunk));
+        }
 
-            if responses % cli.report_every == 0 {
-                let elapsed = start.elapsed().as_secs_f32();
-                let ett = elapsed / (responses as f32) * (queries as f32);
-                let eta = ett - elapsed;
+        while let Some((key, scored_fen)) = client.query_response(false) {
+            // Duplicates searched concurrently are only caught once scored.
+            if let (Some(dedup), Some(key)) = (&dedup, key) {
+                if !dedup.insert(key)? {
+                    duplicates += 1;
+                    continue;
+                }
+            }
 
-                print!(
-                    "\r{}/{} queries done, {:.3} seconds elapsed, ETA {:.3} seconds    ",
-                    responses, queries, elapsed, eta
-                );
-                stdout().flush()?;
+            ofile.write_line(&scored_fen)?;
+            responses += 1;
+            scale_workers(1)?;
+
+            if responses.is_multiple_of(cli.report_every) {
+                report_progress(
+                    client.queue_ref().events(),
+                    responses,
+                    queries,
+                    reader.progress(),
+                    start,
+                )?;
             }
         }
     }
 
     client.stop_workload();
 
-    while let Some(scored_fen) = client.query_response(true) {
-        ofile.write_all(scored_fen.as_bytes())?;
-        responses += 1;
+    while let Some((key, scored_fen)) = client.query_response(true) {
+        // Duplicates searched concurrently are only caught once scored.
+        if let (Some(dedup), Some(key)) = (&dedup, key) {
+            if !dedup.insert(key)? {
+                duplicates += 1;
+                continue;
+            }
+        }
 
-        if responses % cli.report_every == 0 {
-            let elapsed = start.elapsed().as_secs_f32();
-            let ett = elapsed / (responses as f32) * (queries as f32);
-            let eta = ett - elapsed;
+        ofile.write_line(&scored_fen)?;
+        responses += 1;
+        scale_workers(1)?;
 
-            print!(
-                "\r{}/{} queries done, {:.3} seconds elapsed, ETA {:.3} seconds    ",
-                responses, queries, elapsed, eta
-            );
-            stdout().flush()?;
+        if responses.is_multiple_of(cli.report_every) {
+            report_progress(
+                client.queue_ref().events(),
+                responses,
+                queries,
+                reader.progress(),
+                start,
+            )?;
         }
     }
 
     println!();
 
-    for thread in thread_list {
-        thread.join().unwrap();
+    if let Some(workspace) = &workspace {
+        workspace.log(&format!(
+            "{} positions written out of {} read",
+            responses, queries
+        ))?;
+    }
+
+    if let Some(path) = &cli.reuse_scores {
+        println!(
+            "{} positions reused from {}",
+            reused.load(Ordering::Relaxed),
+            path
+        );
+    }
+
+    if cli.with_pv {
+        println!(
+            "{} positions skipped for illegal PVs",
+            illegal_pvs.load(Ordering::Relaxed)
+        );
+    }
+
+    if let Some(path) = &cli.score_cache {
+        println!(
+            "{} positions found in {}",
+            cache_hits.load(Ordering::Relaxed),
+            path
+        );
+    }
+
+    if dedup.is_some() {
+        println!("{} duplicate positions dropped", duplicates);
+    }
+
+    for (_, thread) in thread_list {
+        match thread.join() {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => {
+                return Err(std::io::Error::new(
+                    err.kind(),
+                    format!(
+                        "a scoring thread failed, the output was left incomplete: {}",
+                        err
+                    ),
+                ));
+            }
+            Err(_) => {
+                return Err(std::io::Error::other(
+                    "a scoring thread failed, the output was left incomplete",
+                ));
+            }
+        }
+    }
+
+    if let (Some(reference), Some(path)) = (&reference, &cli.reference) {
+        let stats = reference.stats();
+
+        println!("Compared with {}: {}", path, stats);
+
+        if let Some(workspace) = &workspace {
+            workspace.log(&format!("reference: {}", stats))?;
+        }
+    }
+
+    if let Some(audit) = &audit {
+        let report = audit.report();
+
+        println!("Audit: {}", report);
+
+        if let Some(workspace) = &workspace {
+            workspace.log(&format!("audit: {}", report))?;
+        }
+    }
+
+    if let Some(cache) = &cache {
+        cache.flush()?;
+    }
+
+    if let Some(dedup) = &dedup {
+        dedup.flush()?;
+    }
+
+    // An interrupted or incomplete output is never moved to its final name,
+    // so that it cannot be mistaken for a complete one.
+    if client.queue_ref().is_cancelled() {
+        let path = ofile.finish_incomplete()?;
+
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Interrupted,
+            format!(
+                "interrupted, the positions scored so far are kept in '{}'",
+                path.display()
+            ),
+        ));
+    }
+
+    if client.answered_workloads() < queries {
+        let path = ofile.finish_incomplete()?;
+
+        return Err(std::io::Error::other(format!(
+            "only {} of {} positions were processed, the positions scored so far are kept \
+             in '{}'",
+            client.answered_workloads(),
+            queries,
+            path.display()
+        )));
+    }
+
+    ofile.finish()?;
+
+    if !cli.no_manifest {
+        write_manifest(manifest, &cli, &engine_binary, workspace.as_deref())?;
     }
 
     Ok(())
 }
+
+/// Returns the layout of the input lines, as given on the command line or
+/// detected from the first lines of the input file.
+fn input_schema(cli: &ScoreArgs) -> std::io::Result<InputSchema> {
+    if let Some(schema) = &cli.input_columns {
+        return Ok(schema.clone());
+    }
+
+    if let Some(format) = cli.input_format {
+        return Ok(format.schema());
+    }
+
+    let mut reader = InputReader::open(cli.input_file.as_deref().unwrap(), false)?;
+    let mut lines = Vec::new();
+
+    while lines.len() < SNIFFED_LINES {
+        let Some(line) = reader.next_line()? else {
+            break;
+        };
+
+        lines.push(String::from_utf8_lossy(line).into_owned());
+    }
+
+    let format =
+        InputFormat::sniff(lines.iter().map(String::as_str), cli.chess960).map_err(|err| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{}, use --input-format or --input-columns", err),
+            )
+        })?;
+
+    eprintln!("Detected the input format '{}'", format.name());
+    Ok(format.schema())
+}
+
+/// Scores the first positions of the input with a single engine, printing
+/// the details of their searches instead of writing an output file.
+fn smoke_test(
+    cli: &ScoreArgs,
+    engine: &EngineConfig,
+    schema: &InputSchema,
+    reader: &mut InputReader,
+    count: usize,
+) -> std::io::Result<()> {
+    let mut engine = engine.start()?;
+    let mut scored = 0;
+
+    while scored < count {
+        let Some(line) = reader.next_line()? else {
+            break;
+        };
+        let line = String::from_utf8_lossy(line).trim_end().to_string();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let record = match schema.split(&line) {
+            Ok(record) => record,
+            Err(err) => {
+                println!("Invalid line '{}': {}", line, err);
+                continue;
+            }
+        };
+
+        if let Some(Err(err)) = record.wdl.map(parse_wdl) {
+            println!("Invalid line '{}': {}", line, err);
+            continue;
+        }
+
+        if let Err(err) = Position::from_fen(&record.fen, cli.chess960) {
+            println!("Invalid FEN '{}': {}", record.fen, err);
+            continue;
+        }
+
+        let moves: Vec<&str> = record.mv.into_iter().collect();
+
+        engine.setup_position(&record.fen, &moves)?;
+
+        let result = engine.run_search(&cli.limit)?;
+        let score = match record.mv {
+            Some(_) => result.score.parent(),
+            None => result.score,
+        };
+        let field = |value: Option<String>| value.unwrap_or_else(|| String::from("?"));
+
+        println!("{}", line);
+        println!(
+            "    score {} depth {} nodes {} bestmove {}",
+            score.display(cli.score_format),
+            field(result.depth.map(|depth| depth.to_string())),
+            field(result.nodes.map(|nodes| nodes.to_string())),
+            result.best_move
+        );
+
+        for root_move in result.root_moves.iter().skip(1) {
+            println!(
+                "    alternative {} score {}",
+                root_move.mv,
+                root_move.score.display(cli.score_format)
+            );
+        }
+
+        scored += 1;
+    }
+
+    println!("{} positions scored", scored);
+    Ok(())
+}
+
+/// Prints the progress of a scoring run. Queries are only counted as the
+/// input is read, so the share of the whole input done is estimated from the
+/// share of the file read so far and the share of the read queries answered:
+/// this keeps the ETA meaningful from the first report on.
+fn report_progress(
+    events: &EventBus,
+    responses: usize,
+    queries: usize,
+    input_progress: f64,
+    start: Instant,
+) -> std::io::Result<()> {
+    events.emit(|| Event::Progress {
+        done: responses,
+        queued: queries,
+        input_progress,
+    });
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let done = input_progress * responses as f64 / queries as f64;
+    let eta = elapsed / done - elapsed;
+
+    print!(
+        "\r{}/{} queries done ({:.1}% of the input), {:.3} seconds elapsed, ETA {:.3} seconds    ",
+        responses,
+        queries,
+        100.0 * done,
+        elapsed,
+        eta
+    );
+    stdout().flush()
+}
+
+/// Writes the manifest of a finished scoring run next to its output file.
+fn write_manifest(
+    mut manifest: Manifest,
+    cli: &ScoreArgs,
+    engine_path: &str,
+    workspace: Option<&Workspace>,
+) -> std::io::Result<()> {
+    let output_file = cli.output_file.as_deref().unwrap();
+    let engine = find_executable(engine_path).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("cannot find the engine binary '{}'", engine_path),
+        )
+    })?;
+
+    manifest.add_file("engine", &engine)?;
+    manifest.add_file("input", Path::new(cli.input_file.as_deref().unwrap()))?;
+    manifest.add_file("output", Path::new(output_file))?;
+
+    if let Some(path) = &cli.reuse_scores {
+        manifest.add_file("reused_scores", Path::new(path))?;
+    }
+
+    if let Some(workspace) = workspace {
+        manifest.write(&workspace.file("manifest.json"))?;
+    }
+
+    manifest.write(Path::new(&format!("{}.manifest.json", output_file)))
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::io;
+
+use clap::Args;
+
+use stash_scoring::board::Color;
+use stash_scoring::pgn::{PgnGame, PgnReader};
+use stash_scoring::polyglot::{polyglot_key, polyglot_move, write_book, BookEntry};
+use stash_scoring::wdl::{WdlModel, WdlModelKind};
+
+/// Aggregates the opening moves of finished games into a Polyglot book. Each
+/// move is weighted by the number of games it was played in, times its
+/// average outcome for the side playing it: the game result, blended with
+/// the expected result of its '[%eval ...]' annotation when present. The
+/// weights are scaled so that the best move of each position gets the
+/// maximal weight, and moves whose weight rounds to zero are dropped.
+#[derive(Args)]
+pub struct MakeBookArgs {
+    /// A PGN file to read games from, e.g. written by the match command with
+    /// evaluation comments. You can use this flag as many times as you need.
+    #[arg(short, long, required = true)]
+    input_file: Vec<String>,
+
+    /// The Polyglot book to write.
+    #[arg(short, long)]
+    output_file: String,
+
+    /// The number of plies of each game added to the book.
+    #[arg(long, default_value_t = 16)]
+    max_plies: usize,
+
+    /// The minimal number of games a move must have been played in to be
+    /// added to the book.
+    #[arg(long, default_value_t = 2)]
+    min_games: u32,
+
+    /// The share of the annotated evaluation in the outcome of a move,
+    /// between 0 (results only) and 1 (evaluations only).
+    #[arg(long, default_value_t = 0.5)]
+    eval_weight: f64,
+
+    /// The model used to convert evaluations into expected results.
+    #[arg(long, value_enum, default_value_t = WdlModelKind::Logistic)]
+    wdl_model: WdlModelKind,
+
+    /// The scale of the logistic WDL model, in centipawns.
+    #[arg(long, default_value_t = 400.0)]
+    sigmoid_k: f64,
+}
+
+/// The games a move of a position was played in.
+#[derive(Default)]
+struct MoveStats {
+    games: u32,
+    /// The sum of the outcomes of the games for the side playing the move.
+    outcome: f64,
+}
+
+/// Replays the opening of a game, adding its moves to the book statistics.
+fn add_game(
+    game: &PgnGame,
+    result: f64,
+    args: &MakeBookArgs,
+    model: &WdlModel,
+    stats: &mut HashMap<(u64, u16), MoveStats>,
+) -> Result<(), String> {
+    let mut pos = game.start_position().map_err(|err| err.to_string())?;
+
+    for (san, eval) in game.moves.iter().zip(&game.evals).take(args.max_plies) {
+        let mv = pos.parse_san(san).map_err(|err| err.to_string())?;
+        let key = polyglot_key(&pos);
+        let us = pos.side_to_move();
+        let result = match us {
+            Color::White => result,
+            Color::Black => 1.0 - result,
+        };
+
+        pos.play(mv);
+
+        // Annotations are from White's point of view, and the expected
+        // result is the one of the side to move.
+        let outcome = match eval {
+            Some(eval) => {
+                let eval = match us {
+                    Color::White => eval.flipped(),
+                    Color::Black => *eval,
+                };
+                let expected = 1.0 - model.expected_result(eval, &pos);
+
+                args.eval_weight * expected + (1.0 - args.eval_weight) * result
+            }
+            None => result,
+        };
+        let entry = stats.entry((key, polyglot_move(mv))).or_default();
+
+        entry.games += 1;
+        entry.outcome += outcome;
+    }
+
+    Ok(())
+}
+
+/// Turns the move statistics into book entries, scaling the weights of each
+/// position.
+fn book_entries(stats: &HashMap<(u64, u16), MoveStats>, min_games: u32) -> Vec<BookEntry> {
+    let mut best: HashMap<u64, f64> = HashMap::new();
+
+    for (&(key, _), move_stats) in stats {
+        if move_stats.games >= min_games {
+            let best = best.entry(key).or_default();
+
+            *best = best.max(move_stats.outcome);
+        }
+    }
+
+    stats
+        .iter()
+        .filter(|(_, move_stats)| move_stats.games >= min_games)
+        .filter_map(|(&(key, mv), move_stats)| {
+            let best = best[&key];
+            let weight = match best > 0.0 {
+                true => (move_stats.outcome / best * f64::from(u16::MAX)).round() as u16,
+                false => 0,
+            };
+
+            (weight > 0).then_some(BookEntry { key, mv, weight })
+        })
+        .collect()
+}
+
+pub fn run(args: &MakeBookArgs) -> io::Result<()> {
+    if !(0.0..=1.0).contains(&args.eval_weight) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the evaluation weight must be between 0 and 1",
+        ));
+    }
+
+    let model = WdlModel::new(args.wdl_model, args.sigmoid_k);
+    let mut stats = HashMap::new();
+    let mut games = 0;
+    let mut invalid = 0;
+    let mut unfinished = 0;
+
+    for path in &args.input_file {
+        let mut reader = PgnReader::open(path)?;
+
+        while let Some(game) = reader.next_game()? {
+            games += 1;
+
+            let game = match game {
+                Ok(game) => game,
+                Err(err) => {
+                    eprintln!("Skipping game {}: {}", games, err);
+                    invalid += 1;
+                    continue;
+                }
+            };
+            let Some(result) = game.wdl().map(|wdl| wdl.parse::<f64>().unwrap()) else {
+                unfinished += 1;
+                continue;
+            };
+
+            if let Err(err) = add_game(&game, result, args, &model, &mut stats) {
+                eprintln!("Skipping game {}: {}", games, err);
+                invalid += 1;
+            }
+        }
+    }
+
+    let mut entries = book_entries(&stats, args.min_games);
+    let mut positions: Vec<u64> = entries.iter().map(|entry| entry.key).collect();
+
+    positions.sort_unstable();
+    positions.dedup();
+    write_book(&args.output_file, &mut entries)?;
+
+    println!(
+        "{} games read, {} invalid, {} unfinished, {} moves written for {} positions",
+        games,
+        invalid,
+        unfinished,
+        entries.len(),
+        positions.len()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+/// The `git describe` output of the sources the tool was built from.
+pub const GIT_DESCRIBE: &str = env!("STASH_TOOLS_GIT_DESCRIBE");
+
+/// Returns the SHA-256 hash of a file, in hexadecimal.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+
+    loop {
+        match file.read(&mut buf)? {
+            0 => break,
+            len => hasher.update(&buf[..len]),
+        }
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Finds the executable file started by a command, looking it up in the
+/// PATH directories when it is a bare name.
+pub fn find_executable(command: &str) -> Option<PathBuf> {
+    if command.contains('/') {
+        return Some(PathBuf::from(command)).filter(|path| path.is_file());
+    }
+
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(command))
+        .find(|path| path.is_file())
+}
+
+/// Formats a time as an RFC 3339 UTC timestamp, e.g. 2024-05-01T12:30:00Z.
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Converts the day count into a civil date, following the civil_from_days
+    // algorithm of Howard Hinnant, with years starting in March.
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// The provenance of a generated dataset: the command line and version of
+/// the tool, the hashes of the files it used, and the time of the run. It is
+/// written as a JSON file next to the dataset.
+pub struct Manifest {
+    started_at: SystemTime,
+    files: Map<String, Value>,
+    layout: Value,
+    settings: Value,
+}
+
+impl Manifest {
+    /// Starts the manifest of a run beginning now.
+    pub fn new() -> Self {
+        Self {
+            started_at: SystemTime::now(),
+            files: Map::new(),
+            layout: Value::Null,
+            settings: Value::Null,
+        }
+    }
+
+    /// Records the settings which define the layout of the dataset lines,
+    /// which must be the same for runs appending to the same dataset.
+    pub fn set_layout(&mut self, layout: Value) {
+        self.layout = layout;
+    }
+
+    /// Records settings of the run which are worth keeping along with its
+    /// output, such as how the games of a match were played.
+    pub fn set_settings(&mut self, settings: Value) {
+        self.settings = settings;
+    }
+
+    /// Reads the layout recorded in an existing manifest.
+    pub fn read_layout(path: &Path) -> io::Result<Value> {
+        let manifest: Value = serde_json::from_reader(File::open(path)?)?;
+
+        Ok(manifest["output_layout"].clone())
+    }
+
+    /// Records the path and the hash of a file used or produced by the run.
+    pub fn add_file(&mut self, role: &str, path: &Path) -> io::Result<()> {
+        self.files.insert(
+            role.to_string(),
+            json!({
+                "path": path.to_string_lossy(),
+                "sha256": sha256_file(path)?,
+            }),
+        );
+
+        Ok(())
+    }
+
+    /// Writes the manifest of the finished run.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut manifest = json!({
+            "tool": {
+                "version": env!("CARGO_PKG_VERSION"),
+                "git_describe": GIT_DESCRIBE,
+            },
+            "command_line": env::args().collect::<Vec<String>>(),
+            "files": self.files,
+            "output_layout": self.layout,
+            "started_at": utc_timestamp(self.started_at),
+            "finished_at": utc_timestamp(SystemTime::now()),
+        });
+
+        if !self.settings.is_null() {
+            manifest["settings"] = self.settings.clone();
+        }
+
+        File::create(path)?.write_all((serde_json::to_string_pretty(&manifest)? + "\n").as_bytes())
+    }
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,1045 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::{ArgGroup, Args};
+use serde_json::{json, Value};
+
+use stash_scoring::board::{Color, Position};
+use stash_scoring::chunks::{create_chunk_dir, finish_chunks};
+use stash_scoring::engine::{EngineConfig, Score, SearchLimit, UciEngine};
+use stash_scoring::game::{
+    play_game, GameLimits, GameRecord, GameResult, MoveSampling, Termination, TimeControl,
+};
+use stash_scoring::input::tokenize;
+use stash_scoring::manifest::Manifest;
+use stash_scoring::pgn::PgnWriter;
+use stash_scoring::registry::EngineRegistry;
+use stash_scoring::rng::Rng;
+use stash_scoring::tournament::{GamePair, Schedule, Sprt, Standings};
+
+use crate::dashboard::Dashboard;
+
+/// Plays games between engines, following a round-robin or gauntlet
+/// schedule, and reports the crosstable and the Elo difference between each
+/// pair of engines. Each opening is played twice, with swapped colors, and
+/// the statistics of the pairing are reported after each game pair. When
+/// the output is a terminal, a live status of the match is shown below the
+/// results.
+#[derive(Args)]
+#[command(group(ArgGroup::new("limits").required(true).multiple(true).args(["depth", "nodes", "engine_nodes", "tc"])))]
+#[command(group(ArgGroup::new("random_openings").multiple(true).args(["random_plies", "dfrc"])))]
+pub struct MatchArgs {
+    /// An engine taking part in the tournament, as a comma-separated list of
+    /// settings: 'cmd' (the engine path, required), 'name', 'arg' (an
+    /// argument of the engine, repeatable), 'dir' (the working directory),
+    /// 'clearenv' (true for an empty environment), 'env.<NAME>' (an
+    /// environment variable), 'profile', 'option.<NAME>' (e.g.
+    /// 'cmd=./stash,name=Stash,option.Hash=16') and 'sync' (an option after
+    /// which the engine must be waited for). With 'conf=<NAME>', or just
+    /// '<NAME>', the engine is taken from the --engines-json file or from the
+    /// engine registry, and other settings override its configuration. Use
+    /// this flag once per engine.
+    #[arg(long = "engine", required = true, num_args = 1)]
+    engines: Vec<String>,
+
+    /// A cutechess-cli style engines.json file, holding the configurations
+    /// engines can refer to with 'conf=<NAME>'. Only UCI engines are
+    /// supported.
+    #[arg(long)]
+    engines_json: Option<String>,
+
+    /// How engines are paired. With a gauntlet, the first engine plays all
+    /// the others.
+    #[arg(long, value_enum, default_value_t = Schedule::RoundRobin)]
+    schedule: Schedule,
+
+    /// The number of times each pairing is played, two games each time.
+    #[arg(long, default_value_t = 1)]
+    rounds: usize,
+
+    /// A file with one opening position per line, as a FEN or EPD. Games
+    /// start from the standard position by default.
+    #[arg(long)]
+    openings: Option<String>,
+
+    /// Start games from random openings instead, one per game pair, made of
+    /// this many uniformly random legal moves from the standard position (or
+    /// from the --dfrc ones).
+    #[arg(long, conflicts_with = "openings")]
+    random_plies: Option<usize>,
+
+    /// Start games from random double Fischer random (DFRC) positions
+    /// instead, one per game pair, with independent Chess960 setups for both
+    /// sides. Random plies are then played from these positions.
+    #[arg(long, requires = "chess960", conflicts_with = "openings")]
+    dfrc: bool,
+
+    /// Only keep random openings whose score, as found by a search of the
+    /// first engine to --random-eval-depth, is within this bound in
+    /// centipawns.
+    #[arg(long, requires = "random_openings")]
+    random_eval_bound: Option<u32>,
+
+    /// The depth of the searches checking the scores of random openings.
+    #[arg(long, default_value_t = 10)]
+    random_eval_depth: u16,
+
+    /// Sample the moves of the first plies of each game among the root moves
+    /// searched with MultiPV, following a softmax of their scores. This gives
+    /// the temperature of each of these plies in centipawns, comma-separated,
+    /// e.g. '100,100,50,50'. The schedule is recorded in the manifest of the
+    /// PGN output.
+    #[arg(long, value_delimiter = ',', conflicts_with = "ponder")]
+    temperature: Vec<f64>,
+
+    /// The number of root moves searched for sampling moves.
+    #[arg(long, default_value_t = 4)]
+    sample_multipv: usize,
+
+    /// The seed all random choices of the match are derived from: each game
+    /// pair gets its own random opening and each game its own seed for
+    /// sampling moves, recorded in the 'Seed' tag of the PGN output and in
+    /// the --games-jsonl lines. These only depend on the position of the
+    /// game in the schedule, so that --replay-game can play it again: the
+    /// match seed and the number of each game are recorded as well, in the
+    /// 'MatchSeed' and 'Game' tags.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Only play the game pair holding this game of the schedule, numbered
+    /// from 1 as in the log of the match, with the same opening and random
+    /// choices as in the full match, e.g. for debugging a game. A recorded
+    /// game is replayed with its 'Game' tag and its 'MatchSeed' tag as
+    /// --seed, along with the settings of the match.
+    #[arg(long)]
+    replay_game: Option<usize>,
+
+    /// Play Chess960 games. This enables the UCI_Chess960 option of the
+    /// engines.
+    #[arg(long)]
+    chess960: bool,
+
+    /// The file to write all games to, in PGN format.
+    #[arg(long)]
+    pgn_out: Option<String>,
+
+    /// A directory to write the games to as PGN chunks of --chunk-games games
+    /// each, for a 'pgn-extract --watch' run to pick them up while the match
+    /// goes on. Each chunk only appears once complete, and an 'END' file is
+    /// written once the match is over. The directory must be empty.
+    #[arg(long, conflicts_with = "pgn_out")]
+    chunk_dir: Option<String>,
+
+    /// The number of games of each PGN chunk.
+    #[arg(long, default_value_t = 1000, requires = "chunk_dir")]
+    chunk_games: usize,
+
+    /// The number of games played at the same time, each with its own engine
+    /// processes. The games times the search threads of an engine (given by
+    /// its Threads option) must fit in the available cores.
+    #[arg(long, default_value_t = 1)]
+    concurrency: usize,
+
+    /// Run a sequential probability ratio test between the two engines of the
+    /// match, stopping once it concludes. The test is given as
+    /// 'elo0=<ELO>,elo1=<ELO>,alpha=<P>,beta=<P>', the error probabilities
+    /// defaulting to 0.05.
+    #[arg(long)]
+    sprt: Option<Sprt>,
+
+    /// Stop the match once this many games are finished. The games in
+    /// progress are still completed, but no new game pair is started.
+    #[arg(long)]
+    target_games: Option<usize>,
+
+    /// Stop the match once the finished games hold this many positions (one
+    /// per move played), like --target-games.
+    #[arg(long)]
+    target_positions: Option<usize>,
+
+    /// Stop the match after running for this many hours, like
+    /// --target-games.
+    #[arg(long)]
+    run_hours: Option<f64>,
+
+    /// Write one JSON line per finished game to this file, with its players,
+    /// opening, length, result, termination and average evaluation, so that
+    /// the games can be audited or filtered by their properties.
+    #[arg(long)]
+    games_jsonl: Option<String>,
+
+    /// Annotate the moves of the PGN output with the engine evals and, in
+    /// timed games, the clock times.
+    #[arg(long)]
+    pgn_comments: bool,
+
+    /// The maximal depth for searches.
+    #[arg(short, long)]
+    depth: Option<u16>,
+
+    /// The maximal node count for searches.
+    #[arg(short, long)]
+    nodes: Option<u64>,
+
+    /// The node count each engine searches, in the --engine order,
+    /// comma-separated, e.g. '40000,30000' for node-odds games. Overrides
+    /// --nodes. Games are then played without clocks.
+    #[arg(long, value_delimiter = ',', conflicts_with = "tc")]
+    engine_nodes: Vec<u64>,
+
+    /// The time control of the games, as '<base>+<increment>' in seconds
+    /// (e.g. '10+0.1'). Engines exceeding their time lose the game.
+    #[arg(long)]
+    tc: Option<TimeControl>,
+
+    /// The extra time, in milliseconds, an engine may use beyond its
+    /// remaining time before losing on time.
+    #[arg(long, default_value_t = 0)]
+    timemargin: u64,
+
+    /// Let engines think on their opponent's time. This enables the Ponder
+    /// option of the engines, and only the time spent after the opponent's
+    /// move is deducted from their clock.
+    #[arg(long)]
+    ponder: bool,
+}
+
+/// The interval between two updates of the dashboard without any new game.
+const DASHBOARD_REFRESH: Duration = Duration::from_secs(1);
+
+/// The number of random openings tried for each one kept, before giving up
+/// on an evaluation bound which cannot be met.
+const MAX_OPENING_ATTEMPTS: usize = 1000;
+
+/// Reads the engine configurations of a cutechess-cli engines.json file, by
+/// name. Engines using another protocol than UCI are kept as errors, only
+/// reported if they are used. Settings without an equivalent here (init
+/// strings, stderr files, ...) are ignored.
+fn read_engines_json(path: &str) -> io::Result<Vec<(String, Result<EngineConfig, String>)>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let json: Value = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|err| invalid(format!("{}: {}", path, err)))?;
+    let entries = json
+        .as_array()
+        .ok_or_else(|| invalid(format!("{}: expected an array of engines", path)))?;
+    let mut configs = Vec::new();
+
+    for entry in entries {
+        let field = |key: &str| entry.get(key).and_then(Value::as_str);
+        let name =
+            field("name").ok_or_else(|| invalid(format!("{}: engine without a name", path)))?;
+        let command = field("command")
+            .ok_or_else(|| invalid(format!("{}: engine '{}' has no command", path, name)))?;
+
+        if let Some(protocol) = field("protocol").filter(|&p| p != "uci") {
+            configs.push((
+                name.to_string(),
+                Err(format!(
+                    "engine '{}' uses the unsupported '{}' protocol",
+                    name, protocol
+                )),
+            ));
+            continue;
+        }
+
+        let mut options = Vec::new();
+
+        for option in entry
+            .get("options")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let (Some(option_name), Some(value)) = (
+                option.get("name").and_then(Value::as_str),
+                option.get("value"),
+            ) else {
+                continue;
+            };
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+
+            options.push(format!("{}={}", option_name, value));
+        }
+
+        let config = EngineConfig {
+            name: name.to_string(),
+            command: command.to_string(),
+            working_dir: field("workingDirectory")
+                .filter(|dir| !dir.is_empty())
+                .map(String::from),
+            options,
+            ..Default::default()
+        };
+
+        configs.push((name.to_string(), Ok(config)));
+    }
+
+    Ok(configs)
+}
+
+/// Builds the configuration of an engine from its --engine settings.
+pub(crate) fn resolve_engine(
+    settings: &str,
+    known: &[(String, Result<EngineConfig, String>)],
+) -> Result<EngineConfig, String> {
+    let (conf, rest): (Vec<&str>, Vec<&str>) = settings
+        .split(',')
+        .partition(|setting| setting.starts_with("conf=") || !setting.contains('='));
+    let Some(conf) = conf.last() else {
+        return settings.parse();
+    };
+    let name = conf.strip_prefix("conf=").unwrap_or(conf);
+    let config = known
+        .iter()
+        .find(|(known_name, _)| known_name == name)
+        .map(|(_, config)| config.clone())
+        .ok_or_else(|| format!("unknown engine configuration '{}'", name))??;
+
+    match rest.is_empty() {
+        true => Ok(config),
+        false => config.with_settings(&rest.join(",")),
+    }
+}
+
+/// Reads opening positions, one FEN or EPD per line. EPD operations after the
+/// four position fields are ignored.
+pub(crate) fn read_openings(path: &str, chess960: bool) -> io::Result<Vec<Position>> {
+    let mut openings = Vec::new();
+
+    for (idx, line) in fs::read_to_string(path)?.lines().enumerate() {
+        if tokenize(line).next().is_none() {
+            continue;
+        }
+
+        let pos = Position::from_epd(line, chess960).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path, idx + 1, err),
+            )
+        })?;
+
+        openings.push(pos);
+    }
+
+    if openings.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no opening found in {}", path),
+        ));
+    }
+
+    Ok(openings)
+}
+
+/// Plays random legal moves from a position, returning None if the game ends
+/// before the given number of plies.
+fn random_walk(start: &Position, plies: usize, rng: &mut Rng) -> Option<Position> {
+    let mut pos = start.clone();
+
+    for _ in 0..plies {
+        let moves = pos.legal_moves();
+
+        if moves.is_empty() {
+            return None;
+        }
+
+        pos.play(moves[rng.below(moves.len() as u64) as usize]);
+    }
+
+    (!pos.legal_moves().is_empty()).then_some(pos)
+}
+
+/// Returns the seed of the random opening of a game pair.
+fn opening_seed(seed: u64, pair: &GamePair) -> u64 {
+    Rng::derive(Rng::derive(seed, pair.index as u64), 0)
+}
+
+/// Returns the seed of the random choices of a game of a pair.
+fn game_seed(seed: u64, pair: &GamePair, game: usize) -> u64 {
+    Rng::derive(Rng::derive(seed, pair.index as u64), game as u64 + 1)
+}
+
+/// Generates a random opening for each game pair, from random DFRC positions
+/// with --dfrc, checking their score with the given engine when an evaluation
+/// bound is set.
+fn random_openings(
+    args: &MatchArgs,
+    game_pairs: &[GamePair],
+    config: &EngineConfig,
+) -> io::Result<Vec<Position>> {
+    let standard = Position::from_fen(Position::STARTPOS, args.chess960).unwrap();
+    let plies = args.random_plies.unwrap_or(0);
+    let mut engine = match args.random_eval_bound {
+        Some(_) => Some(config.start()?),
+        None => None,
+    };
+    let limit = SearchLimit {
+        depth: Some(args.random_eval_depth),
+        ..Default::default()
+    };
+    let mut openings = Vec::with_capacity(game_pairs.len());
+
+    for pair in game_pairs {
+        let mut rng = Rng::new(opening_seed(args.seed, pair));
+        let mut opening = None;
+
+        for _ in 0..MAX_OPENING_ATTEMPTS {
+            let start = match args.dfrc {
+                true => Position::from_scharnagl(rng.below(960) as usize, rng.below(960) as usize),
+                false => standard.clone(),
+            };
+            let Some(pos) = random_walk(&start, plies, &mut rng) else {
+                continue;
+            };
+
+            if let (Some(bound), Some(engine)) = (args.random_eval_bound, &mut engine) {
+                engine.setup_position(&pos.to_fen(), &[])?;
+
+                match engine.run_search(&limit)?.score {
+                    Score::Cp(cp) if cp.unsigned_abs() <= bound => (),
+                    _ => continue,
+                }
+            }
+
+            opening = Some(pos);
+            break;
+        }
+
+        openings.push(opening.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "no suitable random opening found in {} attempts",
+                    MAX_OPENING_ATTEMPTS
+                ),
+            )
+        })?);
+    }
+
+    Ok(openings)
+}
+
+/// Writes the manifest of the PGN output, recording how the games were
+/// played.
+fn write_manifest(args: &MatchArgs, names: &[String], mut manifest: Manifest) -> io::Result<()> {
+    let pgn = args.pgn_out.as_deref().unwrap();
+
+    manifest.add_file("output", Path::new(pgn))?;
+
+    if let Some(openings) = &args.openings {
+        manifest.add_file("openings", Path::new(openings))?;
+    }
+
+    manifest.set_settings(json!({
+        "engines": names,
+        "depth": args.depth,
+        "nodes": args.nodes,
+        "engine_nodes": args.engine_nodes,
+        "tc": args.tc.map(|tc| tc.to_pgn()),
+        "random_plies": args.random_plies,
+        "dfrc": args.dfrc,
+        "random_eval_bound": args.random_eval_bound,
+        "temperatures": args.temperature,
+        "sample_multipv": args.sample_multipv,
+        "seed": args.seed,
+        "replay_game": args.replay_game,
+    }));
+    manifest.write(Path::new(&format!("{}.manifest.json", pgn)))
+}
+
+/// The PGN output of a match split into chunks of a fixed number of games.
+/// Chunks are written under a temporary name, and renamed once complete.
+struct PgnChunks {
+    dir: PathBuf,
+    games_per_chunk: usize,
+    comments: bool,
+    chunks: usize,
+    games: usize,
+    writer: Option<PgnWriter<BufWriter<File>>>,
+}
+
+impl PgnChunks {
+    fn new(dir: &str, games_per_chunk: usize, comments: bool) -> io::Result<Self> {
+        create_chunk_dir(Path::new(dir))?;
+
+        Ok(Self {
+            dir: PathBuf::from(dir),
+            games_per_chunk: games_per_chunk.max(1),
+            comments,
+            chunks: 0,
+            games: 0,
+            writer: None,
+        })
+    }
+
+    /// The path of the current chunk.
+    fn path(&self) -> PathBuf {
+        self.dir.join(format!("games-{:06}.pgn", self.chunks))
+    }
+
+    fn tmp_path(&self) -> PathBuf {
+        self.dir.join(format!("games-{:06}.pgn.tmp", self.chunks))
+    }
+
+    fn write_game(&mut self, tags: &[(&str, &str)], record: &GameRecord) -> io::Result<()> {
+        if self.writer.is_none() {
+            self.chunks += 1;
+            self.writer = Some(PgnWriter::new(
+                BufWriter::new(File::create(self.tmp_path())?),
+                self.comments,
+            ));
+        }
+
+        self.writer.as_mut().unwrap().write_game(tags, record)?;
+        self.games += 1;
+
+        match self.games == self.games_per_chunk {
+            true => self.close_chunk(),
+            false => Ok(()),
+        }
+    }
+
+    fn close_chunk(&mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+            drop(writer);
+            fs::rename(self.tmp_path(), self.path())?;
+            self.games = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the last, possibly incomplete, chunk and marks the directory as
+    /// finished.
+    fn finish(&mut self) -> io::Result<()> {
+        self.close_chunk()?;
+        finish_chunks(&self.dir)
+    }
+}
+
+/// A finished game, sent by the worker which played it.
+pub(crate) struct GameReport {
+    /// The index of the game pair in the schedule, and of the game in the
+    /// pair.
+    pub pair: usize,
+    pub game: usize,
+    pub white: usize,
+    pub black: usize,
+    pub record: GameRecord,
+}
+
+/// The number of threads an engine searches with, according to its Threads
+/// option.
+fn engine_threads(config: &EngineConfig) -> usize {
+    config
+        .options
+        .iter()
+        .rev()
+        .filter_map(|option| option.split_once('='))
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Threads"))
+        .find_map(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(1)
+}
+
+/// Plays game pairs taken from the schedule until none is left, starting its
+/// own engine processes when they are first needed. Both games of a pair are
+/// played by the same worker.
+#[allow(clippy::too_many_arguments)]
+fn run_worker(
+    configs: &[EngineConfig],
+    game_pairs: &[GamePair],
+    openings: &[Position],
+    limits: &GameLimits,
+    engine_nodes: &[u64],
+    next_pair: &AtomicUsize,
+    stop: &AtomicBool,
+    reports: Sender<io::Result<GameReport>>,
+) {
+    let mut engines: Vec<Option<UciEngine>> = configs.iter().map(|_| None).collect();
+
+    while !stop.load(Ordering::Relaxed) {
+        let idx = next_pair.fetch_add(1, Ordering::Relaxed);
+        let Some(pair) = game_pairs.get(idx) else {
+            break;
+        };
+        let (first, second) = pair.engines;
+
+        for (game, (white, black)) in [(first, second), (second, first)].into_iter().enumerate() {
+            for engine in [white, black] {
+                if engines[engine].is_none() {
+                    match configs[engine].start() {
+                        Ok(started) => engines[engine] = Some(started),
+                        Err(err) => {
+                            let _ = reports.send(Err(err));
+                            return;
+                        }
+                    }
+                }
+            }
+
+            // Both engines are distinct, so borrow them separately.
+            let (low, high) = engines.split_at_mut(white.max(black));
+            let (white_engine, black_engine) = match white < black {
+                true => (&mut low[white], &mut high[0]),
+                false => (&mut high[0], &mut low[black]),
+            };
+            let limits = GameLimits {
+                side_nodes: [
+                    engine_nodes.get(white).copied(),
+                    engine_nodes.get(black).copied(),
+                ],
+                // Each game gets its own random choices.
+                sampling: limits.sampling.clone().map(|sampling| MoveSampling {
+                    seed: game_seed(sampling.seed, pair, game),
+                    ..sampling
+                }),
+                ..limits.clone()
+            };
+            let record = play_game(
+                [
+                    white_engine.as_mut().unwrap(),
+                    black_engine.as_mut().unwrap(),
+                ],
+                &openings[pair.opening],
+                &limits,
+            );
+
+            // Restart the engine which failed, so that the tournament can
+            // go on. Engines losing on time may still be searching, so they
+            // are restarted as well.
+            if matches!(
+                record.termination,
+                Termination::EngineFailure | Termination::TimeForfeit
+            ) {
+                let loser = match record.result.points(Color::White) {
+                    0.0 => white,
+                    _ => black,
+                };
+
+                eprintln!("Restarting engine {}", configs[loser].name);
+                engines[loser] = None;
+            }
+
+            let report = GameReport {
+                pair: idx,
+                game,
+                white,
+                black,
+                record,
+            };
+
+            if reports.send(Ok(report)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Plays the given game pairs with `concurrency` workers, handing each
+/// finished game to `on_report` as soon as it is received. `on_report` is also
+/// called without a game when none was finished for a while, and no new game
+/// pair is started once it has returned true. Engines search with the node
+/// limit of `engine_nodes` with their index, if any.
+pub(crate) fn play_game_pairs(
+    configs: &[EngineConfig],
+    game_pairs: &[GamePair],
+    openings: &[Position],
+    limits: &GameLimits,
+    engine_nodes: &[u64],
+    concurrency: usize,
+    mut on_report: impl FnMut(Option<GameReport>) -> io::Result<bool>,
+) -> io::Result<()> {
+    let next_pair = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..concurrency {
+            let sender = sender.clone();
+            let (next_pair, stop) = (&next_pair, &stop);
+
+            scope.spawn(move || {
+                run_worker(
+                    configs,
+                    game_pairs,
+                    openings,
+                    limits,
+                    engine_nodes,
+                    next_pair,
+                    stop,
+                    sender,
+                )
+            });
+        }
+
+        drop(sender);
+
+        let result = loop {
+            let report = match receiver.recv_timeout(DASHBOARD_REFRESH) {
+                Ok(report) => report.map(Some),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => break Ok(()),
+            };
+
+            match report.and_then(&mut on_report) {
+                Ok(true) => stop.store(true, Ordering::Relaxed),
+                Ok(false) => (),
+                Err(err) => break Err(err),
+            }
+        };
+
+        // Let the other workers finish their current game and stop.
+        stop.store(true, Ordering::Relaxed);
+        result
+    })
+}
+
+pub fn run(args: &MatchArgs, registry: &EngineRegistry) -> io::Result<()> {
+    if args.engines.len() < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "at least two engines are needed for a match",
+        ));
+    }
+
+    // Engines of the engines.json file take precedence over the registry.
+    let mut known = match &args.engines_json {
+        Some(path) => read_engines_json(path)?,
+        None => Vec::new(),
+    };
+
+    known.extend(
+        registry
+            .engines()
+            .iter()
+            .map(|config| (config.name.clone(), Ok(config.clone()))),
+    );
+
+    let configs = args
+        .engines
+        .iter()
+        .map(|settings| {
+            let mut config = resolve_engine(settings, &known)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+            if args.chess960 {
+                config.options.insert(0, String::from("UCI_Chess960=true"));
+            }
+
+            if args.ponder {
+                config.options.insert(0, String::from("Ponder=true"));
+            }
+
+            Ok(config)
+        })
+        .collect::<io::Result<Vec<EngineConfig>>>()?;
+
+    // Only one engine searches at a time in a game, unless they ponder.
+    let threads_per_engine = configs.iter().map(engine_threads).max().unwrap_or(1);
+    let cores = thread::available_parallelism().map_or(1, usize::from);
+
+    if args.concurrency == 0 || args.concurrency * threads_per_engine > cores {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} concurrent games with {} search threads per engine need more than the {} \
+                 available cores",
+                args.concurrency, threads_per_engine, cores
+            ),
+        ));
+    }
+
+    if args.sprt.is_some() && configs.len() != 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "an SPRT can only be run between two engines",
+        ));
+    }
+
+    if !args.engine_nodes.is_empty() && args.engine_nodes.len() != configs.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} node counts were given for {} engines",
+                args.engine_nodes.len(),
+                configs.len()
+            ),
+        ));
+    }
+
+    if args.sample_multipv == 0
+        || args
+            .temperature
+            .iter()
+            .any(|temperature| !(temperature.is_finite() && *temperature >= 0.0))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "temperatures must be non-negative, and at least one root move must be searched",
+        ));
+    }
+
+    let run_time = match args.run_hours {
+        Some(hours) => Some(Duration::try_from_secs_f64(hours * 3600.0).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the run time must be a non-negative number of hours",
+            )
+        })?),
+        None => None,
+    };
+
+    if args.ponder && args.concurrency * threads_per_engine * 2 > cores {
+        eprintln!("Warning: pondering engines will compete for the available cores");
+    }
+
+    let mut openings = match &args.openings {
+        Some(path) => read_openings(path, args.chess960)?,
+        None => vec![Position::from_fen(Position::STARTPOS, args.chess960).unwrap()],
+    };
+    let mut game_pairs = args
+        .schedule
+        .game_pairs(configs.len(), args.rounds, openings.len());
+    let scheduled_games = game_pairs.len() * 2;
+
+    if let Some(game) = args.replay_game {
+        let Some(&pair) = game
+            .checked_sub(1)
+            .and_then(|game| game_pairs.get(game / 2))
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the schedule only has {} games", scheduled_games),
+            ));
+        };
+
+        game_pairs = vec![pair];
+    }
+
+    if args.dfrc || args.random_plies.is_some() {
+        eprintln!("Generating {} random openings", game_pairs.len());
+        openings = random_openings(args, &game_pairs, &configs[0])?;
+
+        for (idx, pair) in game_pairs.iter_mut().enumerate() {
+            pair.opening = idx;
+        }
+    }
+
+    let manifest = Manifest::new();
+    let mut pgn_file = match &args.pgn_out {
+        Some(path) => Some(PgnWriter::new(
+            BufWriter::new(File::create(path)?),
+            args.pgn_comments,
+        )),
+        None => None,
+    };
+    let mut pgn_chunks = match &args.chunk_dir {
+        Some(dir) => Some(PgnChunks::new(dir, args.chunk_games, args.pgn_comments)?),
+        None => None,
+    };
+    let mut games_file = match &args.games_jsonl {
+        Some(path) => Some(BufWriter::new(File::create(path)?)),
+        None => None,
+    };
+    let names: Vec<String> = configs.iter().map(|config| config.name.clone()).collect();
+    let mut standings = Standings::new(names.clone());
+    let total_games = game_pairs.len() * 2;
+    let limits = GameLimits {
+        search: SearchLimit {
+            depth: args.depth,
+            nodes: args.nodes,
+            ..Default::default()
+        },
+        time_control: args.tc,
+        time_margin: Duration::from_millis(args.timemargin),
+        ponder: args.ponder,
+        side_nodes: [None; 2],
+        sampling: match args.temperature.is_empty() {
+            true => None,
+            false => Some(MoveSampling {
+                temperatures: args.temperature.clone(),
+                multipv: args.sample_multipv,
+                seed: args.seed,
+            }),
+        },
+    };
+    let time_control = args.tc.map_or(String::from("-"), |tc| tc.to_pgn());
+    let mut pair_results: HashMap<usize, [Option<GameResult>; 2]> = HashMap::new();
+    let mut dashboard = Dashboard::new(total_games);
+    let mut concluded = false;
+    let start = Instant::now();
+    let mut games_played = 0;
+    let mut positions = 0;
+    let mut stopped = false;
+
+    play_game_pairs(
+        &configs,
+        &game_pairs,
+        &openings,
+        &limits,
+        &args.engine_nodes,
+        args.concurrency,
+        |report| {
+            if let Some(report) = report {
+                let pair = game_pairs[report.pair];
+                let (white, black) = (report.white, report.black);
+                let game_number = pair.index * 2 + report.game + 1;
+
+                games_played += 1;
+                positions += report.record.moves.len();
+                dashboard.add_game(report.record.termination);
+                dashboard.log(&format!(
+                    "Game {}/{}: {} vs {}: {} ({})",
+                    game_number,
+                    scheduled_games,
+                    names[white],
+                    names[black],
+                    report.record.result.to_pgn(),
+                    report.record.termination.description()
+                ))?;
+
+                let round = (pair.round + 1).to_string();
+                let game_tag = game_number.to_string();
+                let match_seed_tag = args.seed.to_string();
+                let seed = game_seed(args.seed, &pair, report.game);
+                let seed_tag = seed.to_string();
+                let tags = [
+                    ("Event", "stash_tools match"),
+                    ("Site", "?"),
+                    ("Round", &round),
+                    ("White", &names[white]),
+                    ("Black", &names[black]),
+                    ("TimeControl", &time_control),
+                    ("Game", &game_tag),
+                    ("MatchSeed", &match_seed_tag),
+                    ("Seed", &seed_tag),
+                ];
+
+                if let Some(file) = &mut pgn_file {
+                    file.write_game(&tags, &report.record)?;
+                }
+
+                if let Some(chunks) = &mut pgn_chunks {
+                    chunks.write_game(&tags, &report.record)?;
+                }
+
+                if let Some(file) = &mut games_file {
+                    let record = &report.record;
+                    let game = json!({
+                        "game": game_number,
+                        "round": pair.round + 1,
+                        "white": names[white],
+                        "black": names[black],
+                        "opening": record.start.to_fen(),
+                        "plies": record.moves.len(),
+                        "result": record.result.to_pgn(),
+                        "termination": record.termination.description(),
+                        "match_seed": args.seed,
+                        "seed": seed,
+                        "average_eval": record
+                            .average_eval()
+                            .map(|eval| (eval * 10.0).round() / 10.0),
+                    });
+
+                    writeln!(file, "{}", game)?;
+                }
+
+                let results = pair_results.entry(report.pair).or_default();
+
+                results[report.game] = Some(report.record.result);
+
+                if let [Some(first_result), Some(second_result)] = *results {
+                    let (first, second) = pair.engines;
+
+                    standings.add_game_pair(first, second, [first_result, second_result]);
+                    pair_results.remove(&report.pair);
+                    dashboard.log(&format!(
+                        "{} vs {}: {}",
+                        names[first],
+                        names[second],
+                        standings.pentanomial(first, second).summary()
+                    ))?;
+
+                    // Once the test is over, the games in progress are still
+                    // completed, but no new game pair is started.
+                    if let Some(sprt) = &args.sprt {
+                        let ptnml = standings.pentanomial(0, 1);
+
+                        if !concluded && sprt.result(&ptnml).is_some() {
+                            concluded = true;
+                            dashboard.log(&format!("SPRT: {}", sprt.summary(&ptnml)))?;
+                        }
+                    }
+                }
+            }
+
+            // Like a concluded test, reaching a target of the run lets the
+            // games in progress finish.
+            let target = if args
+                .target_games
+                .is_some_and(|target| games_played >= target)
+            {
+                Some(format!("{} games played", games_played))
+            } else if args
+                .target_positions
+                .is_some_and(|target| positions >= target)
+            {
+                Some(format!("{} positions played", positions))
+            } else if run_time.is_some_and(|run_time| start.elapsed() >= run_time) {
+                Some(String::from("run time elapsed"))
+            } else {
+                None
+            };
+
+            if let (Some(target), false) = (target, stopped) {
+                stopped = true;
+                dashboard.log(&format!(
+                    "Stopping the match ({}), finishing the games in progress",
+                    target
+                ))?;
+            }
+
+            dashboard.draw(&standings, args.sprt.as_ref())?;
+            Ok(concluded || stopped)
+        },
+    )?;
+
+    if let Some(file) = &mut games_file {
+        file.flush()?;
+    }
+
+    if let Some(file) = &mut pgn_file {
+        file.flush()?;
+        write_manifest(args, &names, manifest)?;
+    }
+
+    if let Some(chunks) = &mut pgn_chunks {
+        chunks.finish()?;
+    }
+
+    dashboard.erase()?;
+    println!();
+    print!("{}", standings.crosstable());
+    println!();
+    print!("{}", standings.pair_elos());
+
+    if let Some(sprt) = &args.sprt {
+        println!("SPRT: {}", sprt.summary(&standings.pentanomial(0, 1)));
+    }
+
+    println!("{}", dashboard.failures());
+
+    Ok(())
+}
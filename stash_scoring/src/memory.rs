@@ -0,0 +1,99 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs;
+use std::process;
+use std::time::{Duration, Instant};
+
+/// The minimal interval between two measurements of a guard, as scanning
+/// the processes of the system is not free.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Returns the resident memory of this process and all its descendants (the
+/// engines and their own children), in bytes. Only supported on systems with
+/// a Linux-like `/proc`.
+pub fn process_tree_rss() -> Option<u64> {
+    // The (parent, resident kilobytes) of each process.
+    let mut processes: HashMap<u32, (u32, u64)> = HashMap::new();
+
+    for entry in fs::read_dir("/proc").ok()?.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|s| s.parse().ok()) else {
+            continue;
+        };
+        // Processes may exit while the directory is read.
+        let Ok(status) = fs::read_to_string(entry.path().join("status")) else {
+            continue;
+        };
+        let field = |name: &str| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+                .and_then(|value| value.split_whitespace().next()?.parse::<u64>().ok())
+        };
+
+        // Kernel threads have no resident memory field.
+        processes.insert(
+            pid,
+            (
+                field("PPid").unwrap_or(0) as u32,
+                field("VmRSS").unwrap_or(0),
+            ),
+        );
+    }
+
+    let root = process::id();
+    let mut tree = vec![root];
+    let mut total = processes.get(&root)?.1;
+
+    while let Some(parent) = tree.pop() {
+        for (&pid, &(ppid, rss)) in &processes {
+            if ppid == parent {
+                tree.push(pid);
+                total += rss;
+            }
+        }
+    }
+
+    Some(total * 1024)
+}
+
+/// Watches the memory used by the tool and its engines, so that a run can
+/// slow down instead of being killed when it comes close to the memory of
+/// the machine.
+pub struct MemoryGuard {
+    limit: u64,
+    last_check: Cell<Option<Instant>>,
+    rss: Cell<Option<u64>>,
+}
+
+impl MemoryGuard {
+    /// Creates a guard for the given limit, in bytes.
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            last_check: Cell::new(None),
+            rss: Cell::new(None),
+        }
+    }
+
+    /// The last measured memory usage, in bytes.
+    pub fn rss(&self) -> Option<u64> {
+        self.rss.get()
+    }
+
+    /// Whether the tool and its engines used more memory than the limit at
+    /// the last measurement, measuring it again if it is old enough.
+    pub fn exceeded(&self) -> bool {
+        let now = Instant::now();
+
+        if self
+            .last_check
+            .get()
+            .is_none_or(|last| now.duration_since(last) >= CHECK_INTERVAL)
+        {
+            self.last_check.set(Some(now));
+            self.rss.set(process_tree_rss());
+        }
+
+        self.rss.get().is_some_and(|rss| rss > self.limit)
+    }
+}
@@ -0,0 +1,414 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::Args;
+use serde_json::Value;
+
+use stash_scoring::engine::{EngineConfig, SearchLimit};
+use stash_scoring::game::{GameLimits, Termination, TimeControl};
+use stash_scoring::rng::Rng;
+use stash_scoring::tournament::{Schedule, Standings};
+
+use crate::build_engine::{make_engine, run_command, run_command_with_input};
+use crate::match_runner::{play_game_pairs, read_openings};
+
+/// Runs games for an OpenBench server as one of its workers: workloads are
+/// fetched from the server, both engines of the test are built from their
+/// source archives, the games are played with the internal match runner, and
+/// the results are uploaded once the workload is complete.
+///
+/// Engines are built the same way as with the build-engine subcommand. HTTP
+/// requests are made with curl, which must be installed. The forms sent to
+/// the server, holding the account credentials, are passed to curl through
+/// its standard input so that they do not show in its arguments.
+#[derive(Args)]
+pub struct OpenBenchArgs {
+    /// The URL of the OpenBench server.
+    #[arg(short, long)]
+    server: String,
+
+    /// The username of the OpenBench account.
+    #[arg(short, long)]
+    username: String,
+
+    /// A file holding the password of the OpenBench account. By default, the
+    /// password is read from the OPENBENCH_PASSWORD environment variable.
+    #[arg(long)]
+    password_file: Option<String>,
+
+    /// The number of games played at the same time.
+    #[arg(long, default_value_t = 1)]
+    concurrency: usize,
+
+    /// The directory where engines are built and opening books downloaded.
+    /// Built engines are kept there, and reused by later workloads.
+    #[arg(long, default_value = "openbench")]
+    work_dir: String,
+
+    /// Stop after completing this many workloads. By default, the worker runs
+    /// until interrupted.
+    #[arg(long)]
+    workloads: Option<usize>,
+
+    /// The time to wait before asking again when the server has no work, in
+    /// seconds.
+    #[arg(long, default_value_t = 60)]
+    idle_wait: u64,
+}
+
+/// Reads a string field of an object sent by the server.
+fn str_field<'a>(value: &'a Value, key: &str) -> io::Result<&'a str> {
+    value[key].as_str().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("missing '{}' field in the server response", key),
+        )
+    })
+}
+
+/// The environment variable holding the password of the OpenBench account
+/// when no password file is given.
+const PASSWORD_VAR: &str = "OPENBENCH_PASSWORD";
+
+/// Reads the password of the OpenBench account, from its file or from the
+/// environment. A final line end in the file is not part of the password.
+fn read_password(args: &OpenBenchArgs) -> io::Result<String> {
+    match &args.password_file {
+        Some(path) => {
+            let password = fs::read_to_string(path)?;
+
+            Ok(password.trim_end_matches(['\r', '\n']).to_string())
+        }
+        None => env::var(PASSWORD_VAR).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the password must be given with --password-file or {}",
+                    PASSWORD_VAR
+                ),
+            )
+        }),
+    }
+}
+
+/// Encodes a form field for an application/x-www-form-urlencoded body.
+fn url_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => {
+                encoded.push(char::from(byte))
+            }
+            b' ' => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+/// The connection to an OpenBench server, identifying this worker.
+struct Server {
+    url: String,
+    username: String,
+    password: String,
+    machine_id: Option<String>,
+}
+
+impl Server {
+    /// Posts a form to an endpoint of the server, returning the JSON response.
+    fn post(&self, endpoint: &str, fields: &[(&str, String)]) -> io::Result<Value> {
+        let credentials = [
+            ("username", self.username.clone()),
+            ("password", self.password.clone()),
+            ("machine_id", self.machine_id.clone().unwrap_or_default()),
+        ];
+        let form: Vec<String> = credentials
+            .iter()
+            .chain(fields)
+            .map(|(key, value)| format!("{}={}", key, url_encode(value)))
+            .collect();
+        let mut command = Command::new("curl");
+
+        command
+            .args(["--silent", "--show-error", "--fail", "--data-binary", "@-"])
+            .arg(format!("{}/{}/", self.url.trim_end_matches('/'), endpoint));
+
+        let output = run_command_with_input(&mut command, form.join("&").as_bytes())?;
+        let response = serde_json::from_slice::<Value>(&output)?;
+
+        match response["error"].as_str() {
+            Some(error) => Err(io::Error::other(format!("server error: {}", error))),
+            None => Ok(response),
+        }
+    }
+}
+
+/// Downloads a file with curl.
+fn download(url: &str, path: &Path) -> io::Result<()> {
+    run_command(
+        Command::new("curl")
+            .args([
+                "--silent",
+                "--show-error",
+                "--fail",
+                "--location",
+                "--output",
+            ])
+            .arg(path)
+            .arg(url),
+    )
+    .map(drop)
+}
+
+/// Builds an engine of a test from its source archive, unless a binary was
+/// already built for the same commit, and returns the binary path.
+fn build_engine(work_dir: &Path, engine: &Value) -> io::Result<PathBuf> {
+    let sha = str_field(engine, "sha")?;
+    let binary = work_dir.join("engines").join(sha);
+
+    if binary.exists() {
+        return Ok(binary);
+    }
+
+    let build_dir = work_dir.join("build").join(sha);
+
+    if build_dir.exists() {
+        fs::remove_dir_all(&build_dir)?;
+    }
+
+    fs::create_dir_all(&build_dir)?;
+    fs::create_dir_all(work_dir.join("engines"))?;
+    println!("Building {} ({})", str_field(engine, "name")?, sha);
+
+    let archive = build_dir.join("source.zip");
+
+    download(str_field(engine, "source")?, &archive)?;
+    run_command(
+        Command::new("unzip")
+            .arg("-q")
+            .arg(&archive)
+            .arg("-d")
+            .arg(&build_dir),
+    )?;
+    fs::remove_file(&archive)?;
+
+    // Source archives usually hold a single top-level directory.
+    let mut root = build_dir.clone();
+    let entries = fs::read_dir(&build_dir)?.collect::<io::Result<Vec<_>>>()?;
+
+    if let [entry] = entries.as_slice() {
+        if entry.file_type()?.is_dir() {
+            root = entry.path();
+        }
+    }
+
+    make_engine(&root, &binary, &[])?;
+    fs::remove_dir_all(&build_dir)?;
+    Ok(binary)
+}
+
+/// Downloads the opening book of a test, unless it is already present, and
+/// returns its path. Zipped books are extracted.
+fn fetch_book(work_dir: &Path, book: &Value) -> io::Result<PathBuf> {
+    let name = str_field(book, "name")?;
+    let path = work_dir.join("books").join(name);
+
+    if path.exists() {
+        return Ok(path);
+    }
+
+    fs::create_dir_all(work_dir.join("books"))?;
+
+    let source = str_field(book, "source")?;
+
+    if source.ends_with(".zip") {
+        let archive = path.with_extension("zip.tmp");
+
+        download(source, &archive)?;
+
+        let content = run_command(Command::new("unzip").arg("-p").arg(&archive));
+
+        fs::remove_file(&archive)?;
+        fs::write(&path, content?)?;
+    } else {
+        download(source, &path)?;
+    }
+
+    Ok(path)
+}
+
+/// The configuration of an engine of a test, with its options given as
+/// space-separated 'NAME=VALUE' pairs.
+fn engine_config(binary: &Path, engine: &Value) -> io::Result<EngineConfig> {
+    Ok(EngineConfig {
+        name: str_field(engine, "name")?.to_string(),
+        command: binary.to_string_lossy().into_owned(),
+        options: str_field(engine, "options")?
+            .split_whitespace()
+            .map(String::from)
+            .collect(),
+        ..Default::default()
+    })
+}
+
+/// Plays a workload between the dev and base engines of a test, and uploads
+/// the results.
+fn run_workload(
+    args: &OpenBenchArgs,
+    server: &Server,
+    work_dir: &Path,
+    workload: &Value,
+) -> io::Result<()> {
+    let test = &workload["test"];
+    let test_id = test["id"].to_string();
+    let result_id = workload["result"]["id"].to_string();
+    let dev = &test["dev"];
+    let base = &test["base"];
+    let configs = [
+        engine_config(&build_engine(work_dir, dev)?, dev)?,
+        engine_config(&build_engine(work_dir, base)?, base)?,
+    ];
+    let tc = str_field(dev, "time_control")?;
+    let time_control = tc.parse::<TimeControl>().map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported time control '{}': {}", tc, err),
+        )
+    })?;
+    let mut openings = read_openings(
+        &fetch_book(work_dir, &test["book"])?.to_string_lossy(),
+        false,
+    )?;
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+
+    // Start at a random opening of the book, so that workers do not all play
+    // the same ones.
+    let start = Rng::new(seed).below(openings.len() as u64) as usize;
+
+    openings.rotate_left(start);
+
+    let game_pairs = Schedule::Gauntlet.game_pairs(
+        2,
+        test["workload_size"].as_u64().unwrap_or(1) as usize * args.concurrency,
+        openings.len(),
+    );
+    let limits = GameLimits {
+        search: SearchLimit::default(),
+        time_control: Some(time_control),
+        time_margin: Duration::ZERO,
+        ponder: false,
+        side_nodes: [None; 2],
+        sampling: None,
+    };
+    let mut standings = Standings::new(vec![configs[0].name.clone(), configs[1].name.clone()]);
+    let mut pair_results = vec![[None; 2]; game_pairs.len()];
+    let (mut crashes, mut timeloss) = (0, 0);
+
+    println!(
+        "Test {}: {} vs {}, {} games at {}",
+        test_id,
+        configs[0].name,
+        configs[1].name,
+        game_pairs.len() * 2,
+        tc
+    );
+
+    play_game_pairs(
+        &configs,
+        &game_pairs,
+        &openings,
+        &limits,
+        &[],
+        args.concurrency,
+        |report| {
+            if let Some(report) = report {
+                match report.record.termination {
+                    Termination::EngineFailure => crashes += 1,
+                    Termination::TimeForfeit => timeloss += 1,
+                    _ => (),
+                }
+
+                let results = &mut pair_results[report.pair];
+
+                results[report.game] = Some(report.record.result);
+
+                if let [Some(first), Some(second)] = *results {
+                    standings.add_game_pair(0, 1, [first, second]);
+                }
+            }
+
+            Ok(false)
+        },
+    )?;
+
+    let wdl = standings.pair(0, 1);
+    let ptnml = standings.pentanomial(0, 1);
+    let fields = [
+        ("test_id", test_id),
+        ("result_id", result_id),
+        ("wins", wdl.wins.to_string()),
+        ("losses", wdl.losses.to_string()),
+        ("draws", wdl.draws.to_string()),
+        ("crashes", crashes.to_string()),
+        ("timeloss", timeloss.to_string()),
+        (
+            "pentanomial",
+            ptnml.0.map(|count| count.to_string()).join(" "),
+        ),
+    ];
+
+    println!("{}", ptnml.summary());
+    server.post("clientSubmitResults", &fields)?;
+    Ok(())
+}
+
+pub fn run(args: &OpenBenchArgs) -> io::Result<()> {
+    if args.concurrency == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the concurrency must be at least 1",
+        ));
+    }
+
+    fs::create_dir_all(&args.work_dir)?;
+
+    let work_dir = fs::canonicalize(&args.work_dir)?;
+    let mut server = Server {
+        url: args.server.clone(),
+        username: args.username.clone(),
+        password: read_password(args)?,
+        machine_id: None,
+    };
+    let mut completed = 0;
+
+    while args.workloads.is_none_or(|workloads| completed < workloads) {
+        let response = server.post(
+            "clientGetWorkload",
+            &[("concurrency", args.concurrency.to_string())],
+        )?;
+
+        if let Some(machine_id) = response["machine_id"].as_u64() {
+            server.machine_id = Some(machine_id.to_string());
+        }
+
+        if response["workload"].is_null() {
+            println!("No work available, retrying in {}s", args.idle_wait);
+            thread::sleep(Duration::from_secs(args.idle_wait));
+            continue;
+        }
+
+        run_workload(args, &server, &work_dir, &response["workload"])?;
+        completed += 1;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// An output dataset file which is written under a temporary name, and only
+/// renamed to its final name once the run completes, so that a crash never
+/// leaves a half-written file behind under the expected name. Alternatively,
+/// lines can be appended to an existing file shared with other runs.
+///
+/// Writes are buffered, and the buffer is flushed when it is full or when the
+/// flush interval has elapsed, whichever comes first.
+pub struct OutputFile {
+    file: BufWriter<OutputSink>,
+    path: PathBuf,
+    tmp_path: Option<PathBuf>,
+    fsync_every: Option<usize>,
+    unsynced_lines: usize,
+    flush_interval: Option<Duration>,
+    last_flush: Instant,
+}
+
+/// Buffering, durability and compression settings for an [`OutputFile`].
+#[derive(Clone, Copy, Debug)]
+pub struct OutputPolicy {
+    /// The size of the write buffer, in bytes.
+    pub buffer_size: usize,
+    /// The maximal time a written line can stay in the buffer.
+    pub flush_interval: Option<Duration>,
+    /// Sync the data to disk every time this many lines have been written.
+    pub fsync_every: Option<usize>,
+    /// Compress the file with Zstandard, using this many worker threads so
+    /// that compression keeps up with the writers. Requires the 'zstd'
+    /// feature.
+    pub compress_threads: Option<u32>,
+}
+
+impl Default for OutputPolicy {
+    fn default() -> Self {
+        Self {
+            buffer_size: 64 * 1024,
+            flush_interval: None,
+            fsync_every: None,
+            compress_threads: None,
+        }
+    }
+}
+
+/// The file lines are written to.
+enum OutputSink {
+    /// A temporary file owned by this run.
+    Temporary(File),
+    /// A file shared with other runs, which is locked while a buffer is
+    /// written to it. As buffers only hold complete lines, the lines of
+    /// concurrent runs never interleave.
+    Shared(File),
+    /// A temporary file owned by this run, written as a Zstandard stream.
+    #[cfg(feature = "zstd")]
+    Compressed(zstd::stream::write::Encoder<'static, File>),
+}
+
+impl OutputSink {
+    fn file(&self) -> &File {
+        match self {
+            Self::Temporary(file) | Self::Shared(file) => file,
+            #[cfg(feature = "zstd")]
+            Self::Compressed(encoder) => encoder.get_ref(),
+        }
+    }
+
+    /// Ends the compressed stream, if any, returning the underlying file.
+    fn finish(self) -> io::Result<File> {
+        match self {
+            Self::Temporary(file) | Self::Shared(file) => Ok(file),
+            #[cfg(feature = "zstd")]
+            Self::Compressed(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl Write for OutputSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Temporary(file) => file.write(buf),
+            Self::Shared(file) => {
+                file.lock()?;
+
+                let result = file.write_all(buf);
+
+                file.unlock()?;
+                result.map(|_| buf.len())
+            }
+            #[cfg(feature = "zstd")]
+            Self::Compressed(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            // Make the lines compressed so far readable.
+            #[cfg(feature = "zstd")]
+            Self::Compressed(encoder) => encoder.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl OutputFile {
+    /// Creates the temporary file for the given output path.
+    pub fn create(path: &str, policy: OutputPolicy) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        let mut tmp_name = path.file_name().map(OsString::from).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "invalid output file name")
+        })?;
+
+        tmp_name.push(".tmp");
+
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let file = File::create(&tmp_path)?;
+        let file = match policy.compress_threads {
+            None => OutputSink::Temporary(file),
+            #[cfg(feature = "zstd")]
+            Some(threads) => {
+                let mut encoder = zstd::stream::write::Encoder::new(file, 0)?;
+
+                encoder.multithread(threads)?;
+                OutputSink::Compressed(encoder)
+            }
+            #[cfg(not(feature = "zstd"))]
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "output compression requires the 'zstd' feature",
+                ))
+            }
+        };
+
+        Ok(Self::new(file, path, Some(tmp_path), policy))
+    }
+
+    /// Opens the given output path for appending lines to it, creating it if
+    /// needed. The lines are written in place, so that other runs can append
+    /// to the same file at the same time.
+    pub fn append(path: &str, policy: OutputPolicy) -> io::Result<Self> {
+        if policy.compress_threads.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "compressed output files cannot be appended to",
+            ));
+        }
+
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+
+        Ok(Self::new(
+            OutputSink::Shared(file),
+            PathBuf::from(path),
+            None,
+            policy,
+        ))
+    }
+
+    fn new(
+        file: OutputSink,
+        path: PathBuf,
+        tmp_path: Option<PathBuf>,
+        policy: OutputPolicy,
+    ) -> Self {
+        Self {
+            file: BufWriter::with_capacity(policy.buffer_size, file),
+            path,
+            tmp_path,
+            fsync_every: policy.fsync_every,
+            unsynced_lines: 0,
+            flush_interval: policy.flush_interval,
+            last_flush: Instant::now(),
+        }
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.file.write_all(line.as_bytes())?;
+        self.unsynced_lines += 1;
+
+        if self.fsync_every.is_some_and(|n| self.unsynced_lines >= n) {
+            self.flush()?;
+            self.file.get_ref().file().sync_data()?;
+            self.unsynced_lines = 0;
+        } else if self
+            .flush_interval
+            .is_some_and(|interval| self.last_flush.elapsed() >= interval)
+        {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        self.file.flush()
+    }
+
+    /// Syncs the file to disk and moves it to its final name, if it was
+    /// written under a temporary one.
+    pub fn finish(self) -> io::Result<()> {
+        let (path, tmp_path) = (self.path.clone(), self.tmp_path.clone());
+
+        self.sync()?;
+
+        match &tmp_path {
+            Some(tmp_path) => fs::rename(tmp_path, &path),
+            None => Ok(()),
+        }
+    }
+
+    /// Syncs the file to disk without moving it to its final name, for runs
+    /// which did not score all their positions. Returns the path of the file
+    /// holding the positions written so far.
+    pub fn finish_incomplete(self) -> io::Result<PathBuf> {
+        let path = self.tmp_path.clone().unwrap_or_else(|| self.path.clone());
+
+        self.sync()?;
+        Ok(path)
+    }
+
+    fn sync(self) -> io::Result<()> {
+        let file = self
+            .file
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .finish()?;
+
+        file.sync_all()
+    }
+}
@@ -0,0 +1,454 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::time::Duration;
+
+use crate::board::{Color, FenError, Position};
+use crate::engine::Score;
+use crate::game::GameRecord;
+
+/// The possible game termination markers.
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// A game read from a PGN file, with its moves still in SAN.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PgnGame {
+    pub headers: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    /// The `[%eval ...]` annotations following each move, giving the score
+    /// of the position reached after it, from White's point of view.
+    pub evals: Vec<Option<Score>>,
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the rating of the player of the given color, if known.
+    pub fn elo(&self, color: Color) -> Option<u32> {
+        let header = match color {
+            Color::White => "WhiteElo",
+            Color::Black => "BlackElo",
+        };
+
+        self.header(header)?.parse().ok()
+    }
+
+    /// Returns the base time and increment of the game in seconds, for games
+    /// with a '<BASE>+<INC>' or '<BASE>' TimeControl header.
+    pub fn time_control(&self) -> Option<(u32, u32)> {
+        let tc = self.header("TimeControl")?;
+
+        match tc.split_once('+') {
+            Some((base, inc)) => Some((base.parse().ok()?, inc.parse().ok()?)),
+            None => Some((tc.parse().ok()?, 0)),
+        }
+    }
+
+    /// Returns whether the game was played with Chess960 rules, according to
+    /// its Variant header.
+    pub fn is_chess960(&self) -> bool {
+        self.header("Variant").is_some_and(|variant| {
+            let variant = variant.to_ascii_lowercase();
+
+            variant.contains("960") || variant.contains("fischer")
+        })
+    }
+
+    /// Returns the starting position of the game, taken from its FEN header
+    /// if present.
+    pub fn start_position(&self) -> Result<Position, FenError> {
+        let fen = self.header("FEN").unwrap_or(Position::STARTPOS);
+
+        Position::from_fen(fen, self.is_chess960())
+    }
+
+    /// Returns the game result from White's point of view, or None for
+    /// unfinished games.
+    pub fn wdl(&self) -> Option<&'static str> {
+        match self.result.as_str() {
+            "1-0" => Some("1.0"),
+            "0-1" => Some("0.0"),
+            "1/2-1/2" => Some("0.5"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnError(pub String);
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+/// Reads games one at a time from a PGN file.
+pub struct PgnReader<R> {
+    reader: R,
+    pending: Option<String>,
+    line_number: usize,
+    /// The content of the comment being read, if any.
+    comment: Option<String>,
+    variation_depth: usize,
+}
+
+impl PgnReader<BufReader<File>> {
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            pending: None,
+            line_number: 0,
+            comment: None,
+            variation_depth: 0,
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        if let Some(line) = self.pending.take() {
+            return Ok(Some(line));
+        }
+
+        let mut buf = Vec::new();
+
+        if self.reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(None);
+        }
+
+        self.line_number += 1;
+
+        // Old PGN files are frequently encoded in Latin-1, which only matters
+        // for header values.
+        Ok(Some(String::from_utf8_lossy(&buf).into_owned()))
+    }
+
+    fn error(&self, message: &str) -> PgnError {
+        PgnError(format!("line {}: {}", self.line_number, message))
+    }
+
+    /// Reads the next game. A syntax error only invalidates the game it
+    /// occurs in, and reading can continue with the next game.
+    pub fn next_game(&mut self) -> io::Result<Option<Result<PgnGame, PgnError>>> {
+        let mut game = PgnGame::default();
+        let mut error = None;
+        let mut in_movetext = false;
+
+        self.comment = None;
+        self.variation_depth = 0;
+
+        loop {
+            let Some(line) = self.read_line()? else {
+                if game.headers.is_empty() && !in_movetext {
+                    return Ok(None);
+                }
+
+                error.get_or_insert_with(|| self.error("missing game result"));
+                break;
+            };
+            let trimmed = line.trim();
+
+            if self.comment.is_none() {
+                if trimmed.starts_with('%') {
+                    continue;
+                }
+
+                if trimmed.starts_with('[') {
+                    if in_movetext {
+                        // The previous game was not terminated.
+                        self.pending = Some(line);
+                        error.get_or_insert_with(|| self.error("missing game result"));
+                        break;
+                    }
+
+                    match parse_header(trimmed) {
+                        Some(header) => game.headers.push(header),
+                        None => {
+                            error.get_or_insert_with(|| self.error("malformed header"));
+                        }
+                    }
+
+                    continue;
+                }
+            }
+
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            in_movetext = true;
+
+            if self.parse_movetext(trimmed, &mut game, &mut error) {
+                break;
+            }
+        }
+
+        Ok(Some(match error {
+            Some(error) => Err(error),
+            None => Ok(game),
+        }))
+    }
+
+    /// Parses a line of movetext, returning whether the game is over.
+    /// Comments, annotation glyphs and variations are skipped, except for
+    /// `[%eval ...]` comments on main line moves.
+    fn parse_movetext(
+        &mut self,
+        line: &str,
+        game: &mut PgnGame,
+        error: &mut Option<PgnError>,
+    ) -> bool {
+        let mut rest = line;
+
+        loop {
+            if let Some(comment) = &mut self.comment {
+                match rest.find('}') {
+                    Some(end) => {
+                        comment.push_str(&rest[..end]);
+                        rest = &rest[end + 1..];
+
+                        let comment = self.comment.take().unwrap();
+
+                        if self.variation_depth == 0 && !game.moves.is_empty() {
+                            if let Some(eval) = parse_eval(&comment) {
+                                *game.evals.last_mut().unwrap() = Some(eval);
+                            }
+                        }
+                    }
+                    None => {
+                        comment.push_str(rest);
+                        comment.push(' ');
+                        return false;
+                    }
+                }
+            }
+
+            rest = rest.trim_start();
+
+            let Some(c) = rest.chars().next() else {
+                return false;
+            };
+
+            match c {
+                '{' => {
+                    self.comment = Some(String::new());
+                    rest = &rest[1..];
+                }
+                ';' => return false,
+                '(' => {
+                    self.variation_depth += 1;
+                    rest = &rest[1..];
+                }
+                ')' => {
+                    match self.variation_depth {
+                        0 => {
+                            error.get_or_insert_with(|| self.error("unbalanced variation"));
+                        }
+                        _ => self.variation_depth -= 1,
+                    }
+
+                    rest = &rest[1..];
+                }
+                '}' => {
+                    error.get_or_insert_with(|| self.error("unbalanced comment"));
+                    rest = &rest[1..];
+                }
+                _ => {
+                    let end = rest
+                        .find(|c: char| c.is_whitespace() || "{};()".contains(c))
+                        .unwrap_or(rest.len());
+                    let token = &rest[..end];
+
+                    rest = &rest[end..];
+
+                    if RESULTS.contains(&token) {
+                        game.result = token.to_string();
+                        return true;
+                    }
+
+                    // Skip annotation glyphs and variation moves.
+                    if token.starts_with('$') || self.variation_depth > 0 {
+                        continue;
+                    }
+
+                    // Strip move numbers, which may be glued to the move.
+                    let token = match token.starts_with(|c: char| c.is_ascii_digit()) {
+                        true if token.contains('.') => token.rsplit('.').next().unwrap(),
+                        _ => token,
+                    };
+
+                    if !token.is_empty() {
+                        game.moves.push(token.to_string());
+                        game.evals.push(None);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Parses a `[Name "Value"]` header line.
+fn parse_header(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let (name, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+    Some((
+        name.to_string(),
+        value.replace("\\\"", "\"").replace("\\\\", "\\"),
+    ))
+}
+
+/// Extracts the score from an `[%eval ...]` comment command, written in pawns
+/// (e.g. `0.35`) or as a mate distance (e.g. `#-3`).
+fn parse_eval(comment: &str) -> Option<Score> {
+    let start = comment.find("[%eval")? + "[%eval".len();
+    let end = start + comment[start..].find(']')?;
+    let value = comment[start..end]
+        .split([' ', ','])
+        .find(|t| !t.is_empty())?;
+
+    match value.strip_prefix('#') {
+        Some(mate) => mate.parse().ok().map(Score::Mate),
+        None => {
+            let pawns = value.parse::<f64>().ok().filter(|p| p.is_finite())?;
+
+            Some(Score::Cp((pawns * 100.0).round() as i32))
+        }
+    }
+}
+
+/// Writes games in PGN format, optionally annotating moves with `[%eval]`
+/// and `[%clk]` comments as written by lichess.
+pub struct PgnWriter<W> {
+    writer: W,
+    comments: bool,
+}
+
+/// Formats a score from White's point of view for an `[%eval]` comment.
+fn format_eval(score: Score) -> String {
+    match score {
+        Score::Cp(cp) => format!("{:.2}", cp as f64 / 100.0),
+        Score::Mate(mate) => format!("#{}", mate),
+    }
+}
+
+/// Formats a clock time for a `[%clk]` comment, as `h:mm:ss` with tenths of
+/// seconds when needed.
+fn format_clock(time: Duration) -> String {
+    let tenths = time.as_millis() / 100;
+    let secs = tenths / 10;
+    let clock = format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+
+    match tenths % 10 {
+        0 => clock,
+        t => format!("{}.{}", clock, t),
+    }
+}
+
+impl<W: Write> PgnWriter<W> {
+    /// Creates a writer, which annotates moves with their evals and clock
+    /// times when known if `comments` is set.
+    pub fn new(writer: W, comments: bool) -> Self {
+        Self { writer, comments }
+    }
+
+    /// Writes a game, with the given tags before the Result, SetUp and FEN
+    /// ones.
+    pub fn write_game(&mut self, tags: &[(&str, &str)], record: &GameRecord) -> io::Result<()> {
+        let mut pgn = String::new();
+
+        for (name, value) in tags {
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value.replace('"', "\\\"")));
+        }
+
+        pgn.push_str(&format!("[Result \"{}\"]\n", record.result.to_pgn()));
+
+        if record.start.to_fen() != Position::STARTPOS {
+            pgn.push_str(&format!(
+                "[SetUp \"1\"]\n[FEN \"{}\"]\n",
+                record.start.to_fen()
+            ));
+        }
+
+        if record.start.is_chess960() {
+            pgn.push_str("[Variant \"Chess960\"]\n");
+        }
+
+        pgn.push_str(&format!(
+            "[Termination \"{}\"]\n\n",
+            record.termination.description()
+        ));
+
+        let mut pos = record.start.clone();
+        let mut tokens = Vec::new();
+
+        for (i, &mv) in record.moves.iter().enumerate() {
+            if pos.side_to_move() == Color::White {
+                tokens.push(format!("{}.", pos.fullmove_number()));
+            } else if i == 0 || (self.comments && tokens.last().is_some_and(|t| t.ends_with('}'))) {
+                tokens.push(format!("{}...", pos.fullmove_number()));
+            }
+
+            tokens.push(pos.san(mv));
+            pos.play(mv);
+
+            if self.comments {
+                let mut commands = Vec::new();
+
+                if let Some(Some(eval)) = record.evals.get(i) {
+                    commands.push(format!("[%eval {}]", format_eval(*eval)));
+                }
+
+                if let Some(Some(clock)) = record.clocks.get(i) {
+                    commands.push(format!("[%clk {}]", format_clock(*clock)));
+                }
+
+                if !commands.is_empty() {
+                    tokens.push(format!("{{{}}}", commands.join(" ")));
+                }
+            }
+        }
+
+        tokens.push(record.result.to_pgn().to_string());
+
+        // Wrap the movetext at 80 columns, as recommended by the standard.
+        // Comments are split on spaces too, as they may span lines.
+        let mut line_len = 0;
+
+        for word in tokens.iter().flat_map(|token| token.split(' ')) {
+            if line_len > 0 && line_len + word.len() + 1 > 80 {
+                pgn.push('\n');
+                line_len = 0;
+            } else if line_len > 0 {
+                pgn.push(' ');
+                line_len += 1;
+            }
+
+            pgn.push_str(word);
+            line_len += word.len();
+        }
+
+        pgn.push_str("\n\n");
+        self.writer.write_all(pgn.as_bytes())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
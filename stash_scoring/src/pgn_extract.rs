@@ -0,0 +1,472 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::Path;
+
+use clap::{Args, ValueEnum};
+use regex::Regex;
+
+use stash_scoring::board::{Color, Position};
+use stash_scoring::chunks::{finish_chunks, reopen_chunk_dir, ChunkWatcher};
+use stash_scoring::engine::{Score, ScoreFormat};
+use stash_scoring::output::{OutputFile, OutputPolicy};
+use stash_scoring::pgn::{PgnGame, PgnReader};
+use stash_scoring::polyglot::PolyglotBook;
+use stash_scoring::rng::Rng;
+use stash_scoring::sampling::SamplingMethod;
+
+/// Extracts quiet positions (side to move not in check, next move neither a
+/// capture nor a promotion, by default) from the games of a PGN file,
+/// labelled with the game result, in the <FEN WDL> format expected by the
+/// scoring tool.
+#[derive(Args)]
+pub struct PgnExtractArgs {
+    /// The PGN file to read games from, or the directory of PGN chunks with
+    /// --watch.
+    #[arg(short, long)]
+    input_file: String,
+
+    /// The output file for extracted positions, or the directory of position
+    /// chunks with --watch.
+    #[arg(short, long)]
+    output_file: String,
+
+    /// The maximal number of positions kept per game, so that long games do
+    /// not dominate the dataset. By default, all positions are kept.
+    #[arg(long)]
+    max_positions_per_game: Option<usize>,
+
+    /// How positions are picked from games holding more positions than
+    /// --max-positions-per-game.
+    #[arg(long, value_enum, default_value_t = SamplingMethod::Uniform)]
+    sampling: SamplingMethod,
+
+    /// The seed used for sampling positions. With --watch, each chunk is
+    /// sampled with its own seed, derived from this one and the position of
+    /// the chunk in the directory.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Use the '[%eval ...]' annotations of the games (as found in Lichess
+    /// database exports) as labels, writing <FEN WDL EVAL> lines as the
+    /// scoring tool would. Positions without annotation are skipped.
+    #[arg(long)]
+    pgn_evals: bool,
+
+    /// Apply the usual filters for a given game source. 'lichess' skips
+    /// bullet games (estimated duration below 3 minutes), games with a player
+    /// rated below 1800, and games lost on time before move 20.
+    #[arg(long, value_enum)]
+    preset: Option<Preset>,
+
+    /// Skip games with a player rated below this value, or without ratings.
+    #[arg(long)]
+    min_elo: Option<u32>,
+
+    /// Only keep games whose TimeControl header fully matches this regular
+    /// expression (e.g. '(180|300)\+[0-3]'). Can be given several times, in
+    /// which case games matching any of the patterns are kept.
+    #[arg(long)]
+    time_control: Vec<Regex>,
+
+    /// Skip games with this Termination header (e.g. 'Abandoned'), ignoring
+    /// case. Can be given several times.
+    #[arg(long)]
+    termination: Vec<String>,
+
+    /// Only keep games whose Event header matches this regular expression.
+    #[arg(long)]
+    event: Option<Regex>,
+
+    /// Only keep games whose Site header matches this regular expression.
+    #[arg(long)]
+    site: Option<Regex>,
+
+    /// Keep games whose moves exactly match those of a previous game. By
+    /// default, such duplicates (common when merging PGN dumps from several
+    /// sources) are dropped.
+    #[arg(long)]
+    keep_duplicates: bool,
+
+    /// Drop positions found in this Polyglot opening book, as heavily booked
+    /// positions add little training signal.
+    #[arg(long)]
+    exclude_book: Option<String>,
+
+    /// Drop positions less than this many plies away from the standard
+    /// starting position, as given by their move number.
+    #[arg(long, default_value_t = 0)]
+    skip_plies: u32,
+
+    /// Drop positions whose annotated evaluation is this large or larger, in
+    /// centipawns, as well as mate scores. Positions without annotation are
+    /// kept.
+    #[arg(long)]
+    max_eval: Option<u32>,
+
+    /// Also drop positions reached by a capture.
+    #[arg(long)]
+    skip_after_captures: bool,
+
+    /// Keep positions where the side to move is in check.
+    #[arg(long)]
+    keep_checks: bool,
+
+    /// Keep positions whose next move is a capture or a promotion.
+    #[arg(long)]
+    keep_noisy_moves: bool,
+
+    /// Write the positions of Chess960 games with Shredder-FEN castling
+    /// rights (rook files), as expected by most FRC trainers, instead of
+    /// X-FEN ones.
+    #[arg(long)]
+    shredder_fen: bool,
+
+    /// Compress the output file with Zstandard, on this many threads.
+    /// Requires the 'zstd' feature.
+    #[arg(long)]
+    compress_threads: Option<u32>,
+
+    /// Watch the input directory for the PGN chunks written by a match with
+    /// --chunk-dir, extracting the positions of each one to a chunk of the
+    /// output directory as they appear, for the scoring tool to pick up with
+    /// its --watch flag. Chunks already extracted are skipped, and an 'END'
+    /// file is written to the output directory once the match is over.
+    #[arg(long, conflicts_with = "compress_threads")]
+    watch: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    Lichess,
+}
+
+/// Header-based criteria deciding which games are extracted, checked before
+/// replaying the moves.
+#[derive(Clone, Debug, Default)]
+struct GameFilter {
+    /// The minimal rating of both players.
+    min_elo: Option<u32>,
+    /// The minimal estimated game duration in seconds, computed as
+    /// <BASE> + 40 * <INC> like Lichess does.
+    min_duration: Option<u32>,
+    /// Skip games lost on time with less than this many plies.
+    min_time_forfeit_plies: Option<usize>,
+    time_controls: Vec<Regex>,
+    excluded_terminations: Vec<String>,
+    event: Option<Regex>,
+    site: Option<Regex>,
+}
+
+impl GameFilter {
+    fn new(args: &PgnExtractArgs) -> Self {
+        let mut filter = match args.preset {
+            Some(Preset::Lichess) => Self {
+                min_elo: Some(1800),
+                min_duration: Some(180),
+                min_time_forfeit_plies: Some(40),
+                ..Self::default()
+            },
+            None => Self::default(),
+        };
+
+        // Explicit filters take precedence over the preset ones.
+        filter.min_elo = args.min_elo.or(filter.min_elo);
+        filter.time_controls = args
+            .time_control
+            .iter()
+            .map(|re| Regex::new(&format!("^(?:{})$", re.as_str())).unwrap())
+            .collect();
+        filter.excluded_terminations = args.termination.clone();
+        filter.event = args.event.clone();
+        filter.site = args.site.clone();
+        filter
+    }
+
+    /// Checks whether the header matches the pattern, missing headers never
+    /// matching.
+    fn header_matches(game: &PgnGame, name: &str, pattern: &Regex) -> bool {
+        game.header(name)
+            .is_some_and(|value| pattern.is_match(value))
+    }
+
+    fn accepts(&self, game: &PgnGame) -> bool {
+        if let Some(event) = &self.event {
+            if !Self::header_matches(game, "Event", event) {
+                return false;
+            }
+        }
+
+        if let Some(site) = &self.site {
+            if !Self::header_matches(game, "Site", site) {
+                return false;
+            }
+        }
+
+        if !self.time_controls.is_empty()
+            && !self
+                .time_controls
+                .iter()
+                .any(|tc| Self::header_matches(game, "TimeControl", tc))
+        {
+            return false;
+        }
+
+        if let Some(termination) = game.header("Termination") {
+            if self
+                .excluded_terminations
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(termination))
+            {
+                return false;
+            }
+        }
+
+        if let Some(min_elo) = self.min_elo {
+            let rated = [Color::White, Color::Black]
+                .into_iter()
+                .all(|color| game.elo(color).is_some_and(|elo| elo >= min_elo));
+
+            if !rated {
+                return false;
+            }
+        }
+
+        if let Some(min_duration) = self.min_duration {
+            // Games without a parsable time control (e.g. correspondence
+            // games) are never too fast.
+            if let Some((base, inc)) = game.time_control() {
+                if base + 40 * inc < min_duration {
+                    return false;
+                }
+            }
+        }
+
+        if let Some(min_plies) = self.min_time_forfeit_plies {
+            if game.header("Termination") == Some("Time forfeit") && game.moves.len() < min_plies {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Hashes the starting position and the moves of the game, which identify it
+/// regardless of its headers.
+fn game_hash(game: &PgnGame) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    game.header("FEN").hash(&mut hasher);
+    game.moves.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Position-based criteria dropping opening positions, which are massively
+/// overrepresented in game collections.
+struct OpeningFilter {
+    book: Option<PolyglotBook>,
+    skip_plies: u32,
+}
+
+impl OpeningFilter {
+    fn excludes(&self, pos: &Position) -> bool {
+        let ply =
+            2 * (pos.fullmove_number().max(1) - 1) + u32::from(pos.side_to_move() == Color::Black);
+
+        ply < self.skip_plies || self.book.as_ref().is_some_and(|book| book.contains(pos))
+    }
+}
+
+/// Replays the game, returning its quiet positions along with their annotated
+/// evaluation, from the side to move's point of view.
+fn quiet_positions(
+    game: &PgnGame,
+    args: &PgnExtractArgs,
+) -> Result<Vec<(Position, Option<Score>)>, String> {
+    let mut pos = game.start_position().map_err(|err| err.to_string())?;
+    let mut positions = Vec::new();
+    let mut eval: Option<Score> = None;
+    let mut after_capture = false;
+
+    for (san, next_eval) in game.moves.iter().zip(&game.evals) {
+        let mv = pos.parse_san(san).map_err(|err| err.to_string())?;
+        let noisy = pos.is_capture(mv) || mv.promotion.is_some();
+
+        if (args.keep_checks || !pos.in_check())
+            && (args.keep_noisy_moves || !noisy)
+            && !(args.skip_after_captures && after_capture)
+        {
+            let eval = match pos.side_to_move() {
+                Color::White => eval,
+                Color::Black => eval.map(|score| score.flipped()),
+            };
+
+            positions.push((pos.clone(), eval));
+        }
+
+        after_capture = pos.is_capture(mv);
+        pos.play(mv);
+        eval = *next_eval;
+    }
+
+    Ok(positions)
+}
+
+/// Extracts the positions of a PGN file, sampling them with the given seed.
+fn extract(
+    args: &PgnExtractArgs,
+    input_file: &str,
+    output_file: &str,
+    seed: u64,
+) -> io::Result<()> {
+    let mut reader = PgnReader::open(input_file)?;
+    let policy = OutputPolicy {
+        compress_threads: args.compress_threads,
+        ..OutputPolicy::default()
+    };
+    let mut ofile = OutputFile::create(output_file, policy)?;
+    let mut rng = Rng::new(seed);
+    let mut games = 0;
+    let mut invalid = 0;
+    let mut unfinished = 0;
+    let mut filtered = 0;
+    let mut duplicates = 0;
+    let filter = GameFilter::new(args);
+    let mut seen_games = HashSet::new();
+    let mut positions = 0;
+    let mut openings = 0;
+    let mut unbalanced = 0;
+    let opening_filter = OpeningFilter {
+        book: args
+            .exclude_book
+            .as_deref()
+            .map(PolyglotBook::open)
+            .transpose()?,
+        skip_plies: args.skip_plies,
+    };
+
+    while let Some(game) = reader.next_game()? {
+        games += 1;
+
+        let game = match game {
+            Ok(game) => game,
+            Err(err) => {
+                eprintln!("Skipping game {}: {}", games, err);
+                invalid += 1;
+                continue;
+            }
+        };
+        let Some(wdl) = game.wdl() else {
+            unfinished += 1;
+            continue;
+        };
+
+        if !filter.accepts(&game) {
+            filtered += 1;
+            continue;
+        }
+
+        if !args.keep_duplicates && !seen_games.insert(game_hash(&game)) {
+            duplicates += 1;
+            continue;
+        }
+
+        let mut fens = match quiet_positions(&game, args) {
+            Ok(fens) => fens,
+            Err(err) => {
+                eprintln!("Skipping game {}: {}", games, err);
+                invalid += 1;
+                continue;
+            }
+        };
+        if args.pgn_evals {
+            fens.retain(|(_, eval)| eval.is_some());
+        }
+
+        if let Some(max_eval) = args.max_eval {
+            let quiet = fens.len();
+
+            fens.retain(|(_, eval)| {
+                eval.is_none_or(|eval| !eval.is_mate() && eval.folded().unsigned_abs() < max_eval)
+            });
+            unbalanced += quiet - fens.len();
+        }
+
+        let quiet = fens.len();
+
+        fens.retain(|(pos, _)| !opening_filter.excludes(pos));
+        openings += quiet - fens.len();
+
+        let indices = match args.max_positions_per_game {
+            Some(max) => args.sampling.sample(fens.len(), max, &mut rng),
+            None => (0..fens.len()).collect(),
+        };
+
+        for idx in indices {
+            let fen = match args.shredder_fen && game.is_chess960() {
+                true => fens[idx].0.to_shredder_fen(),
+                false => fens[idx].0.to_fen(),
+            };
+            let line = match fens[idx].1 {
+                Some(eval) if args.pgn_evals => {
+                    format!("{} {} {}\n", fen, wdl, eval.display(ScoreFormat::Pound))
+                }
+                _ => format!("{} {}\n", fen, wdl),
+            };
+
+            ofile.write_line(&line)?;
+            positions += 1;
+        }
+    }
+
+    ofile.finish()?;
+
+    println!(
+        "{} games read, {} invalid, {} unfinished, {} filtered out, {} duplicates dropped, \
+         {} opening positions dropped, {} positions beyond the eval bound dropped, \
+         {} positions written",
+        games, invalid, unfinished, filtered, duplicates, openings, unbalanced, positions
+    );
+
+    Ok(())
+}
+
+pub fn run(args: &PgnExtractArgs) -> io::Result<()> {
+    if !args.watch {
+        return extract(args, &args.input_file, &args.output_file, args.seed);
+    }
+
+    let output_dir = Path::new(&args.output_file);
+    let mut watcher = ChunkWatcher::new(Path::new(&args.input_file), "pgn");
+
+    reopen_chunk_dir(output_dir)?;
+
+    // Chunks already extracted are counted as well, so that a resumed run
+    // samples the other ones as the first run would have.
+    let mut chunk_index = 0;
+
+    while let Some(chunk) = watcher.next_chunk(|| false)? {
+        let output = output_dir
+            .join(chunk.file_name().unwrap())
+            .with_extension("txt");
+        let seed = Rng::derive(args.seed, chunk_index);
+
+        chunk_index += 1;
+
+        if output.exists() {
+            continue;
+        }
+
+        eprintln!("Extracting positions from '{}'", chunk.display());
+        extract(
+            args,
+            &chunk.to_string_lossy(),
+            &output.to_string_lossy(),
+            seed,
+        )?;
+    }
+
+    finish_chunks(output_dir)
+}
@@ -0,0 +1,182 @@
+use std::io;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::board::Position;
+use crate::engine::{EngineConfig, Score, SearchLimit, SearchResult};
+use crate::events::EventBus;
+use crate::task_queue::{Task, TaskQueue, TaskWorker, DEFAULT_HEARTBEAT};
+
+/// The number of times a search is attempted, restarting the engine after
+/// each failure, before its error is returned.
+const MAX_ATTEMPTS: usize = 3;
+
+/// A submitted search, with the channel to send its result back to the
+/// caller.
+struct Search {
+    fen: String,
+    limit: SearchLimit,
+    attempts: usize,
+    reply: ResultSender,
+}
+
+/// The channel the results of submitted searches are sent to, along with the
+/// index they were submitted with.
+pub type ResultSender = Sender<(usize, io::Result<SearchResult>)>;
+
+/// A pool of engine instances searching batches of positions, for programs
+/// which need scores without handling the engines and the task queue
+/// themselves.
+///
+/// The pool can be shared between threads, each batch being spread over all
+/// the engines. Failed searches are retried on a restarted engine, and idle
+/// engines which stop answering are restarted too.
+pub struct EnginePool {
+    queue: Arc<TaskQueue<Search, ()>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl EnginePool {
+    /// Starts the given number of instances of the engine. Chess960
+    /// positions require the UCI_Chess960 option to be set in its
+    /// configuration.
+    pub fn new(engine: &EngineConfig, instances: usize) -> io::Result<Self> {
+        let queue = Arc::new(TaskQueue::new());
+        let workers = (0..instances)
+            .map(|_| TaskWorker::try_new(&queue, engine))
+            .collect::<io::Result<Vec<_>>>()?;
+        let threads = workers
+            .into_iter()
+            .map(|mut worker| {
+                worker.set_heartbeat(Some(DEFAULT_HEARTBEAT));
+                thread::spawn(move || run_searches(worker))
+            })
+            .collect();
+
+        Ok(Self { queue, threads })
+    }
+
+    /// The bus the events of the engines are sent to.
+    pub fn events(&self) -> &Arc<EventBus> {
+        self.queue.events()
+    }
+
+    /// Queues the search of a position, whose result is sent with the given
+    /// index to the channel once done.
+    pub fn submit(&self, index: usize, pos: &Position, limit: &SearchLimit, reply: &ResultSender) {
+        let search = Search {
+            fen: pos.to_fen(),
+            limit: limit.clone(),
+            attempts: 0,
+            reply: reply.clone(),
+        };
+
+        self.queue.add_workload(Task::new(index, search));
+    }
+
+    /// Whether some engines are still running. Submitted searches are never
+    /// done once all the engines failed for good.
+    pub fn has_engines(&self) -> bool {
+        !self.queue.no_active_workers()
+    }
+
+    /// Searches the positions with the given limit, returning the results in
+    /// the same order.
+    pub fn search_batch(
+        &self,
+        positions: &[Position],
+        limit: &SearchLimit,
+    ) -> Vec<io::Result<SearchResult>> {
+        let (reply, replies) = mpsc::channel();
+
+        for (index, pos) in positions.iter().enumerate() {
+            self.submit(index, pos, limit, &reply);
+        }
+
+        drop(reply);
+
+        let mut results: Vec<Option<io::Result<SearchResult>>> =
+            positions.iter().map(|_| None).collect();
+        let mut missing = positions.len();
+
+        while missing > 0 {
+            match replies.recv_timeout(Duration::from_millis(100)) {
+                Ok((index, result)) => {
+                    results[index] = Some(result);
+                    missing -= 1;
+                }
+                Err(RecvTimeoutError::Timeout) if self.has_engines() => (),
+                Err(_) => break,
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| Err(io::Error::other("no engine is left in the pool")))
+            })
+            .collect()
+    }
+
+    /// Scores the positions with the given limit, returning the scores in
+    /// the same order.
+    pub fn score_batch(
+        &self,
+        positions: &[Position],
+        limit: &SearchLimit,
+    ) -> Vec<io::Result<Score>> {
+        self.search_batch(positions, limit)
+            .into_iter()
+            .map(|result| result.map(|result| result.score))
+            .collect()
+    }
+}
+
+impl Drop for EnginePool {
+    /// Lets the engines finish the queued searches, and waits for them.
+    fn drop(&mut self) {
+        self.queue.stop_workload();
+
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Searches the queued positions until the pool is dropped, or until the
+/// engine of the worker cannot be restarted.
+fn run_searches(mut worker: TaskWorker<Search, ()>) {
+    while let Ok(Some(task)) = worker.query_workload() {
+        let index = task.index();
+        let mut search = task.into_payload();
+        let engine = worker.engine_mut();
+        let result = engine
+            .setup_position(&search.fen, &[])
+            .and_then(|_| engine.run_search(&search.limit));
+        let err = match result {
+            Ok(result) => {
+                // The caller may have given up on the batch in the meantime.
+                let _ = search.reply.send((index, Ok(result)));
+                continue;
+            }
+            Err(err) => err,
+        };
+
+        // Without an engine, the worker leaves the searches to the others.
+        let restarted = worker.restart_engine(&err.to_string()).is_ok();
+
+        search.attempts += 1;
+
+        if restarted && search.attempts < MAX_ATTEMPTS {
+            worker.requeue_workload(Task::new(index, search));
+        } else {
+            let _ = search.reply.send((index, Err(err)));
+        }
+
+        if !restarted {
+            return;
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::binpack::TrainingDataReader;
+
+/// Reads the lines of a file through a buffered reader, reusing the same
+/// line buffer for the whole file.
+pub struct BufferedLines<R = BufReader<File>> {
+    reader: R,
+    buf: Vec<u8>,
+    offset: u64,
+    size: u64,
+}
+
+/// The reader of a Zstandard-compressed file.
+#[cfg(feature = "zstd")]
+type ZstdReader = BufReader<zstd::Decoder<'static, BufReader<File>>>;
+
+impl BufferedLines {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            reader: BufReader::new(file),
+            buf: Vec::new(),
+            offset: 0,
+            size,
+        })
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl BufferedLines<ZstdReader> {
+    pub fn open_zstd(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            reader: BufReader::new(zstd::Decoder::new(file)?),
+            buf: Vec::new(),
+            offset: 0,
+            size,
+        })
+    }
+
+    /// The number of compressed bytes consumed by the decoder so far. The
+    /// line offset counts decompressed bytes, which cannot be compared with
+    /// the file size.
+    fn compressed_offset(&self) -> u64 {
+        let file_reader = self.reader.get_ref().get_ref();
+        let buffered = file_reader.buffer().len() as u64;
+
+        (&mut file_reader.get_ref())
+            .stream_position()
+            .map_or(0, |pos| pos.saturating_sub(buffered))
+    }
+}
+
+impl<R: BufRead> BufferedLines<R> {
+    /// Returns the next line, including its end-of-line character.
+    pub fn next_line(&mut self) -> io::Result<Option<&[u8]>> {
+        self.buf.clear();
+
+        self.offset += self.reader.read_until(b'\n', &mut self.buf)? as u64;
+
+        match self.buf.len() {
+            0 => Ok(None),
+            _ => Ok(Some(&self.buf)),
+        }
+    }
+
+    /// Returns the next lines, stopping at the first line end after
+    /// `min_size` bytes.
+    pub fn next_chunk(&mut self, min_size: usize) -> io::Result<Option<&[u8]>> {
+        self.buf.clear();
+
+        while self.buf.len() < min_size {
+            match self.reader.read_until(b'\n', &mut self.buf)? {
+                0 => break,
+                len => self.offset += len as u64,
+            }
+        }
+
+        match self.buf.len() {
+            0 => Ok(None),
+            _ => Ok(Some(&self.buf)),
+        }
+    }
+}
+
+/// Reads the lines of a file by mapping it in memory, handing out slices of
+/// the mapping without copying.
+#[cfg(feature = "mmap")]
+pub struct MappedLines {
+    map: memmap2::Mmap,
+    offset: usize,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedLines {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+
+        // SAFETY: the input file is expected not to be modified while the
+        // dataset is being scored.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+
+        #[cfg(unix)]
+        map.advise(memmap2::Advice::Sequential)?;
+
+        Ok(Self { map, offset: 0 })
+    }
+
+    /// Returns the next line, including its end-of-line character.
+    pub fn next_line(&mut self) -> Option<&[u8]> {
+        let remaining = &self.map[self.offset..];
+
+        if remaining.is_empty() {
+            return None;
+        }
+
+        let len = memchr::memchr(b'\n', remaining).map_or(remaining.len(), |idx| idx + 1);
+
+        self.offset += len;
+        Some(&remaining[..len])
+    }
+
+    /// Returns the next lines, stopping at the first line end after
+    /// `min_size` bytes.
+    pub fn next_chunk(&mut self, min_size: usize) -> Option<&[u8]> {
+        let remaining = &self.map[self.offset..];
+
+        if remaining.is_empty() {
+            return None;
+        }
+
+        let start = min_size.min(remaining.len() - 1);
+        let len = memchr::memchr(b'\n', &remaining[start..])
+            .map_or(remaining.len(), |idx| start + idx + 1);
+
+        self.offset += len;
+        Some(&remaining[..len])
+    }
+}
+
+/// Reads the positions of a Stockfish dataset as lines of FENs followed by
+/// their WDL labels. Invalid entries are reported and skipped.
+pub struct TrainingDataLines {
+    reader: TrainingDataReader,
+    buf: Vec<u8>,
+    entries: u64,
+    size: u64,
+}
+
+impl TrainingDataLines {
+    fn new(reader: TrainingDataReader, path: &str) -> io::Result<Self> {
+        Ok(Self {
+            reader,
+            buf: Vec::new(),
+            entries: 0,
+            size: std::fs::metadata(path)?.len(),
+        })
+    }
+
+    /// Appends the line of the next valid entry to the buffer, returning
+    /// false at the end of the file.
+    fn push_entry(&mut self) -> io::Result<bool> {
+        while let Some(entry) = self.reader.next_entry()? {
+            self.entries += 1;
+
+            match entry {
+                Ok(entry) => {
+                    writeln!(self.buf, "{} {}", entry.pos.to_fen(), entry.wdl())?;
+                    return Ok(true);
+                }
+                Err(err) => eprintln!("Skipping entry {}: {}", self.entries, err),
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Returns the next line, including its end-of-line character.
+    pub fn next_line(&mut self) -> io::Result<Option<&[u8]>> {
+        self.buf.clear();
+
+        match self.push_entry()? {
+            true => Ok(Some(&self.buf)),
+            false => Ok(None),
+        }
+    }
+
+    /// Returns the next lines, stopping once they hold `min_size` bytes.
+    pub fn next_chunk(&mut self, min_size: usize) -> io::Result<Option<&[u8]>> {
+        self.buf.clear();
+
+        while self.buf.len() < min_size && self.push_entry()? {}
+
+        match self.buf.len() {
+            0 => Ok(None),
+            _ => Ok(Some(&self.buf)),
+        }
+    }
+}
+
+/// The input file of a scoring run, read with one of the available methods.
+pub enum InputReader {
+    Buffered(BufferedLines),
+    #[cfg(feature = "mmap")]
+    Mapped(MappedLines),
+    #[cfg(feature = "zstd")]
+    Compressed(BufferedLines<ZstdReader>),
+    TrainingData(TrainingDataLines),
+}
+
+impl InputReader {
+    /// Opens the file, memory-mapping it if requested. Stockfish datasets,
+    /// recognized by their '.binpack' or '.plain' extension, are decoded
+    /// into FEN and WDL lines, and files with a '.zst' extension are
+    /// decompressed with Zstandard.
+    pub fn open(path: &str, mmap: bool) -> io::Result<Self> {
+        if Path::new(path).extension() == Some(OsStr::new("zst")) {
+            if mmap {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "compressed input files cannot be memory-mapped",
+                ));
+            }
+
+            #[cfg(feature = "zstd")]
+            return Ok(Self::Compressed(BufferedLines::open_zstd(path)?));
+
+            #[cfg(not(feature = "zstd"))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "compressed input files require the 'zstd' feature",
+            ));
+        }
+
+        if let Some(reader) = TrainingDataReader::open(path)? {
+            if mmap {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Stockfish datasets cannot be memory-mapped",
+                ));
+            }
+
+            return Ok(Self::TrainingData(TrainingDataLines::new(reader, path)?));
+        }
+
+        if mmap {
+            #[cfg(feature = "mmap")]
+            return Ok(Self::Mapped(MappedLines::open(path)?));
+
+            #[cfg(not(feature = "mmap"))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "memory-mapped reading requires the 'mmap' feature",
+            ));
+        }
+
+        Ok(Self::Buffered(BufferedLines::open(path)?))
+    }
+
+    /// The number of bytes of the file read so far, counted in the file
+    /// itself for compressed files.
+    pub fn offset(&self) -> u64 {
+        match self {
+            Self::Buffered(lines) => lines.offset,
+            #[cfg(feature = "mmap")]
+            Self::Mapped(lines) => lines.offset as u64,
+            #[cfg(feature = "zstd")]
+            Self::Compressed(lines) => lines.compressed_offset(),
+            Self::TrainingData(lines) => lines.reader.bytes_read(),
+        }
+    }
+
+    /// The size of the file, in bytes.
+    pub fn size(&self) -> u64 {
+        match self {
+            Self::Buffered(lines) => lines.size,
+            #[cfg(feature = "mmap")]
+            Self::Mapped(lines) => lines.map.len() as u64,
+            #[cfg(feature = "zstd")]
+            Self::Compressed(lines) => lines.size,
+            Self::TrainingData(lines) => lines.size,
+        }
+    }
+
+    /// The fraction of the file read so far, between 0 and 1. Empty files
+    /// count as fully read.
+    pub fn progress(&self) -> f64 {
+        match self.size() {
+            0 => 1.0,
+            size => self.offset() as f64 / size as f64,
+        }
+    }
+
+    /// Returns the next line, including its end-of-line character.
+    pub fn next_line(&mut self) -> io::Result<Option<&[u8]>> {
+        match self {
+            Self::Buffered(lines) => lines.next_line(),
+            #[cfg(feature = "mmap")]
+            Self::Mapped(lines) => Ok(lines.next_line()),
+            #[cfg(feature = "zstd")]
+            Self::Compressed(lines) => lines.next_line(),
+            Self::TrainingData(lines) => lines.next_line(),
+        }
+    }
+
+    /// Returns a shared buffer holding the next complete lines of the file,
+    /// of at least `min_size` bytes unless the end of the file is reached.
+    pub fn next_chunk(&mut self, min_size: usize) -> io::Result<Option<Arc<str>>> {
+        let chunk = match self {
+            Self::Buffered(lines) => lines.next_chunk(min_size)?,
+            #[cfg(feature = "mmap")]
+            Self::Mapped(lines) => lines.next_chunk(min_size),
+            #[cfg(feature = "zstd")]
+            Self::Compressed(lines) => lines.next_chunk(min_size)?,
+            Self::TrainingData(lines) => lines.next_chunk(min_size)?,
+        };
+
+        chunk
+            .map(|bytes| {
+                std::str::from_utf8(bytes)
+                    .map(Arc::from)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            })
+            .transpose()
+    }
+}
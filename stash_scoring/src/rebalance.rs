@@ -0,0 +1,184 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use clap::Args;
+
+use stash_scoring::input::{InputColumn, InputSchema};
+use stash_scoring::output::{OutputFile, OutputPolicy};
+use stash_scoring::reader::InputReader;
+use stash_scoring::rng::Rng;
+
+/// Downsamples the overrepresented result classes of a dataset (usually
+/// draws) so that wins, draws and losses follow the given ratios. The input
+/// file is read twice: once for counting the classes, and once for sampling.
+#[derive(Args)]
+pub struct RebalanceArgs {
+    /// The dataset file to rebalance.
+    #[arg(short, long)]
+    input_file: String,
+
+    /// The layout of the dataset lines, using the same syntax as the
+    /// --input-columns flag of the scoring tool. A 'wdl' column is required.
+    #[arg(long, default_value = "fen,wdl")]
+    input_columns: InputSchema,
+
+    /// The output file for the rebalanced dataset.
+    #[arg(short, long)]
+    output_file: String,
+
+    /// The wanted proportions of wins, draws and losses (from White's point of
+    /// view), as 'WIN:DRAW:LOSS'. They don't need to sum up to 1.
+    #[arg(long, default_value = "1:1:1")]
+    target_ratios: ClassRatios,
+
+    /// The seed used for sampling lines.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+/// Proportions of wins, draws and losses.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClassRatios([f64; 3]);
+
+impl FromStr for ClassRatios {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(':')
+            .map(|v| v.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("invalid ratio in '{}': {}", s, err))?;
+
+        match values[..] {
+            [win, draw, loss] if values.iter().all(|&v| v >= 0.0) && win + draw + loss > 0.0 => {
+                Ok(Self([win, draw, loss]))
+            }
+            _ => Err(format!(
+                "expected three non-negative ratios 'WIN:DRAW:LOSS', got '{}'",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ClassRatios {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.0[0], self.0[1], self.0[2])
+    }
+}
+
+const CLASS_NAMES: [&str; 3] = ["wins", "draws", "losses"];
+
+/// Returns the class of the line (0 for wins, 1 for draws and 2 for losses).
+/// Soft labels are classified by rounding them to the nearest result.
+fn classify(schema: &InputSchema, line: &str) -> Result<usize, String> {
+    let record = schema.split(line).map_err(|err| err.to_string())?;
+    let wdl = record.wdl.unwrap();
+    let value = wdl
+        .parse::<f64>()
+        .map_err(|_| format!("unparsable WDL value '{}'", wdl))?;
+
+    Ok(if value > 0.75 {
+        0
+    } else if value < 0.25 {
+        2
+    } else {
+        1
+    })
+}
+
+/// Iterates over the class of each line of the input file, along with the
+/// line itself.
+fn for_each_line(
+    args: &RebalanceArgs,
+    mut f: impl FnMut(usize, &str) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut reader = InputReader::open(&args.input_file, false)?;
+    let mut line_number = 0;
+
+    while let Some(bytes) = reader.next_line()? {
+        line_number += 1;
+
+        let line = std::str::from_utf8(bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let class = classify(&args.input_columns, line).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", line_number, err),
+            )
+        })?;
+
+        f(class, line)?;
+    }
+
+    Ok(())
+}
+
+fn print_distribution(title: &str, counts: &[usize; 3]) {
+    let total: usize = counts.iter().sum();
+
+    println!("{} ({} lines):", title, total);
+
+    for (name, &count) in CLASS_NAMES.iter().zip(counts) {
+        let share = match total {
+            0 => 0.0,
+            _ => count as f64 / total as f64 * 100.0,
+        };
+
+        println!("  {:<6} {:>12} ({:.2}%)", name, count, share);
+    }
+}
+
+pub fn run(args: &RebalanceArgs) -> io::Result<()> {
+    if !args.input_columns.contains(InputColumn::Wdl) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "rebalancing requires a 'wdl' input column",
+        ));
+    }
+
+    let mut before = [0usize; 3];
+
+    for_each_line(args, |class, _| {
+        before[class] += 1;
+        Ok(())
+    })?;
+
+    // Keep as many lines as possible while matching the target ratios: the
+    // class with the lowest count relative to its ratio is kept entirely.
+    let ratios = args.target_ratios.0;
+    let total_ratio: f64 = ratios.iter().sum();
+    let output_size = (0..3)
+        .filter(|&c| ratios[c] > 0.0 && before[c] > 0)
+        .map(|c| before[c] as f64 * total_ratio / ratios[c])
+        .fold(f64::INFINITY, f64::min);
+    let keep_rates: Vec<f64> = (0..3)
+        .map(|c| match before[c] {
+            0 => 0.0,
+            n => (output_size * ratios[c] / total_ratio / n as f64).min(1.0),
+        })
+        .collect();
+
+    let mut rng = Rng::new(args.seed);
+    let mut ofile = OutputFile::create(&args.output_file, OutputPolicy::default())?;
+    let mut after = [0usize; 3];
+
+    for_each_line(args, |class, line| {
+        if rng.next_f64() < keep_rates[class] {
+            after[class] += 1;
+            ofile.write_line(line)?;
+        }
+
+        Ok(())
+    })?;
+
+    ofile.finish()?;
+
+    println!("Target ratios: {}", args.target_ratios);
+    print_distribution("Before", &before);
+    print_distribution("After", &after);
+
+    Ok(())
+}
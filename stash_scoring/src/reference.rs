@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::sync::Mutex;
+
+use crate::engine::{Score, ScoreFormat};
+
+/// Compares new scores with the ones of a reference file as they come in,
+/// e.g. to check that an engine change did not alter its evaluations. The
+/// positions whose score moved by at least a threshold are written to a
+/// separate file right away, so that they can be inspected while the run is
+/// still going on.
+pub struct ReferenceDiff {
+    scores: HashMap<String, Score>,
+    threshold: u32,
+    score_format: ScoreFormat,
+    stats: Mutex<ReferenceStats>,
+    file: Mutex<Box<dyn Write + Send>>,
+}
+
+/// How the new scores compare with the reference ones so far. Differences
+/// are in centipawns, with mates folded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReferenceStats {
+    /// The positions found in the reference.
+    pub compared: usize,
+    /// The positions missing from the reference.
+    pub missing: usize,
+    /// The positions whose score differs by at least the threshold.
+    pub disagreements: usize,
+    pub total_difference: u64,
+    pub max_difference: u32,
+}
+
+impl ReferenceDiff {
+    /// Creates a comparison with the given reference scores, keyed like
+    /// the positions passed to [`Self::compare`], writing disagreements to
+    /// `file`.
+    pub fn new(
+        scores: HashMap<String, Score>,
+        threshold: u32,
+        score_format: ScoreFormat,
+        file: Box<dyn Write + Send>,
+    ) -> Self {
+        Self {
+            scores,
+            threshold,
+            score_format,
+            stats: Mutex::new(ReferenceStats::default()),
+            file: Mutex::new(file),
+        }
+    }
+
+    /// Compares the new score of a position with its reference one, if
+    /// any. A disagreement is written as a `<POSITION> <REFERENCE> <SCORE>
+    /// <DIFFERENCE>` line, the difference being the new score minus the
+    /// reference one, and the position the text identifying it in the
+    /// output (its FEN, and the move played from it if any).
+    pub fn compare(&self, key: &str, position: &str, score: Score) -> io::Result<()> {
+        let Some(&reference) = self.scores.get(key) else {
+            self.stats.lock().unwrap().missing += 1;
+            return Ok(());
+        };
+        let difference = score.folded().saturating_sub(reference.folded());
+        let disagrees = difference.unsigned_abs() >= self.threshold;
+
+        {
+            let mut stats = self.stats.lock().unwrap();
+
+            stats.compared += 1;
+            stats.disagreements += usize::from(disagrees);
+            stats.total_difference += u64::from(difference.unsigned_abs());
+            stats.max_difference = stats.max_difference.max(difference.unsigned_abs());
+        }
+
+        if disagrees {
+            let mut file = self.file.lock().unwrap();
+
+            writeln!(
+                file,
+                "{} {} {} {:+}",
+                position,
+                reference.display(self.score_format),
+                score.display(self.score_format),
+                difference
+            )?;
+            file.flush()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn stats(&self) -> ReferenceStats {
+        *self.stats.lock().unwrap()
+    }
+}
+
+impl fmt::Display for ReferenceStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mean = match self.compared {
+            0 => 0.0,
+            compared => self.total_difference as f64 / compared as f64,
+        };
+
+        write!(
+            f,
+            "{} positions compared, {} disagreements, score differences: mean {:.1}, max {}, {} positions missing from the reference",
+            self.compared, self.disagreements, mean, self.max_difference, self.missing
+        )
+    }
+}
@@ -0,0 +1,179 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use toml::{Table, Value};
+
+use crate::engine::{EngineConfig, EngineProfile};
+
+/// A registry of named engine configurations, so that tools can refer to an
+/// engine by its name instead of repeating its path and options.
+///
+/// The registry is a TOML file with one table per engine:
+///
+/// ```toml
+/// [stash-dev]
+/// cmd = "/home/user/stash/src/stash"
+/// args = ["--uci"]
+/// dir = "/home/user/stash"
+/// profile = "generic"
+/// sync = ["EvalFile"]
+/// errors = ["^info string NNUE evaluation failed"]
+/// clearenv = true
+///
+/// [stash-dev.options]
+/// Hash = 64
+/// EvalFile = "nets/latest.nnue"
+///
+/// [stash-dev.env]
+/// LC_ALL = "C"
+/// ```
+///
+/// Only 'cmd' is required. 'sync' lists the options after which the engine
+/// must be waited for, besides the ones of its profile. 'errors' lists
+/// regular expressions matching the lines by which the engine reports a
+/// failure at startup, besides the ones of its profile. Options are sent in
+/// the order they are written in.
+pub struct EngineRegistry {
+    engines: Vec<EngineConfig>,
+}
+
+impl EngineRegistry {
+    /// The default location of the registry:
+    /// `$XDG_CONFIG_HOME/stash_tools/engines.toml`, or
+    /// `~/.config/stash_tools/engines.toml` without XDG_CONFIG_HOME.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+
+        Some(config_dir.join("stash_tools").join("engines.toml"))
+    }
+
+    /// Loads the registry at the given path, or at the default one. A missing
+    /// registry is only an error when its path is given explicitly.
+    pub fn load(path: Option<&str>) -> io::Result<Self> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match Self::default_path() {
+                Some(path) if path.is_file() => path,
+                _ => {
+                    return Ok(Self {
+                        engines: Vec::new(),
+                    })
+                }
+            },
+        };
+        let content = fs::read_to_string(&path)?;
+
+        Self::parse(&content).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), err),
+            )
+        })
+    }
+
+    /// Parses the content of a registry file.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let table: Table = content
+            .parse()
+            .map_err(|err: toml::de::Error| err.message().to_string())?;
+        let engines = table
+            .iter()
+            .map(|(name, entry)| {
+                entry
+                    .as_table()
+                    .ok_or_else(|| String::from("expected a table"))
+                    .and_then(|entry| engine_config(name, entry))
+                    .map_err(|err| format!("engine '{}': {}", name, err))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { engines })
+    }
+
+    /// Returns the configuration of an engine by its name.
+    pub fn get(&self, name: &str) -> Option<&EngineConfig> {
+        self.engines.iter().find(|config| config.name == name)
+    }
+
+    pub fn engines(&self) -> &[EngineConfig] {
+        &self.engines
+    }
+}
+
+/// Formats a scalar value as given on the command line: strings without
+/// their quotes, other values as written in TOML.
+fn scalar(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => Ok(value.to_string()),
+        _ => Err(format!("expected a string or a number, got '{}'", value)),
+    }
+}
+
+fn string_list(value: &Value) -> Result<Vec<String>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("expected an array, got '{}'", value))?
+        .iter()
+        .map(scalar)
+        .collect()
+}
+
+fn key_values(value: &Value) -> Result<Vec<(String, String)>, String> {
+    value
+        .as_table()
+        .ok_or_else(|| format!("expected a table, got '{}'", value))?
+        .iter()
+        .map(|(key, value)| Ok((key.clone(), scalar(value)?)))
+        .collect()
+}
+
+/// Builds the configuration of a registry entry.
+fn engine_config(name: &str, entry: &Table) -> Result<EngineConfig, String> {
+    let mut config = EngineConfig {
+        name: name.to_string(),
+        ..Default::default()
+    };
+
+    for (key, value) in entry {
+        let context = |err: String| format!("'{}': {}", key, err);
+
+        match key.as_str() {
+            "cmd" => config.command = scalar(value).map_err(context)?,
+            "args" => config.args = string_list(value).map_err(context)?,
+            "dir" => config.working_dir = Some(scalar(value).map_err(context)?),
+            "clearenv" => {
+                config.clear_env = value
+                    .as_bool()
+                    .ok_or_else(|| context(String::from("expected a boolean")))?
+            }
+            "env" => config.env = key_values(value).map_err(context)?,
+            "profile" => {
+                config.profile = EngineProfile::from_str(&scalar(value).map_err(context)?, true)
+                    .map_err(context)?
+            }
+            "options" => {
+                config.options = key_values(value)
+                    .map_err(context)?
+                    .into_iter()
+                    .map(|(option, value)| format!("{}={}", option, value))
+                    .collect()
+            }
+            "sync" => config.sync_options = string_list(value).map_err(context)?,
+            "errors" => config.startup_errors = string_list(value).map_err(context)?,
+            _ => return Err(format!("unknown setting '{}'", key)),
+        }
+    }
+
+    if config.command.is_empty() {
+        return Err(String::from("missing 'cmd'"));
+    }
+
+    Ok(config)
+}
@@ -0,0 +1,41 @@
+/// A small seedable pseudo-random number generator (SplitMix64), so that the
+/// random choices of the dataset tools can be reproduced from a seed.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub const fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+
+        let mut z = self.state;
+
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns the seed of the `index`-th of a family of independent
+    /// generators derived from a seed, e.g. one per game of a match.
+    pub const fn derive(seed: u64, index: u64) -> u64 {
+        let mut index = Self::new(index);
+        let mut derived = Self::new(seed ^ index.next_u64());
+
+        derived.next_u64()
+    }
+
+    /// Returns a uniformly distributed number in [0, bound).
+    pub fn below(&mut self, bound: u64) -> u64 {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+
+    /// Returns a uniformly distributed number in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,49 @@
+use clap::ValueEnum;
+
+use crate::rng::Rng;
+
+/// How positions are picked when a game holds more positions than wanted.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplingMethod {
+    /// Pick positions uniformly at random.
+    Uniform,
+    /// Pick evenly spaced positions, starting from a random offset, so that
+    /// all phases of the game are represented.
+    Spaced,
+}
+
+impl SamplingMethod {
+    /// Picks at most `max` indices in [0, len), returned in increasing order.
+    pub fn sample(self, len: usize, max: usize, rng: &mut Rng) -> Vec<usize> {
+        if len <= max {
+            return (0..len).collect();
+        }
+
+        let mut indices = match self {
+            Self::Uniform => {
+                // Partial Fisher-Yates shuffle.
+                let mut indices: Vec<usize> = (0..len).collect();
+
+                for i in 0..max {
+                    let j = i + rng.below((len - i) as u64) as usize;
+
+                    indices.swap(i, j);
+                }
+
+                indices.truncate(max);
+                indices
+            }
+            Self::Spaced => {
+                let step = len as f64 / max as f64;
+                let offset = rng.next_f64() * step;
+
+                (0..max)
+                    .map(|i| ((offset + i as f64 * step) as usize).min(len - 1))
+                    .collect()
+            }
+        };
+
+        indices.sort_unstable();
+        indices
+    }
+}
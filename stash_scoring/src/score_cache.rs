@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::Mutex;
+
+use sha2::{Digest, Sha256};
+
+use crate::board::Position;
+use crate::engine::{Score, ScoreFormat, SearchLimit};
+use crate::manifest::sha256_file;
+
+/// An on-disk cache of search scores, shared by the scoring threads, so that
+/// positions repeated within a run or across runs are only searched once.
+///
+/// The cache is an append-only file with one `<CONTEXT> <FEN> [MOVE] <SCORE>`
+/// line per scored position. The context identifies the engine binary, its
+/// options and the search limit, so that a single file can hold the scores of
+/// several configurations: only the entries of the current one are used.
+/// Positions are keyed by their canonical FEN.
+pub struct ScoreCache {
+    context: String,
+    entries: Mutex<HashMap<String, Score>>,
+    file: Mutex<BufWriter<File>>,
+}
+
+/// Identifies the engine configuration and search limit scores were obtained
+/// with, as a short hash.
+pub fn cache_context(engine: &Path, options: &[String], limit: &SearchLimit) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+
+    hasher.update(sha256_file(engine)?.as_bytes());
+
+    for option in options {
+        hasher.update(b"\n");
+        hasher.update(option.as_bytes());
+    }
+
+    hasher.update(b"\n");
+    hasher.update(limit.go_command().as_bytes());
+
+    Ok(hasher.finalize()[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// The key of a scored position, made of its canonical FEN and the move
+/// played from it, if any.
+fn entry_key(pos: &Position, mv: Option<&str>) -> String {
+    let mut key = pos.canonical_fen();
+
+    if let Some(mv) = mv {
+        key.push(' ');
+        key.push_str(mv);
+    }
+
+    key
+}
+
+impl ScoreCache {
+    /// Opens the cache file, creating it if needed, and loads the entries of
+    /// the given context. Lines which cannot be parsed or were left incomplete
+    /// by an interrupted run are ignored.
+    pub fn open(path: &str, context: &str) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut entries = HashMap::new();
+        let mut reader = BufReader::new(&file);
+        let mut buf = Vec::new();
+        let mut partial_line = false;
+
+        loop {
+            buf.clear();
+
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+
+            // Only complete lines are used, as the last one may be truncated.
+            partial_line = buf.last() != Some(&b'\n');
+
+            let Ok(line) = std::str::from_utf8(&buf) else {
+                continue;
+            };
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+
+            if partial_line || tokens.first() != Some(&context) || !(6..=7).contains(&tokens.len())
+            {
+                continue;
+            }
+
+            let (key, score) = tokens[1..].split_at(tokens.len() - 2);
+
+            if let Ok(score) = score[0].parse::<Score>() {
+                entries.insert(key.join(" "), score);
+            }
+        }
+
+        let mut file = BufWriter::new(file);
+
+        // Start new entries on their own line.
+        if partial_line {
+            writeln!(file)?;
+        }
+
+        Ok(Self {
+            context: context.to_string(),
+            entries: Mutex::new(entries),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// The number of entries of the current context.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, pos: &Position, mv: Option<&str>) -> Option<Score> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(&entry_key(pos, mv))
+            .copied()
+    }
+
+    /// Records the score of a position, unless it is already known.
+    pub fn insert(&self, pos: &Position, mv: Option<&str>, score: Score) -> io::Result<()> {
+        let key = entry_key(pos, mv);
+
+        if self
+            .entries
+            .lock()
+            .unwrap()
+            .insert(key.clone(), score)
+            .is_some()
+        {
+            return Ok(());
+        }
+
+        writeln!(
+            self.file.lock().unwrap(),
+            "{} {} {}",
+            self.context,
+            key,
+            score.display(ScoreFormat::Pound)
+        )
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.file.lock().unwrap().flush()
+    }
+}
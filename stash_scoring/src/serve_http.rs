@@ -0,0 +1,376 @@
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use clap::Args;
+use serde_json::{json, Value};
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::{EngineConfig, EngineProfile, ScoreFormat, SearchLimit};
+use stash_scoring::pool::EnginePool;
+use stash_scoring::registry::EngineRegistry;
+
+/// Serves scoring requests over HTTP, so that web applications and analysis
+/// dashboards can use a pool of engines as an evaluation service.
+///
+/// Positions are scored with `POST /score`, whose body is a JSON object
+/// holding the list of FENs to search, and optionally a 'depth' and/or
+/// 'nodes' limit replacing the default one:
+///
+/// {"fens": ["<FEN>", ...], "depth": 12}
+///
+/// The reply lists the score (from the side to move's point of view) and the
+/// best move of each position, in order:
+///
+/// {"scores": [{"fen": "<FEN>", "score": "35", "bestmove": "e2e4"}, ...]}
+///
+/// Errors are reported with a 4xx or 5xx status and an {"error": "..."} body.
+/// `GET /health` can be used for checking that the service is up.
+#[derive(Args)]
+#[command(verbatim_doc_comment)]
+pub struct ServeHttpArgs {
+    /// The path of the engine to use for scoring, or the name of an engine of
+    /// the registry (see --engine-registry).
+    #[arg(short, long)]
+    engine_path: String,
+
+    /// The family of the engine. Defaults to 'generic', or to the profile of
+    /// the engine in the registry.
+    #[arg(short, long, value_enum)]
+    profile: Option<EngineProfile>,
+
+    /// An UCI option which should be passed to the engine at startup, as
+    /// 'Name=Value'. You can use this flag as many times as you need.
+    #[arg(short, long)]
+    config: Vec<String>,
+
+    /// Score Chess960 positions.
+    #[arg(long)]
+    chess960: bool,
+
+    /// The number of engine instances serving the requests.
+    #[arg(short, long, default_value_t = 1)]
+    threads: usize,
+
+    /// The address to listen on. Use port 0 for picking any free port, the
+    /// address actually listened on being printed at startup.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+
+    /// The default search limit, used by requests which do not give theirs.
+    #[command(flatten)]
+    limit: SearchLimit,
+
+    /// The maximal number of positions in a single request.
+    #[arg(long, default_value_t = 1000)]
+    max_positions: usize,
+
+    /// The maximal number of connections handled at once, further ones
+    /// waiting until one of them is closed.
+    #[arg(long, default_value_t = 32)]
+    max_connections: usize,
+
+    /// How mate scores should be written in the replies.
+    #[arg(short, long, value_enum, default_value_t = ScoreFormat::Pound)]
+    score_format: ScoreFormat,
+}
+
+/// The maximal size of a request body, in bytes.
+const MAX_BODY_SIZE: usize = 1 << 20;
+
+/// The maximal size of the request line and headers, in bytes.
+const MAX_HEADER_SIZE: u64 = 8 << 10;
+
+/// How long a connection may stay idle while sending its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The settings shared by the connection threads.
+struct Service {
+    pool: EnginePool,
+    limit: SearchLimit,
+    chess960: bool,
+    max_positions: usize,
+    score_format: ScoreFormat,
+}
+
+/// An error reply, with its HTTP status.
+struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl HttpError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+fn status_text(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Reads a line of the request head, which must end before the reader runs
+/// out of its MAX_HEADER_SIZE budget. Returns 0 at the end of the stream.
+fn read_head_line(
+    reader: &mut io::Take<BufReader<&TcpStream>>,
+    line: &mut String,
+) -> Result<usize, HttpError> {
+    line.clear();
+
+    let size = reader
+        .read_line(line)
+        .map_err(|err| HttpError::new(400, err.to_string()))?;
+
+    if reader.limit() == 0 && !line.ends_with('\n') {
+        return Err(HttpError::new(431, "the request headers are too large"));
+    }
+
+    Ok(size)
+}
+
+/// Reads a request, returning its method, path and body.
+fn read_request(stream: &TcpStream) -> Result<(String, String, Vec<u8>), HttpError> {
+    let bad_request = |err: io::Error| HttpError::new(400, err.to_string());
+    let mut reader = BufReader::new(stream).take(MAX_HEADER_SIZE);
+    let mut line = String::new();
+
+    read_head_line(&mut reader, &mut line)?;
+
+    let mut tokens = line.split_whitespace();
+    let (method, path) = match (tokens.next(), tokens.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(HttpError::new(400, "malformed request line")),
+    };
+    let mut content_length = 0;
+
+    loop {
+        if read_head_line(&mut reader, &mut line)? == 0 {
+            return Err(HttpError::new(400, "truncated request headers"));
+        }
+
+        let header = line.trim_end();
+
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| HttpError::new(400, "invalid Content-Length"))?;
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(HttpError::new(413, "the request body is too large"));
+    }
+
+    let mut body = vec![0; content_length];
+
+    reader.set_limit(content_length as u64);
+    reader.read_exact(&mut body).map_err(bad_request)?;
+    Ok((method, path, body))
+}
+
+fn write_response(mut stream: &TcpStream, status: u16, body: &Value) -> io::Result<()> {
+    let body = body.to_string();
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        status_text(status),
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+impl Service {
+    /// Handles a single request on the connection, then closes it.
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        let reply = read_request(&stream).and_then(|(method, path, body)| {
+            match (method.as_str(), path.as_str()) {
+                ("POST", "/score") => self.score(&body),
+                ("GET", "/health") => Ok(json!({ "status": "ok" })),
+                (_, "/score" | "/health") => Err(HttpError::new(405, "method not allowed")),
+                _ => Err(HttpError::new(404, format!("unknown endpoint '{}'", path))),
+            }
+        });
+
+        match reply {
+            Ok(body) => write_response(&stream, 200, &body),
+            Err(err) => {
+                write_response(&stream, err.status, &json!({ "error": err.message }))?;
+                // Closing the connection with parts of the request left unread
+                // resets it, which may drop the reply before the client reads
+                // it.
+                stream.shutdown(Shutdown::Write)?;
+                let _ = io::copy(&mut (&stream).take(MAX_BODY_SIZE as u64), &mut io::sink());
+                Ok(())
+            }
+        }
+    }
+
+    /// Scores the positions of a request with the engine pool.
+    fn score(&self, body: &[u8]) -> Result<Value, HttpError> {
+        let request: Value = serde_json::from_slice(body)
+            .map_err(|err| HttpError::new(400, format!("invalid JSON: {}", err)))?;
+        let fens: Vec<String> = request
+            .get("fens")
+            .and_then(Value::as_array)
+            .ok_or_else(|| HttpError::new(400, "expected a 'fens' array"))?
+            .iter()
+            .map(|fen| fen.as_str().map(str::to_string))
+            .collect::<Option<_>>()
+            .ok_or_else(|| HttpError::new(400, "'fens' must only hold strings"))?;
+
+        if fens.len() > self.max_positions {
+            return Err(HttpError::new(
+                413,
+                format!(
+                    "at most {} positions can be scored at once",
+                    self.max_positions
+                ),
+            ));
+        }
+
+        let positions = fens
+            .iter()
+            .map(|fen| {
+                Position::from_fen(fen, self.chess960)
+                    .map_err(|err| HttpError::new(400, format!("invalid FEN '{}': {}", fen, err)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let limit = request_limit(&request, &self.limit)?;
+        let scores = self
+            .pool
+            .search_batch(&positions, &limit)
+            .into_iter()
+            .zip(&fens)
+            .map(|(result, fen)| {
+                let result = result
+                    .map_err(|err| HttpError::new(500, format!("the engine failed: {}", err)))?;
+
+                Ok(json!({
+                    "fen": fen,
+                    "score": result.score.display(self.score_format).to_string(),
+                    "bestmove": result.best_move,
+                }))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(json!({ "scores": scores }))
+    }
+}
+
+/// Reads the search limit of a request, falling back to the default one. The
+/// go template of the default limit is kept.
+fn request_limit(request: &Value, default: &SearchLimit) -> Result<SearchLimit, HttpError> {
+    let field = |key: &str| match request.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .filter(|&value| value > 0)
+            .map(Some)
+            .ok_or_else(|| HttpError::new(400, format!("'{}' must be a positive integer", key))),
+    };
+    let depth = field("depth")?
+        .map(|depth| u16::try_from(depth).map_err(|_| HttpError::new(400, "'depth' is too large")))
+        .transpose()?;
+    let nodes = field("nodes")?;
+
+    if depth.is_none() && nodes.is_none() {
+        return Ok(default.clone());
+    }
+
+    let limit = SearchLimit {
+        depth,
+        nodes,
+        go_template: default.go_template.clone(),
+        searchmoves: default.searchmoves.clone(),
+    };
+
+    limit
+        .check_template(false)
+        .map_err(|err| HttpError::new(400, err))?;
+    Ok(limit)
+}
+
+pub fn run(args: &ServeHttpArgs, registry: &EngineRegistry) -> io::Result<()> {
+    args.limit
+        .check_template(false)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    let mut engine = match registry.get(&args.engine_path) {
+        Some(engine) if !args.engine_path.contains('/') => engine.clone(),
+        _ => EngineConfig {
+            name: args.engine_path.clone(),
+            command: args.engine_path.clone(),
+            ..Default::default()
+        },
+    };
+
+    engine.profile = args.profile.unwrap_or(engine.profile);
+    engine.options.extend_from_slice(&args.config);
+    engine.canonical_options = true;
+
+    if args.chess960 {
+        engine.options.insert(0, String::from("UCI_Chess960=true"));
+    }
+
+    let listener = TcpListener::bind(&args.listen)?;
+    let service = Service {
+        pool: EnginePool::new(&engine, args.threads)?,
+        limit: args.limit.clone(),
+        chess960: args.chess960,
+        max_positions: args.max_positions,
+        score_format: args.score_format,
+    };
+
+    println!("Listening on http://{}", listener.local_addr()?);
+    io::stdout().flush()?;
+
+    // A fixed set of threads accepts the connections, so that many clients
+    // cannot exhaust the threads of the machine.
+    thread::scope(|scope| {
+        for _ in 0..args.max_connections.max(1) {
+            scope.spawn(|| {
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(err) => {
+                            eprintln!("Cannot accept a connection: {}", err);
+                            continue;
+                        }
+                    };
+
+                    if let Err(err) = service.handle(stream) {
+                        eprintln!("Cannot answer a request: {}", err);
+                    }
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
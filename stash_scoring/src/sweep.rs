@@ -0,0 +1,209 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+
+use clap::Args;
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::{EngineConfig, EngineProfile, Score, ScoreFormat, SearchLimit};
+use stash_scoring::input::InputSchema;
+use stash_scoring::pool::EnginePool;
+use stash_scoring::registry::EngineRegistry;
+
+/// The number of input lines searched at once, spread over the engines.
+const BATCH_SIZE: usize = 4096;
+
+/// Scores each position at several search limits, e.g. depths 4, 8 and 12,
+/// for studying how labels change with the search effort. Every input line is
+/// written back with one score per limit appended, in the order of the
+/// limits. Scores of positions with a move column are the ones of the move,
+/// like with the scoring tool.
+#[derive(Args)]
+pub struct SweepArgs {
+    /// The path of the engine to use for scoring, or the name of an engine of
+    /// the registry (see --engine-registry).
+    #[arg(short, long)]
+    engine_path: String,
+
+    /// The family of the engine. Defaults to 'generic', or to the profile of
+    /// the engine in the registry.
+    #[arg(short, long, value_enum)]
+    profile: Option<EngineProfile>,
+
+    /// An UCI option which should be passed to the engine at startup, as
+    /// 'Name=Value'. You can use this flag as many times as you need.
+    #[arg(short, long)]
+    config: Vec<String>,
+
+    /// The file holding the positions to score.
+    #[arg(short, long)]
+    input_file: String,
+
+    /// The layout of the input lines, using the same syntax as the
+    /// --input-columns flag of the scoring tool.
+    #[arg(long, default_value = "fen,extra*")]
+    input_columns: InputSchema,
+
+    /// The file to write the scored lines to. Defaults to the standard
+    /// output.
+    #[arg(short, long)]
+    output_file: Option<String>,
+
+    /// The search depths to score the positions at, comma-separated.
+    #[arg(short, long, value_delimiter = ',', required_unless_present = "nodes")]
+    depths: Vec<u16>,
+
+    /// The node counts to score the positions at, comma-separated.
+    #[arg(short, long, value_delimiter = ',', conflicts_with = "depths")]
+    nodes: Vec<u64>,
+
+    /// Read positions as Chess960 ones.
+    #[arg(long)]
+    chess960: bool,
+
+    /// The number of engine instances searching the positions.
+    #[arg(short, long, default_value_t = 1)]
+    threads: usize,
+
+    /// How mate scores should be written in the output file.
+    #[arg(short, long, value_enum, default_value_t = ScoreFormat::Pound)]
+    score_format: ScoreFormat,
+}
+
+/// Reads the position to search from an input line, after the move of the
+/// line if any.
+fn searched_position(line: &str, args: &SweepArgs) -> Result<(Position, bool), String> {
+    let record = args
+        .input_columns
+        .split(line)
+        .map_err(|err| err.to_string())?;
+    let mut pos = Position::from_fen(&record.fen, args.chess960).map_err(|err| err.to_string())?;
+
+    if let Some(mv) = record.mv {
+        let mv = pos.parse_uci(mv).map_err(|err| err.to_string())?;
+
+        pos.play(mv);
+    }
+
+    Ok((pos, record.mv.is_some()))
+}
+
+pub fn run(args: &SweepArgs, registry: &EngineRegistry) -> io::Result<()> {
+    let mut engine = match registry.get(&args.engine_path) {
+        Some(engine) if !args.engine_path.contains('/') => engine.clone(),
+        _ => EngineConfig {
+            name: args.engine_path.clone(),
+            command: args.engine_path.clone(),
+            ..Default::default()
+        },
+    };
+
+    engine.profile = args.profile.unwrap_or(engine.profile);
+    engine.options.extend_from_slice(&args.config);
+
+    if args.chess960 {
+        engine.options.insert(0, String::from("UCI_Chess960=true"));
+    }
+
+    let limits: Vec<SearchLimit> = args
+        .depths
+        .iter()
+        .map(|&depth| (Some(depth), None))
+        .chain(args.nodes.iter().map(|&nodes| (None, Some(nodes))))
+        .map(|(depth, nodes)| SearchLimit {
+            depth,
+            nodes,
+            ..Default::default()
+        })
+        .collect();
+    let pool = EnginePool::new(&engine, args.threads.max(1))?;
+    let mut lines = BufReader::new(File::open(&args.input_file)?).lines();
+    let mut output: Box<dyn Write> = match &args.output_file {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let mut lines_read = 0;
+    let mut scored = 0;
+    let mut skipped = 0;
+
+    loop {
+        let mut batch = Vec::new();
+        let mut positions = Vec::new();
+
+        while batch.len() < BATCH_SIZE {
+            let Some(line) = lines.next().transpose()? else {
+                break;
+            };
+
+            lines_read += 1;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match searched_position(&line, args) {
+                Ok((pos, after_move)) => {
+                    positions.push(pos);
+                    batch.push((line, after_move));
+                }
+                Err(err) => {
+                    eprintln!("Skipping line {}: {}", lines_read, err);
+                    skipped += 1;
+                }
+            }
+        }
+
+        if batch.is_empty() {
+            break;
+        }
+
+        let results: Vec<Vec<io::Result<Score>>> = limits
+            .iter()
+            .map(|limit| pool.score_batch(&positions, limit))
+            .collect();
+
+        for (index, (line, after_move)) in batch.iter().enumerate() {
+            let mut scored_line = line.trim_end().to_string();
+            let mut failure = None;
+
+            for scores in &results {
+                match &scores[index] {
+                    Ok(score) => {
+                        let score = match after_move {
+                            true => score.parent(),
+                            false => *score,
+                        };
+
+                        scored_line.push_str(&format!(" {}", score.display(args.score_format)));
+                    }
+                    Err(err) => failure = Some(err.to_string()),
+                }
+            }
+
+            match failure {
+                Some(err) => {
+                    eprintln!("Skipping '{}': the search failed: {}", line.trim(), err);
+                    skipped += 1;
+                }
+                None => {
+                    writeln!(output, "{}", scored_line)?;
+                    scored += 1;
+                }
+            }
+        }
+    }
+
+    output.flush()?;
+
+    if skipped > 0 {
+        eprintln!("{} lines skipped", skipped);
+    }
+
+    eprintln!(
+        "{} positions scored at {} search limits",
+        scored,
+        limits.len()
+    );
+    Ok(())
+}
@@ -1,171 +1,579 @@
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::{Deref, Range};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::engine::UciEngine;
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
 
-pub struct TaskQueue {
-    workload: VecDeque<String>,
-    response: VecDeque<String>,
-    workload_finished: bool,
-    active_workers: usize,
+use crate::engine::{EngineConfig, StopHandle, UciEngine};
+use crate::events::{Event, EventBus};
+
+/// A single line of input, stored as a range of a buffer shared with the
+/// neighbouring lines, so that queuing a large file does not require one
+/// allocation per line. This is the task payload of the scoring tool.
+#[derive(Clone, Debug)]
+pub struct Workload {
+    buffer: Arc<str>,
+    range: Range<usize>,
+}
+
+impl Workload {
+    pub fn new(buffer: Arc<str>, range: Range<usize>) -> Self {
+        assert!(buffer.get(range.clone()).is_some());
+        Self { buffer, range }
+    }
+
+    /// Splits a buffer into one workload per line, end-of-line characters
+    /// included.
+    pub fn split_lines(buffer: Arc<str>) -> impl Iterator<Item = Workload> {
+        let mut start = 0;
+        let lines: Vec<Range<usize>> = buffer
+            .split_inclusive('\n')
+            .map(|line| {
+                start += line.len();
+                start - line.len()..start
+            })
+            .collect();
+
+        lines.into_iter().map(move |range| Workload {
+            buffer: buffer.clone(),
+            range,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.buffer[self.range.clone()]
+    }
+}
+
+impl Deref for Workload {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<String> for Workload {
+    fn from(line: String) -> Self {
+        let len = line.len();
+
+        Self {
+            buffer: Arc::from(line),
+            range: 0..len,
+        }
+    }
+}
+
+/// A task queued by the client, with its payload. Each task is numbered by
+/// the client when queued, so that responses can be returned in input order
+/// if needed.
+#[derive(Clone, Debug)]
+pub struct Task<T> {
+    index: usize,
+    payload: T,
+}
+
+impl<T> Task<T> {
+    pub fn new(index: usize, payload: T) -> Self {
+        Self { index, payload }
+    }
+
+    /// The position of the task in the input, starting from 0.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn into_payload(self) -> T {
+        self.payload
+    }
+}
+
+impl<T> Deref for Task<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.payload
+    }
+}
+
+/// The response to a task, tagged with the index of the task. Skipped tasks
+/// (e.g. invalid positions) still get an empty response, so that ordered
+/// output knows not to wait for them.
+pub type Response<R> = (usize, Option<R>);
+
+/// The lanes workloads are queued in. Workers take workloads from the
+/// priority lane first, e.g. for retrying failed positions or answering
+/// interactive requests without waiting for the bulk of the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lane {
+    Bulk,
+    Priority,
+}
+
+/// Cancels the work of a task pool: queued tasks are dropped, workers stop
+/// taking new ones, and their engines are told to stop their current search.
+/// Tokens are cheap to clone, e.g. for cancelling from another thread.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<CancellationState>,
+}
+
+#[derive(Default)]
+struct CancellationState {
+    cancelled: AtomicBool,
+    engines: Mutex<Vec<StopHandle>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the work, stopping the searches of all the engines registered
+    /// so far. Engines which already quit are skipped.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Release);
+
+        for engine in self.state.engines.lock().unwrap().iter() {
+            let _ = engine.stop();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Registers an engine to stop on cancellation, stopping it right away
+    /// if the work is already cancelled.
+    pub fn register(&self, engine: StopHandle) {
+        let mut engines = self.state.engines.lock().unwrap();
+
+        if self.is_cancelled() {
+            let _ = engine.stop();
+        }
+
+        engines.push(engine);
+    }
 }
 
-impl TaskQueue {
+/// The queues shared by the client and the workers, with tasks of type T and
+/// responses of type R. The scoring tool queues input lines and gets back
+/// output lines.
+///
+/// Workloads are queued by the client in global lanes, from which workers
+/// take them by batches into their own deque. Workers which run out of work
+/// steal from the deques of the others, so that no lock is shared by all
+/// workers for every workload.
+pub struct TaskQueue<T = Workload, R = String> {
+    workload: Injector<Task<T>>,
+    priority_workload: Injector<Task<T>>,
+    stealers: RwLock<Vec<Stealer<Task<T>>>>,
+    response: Injector<Response<R>>,
+    cancellation: CancellationToken,
+    events: Arc<EventBus>,
+    workload_finished: AtomicBool,
+    active_workers: AtomicUsize,
+}
+
+/// Takes an item from a queue, retrying until it is either found or known to
+/// be missing.
+fn steal<T>(mut attempt: impl FnMut() -> Steal<T>) -> Option<T> {
+    std::iter::repeat_with(&mut attempt)
+        .find(|result| !result.is_retry())
+        .and_then(Steal::success)
+}
+
+impl<T, R> TaskQueue<T, R> {
     pub fn new() -> Self {
         Self {
-            workload: VecDeque::new(),
-            response: VecDeque::new(),
-            workload_finished: false,
-            active_workers: 0,
+            workload: Injector::new(),
+            priority_workload: Injector::new(),
+            stealers: RwLock::new(Vec::new()),
+            response: Injector::new(),
+            cancellation: CancellationToken::new(),
+            events: Arc::new(EventBus::new()),
+            workload_finished: AtomicBool::new(false),
+            active_workers: AtomicUsize::new(0),
         }
     }
 
-    pub fn add_workload(&mut self, fen: String) {
-        self.workload.push_back(fen);
+    pub fn add_workload(&self, task: Task<T>) {
+        self.add_workload_to(Lane::Bulk, task);
     }
 
-    pub fn query_workload(&mut self) -> Option<String> {
-        self.workload.pop_front()
+    pub fn add_workload_to(&self, lane: Lane, task: Task<T>) {
+        match lane {
+            Lane::Bulk => self.workload.push(task),
+            Lane::Priority => self.priority_workload.push(task),
+        }
     }
 
-    pub fn stop_workload(&mut self) {
-        self.workload_finished = true;
+    /// Takes a workload from the global lanes, or from the deque of a worker
+    /// if they are empty.
+    pub fn query_workload(&self) -> Option<Task<T>> {
+        steal(|| self.priority_workload.steal())
+            .or_else(|| steal(|| self.workload.steal()))
+            .or_else(|| self.steal_from_workers())
+    }
+
+    /// Takes a workload for a worker: from the priority lane first, then from
+    /// its own deque, refilled from the bulk lane or from other workers.
+    pub fn query_workload_for(&self, local: &Worker<Task<T>>) -> Option<Task<T>> {
+        steal(|| self.priority_workload.steal())
+            .or_else(|| local.pop())
+            .or_else(|| steal(|| self.workload.steal_batch_and_pop(local)))
+            .or_else(|| self.steal_from_workers())
+    }
+
+    /// The number of workloads waiting in the global lanes, not counting the
+    /// ones already taken by workers.
+    pub fn queued_workloads(&self) -> usize {
+        self.priority_workload.len() + self.workload.len()
+    }
+
+    fn steal_from_workers(&self) -> Option<Task<T>> {
+        let stealers = self.stealers.read().unwrap();
+
+        steal(|| stealers.iter().map(Stealer::steal).collect())
+    }
+
+    /// The bus the events of the workers and their engines are sent to.
+    pub fn events(&self) -> &Arc<EventBus> {
+        &self.events
+    }
+
+    /// The token cancelling the work of this queue.
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Cancels the work: queued workloads are dropped, and the searches in
+    /// progress are stopped. Their responses are discarded.
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+
+        while self.query_workload().is_some() {}
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    pub fn stop_workload(&self) {
+        self.workload_finished.store(true, Ordering::Release);
     }
 
     pub fn is_workload_finished(&self) -> bool {
-        self.workload_finished
+        self.workload_finished.load(Ordering::Acquire)
     }
 
-    pub fn add_response(&mut self, scored_fen: String) {
-        self.response.push_back(scored_fen)
+    pub fn add_response(&self, index: usize, response: Option<R>) {
+        self.response.push((index, response))
     }
 
-    pub fn query_response(&mut self) -> Option<String> {
-        self.response.pop_front()
+    pub fn query_response(&self) -> Option<Response<R>> {
+        steal(|| self.response.steal())
     }
 
-    pub fn add_worker(&mut self) {
-        self.active_workers += 1;
+    /// Registers a worker, returning the deque it takes its workloads from.
+    /// The deque can be stolen from even after the worker is removed.
+    pub fn add_worker(&self) -> Worker<Task<T>> {
+        let local = Worker::new_fifo();
+
+        self.stealers.write().unwrap().push(local.stealer());
+        self.active_workers.fetch_add(1, Ordering::AcqRel);
+        local
     }
 
-    pub fn remove_worker(&mut self) {
-        self.active_workers -= 1;
+    pub fn remove_worker(&self) {
+        self.active_workers.fetch_sub(1, Ordering::AcqRel);
     }
 
-    pub fn no_active_workers(&mut self) -> bool {
-        self.active_workers == 0
+    pub fn no_active_workers(&self) -> bool {
+        self.active_workers.load(Ordering::Acquire) == 0
     }
 }
 
-impl Default for TaskQueue {
+impl<T, R> Default for TaskQueue<T, R> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-pub struct TaskWorker {
+/// The interval between the checks of idle engines used by default.
+pub const DEFAULT_HEARTBEAT: Duration = Duration::from_secs(30);
+
+/// The time an idle engine has to answer a check.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A worker taking tasks from the queue, with its own engine to process them.
+pub struct TaskWorker<T = Workload, R = String> {
     engine: UciEngine,
-    queue: Arc<Mutex<TaskQueue>>,
+    config: EngineConfig,
+    queue: Arc<TaskQueue<T, R>>,
+    local: Worker<Task<T>>,
+    heartbeat: Option<Duration>,
 }
 
-impl TaskWorker {
-    pub fn new(queue: &Arc<Mutex<TaskQueue>>, engine_path: &str, config: &Vec<String>) -> Self {
-        let mut worker = Self {
-            engine: UciEngine::try_new(engine_path).unwrap(),
+impl<T, R> TaskWorker<T, R> {
+    pub fn new(queue: &Arc<TaskQueue<T, R>>, engine: &EngineConfig) -> Self {
+        Self::try_new(queue, engine).unwrap()
+    }
+
+    /// Creates a worker, returning an error if its engine cannot be started.
+    pub fn try_new(queue: &Arc<TaskQueue<T, R>>, engine: &EngineConfig) -> io::Result<Self> {
+        let config = engine.clone();
+        let engine = config.start_reporting_to(Some(queue.events.clone()))?;
+
+        queue.cancellation.register(engine.stop_handle());
+
+        Ok(Self {
+            engine,
+            config,
             queue: queue.clone(),
-        };
+            local: queue.add_worker(),
+            heartbeat: None,
+        })
+    }
 
-        worker.engine.init_protocol(config).unwrap();
-        worker.queue.lock().unwrap().add_worker();
-        worker
+    /// Checks that the engine still answers every time it has been idle for
+    /// the given interval, waiting for workloads, and restarts it if it does
+    /// not. This keeps engines from timing out or being swapped out when the
+    /// input runs dry for a while.
+    pub fn set_heartbeat(&mut self, interval: Option<Duration>) {
+        self.heartbeat = interval;
     }
 
     pub fn engine_mut(&mut self) -> &mut UciEngine {
         &mut self.engine
     }
 
-    pub fn query_workload(&mut self) -> Option<String> {
+    /// Replaces the engine with a new one, e.g. after a crash or a timeout.
+    pub fn restart_engine(&mut self, reason: &str) -> io::Result<()> {
+        let engine = self
+            .config
+            .start_reporting_to(Some(self.queue.events.clone()))?;
+        let previous = std::mem::replace(&mut self.engine, engine);
+
+        self.queue.cancellation.register(self.engine.stop_handle());
+        self.queue.events.emit(|| Event::WorkerRestarted {
+            previous: previous.id(),
+            engine: self.engine.id(),
+            reason: reason.to_string(),
+        });
+        Ok(())
+    }
+
+    /// The bus to report the events of the worker to.
+    pub fn events(&self) -> &EventBus {
+        &self.queue.events
+    }
+
+    /// Waits for the next workload, returning None once all the workloads
+    /// are processed. Fails if the engine stopped answering while idle and
+    /// could not be restarted.
+    pub fn query_workload(&mut self) -> io::Result<Option<Task<T>>> {
+        let mut last_check = Instant::now();
+
         loop {
-            let mut queue = self.queue.lock().unwrap();
+            // The flag is read first, so that workloads queued before the end
+            // of the input are always found.
+            let finished = self.queue.is_workload_finished();
+
+            if self.queue.is_cancelled() {
+                break;
+            }
 
-            if let Some(fen) = queue.query_workload() {
-                return Some(fen);
+            if let Some(task) = self.queue.query_workload_for(&self.local) {
+                return Ok(Some(task));
             }
 
-            if queue.is_workload_finished() {
+            if finished {
                 break;
             }
 
-            drop(queue);
+            if self
+                .heartbeat
+                .is_some_and(|interval| last_check.elapsed() >= interval)
+            {
+                if let Err(err) = self.engine.ping(HEARTBEAT_TIMEOUT) {
+                    let reason = format!("the idle engine stopped answering: {}", err);
+
+                    self.restart_engine(&reason)?;
+                }
+
+                last_check = Instant::now();
+            }
+
             thread::sleep(Duration::from_micros(10));
         }
 
-        None
+        Ok(None)
     }
 
-    pub fn fill_response(&mut self, scored_fen: String) {
-        let mut queue = self.queue.lock().unwrap();
+    /// Whether the work was cancelled, in which case the result of the last
+    /// search may be incomplete, and is discarded anyway.
+    pub fn is_cancelled(&self) -> bool {
+        self.queue.is_cancelled()
+    }
 
-        queue.add_response(scored_fen);
+    pub fn fill_response(&mut self, workload: &Task<T>, response: R) {
+        if !self.is_cancelled() {
+            self.queue.add_response(workload.index(), Some(response));
+        }
     }
 
-    pub fn remove_worker(&mut self) {
-        let mut queue = self.queue.lock().unwrap();
+    /// Queues the workload again in the priority lane, e.g. after a failed
+    /// search, so that it is retried before the rest of the input. It keeps
+    /// its index, so that ordered responses still wait for it.
+    pub fn requeue_workload(&mut self, workload: Task<T>) {
+        self.queue.add_workload_to(Lane::Priority, workload);
+    }
 
-        queue.remove_worker();
+    /// Signals that the workload has been dropped without producing output.
+    pub fn skip_workload(&mut self, workload: &Task<T>) {
+        self.queue.add_response(workload.index(), None);
     }
 }
 
-pub struct TaskClient {
-    queue: Arc<Mutex<TaskQueue>>,
+impl<T, R> Drop for TaskWorker<T, R> {
+    /// Unregisters the worker, even when its thread panics (e.g. after an
+    /// engine crash), so that the client does not wait for it forever. The
+    /// workloads left in its deque are taken over by the other workers.
+    fn drop(&mut self) {
+        self.queue.remove_worker();
+    }
 }
 
-impl TaskClient {
+/// Queues tasks for the workers and collects their responses.
+pub struct TaskClient<T = Workload, R = String> {
+    queue: Arc<TaskQueue<T, R>>,
+    next_workload: usize,
+    ordered: bool,
+    next_response: usize,
+    pending: BTreeMap<usize, Option<R>>,
+    answered: usize,
+}
+
+impl<T, R> TaskClient<T, R> {
     pub fn new() -> Self {
         Self {
-            queue: Arc::new(Mutex::new(TaskQueue::new())),
+            queue: Arc::new(TaskQueue::new()),
+            next_workload: 0,
+            ordered: false,
+            next_response: 0,
+            pending: BTreeMap::new(),
+            answered: 0,
+        }
+    }
+
+    /// Creates a client returning responses in the order the workloads were
+    /// added, instead of the order they were completed in.
+    pub fn ordered() -> Self {
+        Self {
+            ordered: true,
+            ..Self::new()
         }
     }
 
-    pub fn queue_ref(&self) -> &Arc<Mutex<TaskQueue>> {
+    pub fn queue_ref(&self) -> &Arc<TaskQueue<T, R>> {
         &self.queue
     }
 
-    pub fn add_workload(&mut self, fen: String) {
-        let mut queue = self.queue.lock().unwrap();
+    pub fn add_workload(&mut self, task: T) {
+        self.add_workload_to(Lane::Bulk, task);
+    }
+
+    /// Adds a workload to the given lane. Priority workloads are processed
+    /// before all queued bulk ones, but their responses are still returned
+    /// in input order by ordered clients.
+    pub fn add_workload_to(&mut self, lane: Lane, task: T) {
+        self.queue
+            .add_workload_to(lane, Task::new(self.next_workload, task));
+        self.next_workload += 1;
+    }
+
+    /// Adds several workloads at once, returning their count.
+    pub fn add_workloads(&mut self, tasks: impl IntoIterator<Item = T>) -> usize {
+        let mut count = 0;
+
+        for task in tasks {
+            self.add_workload(task);
+            count += 1;
+        }
 
-        queue.add_workload(fen);
+        count
     }
 
     pub fn stop_workload(&mut self) {
-        let mut queue = self.queue.lock().unwrap();
+        self.queue.stop_workload();
+    }
+
+    /// Returns a receiver getting the events of the workers and their engines
+    /// from now on.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.queue.events.subscribe()
+    }
 
-        queue.stop_workload();
+    /// Aborts all queued and in-progress workloads. Responses already
+    /// received can still be queried, up to the first aborted workload for
+    /// ordered clients.
+    pub fn cancel(&mut self) {
+        self.queue.cancel();
     }
 
-    pub fn query_response(&mut self, retry: bool) -> Option<String> {
+    pub fn query_response(&mut self, retry: bool) -> Option<R> {
         loop {
-            let mut queue = self.queue.lock().unwrap();
+            // The worker count is read first, so that the responses of the
+            // last workers are always received.
+            let no_active_workers = self.queue.no_active_workers();
+
+            while let Some((index, response)) = self.queue.query_response() {
+                if !self.ordered {
+                    self.answered += 1;
+
+                    if response.is_some() {
+                        return response;
+                    }
+                } else {
+                    self.pending.insert(index, response);
+                }
+            }
 
-            if let Some(scored_fen) = queue.query_response() {
-                return Some(scored_fen);
+            while let Some(response) = self.pending.remove(&self.next_response) {
+                self.next_response += 1;
+                self.answered += 1;
+
+                if response.is_some() {
+                    return response;
+                }
             }
 
-            if queue.no_active_workers() || !retry {
+            if no_active_workers || !retry {
                 break;
             }
 
-            drop(queue);
             thread::sleep(Duration::from_micros(10));
         }
 
         None
     }
+
+    /// The number of workloads whose response was returned so far, counting
+    /// the skipped ones. It only falls short of the workloads added once all
+    /// responses are received if some workloads were lost, e.g. with the
+    /// workers which failed on them.
+    pub fn answered_workloads(&self) -> usize {
+        self.answered
+    }
 }
 
-impl Default for TaskClient {
+impl<T, R> Default for TaskClient<T, R> {
     fn default() -> Self {
         Self::new()
     }
@@ -0,0 +1,62 @@
+use std::thread;
+use std::time::Duration;
+
+/// Bounds the share of the machine used by a worker, by idling between its
+/// searches. Unlike CPU quotas, which stall engines in the middle of their
+/// searches and make their time management misbehave, the idle time is only
+/// inserted between positions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Throttle {
+    /// The fraction of the time the worker may spend searching.
+    pub duty_cycle: Option<f64>,
+    /// The maximal number of nodes per second searched by the worker.
+    pub max_nps: Option<f64>,
+}
+
+impl Throttle {
+    /// Whether the throttle never idles.
+    pub fn is_unbounded(&self) -> bool {
+        self.duty_cycle.is_none() && self.max_nps.is_none()
+    }
+
+    /// The time to idle after a search which lasted `busy` and searched the
+    /// given number of nodes, if known, so that both bounds are respected.
+    pub fn idle_time(&self, busy: Duration, nodes: Option<u64>) -> Duration {
+        let for_duty_cycle = self.duty_cycle.map_or(Duration::ZERO, |duty_cycle| {
+            busy.mul_f64((1.0 - duty_cycle) / duty_cycle)
+        });
+        let for_nps = match (self.max_nps, nodes) {
+            (Some(max_nps), Some(nodes)) => {
+                Duration::from_secs_f64(nodes as f64 / max_nps).saturating_sub(busy)
+            }
+            _ => Duration::ZERO,
+        };
+
+        for_duty_cycle.max(for_nps)
+    }
+
+    /// The throttle of each of `workers` workers sharing the node rate of this
+    /// one equally.
+    pub fn shared_by(&self, workers: usize) -> Throttle {
+        Throttle {
+            max_nps: self.max_nps.map(|max_nps| max_nps / workers.max(1) as f64),
+            ..*self
+        }
+    }
+}
+
+/// The longest uninterrupted sleep of [`idle`], so that long idle times do
+/// not delay the end of a cancelled run.
+const IDLE_SLICE: Duration = Duration::from_millis(100);
+
+/// Sleeps for the given duration, returning early once `interrupted` does.
+pub fn idle(duration: Duration, interrupted: impl Fn() -> bool) {
+    let mut remaining = duration;
+
+    while !remaining.is_zero() && !interrupted() {
+        let slice = remaining.min(IDLE_SLICE);
+
+        thread::sleep(slice);
+        remaining -= slice;
+    }
+}
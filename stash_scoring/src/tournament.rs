@@ -0,0 +1,505 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+use crate::board::Color;
+use crate::game::GameResult;
+
+/// How engines are paired in a tournament.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    /// Every engine plays every other engine.
+    RoundRobin,
+    /// The first engine plays every other engine.
+    Gauntlet,
+}
+
+/// Two games played by the same engines from the same opening, each engine
+/// playing both colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GamePair {
+    /// The position of the pair in the schedule.
+    pub index: usize,
+    pub round: usize,
+    /// The engine playing White in the first game, and the one playing Black.
+    pub engines: (usize, usize),
+    pub opening: usize,
+}
+
+impl Schedule {
+    /// Lists the game pairs of the tournament, round after round. Openings are
+    /// used in order, one per game pair.
+    pub fn game_pairs(self, engine_count: usize, rounds: usize, openings: usize) -> Vec<GamePair> {
+        let pairings: Vec<(usize, usize)> = match self {
+            Self::RoundRobin => (0..engine_count)
+                .flat_map(|i| (i + 1..engine_count).map(move |j| (i, j)))
+                .collect(),
+            Self::Gauntlet => (1..engine_count).map(|j| (0, j)).collect(),
+        };
+        let mut pairs = Vec::new();
+
+        for round in 0..rounds {
+            for &engines in &pairings {
+                pairs.push(GamePair {
+                    index: pairs.len(),
+                    round,
+                    engines,
+                    opening: pairs.len() % openings.max(1),
+                });
+            }
+        }
+
+        pairs
+    }
+}
+
+/// Converts an expected score in ]0, 1[ into an Elo difference.
+pub fn elo_from_score(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+/// The error function, using the approximation 7.1.26 of Abramowitz and
+/// Stegun (maximal error 1.5e-7).
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let y = 1.0 - poly * (-x * x).exp();
+
+    y.copysign(x)
+}
+
+/// Returns the Elo difference implied by a mean score and its standard error,
+/// with the margin of its 95% confidence interval.
+fn elo_interval(score: f64, stderr: f64) -> (f64, f64) {
+    let margin = 1.959964 * stderr;
+    let low = elo_from_score((score - margin).max(1e-6));
+    let high = elo_from_score((score + margin).min(1.0 - 1e-6));
+
+    (elo_from_score(score), (high - low) / 2.0)
+}
+
+/// Win/draw/loss counts of an engine against another one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Wdl {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Wdl {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+
+    /// Returns the Elo difference implied by the results, with the margin of
+    /// its 95% confidence interval. Returns None if the results are too
+    /// one-sided for an estimate.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let n = self.games() as f64;
+        let score = self.points() / n;
+
+        if self.games() == 0 || score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+
+        let variance = (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / n;
+
+        Some(elo_interval(score, (variance / n).sqrt()))
+    }
+}
+
+/// The distribution of the scores of an engine over game pairs, from 0 to 2
+/// points in half-point steps. Both games of a pair share the same opening,
+/// so pair scores are much less noisy than individual game results.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pentanomial(pub [u32; 5]);
+
+impl Pentanomial {
+    pub fn pairs(&self) -> u32 {
+        self.0.iter().sum()
+    }
+
+    /// Records a game pair where the engine scored the given points.
+    pub fn add(&mut self, points: f64) {
+        self.0[(points * 2.0).round() as usize] += 1;
+    }
+
+    /// Returns the mean score per game and its standard error, or None
+    /// without any pair played.
+    fn score_stats(&self) -> Option<(f64, f64)> {
+        let n = self.pairs() as f64;
+
+        if self.pairs() == 0 {
+            return None;
+        }
+
+        let pair_score = |idx: usize| idx as f64 / 4.0;
+        let score = (0..5)
+            .map(|i| self.0[i] as f64 * pair_score(i))
+            .sum::<f64>()
+            / n;
+        let variance = (0..5)
+            .map(|i| self.0[i] as f64 * (pair_score(i) - score).powi(2))
+            .sum::<f64>()
+            / n;
+
+        Some((score, (variance / n).sqrt()))
+    }
+
+    /// Returns the Elo difference implied by the pair scores, with the margin
+    /// of its 95% confidence interval. Returns None if the results are too
+    /// one-sided for an estimate.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let (score, stderr) = self.score_stats()?;
+
+        if score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+
+        Some(elo_interval(score, stderr))
+    }
+
+    /// Returns the likelihood of superiority, i.e. the probability that the
+    /// engine is stronger than its opponent. Returns None when all pairs
+    /// ended with the same score.
+    pub fn los(&self) -> Option<f64> {
+        let (score, stderr) = self.score_stats()?;
+
+        if stderr == 0.0 {
+            return None;
+        }
+
+        Some(0.5 + 0.5 * erf((score - 0.5) / (stderr * std::f64::consts::SQRT_2)))
+    }
+
+    /// Returns the fraction of drawn games implied by the normalized pair
+    /// frequencies: pairs scoring 0.5 or 1.5 points hold one draw out of two
+    /// games, and even pairs are counted as two draws, since pairs won once
+    /// by each engine cannot be told apart from them.
+    pub fn draw_ratio(&self) -> Option<f64> {
+        let n = self.pairs() as f64;
+
+        if self.pairs() == 0 {
+            return None;
+        }
+
+        let frequency = |idx: usize| self.0[idx] as f64 / n;
+
+        Some((frequency(1) + frequency(3)) / 2.0 + frequency(2))
+    }
+
+    /// Formats the statistics on a single line.
+    pub fn summary(&self) -> String {
+        let elo = match self.elo() {
+            Some((elo, margin)) => format!("{:+.1} +/- {:.1}", elo, margin),
+            None => String::from("n/a"),
+        };
+        let percent = |value: Option<f64>| match value {
+            Some(value) => format!("{:.1}%", value * 100.0),
+            None => String::from("n/a"),
+        };
+        let counts: Vec<String> = self.0.iter().map(u32::to_string).collect();
+
+        format!(
+            "Elo {}, LOS {}, DrawRatio {}, Ptnml(0-2) [{}]",
+            elo,
+            percent(self.los()),
+            percent(self.draw_ratio()),
+            counts.join(", ")
+        )
+    }
+}
+
+/// A sequential probability ratio test between two Elo hypotheses, as used
+/// by fishtest and cutechess-cli, on game pair statistics.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl FromStr for Sprt {
+    type Err = String;
+
+    /// Parses the test parameters, written as
+    /// `elo0=<ELO>,elo1=<ELO>,alpha=<P>,beta=<P>`. The error probabilities
+    /// default to 0.05.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut elo0, mut elo1) = (None, None);
+        let (mut alpha, mut beta) = (0.05, 0.05);
+
+        for setting in s.split(',') {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("expected 'key=value', got '{}'", setting))?;
+            let value = value
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite())
+                .ok_or_else(|| format!("invalid value for '{}' in '{}'", key, s))?;
+
+            match key {
+                "elo0" => elo0 = Some(value),
+                "elo1" => elo1 = Some(value),
+                "alpha" => alpha = value,
+                "beta" => beta = value,
+                _ => return Err(format!("unknown SPRT parameter '{}'", key)),
+            }
+        }
+
+        let (Some(elo0), Some(elo1)) = (elo0, elo1) else {
+            return Err(format!("missing 'elo0' or 'elo1' in '{}'", s));
+        };
+
+        if elo0 >= elo1 {
+            return Err(format!("elo0 must be lower than elo1 in '{}'", s));
+        }
+
+        if !(0.0..0.5).contains(&alpha) || !(0.0..0.5).contains(&beta) || alpha * beta == 0.0 {
+            return Err(format!("alpha and beta must be in ]0, 0.5[ in '{}'", s));
+        }
+
+        Ok(Self {
+            elo0,
+            elo1,
+            alpha,
+            beta,
+        })
+    }
+}
+
+/// The conclusion of an SPRT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtResult {
+    /// The Elo difference is likely below elo0.
+    H0,
+    /// The Elo difference is likely above elo1.
+    H1,
+}
+
+impl Sprt {
+    /// Returns the lower and upper bounds of the log-likelihood ratio.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    /// Returns the log-likelihood ratio of the hypotheses, using the normal
+    /// approximation of the pair score distribution. As done by fishtest, a
+    /// tiny count is added to each pair score, so that one-sided results
+    /// still have some variance. Returns None without any pair played.
+    pub fn llr(&self, ptnml: &Pentanomial) -> Option<f64> {
+        if ptnml.pairs() == 0 {
+            return None;
+        }
+
+        let counts = ptnml.0.map(|count| count as f64 + 1e-3);
+        let n: f64 = counts.iter().sum();
+        let pair_score = |idx: usize| idx as f64 / 4.0;
+        let score = (0..5).map(|i| counts[i] * pair_score(i)).sum::<f64>() / n;
+        let variance = (0..5)
+            .map(|i| counts[i] * (pair_score(i) - score).powi(2))
+            .sum::<f64>()
+            / n;
+        let n = ptnml.pairs() as f64;
+        let expected = |elo: f64| 1.0 / (1.0 + 10f64.powf(-elo / 400.0));
+        let (s0, s1) = (expected(self.elo0), expected(self.elo1));
+
+        Some(n * (s1 - s0) * (2.0 * score - s0 - s1) / (2.0 * variance))
+    }
+
+    /// Returns the conclusion of the test, if the log-likelihood ratio
+    /// crossed one of its bounds.
+    pub fn result(&self, ptnml: &Pentanomial) -> Option<SprtResult> {
+        let llr = self.llr(ptnml)?;
+        let (lower, upper) = self.bounds();
+
+        match llr {
+            llr if llr <= lower => Some(SprtResult::H0),
+            llr if llr >= upper => Some(SprtResult::H1),
+            _ => None,
+        }
+    }
+
+    /// Formats the current state of the test on a single line.
+    pub fn summary(&self, ptnml: &Pentanomial) -> String {
+        let (lower, upper) = self.bounds();
+        let llr = self
+            .llr(ptnml)
+            .map_or(String::from("n/a"), |llr| format!("{:.2}", llr));
+        let status = match self.result(ptnml) {
+            Some(SprtResult::H0) => " - H0 accepted",
+            Some(SprtResult::H1) => " - H1 accepted",
+            None => "",
+        };
+
+        format!(
+            "LLR {} ({:.2}, {:.2}) [{:.2}, {:.2}]{}",
+            llr, lower, upper, self.elo0, self.elo1, status
+        )
+    }
+}
+
+/// The results of a tournament, between each pair of engines.
+#[derive(Clone, Debug)]
+pub struct Standings {
+    names: Vec<String>,
+    /// The results of each engine against each other engine, from the point
+    /// of view of the first one.
+    results: Vec<Vec<Wdl>>,
+    /// The game pair statistics of each engine against each other engine.
+    pairs: Vec<Vec<Pentanomial>>,
+}
+
+impl Standings {
+    pub fn new(names: Vec<String>) -> Self {
+        let n = names.len();
+
+        Self {
+            names,
+            results: vec![vec![Wdl::default(); n]; n],
+            pairs: vec![vec![Pentanomial::default(); n]; n],
+        }
+    }
+
+    /// Records a game pair, the first engine playing White in the first game
+    /// and Black in the second one.
+    pub fn add_game_pair(&mut self, first: usize, second: usize, results: [GameResult; 2]) {
+        let points = results[0].points(Color::White) + results[1].points(Color::Black);
+
+        self.add_game(first, second, results[0]);
+        self.add_game(second, first, results[1]);
+        self.pairs[first][second].add(points);
+        self.pairs[second][first].add(2.0 - points);
+    }
+
+    pub fn add_game(&mut self, white: usize, black: usize, result: GameResult) {
+        for (us, them, color) in [(white, black, Color::White), (black, white, Color::Black)] {
+            let wdl = &mut self.results[us][them];
+
+            match result.points(color) {
+                1.0 => wdl.wins += 1,
+                0.0 => wdl.losses += 1,
+                _ => wdl.draws += 1,
+            }
+        }
+    }
+
+    pub fn pair(&self, us: usize, them: usize) -> Wdl {
+        self.results[us][them]
+    }
+
+    pub fn pentanomial(&self, us: usize, them: usize) -> Pentanomial {
+        self.pairs[us][them]
+    }
+
+    /// Returns the combined results of an engine against all others.
+    pub fn total(&self, engine: usize) -> Wdl {
+        self.results[engine]
+            .iter()
+            .fold(Wdl::default(), |acc, wdl| Wdl {
+                wins: acc.wins + wdl.wins,
+                draws: acc.draws + wdl.draws,
+                losses: acc.losses + wdl.losses,
+            })
+    }
+
+    /// Formats the crosstable of the tournament, engines being ranked by
+    /// points.
+    pub fn crosstable(&self) -> String {
+        let mut ranking: Vec<usize> = (0..self.names.len()).collect();
+        let width = self.names.iter().map(String::len).max().unwrap_or(0).max(6);
+        let mut table = format!(
+            "{:>4} {:<width$} {:>7} {:>6} {:>7} |",
+            "Rank", "Engine", "Points", "Games", "Score"
+        );
+
+        ranking.sort_by(|&a, &b| self.total(b).points().total_cmp(&self.total(a).points()));
+
+        for &engine in &ranking {
+            table.push_str(&format!(" {:>9}", truncate(&self.names[engine], 9)));
+        }
+
+        table.push('\n');
+
+        for (rank, &engine) in ranking.iter().enumerate() {
+            let total = self.total(engine);
+            let score = match total.games() {
+                0 => 0.0,
+                n => total.points() / n as f64 * 100.0,
+            };
+
+            table.push_str(&format!(
+                "{:>4} {:<width$} {:>7.1} {:>6} {:>6.1}% |",
+                rank + 1,
+                self.names[engine],
+                total.points(),
+                total.games(),
+                score
+            ));
+
+            for &opponent in &ranking {
+                let wdl = self.pair(engine, opponent);
+                let cell = match (opponent == engine, wdl.games()) {
+                    (true, _) => String::from("---"),
+                    (false, 0) => String::new(),
+                    (false, n) => format!("{}/{}", wdl.points(), n),
+                };
+
+                table.push_str(&format!(" {:>9}", cell));
+            }
+
+            table.push('\n');
+        }
+
+        table
+    }
+
+    /// Formats the Elo difference between each pair of engines which played
+    /// each other, computed from game pair statistics.
+    pub fn pair_elos(&self) -> String {
+        let mut report = String::new();
+
+        for us in 0..self.names.len() {
+            for them in us + 1..self.names.len() {
+                let wdl = self.pair(us, them);
+
+                if wdl.games() == 0 {
+                    continue;
+                }
+
+                report.push_str(&format!(
+                    "{} vs {}: {} (W {} / D {} / L {})\n",
+                    self.names[us],
+                    self.names[them],
+                    self.pentanomial(us, them).summary(),
+                    wdl.wins,
+                    wdl.draws,
+                    wdl.losses
+                ));
+            }
+        }
+
+        report
+    }
+}
+
+fn truncate(name: &str, len: usize) -> &str {
+    match name.char_indices().nth(len) {
+        Some((idx, _)) => &name[..idx],
+        None => name,
+    }
+}
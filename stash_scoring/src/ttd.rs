@@ -0,0 +1,173 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use clap::Args;
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::{EngineConfig, SearchLimit};
+use stash_scoring::registry::EngineRegistry;
+
+use crate::match_runner::{read_openings, resolve_engine};
+
+/// The positions searched when no suite is given, taken from the usual
+/// engine bench suites.
+const DEFAULT_SUITE: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "rq3rk1/ppp2ppp/1bnpb3/3N2B1/3NP3/7P/PPPQ1PP1/2KR3R w - - 7 14",
+    "r1bq1r1k/1pp1n1pp/1p1p4/4p2Q/4Pp2/1BNP4/PPP2PPP/3R1RK1 w - - 2 14",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "r1bbk1nr/pp3p1p/2n5/1N4p1/2Np1B2/8/PPP2PPP/2KR1B1R w kq - 0 13",
+];
+
+/// Measures the time engines take to reach a depth over a suite of
+/// positions, for each of several thread counts, and reports the median
+/// times and the speedups over the first thread count. Each position is
+/// searched from a new game.
+#[derive(Args)]
+pub struct TtdArgs {
+    /// An engine to measure, with the same settings as the --engine flag of
+    /// the match command. Use this flag once per engine.
+    #[arg(long = "engine", required = true, num_args = 1)]
+    engines: Vec<String>,
+
+    /// The thread counts to measure the engines with, comma-separated. They
+    /// are set with the Threads option.
+    #[arg(long, value_delimiter = ',', default_value = "1")]
+    threads: Vec<usize>,
+
+    /// The depth to search the positions to.
+    #[arg(short, long)]
+    depth: u16,
+
+    /// A file with one position per line, as a FEN or EPD. A small built-in
+    /// suite is used by default.
+    #[arg(long)]
+    suite: Option<String>,
+}
+
+/// The time to depth of an engine with a thread count.
+struct Measure {
+    engine: String,
+    threads: usize,
+    median: Duration,
+}
+
+fn median(mut times: Vec<Duration>) -> Duration {
+    times.sort_unstable();
+
+    match times.len() {
+        0 => Duration::ZERO,
+        len if len % 2 == 1 => times[len / 2],
+        len => (times[len / 2 - 1] + times[len / 2]) / 2,
+    }
+}
+
+/// Searches every position, returning the time each search took.
+fn time_to_depth(
+    config: &EngineConfig,
+    positions: &[Position],
+    limit: &SearchLimit,
+) -> io::Result<Vec<Duration>> {
+    let mut engine = config.start()?;
+    let mut times = Vec::with_capacity(positions.len());
+
+    for pos in positions {
+        engine.setup_position(&pos.to_fen(), &[])?;
+
+        let start = Instant::now();
+
+        engine.run_search(limit)?;
+        times.push(start.elapsed());
+    }
+
+    Ok(times)
+}
+
+pub fn run(args: &TtdArgs, registry: &EngineRegistry) -> io::Result<()> {
+    let known: Vec<_> = registry
+        .engines()
+        .iter()
+        .map(|config| (config.name.clone(), Ok(config.clone())))
+        .collect();
+    let configs = args
+        .engines
+        .iter()
+        .map(|settings| {
+            resolve_engine(settings, &known)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+        })
+        .collect::<io::Result<Vec<EngineConfig>>>()?;
+    let positions = match &args.suite {
+        Some(path) => read_openings(path, false)?,
+        None => DEFAULT_SUITE
+            .iter()
+            .map(|fen| Position::from_fen(fen, false).unwrap())
+            .collect(),
+    };
+    let limit = SearchLimit {
+        depth: Some(args.depth),
+        ..Default::default()
+    };
+
+    if args.threads.is_empty() || args.threads.contains(&0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "thread counts must be positive",
+        ));
+    }
+
+    let mut measures = Vec::new();
+
+    for config in &configs {
+        for &threads in &args.threads {
+            let mut config = config.clone();
+
+            config.options.push(format!("Threads={}", threads));
+            eprintln!(
+                "Searching {} positions to depth {} with {} ({} threads)",
+                positions.len(),
+                args.depth,
+                config.name,
+                threads
+            );
+
+            let times = time_to_depth(&config, &positions, &limit)?;
+
+            measures.push(Measure {
+                engine: config.name.clone(),
+                threads,
+                median: median(times),
+            });
+        }
+    }
+
+    println!(
+        "{:<24} {:>8} {:>14} {:>8}",
+        "Engine", "Threads", "Median (ms)", "Speedup"
+    );
+
+    for measures in measures.chunks(args.threads.len()) {
+        let base = measures[0].median.as_secs_f64();
+
+        for measure in measures {
+            let median = measure.median.as_secs_f64();
+            let speedup = match median > 0.0 {
+                true => base / median,
+                false => 1.0,
+            };
+
+            println!(
+                "{:<24} {:>8} {:>14.1} {:>8.2}",
+                measure.engine,
+                measure.threads,
+                median * 1000.0,
+                speedup
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,198 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+use clap::Args;
+use serde_json::json;
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::Score;
+use stash_scoring::input::InputSchema;
+
+/// Checks a scored or extracted dataset for corrupted lines: unparsable or
+/// illegal FENs, unexpected WDL values, unparsable evals, and truncated lines
+/// left by interrupted runs. A JSON report is written at the end.
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// The dataset file to check.
+    #[arg(short, long)]
+    input_file: String,
+
+    /// The layout of the dataset lines, using the same syntax as the
+    /// --input-columns flag of the scoring tool.
+    #[arg(long, default_value = "fen,wdl,eval")]
+    input_columns: InputSchema,
+
+    /// The file the dataset was generated from. When given, both files must
+    /// have the same number of lines.
+    #[arg(short, long)]
+    source_file: Option<String>,
+
+    /// Accept any WDL value in [0, 1] instead of only 0, 0.5 and 1.
+    #[arg(long)]
+    soft_labels: bool,
+
+    /// Accept Chess960 castling rights in FENs.
+    #[arg(long)]
+    chess960: bool,
+
+    /// The file to write the JSON report to. Defaults to the standard output.
+    #[arg(short, long)]
+    report_file: Option<String>,
+
+    /// The maximal number of individual errors listed in the report.
+    #[arg(long, default_value_t = 100)]
+    max_errors: usize,
+}
+
+#[derive(Default)]
+struct ErrorCounts {
+    malformed: usize,
+    invalid_fen: usize,
+    invalid_wdl: usize,
+    invalid_eval: usize,
+    truncated: usize,
+}
+
+impl ErrorCounts {
+    fn total(&self) -> usize {
+        self.malformed + self.invalid_fen + self.invalid_wdl + self.invalid_eval + self.truncated
+    }
+}
+
+fn count_lines(path: &str) -> io::Result<usize> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buf = Vec::new();
+    let mut lines = 0;
+
+    while reader.read_until(b'\n', &mut buf)? != 0 {
+        lines += 1;
+        buf.clear();
+    }
+
+    Ok(lines)
+}
+
+fn check_wdl(wdl: &str, soft_labels: bool) -> Result<(), String> {
+    let value = wdl
+        .parse::<f32>()
+        .map_err(|_| format!("unparsable WDL '{}'", wdl))?;
+
+    if soft_labels && (0.0..=1.0).contains(&value) {
+        return Ok(());
+    }
+
+    if [0.0, 0.5, 1.0].contains(&value) {
+        return Ok(());
+    }
+
+    Err(format!("unexpected WDL value '{}'", wdl))
+}
+
+/// Runs the verification, returning whether the dataset is valid.
+pub fn run(args: &VerifyArgs) -> io::Result<bool> {
+    let mut reader = BufReader::new(File::open(&args.input_file)?);
+    let mut buf = Vec::new();
+    let mut counts = ErrorCounts::default();
+    let mut errors = Vec::new();
+    let mut lines = 0;
+
+    loop {
+        buf.clear();
+
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+
+        lines += 1;
+
+        let result = if buf.last() != Some(&b'\n') {
+            counts.truncated += 1;
+            Err(("truncated", String::from("missing end of line")))
+        } else {
+            match std::str::from_utf8(&buf) {
+                Err(_) => {
+                    counts.malformed += 1;
+                    Err(("malformed", String::from("invalid UTF-8")))
+                }
+                Ok(line) => check_line(args, line, &mut counts),
+            }
+        };
+
+        if let Err((kind, message)) = result {
+            if errors.len() < args.max_errors {
+                errors.push(json!({
+                    "line": lines,
+                    "kind": kind,
+                    "message": message,
+                }));
+            }
+        }
+    }
+
+    let source_lines = match &args.source_file {
+        Some(path) => Some(count_lines(path)?),
+        None => None,
+    };
+    let line_count_matches = source_lines.is_none_or(|n| n == lines);
+    let valid = counts.total() == 0 && line_count_matches;
+
+    let report = json!({
+        "file": args.input_file,
+        "valid": valid,
+        "lines": lines,
+        "source_lines": source_lines,
+        "line_count_matches": line_count_matches,
+        "error_counts": {
+            "malformed": counts.malformed,
+            "invalid_fen": counts.invalid_fen,
+            "invalid_wdl": counts.invalid_wdl,
+            "invalid_eval": counts.invalid_eval,
+            "truncated": counts.truncated,
+        },
+        "errors": errors,
+    });
+    let report = serde_json::to_string_pretty(&report)? + "\n";
+
+    match &args.report_file {
+        Some(path) => File::create(path)?.write_all(report.as_bytes())?,
+        None => io::stdout().write_all(report.as_bytes())?,
+    }
+
+    Ok(valid)
+}
+
+fn check_line(
+    args: &VerifyArgs,
+    line: &str,
+    counts: &mut ErrorCounts,
+) -> Result<(), (&'static str, String)> {
+    let record = args.input_columns.split(line).map_err(|err| {
+        counts.malformed += 1;
+        ("malformed", err.to_string())
+    })?;
+
+    Position::from_fen(&record.fen, args.chess960)
+        .and_then(|pos| pos.check_legality())
+        .map_err(|err| {
+            counts.invalid_fen += 1;
+            ("invalid_fen", format!("{}: {}", record.fen, err))
+        })?;
+
+    if let Some(wdl) = record.wdl {
+        check_wdl(wdl, args.soft_labels).map_err(|err| {
+            counts.invalid_wdl += 1;
+            ("invalid_wdl", err)
+        })?;
+    }
+
+    if let Some(eval) = record.eval {
+        eval.parse::<Score>().map_err(|_| {
+            counts.invalid_eval += 1;
+            ("invalid_eval", format!("unparsable eval '{}'", eval))
+        })?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use clap::ValueEnum;
+
+use crate::board::{PieceType, Position};
+use crate::engine::Score;
+
+/// The win, draw and loss probabilities of a position, from the side to
+/// move's point of view.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wdl {
+    pub win: f64,
+    pub draw: f64,
+    pub loss: f64,
+}
+
+impl Wdl {
+    /// The expected game result, between 0 (loss) and 1 (win).
+    pub fn expected_result(&self) -> f64 {
+        self.win + self.draw / 2.0
+    }
+}
+
+/// The families of models which can be selected from the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum WdlModelKind {
+    /// A single logistic curve, without draws.
+    Logistic,
+    /// Stockfish's model, whose curve depends on the material left.
+    Material,
+}
+
+/// A model mapping centipawn scores to win/draw/loss probabilities.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WdlModel {
+    /// The expected result is `1 / (1 + exp(-cp / k))`, and the model
+    /// predicts no draws.
+    Logistic { k: f64 },
+    /// The win probability is `1 / (1 + exp((a - v) / b))`, with `a` and `b`
+    /// polynomials (highest degree first) of the material left divided by
+    /// 58, and `v` the score scaled so that 100 centipawns give a 50% win
+    /// probability, as in Stockfish's normalized scores. The loss
+    /// probability is the win probability of the negated score.
+    Material { a: [f64; 4], b: [f64; 4] },
+}
+
+impl WdlModel {
+    /// The material model fitted for Stockfish 16.1 on Fishtest games.
+    pub const STOCKFISH: Self = Self::Material {
+        a: [-1.06249702, 7.42016937, 0.89425629, 348.60356174],
+        b: [-5.33122190, 39.57831533, -90.84473771, 123.40620748],
+    };
+
+    pub fn new(kind: WdlModelKind, k: f64) -> Self {
+        match kind {
+            WdlModelKind::Logistic => Self::Logistic { k },
+            WdlModelKind::Material => Self::STOCKFISH,
+        }
+    }
+
+    /// Returns the WDL probabilities of a score in the given position.
+    pub fn wdl(&self, score: Score, pos: &Position) -> Wdl {
+        match (*self, score) {
+            (_, Score::Mate(mate)) if mate > 0 => Wdl {
+                win: 1.0,
+                draw: 0.0,
+                loss: 0.0,
+            },
+            (_, Score::Mate(_)) => Wdl {
+                win: 0.0,
+                draw: 0.0,
+                loss: 1.0,
+            },
+            (Self::Logistic { k }, Score::Cp(cp)) => {
+                let win = sigmoid(cp as f64 / k);
+
+                Wdl {
+                    win,
+                    draw: 0.0,
+                    loss: 1.0 - win,
+                }
+            }
+            (Self::Material { a, b }, Score::Cp(cp)) => {
+                let (a, b) = material_parameters(&a, &b, pos);
+
+                material_wdl(cp as f64, a, b)
+            }
+        }
+    }
+
+    /// Returns the expected result of a score in the given position, from
+    /// the side to move's point of view.
+    pub fn expected_result(&self, score: Score, pos: &Position) -> f64 {
+        self.wdl(score, pos).expected_result()
+    }
+
+    /// Returns the centipawn score whose expected result is the given one,
+    /// the inverse of `expected_result`. Certain results give infinite
+    /// scores.
+    pub fn centipawns(&self, expected_result: f64, pos: &Position) -> f64 {
+        if expected_result <= 0.0 {
+            return f64::NEG_INFINITY;
+        }
+
+        if expected_result >= 1.0 {
+            return f64::INFINITY;
+        }
+
+        let (a, b) = match *self {
+            Self::Logistic { k } => return k * (expected_result / (1.0 - expected_result)).ln(),
+            Self::Material { a, b } => material_parameters(&a, &b, pos),
+        };
+
+        // The expected result increases with the score, so it can be
+        // inverted by bisection.
+        let (mut low, mut high) = (-100_000.0, 100_000.0);
+
+        for _ in 0..100 {
+            let mid = (low + high) / 2.0;
+
+            match material_wdl(mid, a, b).expected_result() < expected_result {
+                true => low = mid,
+                false => high = mid,
+            }
+        }
+
+        (low + high) / 2.0
+    }
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Evaluates the polynomials of the material model for the material left in
+/// the position, counted as in Stockfish and clamped to the range the model
+/// was fitted on.
+fn material_parameters(a: &[f64; 4], b: &[f64; 4], pos: &Position) -> (f64, f64) {
+    let count = |kind| pos.kind_pieces(kind).count_ones() as f64;
+    let material = count(PieceType::Pawn)
+        + 3.0 * count(PieceType::Knight)
+        + 3.0 * count(PieceType::Bishop)
+        + 5.0 * count(PieceType::Rook)
+        + 9.0 * count(PieceType::Queen);
+    let m = material.clamp(17.0, 78.0) / 58.0;
+    let poly = |c: &[f64; 4]| ((c[0] * m + c[1]) * m + c[2]) * m + c[3];
+
+    (poly(a), poly(b))
+}
+
+/// The WDL probabilities given by the material model, once its parameters
+/// are evaluated for the position.
+fn material_wdl(cp: f64, a: f64, b: f64) -> Wdl {
+    let win = sigmoid((cp / 100.0 - 1.0) * a / b);
+    let loss = sigmoid((-cp / 100.0 - 1.0) * a / b);
+
+    Wdl {
+        win,
+        draw: 1.0 - win - loss,
+        loss,
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use clap::ValueEnum;
+
+use crate::manifest::utc_timestamp;
+
+/// When the workspace of a run is deleted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CleanupPolicy {
+    /// Always keep the workspace.
+    Never,
+    /// Delete the workspace if the run succeeded, keeping it for
+    /// investigating failures.
+    OnSuccess,
+    /// Always delete the workspace when the run ends.
+    Always,
+}
+
+/// A directory holding the side files of a single run: its log, the
+/// standard error of its engines, the input lines it rejected and a copy of
+/// its manifest. Each run gets its own directory, so that concurrent runs
+/// never mix their files.
+pub struct Workspace {
+    dir: PathBuf,
+    cleanup: CleanupPolicy,
+    log: Mutex<BufWriter<File>>,
+    rejects: Mutex<Option<BufWriter<File>>>,
+}
+
+impl Workspace {
+    /// Creates a new run directory under the given parent directory, named
+    /// after the run, its start time and the process id.
+    pub fn create(parent: &Path, name: &str, cleanup: CleanupPolicy) -> io::Result<Self> {
+        let timestamp = utc_timestamp(SystemTime::now()).replace([':', '-'], "");
+        let dir = parent.join(format!("{}-{}-{}", name, timestamp, process::id()));
+
+        fs::create_dir_all(parent)?;
+        // Fails if the directory already exists, instead of sharing it.
+        fs::create_dir(&dir)?;
+
+        let log = File::create(dir.join("run.log"))?;
+
+        Ok(Self {
+            dir,
+            cleanup,
+            log: Mutex::new(BufWriter::new(log)),
+            rejects: Mutex::new(None),
+        })
+    }
+
+    /// The run directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The path of a file of the run directory.
+    pub fn file(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Writes a timestamped message to the log of the run.
+    pub fn log(&self, message: &str) -> io::Result<()> {
+        let mut log = self.log.lock().unwrap();
+
+        writeln!(log, "[{}] {}", utc_timestamp(SystemTime::now()), message)?;
+        log.flush()
+    }
+
+    /// Records an input line which was not scored, along with the reason,
+    /// as a '<REASON>\t<LINE>' line of the 'rejects.tsv' file, which is only
+    /// created if some lines are rejected.
+    pub fn reject(&self, line: &str, reason: &str) -> io::Result<()> {
+        let mut rejects = self.rejects.lock().unwrap();
+
+        if rejects.is_none() {
+            let file = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(self.file("rejects.tsv"))?;
+
+            *rejects = Some(BufWriter::new(file));
+        }
+
+        let reason = reason.replace(['\t', '\n'], " ");
+
+        writeln!(rejects.as_mut().unwrap(), "{}\t{}", reason, line.trim_end())
+    }
+
+    /// Ends the run, deleting the directory if the cleanup policy says so.
+    pub fn finish(&self, success: bool) -> io::Result<()> {
+        if let Some(rejects) = self.rejects.lock().unwrap().as_mut() {
+            rejects.flush()?;
+        }
+
+        self.log(match success {
+            true => "run succeeded",
+            false => "run failed",
+        })?;
+
+        match (self.cleanup, success) {
+            (CleanupPolicy::Always, _) | (CleanupPolicy::OnSuccess, true) => {
+                fs::remove_dir_all(&self.dir)
+            }
+            _ => Ok(()),
+        }
+    }
+}
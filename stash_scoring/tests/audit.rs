@@ -0,0 +1,61 @@
+use stash_scoring::audit::{Audit, AuditJob, AuditReport};
+use stash_scoring::engine::Score;
+
+fn job(engine: usize, score: Score) -> AuditJob {
+    AuditJob {
+        fen: String::from("8/8/8/8/8/8/8/K6k w - - 0 1"),
+        moves: Vec::new(),
+        score,
+        engine,
+    }
+}
+
+#[test]
+fn prefers_other_engines() {
+    let audit = Audit::new(1.0);
+
+    audit.push(job(1, Score::Cp(0)));
+    audit.push(job(2, Score::Cp(0)));
+
+    assert_eq!(audit.take_for(1, false).unwrap().engine, 2);
+    assert!(audit.take_for(1, false).is_none());
+    assert_eq!(audit.take_for(1, true).unwrap().engine, 1);
+    assert!(audit.take_for(1, true).is_none());
+}
+
+#[test]
+fn samples_positions_by_index() {
+    let audit = Audit::new(0.25);
+    let sampled = (0..10000).filter(|&index| audit.is_sampled(index)).count();
+
+    assert!((2300..2700).contains(&sampled), "{}", sampled);
+    assert!((0..100).all(|index| audit.is_sampled(index) == audit.is_sampled(index)));
+    assert!((0..100).all(|index| !Audit::new(0.0).is_sampled(index)));
+    assert!((0..100).all(|index| Audit::new(1.0).is_sampled(index)));
+}
+
+#[test]
+fn reports_score_differences() {
+    let audit = Audit::new(1.0);
+
+    assert_eq!(audit.report().audited, 0);
+
+    for cp in 0..100 {
+        audit.record(&job(1, Score::Cp(50)), Score::Cp(50 + cp));
+    }
+
+    audit.record(&job(1, Score::Cp(50)), Score::Cp(50));
+    audit.record(&job(1, Score::Mate(3)), Score::Mate(3));
+
+    assert_eq!(
+        audit.report(),
+        AuditReport {
+            audited: 102,
+            identical: 3,
+            median: 48,
+            p90: 88,
+            p99: 97,
+            max: 99,
+        }
+    );
+}
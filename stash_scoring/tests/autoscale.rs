@@ -0,0 +1,60 @@
+use std::time::{Duration, Instant};
+
+use stash_scoring::autoscale::{Scaling, ThreadScaler};
+
+const WINDOW: Duration = Duration::from_secs(10);
+
+/// Feeds the scaler with a window of responses at the given rate per second,
+/// returning its decision.
+fn measure(scaler: &mut ThreadScaler, now: &mut Instant, rate: usize, memory_low: bool) -> Scaling {
+    *now += WINDOW;
+    scaler.record(rate * WINDOW.as_secs() as usize, *now, memory_low)
+}
+
+#[test]
+fn grows_while_throughput_increases() {
+    let start = Instant::now();
+    let mut now = start;
+    let mut scaler = ThreadScaler::new(8, WINDOW, start);
+
+    assert_eq!(scaler.workers(), 1);
+    // Windows are only complete after their duration.
+    assert_eq!(
+        scaler.record(1000, start + Duration::from_secs(1), false),
+        Scaling::Hold
+    );
+    scaler.start_window(start);
+    assert_eq!(measure(&mut scaler, &mut now, 10, false), Scaling::Grow);
+    assert_eq!(measure(&mut scaler, &mut now, 19, false), Scaling::Grow);
+    assert_eq!(measure(&mut scaler, &mut now, 27, false), Scaling::Grow);
+    assert_eq!(scaler.workers(), 4);
+    assert_eq!(scaler.settled_throughput(), None);
+
+    // A fourth engine barely helps, so it is stopped.
+    assert_eq!(measure(&mut scaler, &mut now, 28, false), Scaling::Shrink);
+    assert_eq!(scaler.workers(), 3);
+    assert_eq!(scaler.settled_throughput(), Some(27.0));
+    assert_eq!(measure(&mut scaler, &mut now, 50, false), Scaling::Hold);
+    assert_eq!(scaler.workers(), 3);
+}
+
+#[test]
+fn stops_at_the_limits() {
+    let start = Instant::now();
+    let mut now = start;
+    let mut scaler = ThreadScaler::new(2, WINDOW, start);
+
+    assert_eq!(measure(&mut scaler, &mut now, 10, false), Scaling::Grow);
+    assert_eq!(measure(&mut scaler, &mut now, 20, false), Scaling::Hold);
+    assert_eq!(scaler.workers(), 2);
+    assert_eq!(scaler.settled_throughput(), Some(20.0));
+
+    // Low memory undoes the last increase.
+    let mut now = start;
+    let mut scaler = ThreadScaler::new(8, WINDOW, start);
+
+    assert_eq!(measure(&mut scaler, &mut now, 10, false), Scaling::Grow);
+    assert_eq!(measure(&mut scaler, &mut now, 20, true), Scaling::Shrink);
+    assert_eq!(scaler.workers(), 1);
+    assert_eq!(scaler.settled_throughput(), Some(10.0));
+}
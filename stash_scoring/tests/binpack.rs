@@ -0,0 +1,157 @@
+mod common;
+
+use std::io::Cursor;
+
+use stash_scoring::binpack::{BinpackReader, PlainReader, TrainingEntry};
+use stash_scoring::engine::Score;
+
+use common::*;
+
+/// A binpack chunk holding a single chain: the start position with 1. e4
+/// scored 20, then 1... e5 scored -15.
+fn binpack() -> Vec<u8> {
+    let mut data = b"BINP".to_vec();
+
+    data.extend_from_slice(&36u32.to_le_bytes());
+    // The occupancy, then the pieces in square order, two per byte.
+    data.extend_from_slice(&[0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff]);
+    data.extend_from_slice(&[
+        0x2d, 0x84, 0x4a, 0xd2, 0x00, 0x00, 0x00, 0x00, 0x11, 0x11, 0x11, 0x11, 0x3e, 0x95, 0x5b,
+        0xe3,
+    ]);
+    // The move, score, ply and result, and halfmove clock.
+    data.extend_from_slice(&[0x0c, 0x70, 0x00, 0x28, 0x00, 0x00, 0x00, 0x00]);
+    // One more ply: the 5th pawn of Black, its double push, and a score
+    // delta of 5 from the negated previous score.
+    data.extend_from_slice(&[0x00, 0x01, 0x42, 0x80]);
+    data
+}
+
+fn read_binpack(data: Vec<u8>) -> Vec<Result<TrainingEntry, String>> {
+    let mut reader = BinpackReader::new(Cursor::new(data));
+    let mut entries = Vec::new();
+
+    while let Some(entry) = reader.next_entry().unwrap() {
+        entries.push(entry);
+    }
+
+    entries
+}
+
+#[test]
+fn reads_binpack_chains() {
+    let entries = read_binpack(binpack());
+
+    assert_eq!(entries.len(), 2);
+
+    let first = entries[0].as_ref().unwrap();
+    let second = entries[1].as_ref().unwrap();
+
+    assert_eq!(first.pos.to_fen(), STARTPOS);
+    assert_eq!(first.mv.to_uci(false), "e2e4");
+    assert_eq!((first.score, first.ply, first.result), (20, 0, 0));
+    assert_eq!(
+        second.pos.to_fen(),
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+    );
+    assert_eq!(second.mv.to_uci(false), "e7e5");
+    assert_eq!((second.score, second.ply), (-15, 1));
+    assert_eq!(second.to_scored().unwrap().score, Score::Cp(-15));
+
+    // A truncated chain only loses the positions which cannot be read.
+    let mut truncated = binpack();
+
+    truncated[4] = 35;
+    truncated.pop();
+
+    let entries = read_binpack(truncated);
+
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].is_ok() && entries[1].is_err());
+}
+
+#[test]
+fn reads_plain_positions() {
+    let data = format!(
+        "fen {}\nmove e2e4\nscore 31990\nply 0\nresult 1\ne\n\
+         fen {}\nmove e1e8\nscore 0\nply 4\nresult 0\ne\n\
+         fen {}\nmove g8f6\nscore -40\nply 2\nresult -1\ne\n\
+         fen {}\nmove e2e4\nscore 32002\nply 0\nresult 0\ne\n",
+        STARTPOS,
+        STARTPOS,
+        STARTPOS.replace(" w ", " b "),
+        STARTPOS
+    );
+    let mut reader = PlainReader::new(Cursor::new(data));
+    let first = reader.next_entry().unwrap().unwrap().unwrap();
+
+    // Stockfish mate scores are counted in plies from 32000.
+    assert_eq!(first.score(), Some(Score::Mate(5)));
+    assert_eq!(first.wdl(), 1.0);
+
+    // The illegal move only invalidates its own position.
+    assert!(reader.next_entry().unwrap().unwrap().is_err());
+
+    let third = reader.next_entry().unwrap().unwrap().unwrap();
+
+    // Results are from the side to move's point of view.
+    assert_eq!(third.wdl(), 1.0);
+    assert_eq!(third.score(), Some(Score::Cp(-40)));
+
+    // Positions written without a score (VALUE_NONE) are not mates.
+    let fourth = reader.next_entry().unwrap().unwrap().unwrap();
+
+    assert_eq!(fourth.score(), None);
+    assert!(fourth.to_scored().is_err());
+    assert!(reader.next_entry().unwrap().is_none());
+}
+
+#[test]
+fn converts_stockfish_datasets() {
+    let harness = Harness::new();
+    let input = harness.path_str("data.binpack");
+    let output = harness.path_str("data.txt");
+
+    std::fs::write(&input, binpack()).unwrap();
+
+    let args = [
+        "convert", "-i", &input, "--from", "binpack", "-o", &output, "--to", "text",
+    ];
+
+    assert!(harness.run(&args, None).status.success());
+    assert_eq!(
+        harness.read("data.txt").unwrap(),
+        format!(
+            "{} 0.5 20\nrnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 0.5 -15\n",
+            STARTPOS
+        )
+    );
+
+    // Stockfish datasets cannot be written.
+    let args = [
+        "convert", "-i", &output, "--from", "text", "-o", &input, "--to", "binpack",
+    ];
+
+    assert!(!harness.run(&args, None).status.success());
+}
+
+#[test]
+fn scores_stockfish_datasets() {
+    let harness = Harness::new();
+    let script = search_script("info depth 1 score cp 42 pv e2e4");
+    let input = harness.write(
+        "data.plain",
+        &format!(
+            "fen {}\nmove e2e4\nscore 20\nply 0\nresult -1\ne\n",
+            STARTPOS
+        ),
+    );
+    let output = harness.path_str("output.txt");
+    let args = ["-e", MOCK_ENGINE, "-i", &input, "-o", &output, "-d", "1"];
+
+    assert!(harness.run(&args, Some(&script)).status.success());
+    assert_eq!(
+        harness.read("output.txt").unwrap(),
+        format!("{} 0 42\n", STARTPOS)
+    );
+}
@@ -0,0 +1,360 @@
+use stash_scoring::board::{canonical_fen, MoveNotation, Position, Square};
+
+fn perft(pos: &Position, depth: u32) -> u64 {
+    if depth == 1 {
+        return pos.legal_moves().len() as u64;
+    }
+
+    pos.legal_moves()
+        .into_iter()
+        .map(|mv| {
+            let mut next = pos.clone();
+
+            next.play(mv);
+            perft(&next, depth - 1)
+        })
+        .sum()
+}
+
+#[test]
+fn generates_legal_moves() {
+    let cases = [
+        (Position::STARTPOS, false, 3, 8902),
+        (
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            false,
+            3,
+            97862,
+        ),
+        ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", false, 4, 43238),
+        (
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            false,
+            3,
+            9467,
+        ),
+        (
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            true,
+            3,
+            12189,
+        ),
+        (
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            true,
+            3,
+            18002,
+        ),
+    ];
+
+    for (fen, chess960, depth, nodes) in cases {
+        let pos = Position::from_fen(fen, chess960).unwrap();
+
+        assert_eq!(perft(&pos, depth), nodes, "perft({}) of '{}'", depth, fen);
+    }
+}
+
+#[test]
+fn plays_moves() {
+    let mut pos = Position::from_fen(Position::STARTPOS, false).unwrap();
+
+    for san in [
+        "e4", "c5", "e5", "d5", "exd6", "Nf6", "dxe7", "Nc6", "exf8=Q+", "Rxf8",
+    ] {
+        let mv = pos.parse_san(san).unwrap();
+
+        pos.play(mv);
+    }
+
+    assert_eq!(
+        pos.to_fen(),
+        "r1bqkr2/pp3ppp/2n2n2/2p5/8/8/PPPP1PPP/RNBQKBNR w KQq - 0 6"
+    );
+
+    for uci in ["g1f3", "c8g4", "f1c4", "g4f3", "d1f3", "c6d4", "e1g1"] {
+        let mv = pos.parse_uci(uci).unwrap();
+
+        pos.play(mv);
+    }
+
+    assert_eq!(
+        pos.to_fen(),
+        "r2qkr2/pp3ppp/5n2/2p5/2Bn4/5Q2/PPPP1PPP/RNB2RK1 b q - 2 9"
+    );
+}
+
+#[test]
+fn parses_castling_moves() {
+    let pos = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", false).unwrap();
+
+    for (uci, to) in [("e1g1", "h1"), ("e1h1", "h1"), ("e1c1", "a1")] {
+        let mv = pos.parse_uci(uci).unwrap();
+
+        assert_eq!(mv.to, to.parse::<Square>().unwrap());
+    }
+
+    assert_eq!(pos.parse_san("O-O-O").unwrap().to_uci(false), "e1c1");
+    assert_eq!(pos.parse_san("O-O-O").unwrap().to_uci(true), "e1a1");
+}
+
+#[test]
+fn rejects_illegal_and_ambiguous_moves() {
+    let pos = Position::from_fen("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", false).unwrap();
+
+    assert!(pos.parse_san("Rd1").is_err());
+    assert!(pos.parse_san("Rad1").is_ok());
+    assert!(pos.parse_san("R1f1").is_err());
+    assert!(pos.parse_san("O-O").is_err());
+    assert!(pos.parse_san("Kd3").is_ok());
+    assert!(pos.parse_san("e4").is_err());
+    assert!(pos.parse_uci("e2e4").is_err());
+}
+
+#[test]
+fn converts_move_notations() {
+    let pos = Position::from_fen(
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        false,
+    )
+    .unwrap();
+
+    assert_eq!(pos.san_to_uci("O-O-O").unwrap(), "e1c1");
+    assert_eq!(pos.uci_to_san("e5f7").unwrap(), "Nxf7");
+    assert_eq!(pos.uci_to_san("d5e6").unwrap(), "dxe6");
+    assert!(pos.uci_to_san("e5e6").is_err());
+
+    let moves = ["Qxf6", "e7f6", "O-O", "e8g8"];
+
+    assert_eq!(
+        pos.convert_moves(&moves, MoveNotation::Uci).unwrap(),
+        ["f3f6", "e7f6", "e1g1", "e8g8"]
+    );
+    assert_eq!(
+        pos.convert_moves(&moves, MoveNotation::San).unwrap(),
+        ["Qxf6", "Qxf6", "O-O", "O-O"]
+    );
+    assert!(pos
+        .convert_moves(&["O-O", "O-O", "O-O"], MoveNotation::Uci)
+        .is_err());
+}
+
+#[test]
+fn checks_uci_lines() {
+    let pos = Position::from_fen(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        false,
+    )
+    .unwrap();
+
+    assert!(pos.check_uci_line(&[]).is_ok());
+    assert!(pos.check_uci_line(&["e2e4", "e7e5", "g1f3"]).is_ok());
+
+    let err = pos.check_uci_line(&["e2e4", "e2e4"]).unwrap_err();
+
+    assert!(err.to_string().starts_with("move 2: illegal move 'e2e4'"));
+}
+
+#[test]
+fn canonicalizes_fens() {
+    let cases = [
+        // Move counters are dropped.
+        (
+            Position::STARTPOS,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -",
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 17 42",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -",
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -",
+        ),
+        // No pawn can capture en passant.
+        (
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq -",
+        ),
+        // The capture is possible.
+        (
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3",
+        ),
+        // A pawn stands next to the pushed pawn, but for the wrong side.
+        (
+            "rnbqkbnr/pppppppp/8/8/3PP3/8/PPP2PPP/RNBQKBNR b KQkq e3 0 2",
+            "rnbqkbnr/pppppppp/8/8/3PP3/8/PPP2PPP/RNBQKBNR b KQkq -",
+        ),
+        // The capturing pawn is pinned, on its file or on a diagonal.
+        (
+            "3k4/8/8/8/3pP3/8/8/3R3K b - e3 0 1",
+            "3k4/8/8/8/3pP3/8/8/3R3K b - -",
+        ),
+        (
+            "8/8/5B2/8/3pP3/8/1k6/7K b - e3 0 1",
+            "8/8/5B2/8/3pP3/8/1k6/7K b - -",
+        ),
+        // Both pawns leave the rank, exposing the king.
+        (
+            "8/8/8/8/k2pP2R/8/8/7K b - e3 0 1",
+            "8/8/8/8/k2pP2R/8/8/7K b - -",
+        ),
+        // Pinned along the diagonal the capture follows.
+        (
+            "8/k7/8/8/3pP3/8/5B2/7K b - e3 0 1",
+            "8/k7/8/8/3pP3/8/5B2/7K b - e3",
+        ),
+        // The capture removes the pawn giving check.
+        (
+            "8/8/8/5k2/3pP3/8/8/7K b - e3 0 1",
+            "8/8/8/5k2/3pP3/8/8/7K b - e3",
+        ),
+        // The capture blocks a check.
+        (
+            "K7/8/7k/8/3pP3/8/8/2B5 b - e3 0 1",
+            "K7/8/7k/8/3pP3/8/8/2B5 b - e3",
+        ),
+        // The capture leaves the king in check.
+        (
+            "7K/8/8/8/3pP3/8/8/k6R b - e3 0 1",
+            "7K/8/8/8/3pP3/8/8/k6R b - -",
+        ),
+        // White captures too.
+        (
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6",
+        ),
+        (
+            "4k3/8/8/3p3P/8/8/8/4K3 w - d6 0 1",
+            "4k3/8/8/3p3P/8/8/8/4K3 w - -",
+        ),
+    ];
+
+    for (fen, expected) in cases {
+        assert_eq!(canonical_fen(fen, false).unwrap(), expected, "{}", fen);
+    }
+
+    // The canonical FEN is a valid FEN, and its own canonical form.
+    for (_, expected) in cases {
+        assert_eq!(canonical_fen(expected, false).unwrap(), expected);
+    }
+
+    assert!(canonical_fen("8/8/8/8 w - -", false).is_err());
+}
+
+#[test]
+fn canonicalizes_chess960_castling_rights() {
+    // Shredder-FEN and X-FEN castling rights describe the same position.
+    let shredder = "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEge - 0 1";
+    let xfen = "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w KQkq - 4 3";
+
+    assert_eq!(
+        canonical_fen(shredder, true).unwrap(),
+        canonical_fen(xfen, true).unwrap()
+    );
+}
+
+#[test]
+fn builds_chess960_starting_positions() {
+    assert_eq!(
+        Position::from_scharnagl(518, 518).to_fen(),
+        Position::STARTPOS
+    );
+    assert_eq!(
+        Position::from_scharnagl(0, 959).to_shredder_fen(),
+        "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFca - 0 1"
+    );
+
+    let mut setups: Vec<String> = (0..960)
+        .map(|number| Position::from_scharnagl(number, number).to_fen())
+        .collect();
+
+    setups.sort();
+    setups.dedup();
+    assert_eq!(setups.len(), 960);
+    assert!(setups.iter().all(|fen| fen.contains(" w KQkq - ")));
+}
+
+#[test]
+fn computes_zobrist_keys() {
+    let play = |fen: &str, moves: &[&str]| {
+        let mut pos = Position::from_fen(fen, false).unwrap();
+
+        for uci in moves {
+            let mv = pos.parse_uci(uci).unwrap();
+
+            pos.play(mv);
+        }
+
+        pos
+    };
+    let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    // Keys updated along with the position match the ones of parsed FENs,
+    // including after castling, captures, promotions and en passant.
+    for (fen, moves) in [
+        (Position::STARTPOS, &["e2e4", "d7d5", "e4d5", "g8f6"][..]),
+        (kiwipete, &["e1g1", "h3g2", "a2a4", "b4a3", "f3f6"]),
+        (kiwipete, &["e1c1", "e8c8"]),
+        ("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", &["b7b8q"]),
+    ] {
+        let pos = play(fen, moves);
+
+        assert_eq!(
+            pos.key(),
+            Position::from_fen(&pos.to_fen(), false).unwrap().key(),
+            "{:?}",
+            moves
+        );
+    }
+
+    // Transpositions share their keys, unlike the same placement with
+    // another side to move or castling rights.
+    let start = play(Position::STARTPOS, &[]);
+
+    assert_eq!(
+        play(Position::STARTPOS, &["g1f3", "g8f6", "f3g1", "f6g8"]).key(),
+        start.key()
+    );
+    assert_ne!(
+        play(
+            Position::STARTPOS,
+            &["e2e4", "e7e5", "e1e2", "e8e7", "e2e1", "e7e8"]
+        )
+        .key(),
+        start.key()
+    );
+    assert_ne!(
+        Position::from_fen(&Position::STARTPOS.replace(" w ", " b "), false)
+            .unwrap()
+            .key(),
+        start.key()
+    );
+
+    // Unusable en passant squares are ignored.
+    assert_eq!(
+        play(Position::STARTPOS, &["e2e4"]).key(),
+        Position::from_fen(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            false
+        )
+        .unwrap()
+        .key()
+    );
+}
+
+#[test]
+fn detects_insufficient_material() {
+    for (fen, insufficient) in [
+        ("8/8/4k3/8/8/3K4/8/8 w - - 0 1", true),
+        ("8/8/4k3/8/8/3K4/5N2/8 w - - 0 1", true),
+        ("8/2b5/4k3/8/8/3K4/5B2/8 w - - 0 1", true),
+        ("8/3b4/4k3/8/8/3K4/5B2/8 w - - 0 1", false),
+        ("8/8/4k3/8/8/3K4/4NN2/8 w - - 0 1", false),
+        ("8/8/4k3/8/8/3K4/4P3/8 w - - 0 1", false),
+    ] {
+        let pos = Position::from_fen(fen, false).unwrap();
+
+        assert_eq!(pos.has_insufficient_material(), insufficient, "{}", fen);
+    }
+}
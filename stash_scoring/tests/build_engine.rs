@@ -0,0 +1,103 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use common::{Harness, MOCK_ENGINE};
+
+fn git(repo: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+#[test]
+fn builds_commits() {
+    let harness = Harness::new();
+    let repo = harness.path("stash");
+
+    // The makefile installs the mock engine, and records the ARCH flag.
+    fs::create_dir_all(repo.join("src")).unwrap();
+    fs::write(
+        repo.join("src/Makefile"),
+        format!(
+            "all:\n\tcp {} $(EXE)\n\techo '$(ARCH)' > ../arch.txt\n",
+            MOCK_ENGINE
+        ),
+    )
+    .unwrap();
+    git(&repo, &["init", "--quiet", "--initial-branch=master"]);
+    git(&repo, &["add", "-A"]);
+    git(&repo, &["commit", "--quiet", "-m", "First"]);
+
+    let first_sha = git(&repo, &["rev-parse", "HEAD"]);
+    let cache_dir = harness.path_str("cache");
+    let repo_url = repo.to_str().unwrap();
+    let build = |commit: &str| {
+        let output = harness.run(
+            &[
+                "build-engine",
+                "-r",
+                repo_url,
+                "-c",
+                commit,
+                "-a",
+                "avx2",
+                "--cache-dir",
+                &cache_dir,
+            ],
+            None,
+        );
+
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    };
+
+    let first = build("master");
+
+    assert!(first.ends_with(&format!("stash-{}-avx2", &first_sha[..12])));
+    assert!(Path::new(&first).exists());
+    assert_eq!(harness.read("cache/repo/arch.txt").unwrap(), "avx2\n");
+
+    // New commits on a branch are fetched, while older ones are not rebuilt.
+    fs::write(repo.join("README"), "Updated\n").unwrap();
+    git(&repo, &["add", "-A"]);
+    git(&repo, &["commit", "--quiet", "-m", "Second"]);
+
+    let second_sha = git(&repo, &["rev-parse", "HEAD"]);
+    let second = build("master");
+
+    assert!(second.ends_with(&format!("stash-{}-avx2", &second_sha[..12])));
+    assert!(Path::new(&second).exists());
+
+    fs::remove_file(harness.path("cache/repo/arch.txt")).unwrap();
+    assert_eq!(build(&first_sha), first);
+    assert!(harness.read("cache/repo/arch.txt").is_none());
+
+    let output = harness.run(
+        &[
+            "build-engine",
+            "-r",
+            repo_url,
+            "-c",
+            "unknown",
+            "--cache-dir",
+            &cache_dir,
+        ],
+        None,
+    );
+
+    assert!(!output.status.success());
+}
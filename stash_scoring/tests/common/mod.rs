@@ -0,0 +1,82 @@
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+pub const TOOL: &str = env!("CARGO_BIN_EXE_stash_scoring");
+pub const MOCK_ENGINE: &str = env!("CARGO_BIN_EXE_mock_engine");
+
+pub const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+pub const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+/// A temporary directory holding the files of a single test run.
+pub struct Harness {
+    dir: TempDir,
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        Self {
+            dir: TempDir::new().unwrap(),
+        }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    pub fn path_str(&self, name: &str) -> String {
+        self.path(name).to_str().unwrap().to_string()
+    }
+
+    pub fn write(&self, name: &str, content: &str) -> String {
+        fs::write(self.path(name), content).unwrap();
+        self.path_str(name)
+    }
+
+    pub fn read(&self, name: &str) -> Option<String> {
+        fs::read_to_string(self.path(name)).ok()
+    }
+
+    /// Runs the tool with the given arguments, the mock engine replaying the
+    /// given script (if any). The commands received by the mock engines are
+    /// logged to 'engine.log'.
+    pub fn run(&self, args: &[&str], script: Option<&str>) -> Output {
+        let mut command = Command::new(TOOL);
+
+        command
+            .args(args)
+            .env("MOCK_ENGINE_LOG", self.path("engine.log"));
+
+        if let Some(script) = script {
+            command.env("MOCK_ENGINE_SCRIPT", self.write("script.txt", script));
+        }
+
+        command.output().unwrap()
+    }
+
+    /// Scores the input with the mock engine at depth 1, returning the output
+    /// file content if the run succeeded.
+    pub fn score(&self, input: &str, script: Option<&str>, extra_args: &[&str]) -> Option<String> {
+        let input = self.write("input.txt", input);
+        let output = self.path_str("output.txt");
+        let mut args = vec!["-e", MOCK_ENGINE, "-i", &input, "-o", &output, "-d", "1"];
+
+        args.extend_from_slice(extra_args);
+
+        let result = self.run(&args, script);
+
+        match result.status.success() {
+            true => self.read("output.txt"),
+            false => None,
+        }
+    }
+}
+
+/// Builds a script answering every search with the given info line.
+pub fn search_script(info: &str) -> String {
+    format!("[go]\n{}\nbestmove e2e4\n", info)
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::*;
+
+const ENDGAME: &str = "8/5k2/8/8/8/8/2R5/4K3 w - - 0 1";
+
+fn dataset(fen: &str, evals: &[i32]) -> String {
+    evals
+        .iter()
+        .map(|eval| format!("{} 0.5 {}\n", fen, eval))
+        .collect()
+}
+
+fn compare(harness: &Harness, first: &str, second: &str, extra_args: &[&str]) -> Option<String> {
+    let first = harness.write("a.txt", first);
+    let second = harness.write("b.txt", second);
+    let mut args = vec!["compare-dist", "-a", &first, "-b", &second];
+
+    args.extend_from_slice(extra_args);
+
+    let output = harness.run(&args, None);
+
+    match output.status.success() {
+        true => Some(String::from_utf8(output.stdout).unwrap()),
+        false => None,
+    }
+}
+
+fn row<'a>(report: &'a str, name: &str) -> Vec<&'a str> {
+    report
+        .lines()
+        .find(|line| line.starts_with(name))
+        .unwrap()
+        .split_whitespace()
+        .collect()
+}
+
+#[test]
+fn reports_identical_distributions() {
+    let harness = Harness::new();
+    let content = dataset(STARTPOS, &[-50, 0, 20, 50]) + &dataset(ENDGAME, &[400, 600]);
+    let report = compare(&harness, &content, &content, &[]).unwrap();
+
+    assert_eq!(
+        row(&report, "all")[..10],
+        ["all", "6", "6", "170.0", "170.0", "+0.0", "242.2", "242.2", "+0.0", "0.0000"]
+    );
+    assert_eq!(row(&report, "opening")[..4], ["opening", "4", "4", "5.0"]);
+    assert_eq!(row(&report, "middlegame"), ["middlegame", "0", "0"]);
+    assert_eq!(row(&report, "endgame")[..4], ["endgame", "2", "2", "500.0"]);
+}
+
+#[test]
+fn reports_shifted_distributions() {
+    let harness = Harness::new();
+    let first = dataset(STARTPOS, &[-10, 0, 10, 20]);
+    let second = dataset(STARTPOS, &[90, 100, 110, 120]) + "invalid line\n";
+    let report = compare(&harness, &first, &second, &["--bin-width", "500"]).unwrap();
+    let all = row(&report, "all");
+
+    assert_eq!(all[..6], ["all", "4", "4", "5.0", "105.0", "+100.0"]);
+    assert_eq!(all[9], "1.0000");
+
+    // Evals beyond the clamp value are counted in the end bins.
+    let report = compare(
+        &harness,
+        &dataset(STARTPOS, &[-2000, 0]),
+        &dataset(STARTPOS, &[0, 1500]),
+        &["--bin-width", "500"],
+    )
+    .unwrap();
+
+    assert!(report.contains("[-1000, -500)   50.00%    0.00%  -50.00%"));
+    assert!(report.contains("[0, 500)   50.00%   50.00%   +0.00%"));
+    assert!(report.contains("[500, 1000)    0.00%   50.00%  +50.00%"));
+}
+
+#[test]
+fn requires_an_eval_column() {
+    let harness = Harness::new();
+    let content = dataset(STARTPOS, &[0]);
+
+    assert!(compare(
+        &harness,
+        &content,
+        &content,
+        &["--input-columns", "fen,wdl,extra"]
+    )
+    .is_none());
+    assert!(compare(&harness, &content, &content, &["--bin-width", "0"]).is_none());
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use serde_json::Value;
+
+use common::*;
+
+fn check(harness: &Harness, script: &str, extra_args: &[&str]) -> (bool, Value) {
+    let input = harness.write("input.txt", &format!("{}\n", STARTPOS));
+    let report = harness.path_str("report.json");
+    let mut args = vec![
+        "consistency",
+        "-e",
+        MOCK_ENGINE,
+        "-i",
+        &input,
+        "-d",
+        "1",
+        "--report-file",
+        &report,
+    ];
+
+    args.extend_from_slice(extra_args);
+
+    let result = harness.run(&args, Some(script));
+    let report = serde_json::from_str(&harness.read("report.json").unwrap()).unwrap();
+
+    (result.status.success(), report)
+}
+
+#[test]
+fn accepts_consistent_engines() {
+    let harness = Harness::new();
+    let script = search_script("info depth 1 score cp 12 pv e2e4");
+    let (consistent, report) = check(&harness, &script, &["--variant", "Hash=64,Threads=2"]);
+
+    assert!(consistent);
+    assert_eq!(report["positions"], 1);
+    assert_eq!(report["configurations"][1], "Hash=64,Threads=2");
+    assert_eq!(report["inconsistent_positions"], 0);
+
+    // The variant options are set after the base ones.
+    let log = harness.read("engine.log").unwrap();
+
+    assert!(log.contains("setoption name Hash value 64\n"));
+    assert!(log.contains("setoption name Threads value 2\n"));
+    assert_eq!(log.matches("go depth 1").count(), 6);
+}
+
+#[test]
+fn reports_varying_results() {
+    let harness = Harness::new();
+    let script = "[go]\ninfo depth 1 score cp 12 pv e2e4\nbestmove e2e4\n\
+                  [go]\ninfo depth 1 score cp 15 pv e2e4\nbestmove e2e4\n";
+    let (consistent, report) = check(&harness, script, &["-r", "2"]);
+
+    assert!(!consistent);
+    assert_eq!(report["inconsistent_positions"], 1);
+
+    let position = &report["inconsistencies"][0];
+
+    assert_eq!(position["fen"], STARTPOS);
+    assert_eq!(position["bestmoves"]["e2e4"], 2);
+    assert_eq!(position["score_spread"], 3);
+    assert_eq!(position["searches"][1]["score"], "15");
+
+    let (consistent, _) = check(&harness, script, &["-r", "2", "--tolerance", "3"]);
+
+    assert!(consistent);
+
+    let script = "[go]\ninfo depth 1 score cp 12 pv e2e4\nbestmove e2e4\n\
+                  [go]\ninfo depth 1 score cp 12 pv d2d4\nbestmove d2d4\n";
+    let (consistent, report) = check(&harness, script, &["-r", "2", "--tolerance", "3"]);
+
+    assert!(!consistent);
+    assert_eq!(report["inconsistencies"][0]["bestmoves"]["d2d4"], 1);
+}
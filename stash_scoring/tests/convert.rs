@@ -0,0 +1,118 @@
+mod common;
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::Score;
+use stash_scoring::formats::ScoredPosition;
+
+use common::*;
+
+const BLACK_TO_MOVE: &str = "r3k2r/8/8/8/4p3/8/8/R3K2R b KQkq - 3 20";
+
+fn dataset() -> String {
+    format!(
+        "{} 0.5 12\n{} 1 #3\n{} 0 -250\n{} 0.25 #-2\n",
+        STARTPOS, KIWIPETE, BLACK_TO_MOVE, STARTPOS
+    )
+}
+
+/// Converts the file with the given formats, returning the output path.
+fn convert(harness: &Harness, input: &str, from: &str, to: &str) -> String {
+    let output = harness.path_str(&format!("converted.{}", to));
+    let result = harness.run(
+        &[
+            "convert", "-i", input, "--from", from, "-o", &output, "--to", to, "-t", "2",
+        ],
+        None,
+    );
+
+    assert!(result.status.success());
+    output
+}
+
+#[test]
+fn round_trips_text_formats() {
+    let harness = Harness::new();
+    let input = harness.write("input.txt", &dataset());
+
+    for format in ["epd", "jsonl", "csv"] {
+        let converted = convert(&harness, &input, "text", format);
+        let back = convert(&harness, &converted, format, "text");
+
+        assert_eq!(harness.read(&back).unwrap(), dataset(), "{}", format);
+    }
+
+    let epd = convert(&harness, &input, "text", "epd");
+
+    assert_eq!(
+        harness.read(&epd).unwrap().lines().nth(1).unwrap(),
+        format!(
+            "{} hmvc 0; fmvn 1; dm 3; c9 \"1-0\";",
+            KIWIPETE.replace(" 0 1", "")
+        )
+    );
+
+    let jsonl = convert(&harness, &input, "text", "jsonl");
+    let first: serde_json::Value =
+        serde_json::from_str(harness.read(&jsonl).unwrap().lines().next().unwrap()).unwrap();
+
+    assert_eq!(first["fen"], STARTPOS);
+    assert_eq!(first["wdl"], 0.5);
+    assert_eq!(first["eval"], 12);
+}
+
+#[test]
+fn encodes_bullet_records() {
+    let pos = Position::from_fen(BLACK_TO_MOVE, false).unwrap();
+    let record = ScoredPosition {
+        pos,
+        wdl: 0.0,
+        score: Score::Cp(-250),
+    }
+    .to_bullet();
+
+    // The board is flipped for Black: its king is now on e1, and its pawn on
+    // e5.
+    assert_eq!(
+        u64::from_le_bytes(record[..8].try_into().unwrap()),
+        (1 << 0) | (1 << 4) | (1 << 7) | (1 << 36) | (1 << 56) | (1 << 60) | (1 << 63)
+    );
+    // Our rook, our king, our rook, our pawn, then the opponent's pieces.
+    assert_eq!(record[8..12], [0x53, 0x03, 0xdb, 0x0b]);
+    assert_eq!(i16::from_le_bytes([record[24], record[25]]), -250);
+    // Black won, from the side to move's point of view.
+    assert_eq!(record[26], 2);
+    assert_eq!((record[27], record[28]), (4, 4));
+
+    let decoded = ScoredPosition::from_bullet(&record).unwrap();
+
+    assert_eq!(decoded.pos.to_fen(), "r3k2r/8/8/4P3/8/8/8/R3K2R w - - 0 1");
+    assert_eq!((decoded.wdl, decoded.score), (1.0, Score::Cp(-250)));
+}
+
+#[test]
+fn round_trips_bullet_records() {
+    let harness = Harness::new();
+    let input = harness.write(
+        "input.txt",
+        &format!(
+            "{} 0.5 12\n{} 1 -40\ninvalid line\n{} 0 250\n",
+            STARTPOS, KIWIPETE, BLACK_TO_MOVE
+        ),
+    );
+    let bullet = convert(&harness, &input, "text", "bullet");
+
+    assert_eq!(std::fs::metadata(&bullet).unwrap().len(), 3 * 32);
+
+    let back = convert(&harness, &bullet, "bullet", "text");
+
+    // Positions lose their castling rights and side to move.
+    assert_eq!(
+        harness.read(&back).unwrap(),
+        format!(
+            "{} 0.5 12\n{} 1 -40\n{} 1 250\n",
+            STARTPOS.replace("KQkq", "-"),
+            KIWIPETE.replace("KQkq", "-"),
+            "r3k2r/8/8/4P3/8/8/8/R3K2R w - - 0 1"
+        )
+    );
+}
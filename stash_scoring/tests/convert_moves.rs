@@ -0,0 +1,48 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn converts_move_sequences() {
+    let harness = Harness::new();
+    let input = harness.write(
+        "moves.txt",
+        &format!(
+            "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. O-O\n\ne2e4 e7e5 e1e3 e8e7\n{} moves e5f7 e8g8\n",
+            KIWIPETE
+        ),
+    );
+    let output_file = harness.path_str("converted.txt");
+
+    for (notation, expected) in [
+        (
+            "uci",
+            format!(
+                "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 e1g1\n{} moves e5f7 e8g8\n",
+                KIWIPETE
+            ),
+        ),
+        (
+            "san",
+            format!("e4 e5 Nf3 Nc6 Bb5 a6 O-O\n{} moves Nxf7 O-O\n", KIWIPETE),
+        ),
+    ] {
+        let output = harness.run(
+            &[
+                "convert-moves",
+                "-i",
+                &input,
+                "-o",
+                &output_file,
+                "--to",
+                notation,
+            ],
+            None,
+        );
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert!(output.status.success());
+        assert!(stderr.contains("Skipping line 3: illegal move 'e1e3'"));
+        assert_eq!(harness.read("converted.txt").unwrap(), expected);
+    }
+}
@@ -0,0 +1,224 @@
+mod common;
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::time::{Duration, Instant};
+
+use stash_scoring::engine::{
+    parse_go_template, EngineProfile, OptionType, SearchClocks, SearchLimit, UciEngine, UciOption,
+};
+
+use common::*;
+
+#[test]
+fn expands_go_templates() {
+    let limit = SearchLimit {
+        nodes: Some(5000),
+        go_template: Some(parse_go_template("go nodes {nodes} wtime {wtime} extra").unwrap()),
+        ..Default::default()
+    };
+    let clocks = SearchClocks {
+        time: [Duration::from_secs(3), Duration::from_secs(2)],
+        increment: [Duration::ZERO; 2],
+    };
+
+    assert_eq!(
+        limit.timed_go_command(Some(&clocks)),
+        "go nodes 5000 wtime 3000 extra\n"
+    );
+    assert_eq!(
+        limit.ponder_command(Some(&clocks)),
+        "go ponder nodes 5000 wtime 3000 extra\n"
+    );
+    assert!(limit.check_template(true).is_ok());
+    assert!(limit.check_template(false).is_err());
+
+    let limit = SearchLimit {
+        go_template: Some(String::from("go depth {depth}")),
+        ..limit
+    };
+
+    assert!(limit.check_template(false).is_err());
+    assert!(parse_go_template("go movetime 100").is_ok());
+    assert!(parse_go_template("go {movetime}").is_err());
+    assert!(parse_go_template("go nodes {nodes").is_err());
+    assert!(parse_go_template("{nodes} go").is_err());
+}
+
+#[test]
+fn restricts_searches_to_root_moves() {
+    let limit = SearchLimit {
+        depth: Some(3),
+        searchmoves: vec![String::from("e2e4"), String::from("d2d4")],
+        ..Default::default()
+    };
+
+    assert_eq!(limit.go_command(), "go depth 3 searchmoves e2e4 d2d4\n");
+
+    let limit = SearchLimit {
+        go_template: Some(String::from("go depth {depth} moves {moves}")),
+        ..limit
+    };
+
+    assert_eq!(limit.go_command(), "go depth 3 moves e2e4 d2d4\n");
+    assert!(limit.check_template(false).is_ok());
+
+    let limit = SearchLimit {
+        go_template: Some(String::from("go depth {depth} searchmoves {moves}")),
+        ..limit
+    };
+
+    assert_eq!(limit.go_command(), "go depth 3 searchmoves e2e4 d2d4\n");
+
+    // The keyword is dropped along with an empty move list.
+    let limit = SearchLimit {
+        searchmoves: Vec::new(),
+        ..limit
+    };
+
+    assert_eq!(limit.go_command(), "go depth 3\n");
+    assert_eq!(limit.ponder_command(None), "go ponder depth 3\n");
+}
+
+#[test]
+fn parses_option_declarations() {
+    let parse = |line| UciOption::parse(line).unwrap();
+
+    assert_eq!(
+        parse("option name Hash type spin default 16 min 1 max 33554432"),
+        UciOption {
+            name: String::from("Hash"),
+            kind: OptionType::Spin {
+                min: 1,
+                max: 33554432
+            },
+            default: Some(String::from("16")),
+        }
+    );
+    assert_eq!(
+        parse("option name Clear Hash type button"),
+        UciOption {
+            name: String::from("Clear Hash"),
+            kind: OptionType::Button,
+            default: None,
+        }
+    );
+    assert_eq!(
+        parse("option name Analysis Contempt type combo default Both var Off var White var Both"),
+        UciOption {
+            name: String::from("Analysis Contempt"),
+            kind: OptionType::Combo {
+                choices: vec![
+                    String::from("Off"),
+                    String::from("White"),
+                    String::from("Both")
+                ]
+            },
+            default: Some(String::from("Both")),
+        }
+    );
+    assert_eq!(
+        parse("option name SyzygyPath type string default <empty>").kind,
+        OptionType::String
+    );
+    assert_eq!(
+        parse("option name Ponder type check default false").default,
+        Some(String::from("false"))
+    );
+
+    assert_eq!(
+        UciOption::parse("option name Hash type spin default 16"),
+        None
+    );
+    assert_eq!(UciOption::parse("option name type check"), None);
+    assert_eq!(UciOption::parse("option name Foo type unknown"), None);
+    assert_eq!(UciOption::parse("id name Foo type check"), None);
+}
+
+#[test]
+fn coerces_option_values() {
+    let check = UciOption::parse("option name Ponder type check default false").unwrap();
+    let spin = UciOption::parse("option name Hash type spin default 16 min 1 max 1024").unwrap();
+    let combo =
+        UciOption::parse("option name Style type combo default Normal var Solid var Normal")
+            .unwrap();
+    let button = UciOption::parse("option name Clear Hash type button").unwrap();
+
+    assert_eq!(check.coerce("1"), Ok(String::from("true")));
+    assert_eq!(check.coerce("Off"), Ok(String::from("false")));
+    assert!(check.coerce("maybe").is_err());
+    assert_eq!(spin.coerce("1024"), Ok(String::from("1024")));
+    assert!(spin.coerce("2048").is_err());
+    assert!(spin.coerce("16MB").is_err());
+    assert_eq!(combo.coerce("solid"), Ok(String::from("Solid")));
+    assert!(combo.coerce("Wild").is_err());
+    assert_eq!(button.coerce(""), Ok(String::new()));
+    assert!(button.coerce("true").is_err());
+}
+
+#[test]
+fn collects_engine_options() {
+    let mut engine = UciEngine::try_new(MOCK_ENGINE, EngineProfile::Generic).unwrap();
+
+    engine
+        .init_protocol(&[String::from("hash=64"), String::from("ponder=on")])
+        .unwrap();
+    assert!(engine
+        .options()
+        .iter()
+        .any(|option| option.name == "MoveIndex"));
+
+    let mut engine = UciEngine::try_new(MOCK_ENGINE, EngineProfile::Generic).unwrap();
+
+    assert!(engine.init_protocol(&[String::from("Hash=4096")]).is_err());
+}
+
+/// Starts the mock engine with a script, through a wrapper setting its
+/// environment.
+fn scripted_engine(harness: &Harness, script: &str) -> UciEngine {
+    let script = harness.write("script.txt", script);
+    let wrapper = harness.write(
+        "engine.sh",
+        &format!(
+            "#!/bin/sh\nMOCK_ENGINE_SCRIPT={} exec {}\n",
+            script, MOCK_ENGINE
+        ),
+    );
+
+    fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).unwrap();
+    UciEngine::try_new(&wrapper, EngineProfile::Generic).unwrap()
+}
+
+#[test]
+fn times_out_reads() {
+    let harness = Harness::new();
+    let mut engine = scripted_engine(
+        &harness,
+        "[go]\n!sleep 500\ninfo depth 1 score cp 5 pv e2e4\nbestmove e2e4\n[go]\n!exit 0\n",
+    );
+
+    engine.write(b"go depth 1\n").unwrap();
+
+    let start = Instant::now();
+    let err = engine
+        .read_line_timeout(Duration::from_millis(100))
+        .unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(start.elapsed() < Duration::from_millis(400));
+
+    // The line is received once the engine answers.
+    let line = engine.read_line_timeout(Duration::from_secs(5)).unwrap();
+
+    assert_eq!(line, "info depth 1 score cp 5 pv e2e4\n");
+    assert_eq!(engine.read_line().unwrap(), "bestmove e2e4\n");
+
+    engine.write(b"go depth 1\n").unwrap();
+
+    let err = engine
+        .read_line_timeout(Duration::from_secs(5))
+        .unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,123 @@
+use stash_scoring::board::Position;
+use stash_scoring::engine::{RootMove, Score};
+use stash_scoring::game::{Game, GameResult, MoveSampling, Termination};
+use stash_scoring::rng::Rng;
+
+#[test]
+fn samples_root_moves() {
+    let sampling = MoveSampling {
+        temperatures: vec![100.0, 0.0],
+        multipv: 3,
+        seed: 0,
+    };
+    let root_moves = [
+        (String::from("e2e4"), Score::Cp(50)),
+        (String::from("d2d4"), Score::Cp(-50)),
+        (String::from("f2f3"), Score::Mate(-1)),
+    ]
+    .map(|(mv, score)| RootMove { mv, score });
+    let mut rng = Rng::new(0);
+    let mut counts = [0; 3];
+
+    for _ in 0..10000 {
+        let picked = sampling.pick(&root_moves, 0, &mut rng).unwrap();
+
+        counts[root_moves.iter().position(|rm| rm == picked).unwrap()] += 1;
+    }
+
+    // The second move is e times less likely, and mates are never played.
+    let ratio = f64::from(counts[0]) / f64::from(counts[1]);
+
+    assert!((ratio - std::f64::consts::E).abs() < 0.2, "{:?}", counts);
+    assert_eq!(counts[2], 0);
+
+    // Best moves are played with a zero temperature, and after the sampled
+    // plies.
+    assert_eq!(sampling.pick(&root_moves, 1, &mut rng), None);
+    assert_eq!(sampling.pick(&root_moves, 2, &mut rng), None);
+    assert_eq!(sampling.pick(&[], 0, &mut rng), None);
+}
+
+/// Plays the moves from the position, returning the outcome of the game
+/// after each of them.
+fn play(fen: &str, moves: &[&str]) -> Vec<Option<(GameResult, Termination)>> {
+    let mut game = Game::new(Position::from_fen(fen, false).unwrap());
+
+    moves
+        .iter()
+        .map(|uci| {
+            game.play(game.position().parse_uci(uci).unwrap());
+            game.outcome()
+        })
+        .collect()
+}
+
+#[test]
+fn adjudicates_draws() {
+    let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+    let outcomes = play(Position::STARTPOS, &shuffle.repeat(2));
+
+    // The starting position occurs for the third time after 8 plies.
+    assert!(outcomes[..7].iter().all(Option::is_none));
+    assert_eq!(
+        outcomes[7],
+        Some((GameResult::Draw, Termination::Repetition))
+    );
+
+    // Irreversible moves reset the repetition history.
+    let outcomes = play(
+        Position::STARTPOS,
+        &[&shuffle[..], &["e2e4", "e7e5"], &shuffle[..]].concat(),
+    );
+
+    assert!(outcomes.iter().all(Option::is_none));
+
+    let fifty = "4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80";
+
+    assert_eq!(
+        play(fifty, &["a1a2"]),
+        [Some((GameResult::Draw, Termination::FiftyMoves))]
+    );
+    assert_eq!(play(fifty, &["e2e3"]), [None]);
+    // Checkmate takes precedence over the fifty-move rule.
+    assert_eq!(
+        play("7k/8/6K1/8/8/8/8/R7 w - - 99 80", &["a1a8"]),
+        [Some((GameResult::WhiteWin, Termination::Checkmate))]
+    );
+
+    for (fen, uci, draw) in [
+        ("4k3/8/8/8/8/8/4r3/4KB2 w - - 0 1", "e1e2", true),
+        ("4k3/8/8/8/8/8/4r3/4KN2 w - - 0 1", "e1e2", true),
+        ("2b1k3/8/8/8/8/8/4r3/4KB2 w - - 0 1", "e1e2", true),
+        ("4kb2/8/8/8/8/8/4r3/4KB2 w - - 0 1", "e1e2", false),
+        ("4kn2/8/8/8/8/8/4r3/4KN2 w - - 0 1", "e1e2", false),
+    ] {
+        let expected = draw.then_some((GameResult::Draw, Termination::InsufficientMaterial));
+
+        assert_eq!(play(fen, &[uci]), [expected], "{}", fen);
+    }
+}
+
+#[test]
+fn averages_evals() {
+    let mut game = Game::new(Position::from_fen(Position::STARTPOS, false).unwrap());
+
+    for (uci, eval) in [
+        ("e2e4", Some(Score::Cp(40))),
+        ("e7e5", None),
+        ("g1f3", Some(Score::Mate(12))),
+        ("b8c6", Some(Score::Cp(-10))),
+    ] {
+        game.play_annotated(game.position().parse_uci(uci).unwrap(), eval, None);
+    }
+
+    let record = game.finish(GameResult::Draw, Termination::Repetition);
+
+    assert_eq!(record.average_eval(), Some(15.0));
+    assert_eq!(
+        Game::new(record.start.clone())
+            .finish(GameResult::Draw, Termination::Repetition)
+            .average_eval(),
+        None
+    );
+}
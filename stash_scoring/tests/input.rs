@@ -0,0 +1,174 @@
+use stash_scoring::input::{tokenize, InputFormat, InputSchema};
+use stash_scoring::rng::Rng;
+
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+const SEPARATORS: [&str; 5] = [" ", "\t", "  ", " \t ", "\u{a0}"];
+
+/// Joins the tokens with random runs of whitespace, also adding some at the
+/// ends of the line, as found in hand-edited or converted datasets.
+fn messy_line(rng: &mut Rng, tokens: &[&str]) -> String {
+    let mut line = String::from(["", " ", "\t", "\u{feff}"][rng.below(4) as usize]);
+
+    for (idx, token) in tokens.iter().enumerate() {
+        if idx > 0 {
+            line.push_str(SEPARATORS[rng.below(SEPARATORS.len() as u64) as usize]);
+        }
+
+        line.push_str(token);
+    }
+
+    line.push_str(["", " ", "\t", "\r"][rng.below(4) as usize]);
+    line.push('\n');
+    line
+}
+
+#[test]
+fn tokenizes_messy_whitespace() {
+    assert_eq!(
+        tokenize("\u{feff}a \t b\r\n").collect::<Vec<_>>(),
+        ["a", "b"]
+    );
+    assert_eq!(tokenize(" \t\r\n").count(), 0);
+
+    let mut rng = Rng::new(1);
+    let words = ["8/8/8", "w", "-", "0.5", "e2e4", "42", "#-3", "extra"];
+
+    for _ in 0..1000 {
+        let tokens: Vec<&str> = (0..rng.below(10))
+            .map(|_| words[rng.below(words.len() as u64) as usize])
+            .collect();
+        let line = messy_line(&mut rng, &tokens);
+
+        assert_eq!(tokenize(&line).collect::<Vec<_>>(), tokens, "{:?}", line);
+    }
+}
+
+#[test]
+fn splits_messy_lines() {
+    let schemas: [(&str, &[&str]); 3] = [
+        ("fen,wdl", &["0.5"]),
+        ("fen,move,wdl,extra", &["e1g1", "1.0", "ply:12"]),
+        ("fen,wdl,extra*", &["0", "a", "b"]),
+    ];
+    let mut rng = Rng::new(2);
+
+    for (schema, fields) in schemas {
+        let schema: InputSchema = schema.parse().unwrap();
+
+        for _ in 0..200 {
+            let mut tokens: Vec<&str> = KIWIPETE.split(' ').collect();
+
+            // Hand-written FENs often lack their move counters.
+            if rng.below(2) == 0 {
+                tokens.truncate(4);
+            }
+
+            let fen = tokens.join(" ");
+
+            tokens.extend_from_slice(fields);
+
+            let line = messy_line(&mut rng, &tokens);
+            let record = schema.split(&line).unwrap();
+            let mut parsed: Vec<&str> = record.wdl.into_iter().collect();
+
+            parsed.extend(record.mv);
+            parsed.extend(record.extras);
+            parsed.sort();
+
+            let mut expected = fields.to_vec();
+
+            expected.sort();
+            assert_eq!(record.fen, fen, "{:?}", line);
+            assert_eq!(parsed, expected, "{:?}", line);
+        }
+    }
+}
+
+#[test]
+fn sniffs_input_formats() {
+    let short = KIWIPETE.replace(" 0 1", "");
+    let cases = [
+        (format!("{}\n{}\n", KIWIPETE, short), InputFormat::Fen),
+        (
+            format!("{} 0.5\n{} 1\n", KIWIPETE, short),
+            InputFormat::FenWdl,
+        ),
+        (
+            format!("{} 0 -35\n{} 1 #4\n", KIWIPETE, KIWIPETE),
+            InputFormat::FenWdlEval,
+        ),
+        (
+            format!("{} bm e2a6; c9 \"1-0\";\n", short),
+            InputFormat::Epd,
+        ),
+        // A few invalid lines do not change the detected format.
+        (
+            format!("junk\n{} 0.5\n{} 0\n", KIWIPETE, KIWIPETE),
+            InputFormat::FenWdl,
+        ),
+        // Short FENs followed by '0 1' are read as complete FENs.
+        (format!("{} 0 1\n", short), InputFormat::Fen),
+    ];
+
+    for (lines, format) in cases {
+        assert_eq!(
+            InputFormat::sniff(lines.lines(), false),
+            Ok(format),
+            "{}",
+            lines
+        );
+    }
+
+    assert!(InputFormat::sniff("junk\n\n".lines(), false).is_err());
+}
+
+#[test]
+fn splits_epd_records() {
+    let schema = InputFormat::Epd.schema();
+    let short = KIWIPETE.replace(" 0 1", "");
+    let line = format!("{} hmvc 3; fmvn 12; id \"pos 1\"; c9 \"1/2-1/2\";", short);
+    let record = schema.split(&line).unwrap();
+
+    assert_eq!(record.fen, format!("{} 3 12", short));
+    assert_eq!(record.wdl, Some("0.5"));
+
+    let line = format!("{} bm e2a6;", short);
+    let record = schema.split(&line).unwrap();
+
+    assert_eq!(record.fen, KIWIPETE);
+    assert_eq!(record.wdl, None);
+}
+
+#[test]
+fn delimits_fens_from_the_schema() {
+    let short = KIWIPETE.replace(" 0 1", "");
+    let schema: InputSchema = "fen,wdl,extra*".parse().unwrap();
+
+    // Integer WDL and extra columns after a FEN without move counters are not
+    // taken for them, unless the WDL column would be left without a value.
+    for (line, fen, wdl, extras) in [
+        (format!("{} 1 42", short), short.as_str(), "1", vec!["42"]),
+        (
+            format!("{} 0 7 x", short),
+            short.as_str(),
+            "0",
+            vec!["7", "x"],
+        ),
+        (format!("{} 1 42", KIWIPETE), KIWIPETE, "1", vec!["42"]),
+        (format!("{} 0.5", KIWIPETE), KIWIPETE, "0.5", vec![]),
+    ] {
+        let record = schema.split(&line).unwrap();
+
+        assert_eq!(record.fen, fen, "{}", line);
+        assert_eq!((record.wdl, record.extras), (Some(wdl), extras), "{}", line);
+    }
+
+    // The same goes for scores.
+    let schema: InputSchema = "fen,eval,extra*".parse().unwrap();
+    let line = format!("{} 12 3", short);
+    let record = schema.split(&line).unwrap();
+
+    assert_eq!(record.fen, short);
+    assert_eq!((record.eval, record.extras), (Some("12"), vec!["3"]));
+}
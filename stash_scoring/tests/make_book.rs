@@ -0,0 +1,97 @@
+mod common;
+
+use stash_scoring::board::Position;
+use stash_scoring::polyglot::{polyglot_key, polyglot_move};
+
+use common::*;
+
+const GAMES: &str = r#"[Result "1-0"]
+
+1. e4 e5 2. Nf3 1-0
+
+[Result "1/2-1/2"]
+
+1. e4 e5 1/2-1/2
+
+[Result "1-0"]
+
+1. e4 c5 1-0
+
+[Result "1/2-1/2"]
+
+1. d4 {[%eval 0.0]} d5 1/2-1/2
+
+[Result "0-1"]
+
+1. d4 {[%eval 0.0]} d5 0-1
+
+[Result "*"]
+
+1. d4 Nf6 *
+"#;
+
+/// Builds a book from the games, returning its entries as (key, move,
+/// weight) triples.
+fn make_book(harness: &Harness, extra_args: &[&str]) -> Vec<(u64, u16, u16)> {
+    let input = harness.write("games.pgn", GAMES);
+    let output = harness.path_str("book.bin");
+    let mut args = vec!["make-book", "-i", &input, "-o", &output];
+
+    args.extend_from_slice(extra_args);
+    assert!(harness.run(&args, None).status.success());
+
+    std::fs::read(harness.path("book.bin"))
+        .unwrap()
+        .chunks_exact(16)
+        .map(|entry| {
+            (
+                u64::from_be_bytes(entry[..8].try_into().unwrap()),
+                u16::from_be_bytes([entry[8], entry[9]]),
+                u16::from_be_bytes([entry[10], entry[11]]),
+            )
+        })
+        .collect()
+}
+
+/// Returns the book entry of a move played after the given ones.
+fn entry(moves: &[&str], uci: &str) -> (u64, u16) {
+    let mut pos = Position::from_fen(STARTPOS, false).unwrap();
+
+    for uci in moves {
+        let mv = pos.parse_uci(uci).unwrap();
+
+        pos.play(mv);
+    }
+
+    (
+        polyglot_key(&pos),
+        polyglot_move(pos.parse_uci(uci).unwrap()),
+    )
+}
+
+#[test]
+fn weights_moves_by_results() {
+    let harness = Harness::new();
+    let book = make_book(&harness, &["--eval-weight", "0"]);
+    let weights: Vec<_> = book
+        .iter()
+        .map(|&(key, mv, weight)| ((key, mv), weight))
+        .collect();
+
+    // Moves played once (2. Nf3, 1... c5) and unfinished games are left out.
+    // 1. e4 scored 2.5 in 3 games, and 1. d4 0.5 in 2 games.
+    assert_eq!(weights.len(), 4);
+    assert!(weights.contains(&(entry(&[], "e2e4"), 65535)));
+    assert!(weights.contains(&(entry(&[], "d2d4"), 13107)));
+    assert!(weights.contains(&(entry(&["e2e4"], "e7e5"), 65535)));
+    assert!(weights.contains(&(entry(&["d2d4"], "d7d5"), 65535)));
+
+    // Entries are sorted by key.
+    assert!(book.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+
+    // Both games with 1. d4 count as draws from their evaluations.
+    let book = make_book(&harness, &["--eval-weight", "1", "--max-plies", "1"]);
+
+    assert_eq!(book.len(), 2);
+    assert!(book.contains(&(entry(&[], "d2d4").0, entry(&[], "d2d4").1, 26214)));
+}
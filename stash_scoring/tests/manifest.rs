@@ -0,0 +1,49 @@
+mod common;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use stash_scoring::manifest::{find_executable, sha256_file, utc_timestamp};
+
+use common::Harness;
+
+#[test]
+fn hashes_files() {
+    let harness = Harness::new();
+
+    harness.write("abc.txt", "abc");
+    harness.write("empty.txt", "");
+
+    assert_eq!(
+        sha256_file(&harness.path("abc.txt")).unwrap(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        sha256_file(&harness.path("empty.txt")).unwrap(),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert!(sha256_file(&harness.path("missing.txt")).is_err());
+}
+
+#[test]
+fn formats_utc_timestamps() {
+    let cases = [
+        (0, "1970-01-01T00:00:00Z"),
+        (951782400, "2000-02-29T00:00:00Z"),
+        (1714566600, "2024-05-01T12:30:00Z"),
+        (4107542399, "2100-02-28T23:59:59Z"),
+    ];
+
+    for (secs, expected) in cases {
+        assert_eq!(
+            utc_timestamp(UNIX_EPOCH + Duration::from_secs(secs)),
+            expected
+        );
+    }
+}
+
+#[test]
+fn finds_executables() {
+    assert!(find_executable("sh").is_some_and(|path| path.is_absolute()));
+    assert!(find_executable("./missing-engine").is_none());
+    assert!(find_executable("missing-engine-name").is_none());
+}
@@ -0,0 +1,670 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::*;
+
+fn engine(name: &str, move_index: usize) -> String {
+    format!(
+        "cmd={},name={},option.MoveIndex={}",
+        MOCK_ENGINE, name, move_index
+    )
+}
+
+#[test]
+fn plays_a_round_robin() {
+    let harness = Harness::new();
+    let pgn = harness.path_str("games.pgn");
+    let engines = [engine("A", 0), engine("B", 3), engine("C", 5)];
+    let mut args = vec!["match", "-n", "1", "--rounds", "2", "--pgn-out", &pgn];
+
+    for engine in &engines {
+        args.extend(["--engine", engine]);
+    }
+
+    let output = harness.run(&args, Some("[go]\n!legal\n"));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Game 12/12: C vs B"));
+    assert!(stdout.contains("A vs B: Elo"));
+    assert!(stdout.contains("B vs C: Elo"));
+
+    // The PGN output can be read back by the extractor.
+    let positions = harness.path_str("positions.txt");
+    let output = harness.run(&["pgn-extract", "-i", &pgn, "-o", &positions], None);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(stdout.starts_with("12 games read, 0 invalid"), "{}", stdout);
+}
+
+#[test]
+fn plays_a_gauntlet_from_openings() {
+    let harness = Harness::new();
+    let openings = harness.write(
+        "openings.epd",
+        &format!(
+            "{}\n{} id \"kiwipete\";\n",
+            STARTPOS,
+            &KIWIPETE[..KIWIPETE.len() - 4]
+        ),
+    );
+    let engines = [engine("A", 1), engine("B", 2), engine("C", 3)];
+    let mut args = vec![
+        "match",
+        "-n",
+        "1",
+        "--schedule",
+        "gauntlet",
+        "--openings",
+        &openings,
+    ];
+
+    for engine in &engines {
+        args.extend(["--engine", engine]);
+    }
+
+    let output = harness.run(&args, Some("[go]\n!legal\n"));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Game 4/4: C vs A"));
+    assert!(!stdout.contains("B vs C"));
+}
+
+#[test]
+fn writes_game_metadata() {
+    let harness = Harness::new();
+    let jsonl = harness.path_str("games.jsonl");
+    let (a, b) = (engine("A", 0), engine("B", 0));
+    let args = [
+        "match",
+        "-n",
+        "1",
+        "--engine",
+        &a,
+        "--engine",
+        &b,
+        "--games-jsonl",
+        &jsonl,
+    ];
+
+    // Black answers with an illegal move.
+    let output = harness.run(
+        &args,
+        Some("[go]\ninfo depth 1 score cp 30 pv a2a3\nbestmove a2a3\n"),
+    );
+    let games: Vec<serde_json::Value> = harness
+        .read("games.jsonl")
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert!(output.status.success());
+    assert_eq!(games.len(), 2);
+    assert_eq!(games[0]["game"], 1);
+    assert_eq!(games[0]["white"], "A");
+    assert_eq!(games[0]["opening"], STARTPOS);
+    assert_eq!(games[0]["plies"], 1);
+    assert_eq!(games[0]["result"], "1-0");
+    assert_eq!(games[0]["termination"], "illegal move");
+    assert_eq!(games[0]["average_eval"], 30.0);
+}
+
+#[test]
+fn restarts_crashed_engines() {
+    let harness = Harness::new();
+    let (a, b) = (engine("A", 0), engine("B", 0));
+    let args = ["match", "-n", "1", "--engine", &a, "--engine", &b];
+
+    // Engines crash when asked for their second move.
+    let output = harness.run(&args, Some("[go]\n!legal\n[go]\n!exit 1\n"));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Game 1/2: A vs B: 0-1 (engine failure)"));
+    assert!(stdout.contains("Game 2/2: B vs A: 0-1 (engine failure)"));
+    assert!(stdout.contains(
+        "A vs B: Elo +0.0 +/- 0.0, LOS n/a, DrawRatio 100.0%, Ptnml(0-2) [0, 0, 1, 0, 0]\n"
+    ));
+}
+
+#[test]
+fn forfeits_games_on_time() {
+    let harness = Harness::new();
+    let (a, b) = (engine("A", 0), engine("B", 0));
+    let pgn = harness.path_str("games.pgn");
+    let args = [
+        "match",
+        "--tc",
+        "0.2+0",
+        "--engine",
+        &a,
+        "--engine",
+        &b,
+        "--pgn-out",
+        &pgn,
+    ];
+
+    // Engines use more than their whole time for their second move.
+    let output = harness.run(&args, Some("[go]\n!legal\n[go]\n!sleep 500\n!legal\n"));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Game 1/2: A vs B: 0-1 (time forfeit)"));
+    assert!(stdout.contains("Game 2/2: B vs A: 0-1 (time forfeit)"));
+
+    let pgn = std::fs::read_to_string(pgn).unwrap();
+
+    assert!(pgn.contains("[TimeControl \"0.2+0\"]"));
+}
+
+#[test]
+fn forfeits_games_of_stalled_engines() {
+    let harness = Harness::new();
+    let (a, b) = (engine("A", 0), engine("B", 0));
+    let args = ["match", "--tc", "0.2+0", "--engine", &a, "--engine", &b];
+    let start = Instant::now();
+
+    // Engines never answer when asked for their second move, and must be
+    // killed once their time is up.
+    let output = harness.run(&args, Some("[go]\n!legal\n[go]\n!stall\n"));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert!(stdout.contains("Game 1/2: A vs B: 0-1 (time forfeit)"));
+    assert!(stdout.contains("Game 2/2: B vs A: 0-1 (time forfeit)"));
+}
+
+#[test]
+fn reads_cutechess_engine_configurations() {
+    let harness = Harness::new();
+    let dir = harness.path_str("");
+    let engines = serde_json::json!([
+        {
+            "name": "A",
+            "command": MOCK_ENGINE,
+            "workingDirectory": dir,
+            "protocol": "uci",
+            "options": [{"name": "MoveIndex", "value": 3, "type": "spin"}],
+        },
+        {
+            "name": "B",
+            "command": MOCK_ENGINE,
+            "protocol": "uci",
+            "initStrings": [],
+        },
+        {
+            "name": "C",
+            "command": MOCK_ENGINE,
+            "protocol": "xboard",
+        },
+    ]);
+    let json = harness.write("engines.json", &engines.to_string());
+    let args = [
+        "match",
+        "-n",
+        "1",
+        "--engines-json",
+        &json,
+        "--engine",
+        "conf=A",
+        "--engine",
+        "conf=B,name=B2,option.MoveIndex=5",
+    ];
+    let output = harness.run(&args, Some("[go]\n!legal\n"));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Game 2/2: B2 vs A"));
+
+    for (engine, error) in [
+        ("conf=D", "unknown engine configuration 'D'"),
+        ("conf=C", "unsupported 'xboard' protocol"),
+    ] {
+        let args = [
+            "match",
+            "-n",
+            "1",
+            "--engines-json",
+            &json,
+            "--engine",
+            "conf=A",
+            "--engine",
+            engine,
+        ];
+        let output = harness.run(&args, None);
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8(output.stderr).unwrap().contains(error));
+    }
+}
+
+#[test]
+fn uses_registered_engines() {
+    let harness = Harness::new();
+    let registry = harness.write(
+        "engines.toml",
+        &format!(
+            "[mock]\ncmd = \"{}\"\n\n[mock.options]\nMoveIndex = 3\n",
+            MOCK_ENGINE
+        ),
+    );
+    let args = [
+        "match",
+        "-n",
+        "1",
+        "--engine-registry",
+        &registry,
+        "--engine",
+        "mock",
+        "--engine",
+        "mock,name=mock2,option.MoveIndex=5",
+    ];
+    let output = harness.run(&args, Some("[go]\n!legal\n"));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Game 2/2: mock2 vs mock"));
+
+    let log = harness.read("engine.log").unwrap();
+
+    assert!(log.contains("setoption name MoveIndex value 3\n"));
+    assert!(log.contains("setoption name MoveIndex value 5\n"));
+}
+
+#[test]
+fn plays_node_odds_games() {
+    let harness = Harness::new();
+    let (a, b) = (engine("A", 0), engine("B", 0));
+    let mut args = vec!["match", "--engine", &a, "--engine", &b, "--engine-nodes"];
+
+    let output = harness.run(&[args.as_slice(), &["40000"]].concat(), None);
+
+    assert!(!output.status.success());
+
+    args.push("40000,30000");
+
+    let output = harness.run(&args, Some("[go]\n!legal\n"));
+    let log = harness.read("engine.log").unwrap();
+
+    assert!(output.status.success());
+    assert!(log.contains("go nodes 40000\n"));
+    assert!(log.contains("go nodes 30000\n"));
+    assert!(!log.contains("wtime"));
+}
+
+#[test]
+fn plays_random_openings() {
+    let harness = Harness::new();
+    let pgn = harness.path_str("games.pgn");
+    let (a, b) = (engine("A", 0), engine("B", 0));
+    let args = [
+        "match",
+        "-d",
+        "1",
+        "--rounds",
+        "3",
+        "--random-plies",
+        "4",
+        "--engine",
+        &a,
+        "--engine",
+        &b,
+        "--pgn-out",
+        &pgn,
+    ];
+
+    let output = harness.run(&args, Some("[go]\n!legal\n"));
+    let games = harness.read("games.pgn").unwrap();
+    let fens: Vec<_> = games
+        .lines()
+        .filter_map(|line| line.strip_prefix("[FEN \""))
+        .collect();
+
+    assert!(output.status.success());
+    assert_eq!(fens.len(), 6);
+    assert!(fens
+        .iter()
+        .all(|fen| fen.contains(" w ") && fen.ends_with(" 3\"]")));
+    // Both games of a pair start from the same opening.
+    assert_eq!(fens[0], fens[1]);
+    assert!(fens[0] != fens[2] || fens[0] != fens[4]);
+
+    // Openings are rejected when the engine finds them unbalanced.
+    let args = [&args[..], &["--random-eval-bound", "100"]].concat();
+    let output = harness.run(
+        &args,
+        Some("[go]\ninfo depth 1 score cp 250\nbestmove a2a3\n"),
+    );
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("no suitable random opening found"));
+}
+
+#[test]
+fn plays_dfrc_games() {
+    let harness = Harness::new();
+    let pgn = harness.path_str("games.pgn");
+    let (a, b) = (engine("A", 0), engine("B", 0));
+    let args = [
+        "match",
+        "-d",
+        "1",
+        "--rounds",
+        "2",
+        "--dfrc",
+        "--chess960",
+        "--engine",
+        &a,
+        "--engine",
+        &b,
+        "--pgn-out",
+        &pgn,
+    ];
+
+    let output = harness.run(&args, Some("[go]\n!legal\n"));
+    let games = harness.read("games.pgn").unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(games.matches("[Variant \"Chess960\"]").count(), 4);
+
+    // Positions can be extracted with Shredder-FEN castling rights.
+    let positions = harness.path_str("positions.txt");
+    let output = harness.run(
+        &[
+            "pgn-extract",
+            "-i",
+            &pgn,
+            "-o",
+            &positions,
+            "--shredder-fen",
+        ],
+        None,
+    );
+    let first = harness.read("positions.txt").unwrap();
+    let castling = first.split(' ').nth(2).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(castling.len(), 4);
+    assert!(castling[..2].chars().all(|c| ('A'..='H').contains(&c)));
+    assert!(castling[2..].chars().all(|c| ('a'..='h').contains(&c)));
+}
+
+#[test]
+fn samples_opening_moves() {
+    let harness = Harness::new();
+    let pgn = harness.path_str("games.pgn");
+    let (a, b) = (engine("A", 0), engine("B", 0));
+    let args = [
+        "match",
+        "-d",
+        "1",
+        "--temperature",
+        "100,50",
+        "--engine",
+        &a,
+        "--engine",
+        &b,
+        "--pgn-out",
+        &pgn,
+    ];
+
+    let output = harness.run(&args, Some("[go]\n!legal\n"));
+    let log = harness.read("engine.log").unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(log.matches("setoption name MultiPV value 4\n").count(), 4);
+    assert_eq!(log.matches("setoption name MultiPV value 1\n").count(), 4);
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&harness.read("games.pgn.manifest.json").unwrap()).unwrap();
+
+    assert_eq!(
+        manifest["settings"]["temperatures"],
+        serde_json::json!([100.0, 50.0])
+    );
+    assert_eq!(manifest["settings"]["sample_multipv"], 4);
+}
+
+#[test]
+fn ponders_without_changing_games() {
+    let harness = Harness::new();
+
+    // With the same move index, engines always expect the opponent's reply.
+    for indexes in [(1, 1), (1, 2)] {
+        let (a, b) = (engine("A", indexes.0), engine("B", indexes.1));
+        let mut pgns = Vec::new();
+
+        for ponder in [false, true] {
+            let pgn = harness.path_str("games.pgn");
+            let mut args = vec![
+                "match",
+                "--tc",
+                "10+0.1",
+                "--engine",
+                &a,
+                "--engine",
+                &b,
+                "--pgn-out",
+                &pgn,
+            ];
+
+            if ponder {
+                args.push("--ponder");
+            }
+
+            let output = harness.run(&args, Some("[go]\n!legal\n"));
+
+            assert!(output.status.success());
+            pgns.push(harness.read("games.pgn").unwrap());
+        }
+
+        assert_eq!(pgns[0], pgns[1]);
+        assert!(pgns[0].contains("[Termination \"threefold repetition\"]"));
+    }
+}
+
+#[test]
+fn budgets_cores_for_concurrent_games() {
+    let harness = Harness::new();
+    let (a, b) = (engine("A", 0), engine("B", 1));
+    let many_threads = format!("{},option.Threads=100000", engine("C", 2));
+    let args = [
+        "match",
+        "-n",
+        "1",
+        "--engine",
+        &a,
+        "--engine",
+        &many_threads,
+    ];
+    let output = harness.run(&args, Some("[go]\n!legal\n"));
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("1 concurrent games with 100000 search threads per engine"));
+
+    let cores = std::thread::available_parallelism().map_or(1, usize::from);
+
+    if cores < 2 {
+        return;
+    }
+
+    let args = [
+        "match",
+        "-n",
+        "1",
+        "--rounds",
+        "3",
+        "--concurrency",
+        "2",
+        "--engine",
+        &a,
+        "--engine",
+        &b,
+    ];
+    let output = harness.run(&args, Some("[go]\n!legal\n"));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+
+    for game in 1..=6 {
+        assert!(stdout.contains(&format!("Game {}/6:", game)));
+    }
+
+    assert!(stdout.contains("Ptnml(0-2)"));
+}
+
+#[test]
+fn stops_concluded_sprts() {
+    let harness = Harness::new();
+    let (a, b) = (engine("A", 0), engine("B", 0));
+    let args = [
+        "match",
+        "-n",
+        "1",
+        "--rounds",
+        "10",
+        "--sprt",
+        "elo0=0,elo1=50",
+        "--engine",
+        &a,
+        "--engine",
+        &b,
+    ];
+
+    // Each engine loses the game it plays White, so all game pairs are even.
+    // The worker may already have started the next pair when the test ends.
+    let output = harness.run(&args, Some("[go]\n!legal\n[go]\n!exit 1\n"));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("H0 accepted"));
+    assert!(!stdout.contains("Game 7/20"));
+    assert!(stdout.contains("Crashes: "));
+
+    let c = engine("C", 0);
+    let output = harness.run(
+        &[
+            "match",
+            "-n",
+            "1",
+            "--sprt",
+            "elo0=0,elo1=5",
+            "--engine",
+            &a,
+            "--engine",
+            &b,
+            "--engine",
+            &c,
+        ],
+        None,
+    );
+
+    assert!(!output.status.success());
+}
+
+#[test]
+fn stops_at_run_targets() {
+    let harness = Harness::new();
+    let pgn = harness.path_str("games.pgn");
+    let (a, b) = (engine("A", 0), engine("B", 3));
+    let args = [
+        "match",
+        "-n",
+        "1",
+        "--rounds",
+        "10",
+        "--engine",
+        &a,
+        "--engine",
+        &b,
+        "--pgn-out",
+        &pgn,
+    ];
+    let play = |target: &[&str]| {
+        let output = harness.run(&[&args[..], target].concat(), Some("[go]\n!legal\n"));
+
+        assert!(output.status.success());
+        harness
+            .read("games.pgn")
+            .unwrap()
+            .matches("[Event ")
+            .count()
+    };
+
+    // The game pair in progress is finished.
+    assert_eq!(play(&["--target-games", "3"]), 4);
+    assert_eq!(play(&["--target-positions", "1"]), 2);
+    assert_eq!(play(&["--run-hours", "0"]), 2);
+    assert_eq!(play(&[]), 20);
+
+    let output = harness.run(&[&args[..], &["--run-hours", "-1"]].concat(), None);
+
+    assert!(!output.status.success());
+}
+
+#[test]
+fn replays_games_from_their_seeds() {
+    let harness = Harness::new();
+    let pgn = harness.path_str("games.pgn");
+    let (a, b) = (engine("A", 0), engine("B", 0));
+    let args = [
+        "match",
+        "-d",
+        "1",
+        "--rounds",
+        "3",
+        "--random-plies",
+        "6",
+        "--temperature",
+        "100,100",
+        "--seed",
+        "7",
+        "--engine",
+        &a,
+        "--engine",
+        &b,
+        "--pgn-out",
+        &pgn,
+    ];
+    let play = |extra: &[&str]| {
+        let output = harness.run(&[&args[..], extra].concat(), Some("[go]\n!legal\n"));
+
+        assert!(output.status.success());
+
+        let games = harness.read("games.pgn").unwrap();
+
+        games
+            .split("[Event ")
+            .skip(1)
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    let games = play(&[]);
+    let seeds: Vec<_> = games
+        .iter()
+        .map(|game| game.lines().find(|line| line.starts_with("[Seed ")))
+        .collect();
+
+    assert_eq!(games.len(), 6);
+    assert!(seeds.iter().all(Option::is_some));
+    assert!((1..6).all(|idx| seeds[idx] != seeds[idx - 1]));
+    // The game is found again from its number and the seed of the match.
+    assert!(games[3].contains("[Game \"4\"]\n[MatchSeed \"7\"]\n"));
+
+    // The game pair is played again from the same opening, with the same
+    // seeds.
+    assert_eq!(play(&["--replay-game", "4"]), games[2..4]);
+    assert_eq!(play(&[]), games);
+
+    let output = harness.run(&[&args[..], &["--replay-game", "7"]].concat(), None);
+
+    assert!(!output.status.success());
+}
@@ -0,0 +1,196 @@
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::TcpListener;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+
+use common::{Harness, MOCK_ENGINE, STARTPOS};
+
+/// Decodes the fields of an URL-encoded form.
+fn parse_form(body: &str) -> HashMap<String, String> {
+    body.split('&')
+        .filter_map(|field| field.split_once('='))
+        .map(|(key, value)| {
+            let value = value.replace('+', " ");
+            let mut decoded = Vec::new();
+            let mut bytes = value.bytes();
+
+            while let Some(byte) = bytes.next() {
+                match byte {
+                    b'%' => {
+                        let hex: String = bytes.by_ref().take(2).map(char::from).collect();
+
+                        decoded.push(u8::from_str_radix(&hex, 16).unwrap());
+                    }
+                    _ => decoded.push(byte),
+                }
+            }
+
+            (key.to_string(), String::from_utf8(decoded).unwrap())
+        })
+        .collect()
+}
+
+/// Serves a single workload, then sends the submitted results through the
+/// returned channel. Returns the server URL.
+fn serve_workload(workload: String) -> (String, mpsc::Receiver<HashMap<String, String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            let mut content_length = 0;
+
+            reader.read_line(&mut request_line).unwrap();
+
+            loop {
+                let mut header = String::new();
+
+                reader.read_line(&mut header).unwrap();
+
+                if header.trim().is_empty() {
+                    break;
+                }
+
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+
+            let mut body = vec![0; content_length];
+
+            reader.read_exact(&mut body).unwrap();
+
+            let response = match request_line.split_whitespace().nth(1) {
+                Some("/clientGetWorkload/") => workload.clone(),
+                Some("/clientSubmitResults/") => {
+                    sender
+                        .send(parse_form(&String::from_utf8(body).unwrap()))
+                        .unwrap();
+                    String::from("{}")
+                }
+                _ => String::from(r#"{"error": "unknown endpoint"}"#),
+            };
+
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+        }
+    });
+
+    (url, receiver)
+}
+
+#[test]
+fn plays_workloads() {
+    let harness = Harness::new();
+
+    // The "sources" of the engine only install the mock engine.
+    fs::create_dir_all(harness.path("engine/src")).unwrap();
+    harness.write(
+        "engine/src/Makefile",
+        &format!("all:\n\tcp {} $(EXE)\n", MOCK_ENGINE),
+    );
+
+    let zipped = Command::new("zip")
+        .args(["-q", "-r", "engine.zip", "engine"])
+        .current_dir(harness.path(""))
+        .status()
+        .unwrap();
+
+    assert!(zipped.success());
+
+    let source = format!("file://{}", harness.path_str("engine.zip"));
+    let book = harness.write("book.epd", &format!("{}\n", STARTPOS));
+    let workload = serde_json::json!({
+        "machine_id": 7,
+        "workload": {
+            "test": {
+                "id": 12,
+                "workload_size": 2,
+                "book": {"name": "book.epd", "source": format!("file://{}", book)},
+                "dev": {
+                    "name": "dev",
+                    "sha": "1111",
+                    "source": source,
+                    "options": "Threads=1 Hash=16",
+                    "time_control": "10+0.1",
+                },
+                "base": {
+                    "name": "base",
+                    "sha": "2222",
+                    "source": source,
+                    "options": "Threads=1 Hash=16",
+                    "time_control": "10+0.1",
+                },
+            },
+            "result": {"id": 34},
+        },
+    });
+    let (url, results) = serve_workload(workload.to_string());
+    let work_dir = harness.path_str("work");
+    let password = harness.write("password.txt", "s3cret & co=1%\n");
+    let mut args = vec![
+        "openbench",
+        "-s",
+        &url,
+        "-u",
+        "user",
+        "--work-dir",
+        &work_dir,
+    ];
+
+    args.extend_from_slice(&["--workloads", "1"]);
+
+    // The password is never taken from the command line.
+    assert!(!harness.run(&args, None).status.success());
+
+    args.extend_from_slice(&["--password-file", &password]);
+
+    let output = harness.run(&args, Some("[go]\n!legal\n"));
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let submitted = results.recv().unwrap();
+    let count = |key: &str| submitted[key].parse::<u32>().unwrap();
+
+    assert_eq!(submitted["username"], "user");
+    assert_eq!(submitted["password"], "s3cret & co=1%");
+    assert_eq!(submitted["machine_id"], "7");
+    assert_eq!(submitted["test_id"], "12");
+    assert_eq!(submitted["result_id"], "34");
+    assert_eq!(count("wins") + count("losses") + count("draws"), 4);
+    assert_eq!(count("crashes") + count("timeloss"), 0);
+
+    let ptnml: Vec<u32> = submitted["pentanomial"]
+        .split(' ')
+        .map(|count| count.parse().unwrap())
+        .collect();
+
+    assert_eq!(ptnml.len(), 5);
+    assert_eq!(ptnml.iter().sum::<u32>(), 2);
+
+    // Both engines are built once, and kept for later workloads.
+    assert!(harness.path("work/engines/1111").exists());
+    assert!(harness.path("work/engines/2222").exists());
+    assert!(!harness.path("work/build/1111").exists());
+}
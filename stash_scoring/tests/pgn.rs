@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::Score;
+use stash_scoring::game::{Game, GameResult, Termination};
+use stash_scoring::pgn::{PgnReader, PgnWriter};
+
+fn scholars_mate() -> Game {
+    let mut game = Game::new(Position::from_fen(Position::STARTPOS, false).unwrap());
+    let moves = ["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6", "h5f7"];
+    let evals = [
+        Score::Cp(35),
+        Score::Cp(-20),
+        Score::Cp(40),
+        Score::Cp(-410),
+        Score::Cp(300),
+        Score::Mate(1),
+        Score::Mate(1),
+    ];
+
+    for (i, (uci, eval)) in moves.iter().zip(evals).enumerate() {
+        let mv = game.position().parse_uci(uci).unwrap();
+        let clock = Duration::from_millis(60_000 - 1500 * i as u64);
+
+        game.play_annotated(mv, Some(eval), Some(clock));
+    }
+
+    game
+}
+
+#[test]
+fn writes_annotated_games() {
+    let record = scholars_mate().finish(GameResult::WhiteWin, Termination::Checkmate);
+    let mut pgn = Vec::new();
+
+    PgnWriter::new(&mut pgn, true)
+        .write_game(&[("White", "A"), ("Black", "B")], &record)
+        .unwrap();
+
+    let pgn = String::from_utf8(pgn).unwrap();
+
+    assert!(pgn.starts_with(
+        "[White \"A\"]\n[Black \"B\"]\n[Result \"1-0\"]\n[Termination \"checkmate\"]\n\n"
+    ));
+    assert!(pgn.contains("1. e4 {[%eval 0.35] [%clk 0:01:00]} 1... e5 {[%eval -0.20]"));
+    assert!(pgn.ends_with("Qxf7# {[%eval #1] [%clk 0:00:51]} 1-0\n\n"));
+
+    let game = PgnReader::new(pgn.as_bytes())
+        .next_game()
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        game.moves,
+        ["e4", "e5", "Bc4", "Nc6", "Qh5", "Nf6", "Qxf7#"]
+    );
+    assert_eq!(game.evals[3], Some(Score::Cp(-410)));
+    assert_eq!(game.evals[5], Some(Score::Mate(1)));
+    assert_eq!(game.result, "1-0");
+}
+
+#[test]
+fn writes_plain_games_from_positions() {
+    let start = Position::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1", false).unwrap();
+    let mut game = Game::new(start);
+
+    game.play(game.position().parse_uci("e8d7").unwrap());
+
+    let record = game.finish(GameResult::Draw, Termination::Repetition);
+    let mut pgn = Vec::new();
+
+    PgnWriter::new(&mut pgn, true)
+        .write_game(&[], &record)
+        .unwrap();
+
+    let pgn = String::from_utf8(pgn).unwrap();
+
+    assert_eq!(
+        pgn,
+        "[Result \"1/2-1/2\"]\n[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]\n\
+         [Termination \"threefold repetition\"]\n\n1... Kd7 1/2-1/2\n\n"
+    );
+}
+
+#[test]
+fn rejects_unbalanced_comments() {
+    let pgn = "[Event \"A\"]\n\n1. e4 e5 } 2. Nf3 1-0\n\n[Event \"B\"]\n\n1. d4 {ok} d5 0-1\n";
+    let mut reader = PgnReader::new(pgn.as_bytes());
+
+    assert!(reader.next_game().unwrap().unwrap().is_err());
+    assert_eq!(
+        reader.next_game().unwrap().unwrap().unwrap().moves,
+        ["d4", "d5"]
+    );
+    assert!(reader.next_game().unwrap().is_none());
+}
@@ -0,0 +1,361 @@
+mod common;
+
+use common::*;
+
+const GAMES: &str = r#"[Event "Test"]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {book exit} Nc6 3. Bb5 a6 4. Bxc6 dxc6 5. O-O f6
+6. d4 exd4 7. Nxd4 c5 8. Nb3 Qxd1 9. Rxd1 Bg4 10. f3 Be6 1-0
+
+[Event "Test"]
+[Result "*"]
+
+1. d4 d5 *
+
+[Event "Test"]
+[Result "1/2-1/2"]
+[Variant "Chess960"]
+[SetUp "1"]
+[FEN "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"]
+
+9. g3 d5 10. Nb4 Ne7 1/2-1/2
+
+[Event "Test"]
+[Result "0-1"]
+
+1. e4 e5 2. Ke3 Qh4 0-1
+"#;
+
+fn extract(harness: &Harness, extra_args: &[&str]) -> Option<Vec<String>> {
+    let input = harness.write("games.pgn", GAMES);
+    let output = harness.path_str("positions.txt");
+    let mut args = vec!["pgn-extract", "-i", &input, "-o", &output];
+
+    args.extend_from_slice(extra_args);
+
+    match harness.run(&args, None).status.success() {
+        true => harness
+            .read("positions.txt")
+            .map(|content| content.lines().map(String::from).collect()),
+        false => None,
+    }
+}
+
+#[test]
+fn extracts_quiet_positions() {
+    let harness = Harness::new();
+    let lines = extract(&harness, &[]).unwrap();
+
+    // 20 plies in the first game, minus 6 captures (Bxc6, dxc6, exd4, Nxd4,
+    // Qxd1, Rxd1), and no checks.
+    assert_eq!(lines.len(), 14 + 4);
+    assert_eq!(lines[0], format!("{} 1.0", STARTPOS));
+    assert!(lines[13].ends_with(" b kq - 0 10 1.0"));
+
+    // Chess960 games are replayed from their starting position, while the
+    // unfinished and illegal games are skipped.
+    assert_eq!(
+        lines[14],
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9 0.5"
+    );
+    assert!(lines[17].ends_with(" b KQkq - 1 10 0.5"));
+}
+
+#[test]
+fn writes_shredder_fens() {
+    let harness = Harness::new();
+    let lines = extract(&harness, &["--shredder-fen"]).unwrap();
+
+    // Only the positions of Chess960 games are affected.
+    assert_eq!(lines[0], format!("{} 1.0", STARTPOS));
+    assert_eq!(
+        lines[14],
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9 0.5"
+    );
+}
+
+#[test]
+fn caps_positions_per_game() {
+    for sampling in ["uniform", "spaced"] {
+        let harness = Harness::new();
+        let args = ["--max-positions-per-game", "3", "--sampling", sampling];
+        let lines = extract(&harness, &args).unwrap();
+        let first_game = lines.iter().filter(|line| line.ends_with(" 1.0")).count();
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(first_game, 3);
+
+        // The same seed gives the same sample.
+        assert_eq!(extract(&harness, &args), Some(lines.clone()));
+
+        let other_seed = extract(&harness, &[&args[..], &["--seed", "42"]].concat()).unwrap();
+
+        assert_eq!(other_seed.len(), 6);
+    }
+}
+
+#[test]
+fn spaces_sampled_positions() {
+    let harness = Harness::new();
+    let all = extract(&harness, &[]).unwrap();
+    let spaced = extract(
+        &harness,
+        &["--max-positions-per-game", "2", "--sampling", "spaced"],
+    )
+    .unwrap();
+    let indices: Vec<usize> = spaced[..2]
+        .iter()
+        .map(|line| all.iter().position(|l| l == line).unwrap())
+        .collect();
+
+    // The 14 positions of the first game are split into two halves.
+    assert!(indices[0] < 7 && indices[1] >= 7);
+}
+
+#[test]
+fn drops_duplicate_games() {
+    let harness = Harness::new();
+    let once = extract(&harness, &[]).unwrap();
+    let input = harness.write("games.pgn", &GAMES.repeat(3));
+    let output = harness.path_str("positions.txt");
+    let result = harness.run(&["pgn-extract", "-i", &input, "-o", &output], None);
+
+    assert!(String::from_utf8_lossy(&result.stdout).contains("6 duplicates dropped"));
+    assert_eq!(harness.read("positions.txt"), Some(once.join("\n") + "\n"));
+
+    let result = harness.run(
+        &[
+            "pgn-extract",
+            "-i",
+            &input,
+            "-o",
+            &output,
+            "--keep-duplicates",
+        ],
+        None,
+    );
+
+    assert!(result.status.success());
+    assert_eq!(
+        harness.read("positions.txt").unwrap().lines().count(),
+        3 * once.len()
+    );
+}
+
+const ANNOTATED: &str = r#"[Event "Rated Blitz game"]
+[Site "https://lichess.org/abcdefgh"]
+[Result "0-1"]
+
+1. e4 { [%eval 0.24] [%clk 0:03:00] } 1... e5 $1 { [%eval 0.2] } 2. Nf3
+{ A long comment, spanning
+two lines [%eval -0.31] } ( 2. Qh5 Nc6 3. Bc4 Nf6 { [%eval 5.1] } )
+2... Nc6?! { [%eval #-3] } 3. Bc4 ; a line comment 1-0
+3... Nf6 0-1
+"#;
+
+#[test]
+fn skips_annotations_and_variations() {
+    let harness = Harness::new();
+    let input = harness.write("games.pgn", ANNOTATED);
+    let output = harness.path_str("positions.txt");
+
+    let result = harness.run(&["pgn-extract", "-i", &input, "-o", &output], None);
+
+    assert!(result.status.success());
+    assert_eq!(harness.read("positions.txt").unwrap().lines().count(), 6);
+}
+
+#[test]
+fn extracts_pgn_evals() {
+    let harness = Harness::new();
+    let input = harness.write("games.pgn", ANNOTATED);
+    let output = harness.path_str("positions.txt");
+    let args = ["pgn-extract", "-i", &input, "-o", &output, "--pgn-evals"];
+
+    assert!(harness.run(&args, None).status.success());
+
+    let lines: Vec<String> = harness
+        .read("positions.txt")
+        .unwrap()
+        .lines()
+        .map(|line| line.split(' ').skip(6).collect::<Vec<_>>().join(" "))
+        .collect();
+
+    // Evals are converted to centipawns from the side to move's point of
+    // view, and the position after 3. Bc4 has no annotation.
+    assert_eq!(lines, ["0.0 -24", "0.0 20", "0.0 31", "0.0 #-3"]);
+
+    // Large evaluations and mate scores can be left out.
+    let args = [&args[..], &["--max-eval", "25"]].concat();
+
+    assert!(harness.run(&args, None).status.success());
+    assert_eq!(harness.read("positions.txt").unwrap().lines().count(), 2);
+}
+
+#[test]
+fn configures_quiet_positions() {
+    let harness = Harness::new();
+
+    // The quiet moves following 4... dxc6, 7. Nxd4 and 9. Rxd1 are now left
+    // out.
+    assert_eq!(
+        extract(&harness, &["--skip-after-captures"]).unwrap().len(),
+        11 + 4
+    );
+    assert_eq!(
+        extract(&harness, &["--keep-noisy-moves"]).unwrap().len(),
+        20 + 4
+    );
+}
+
+fn lichess_game(white_elo: &str, time_control: &str, termination: &str, moves: &str) -> String {
+    format!(
+        "[Event \"Rated game\"]\n[Result \"1-0\"]\n[WhiteElo \"{}\"]\n[BlackElo \"2100\"]\n\
+         [TimeControl \"{}\"]\n[Termination \"{}\"]\n\n{} 1-0\n\n",
+        white_elo, time_control, termination, moves
+    )
+}
+
+#[test]
+fn applies_the_lichess_preset() {
+    let harness = Harness::new();
+    let short = "1. e4 e5 2. Nf3";
+    let long = "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 \
+        8. c3 O-O 9. h3 Nb8 10. d4 Nbd7 11. c4 c6 12. cxb5 axb5 13. Nc3 Bb7 \
+        14. Bg5 b4 15. Nb1 h6 16. Bh4 c5 17. dxe5 Nxe4 18. Bxe7 Qxe7 \
+        19. exd6 Qf6 20. Nbd2 Nxd6 21. Nc4";
+    let games = [
+        lichess_game("2000", "180+0", "Normal", short),
+        lichess_game("1500", "180+0", "Normal", "1. d4 d5 2. c4"),
+        lichess_game("2000", "60+0", "Normal", "1. c4 e5 2. g3"),
+        lichess_game("2000", "120+1", "Normal", "1. Nf3 d5 2. g3"),
+        lichess_game("2000", "-", "Normal", "1. b3 e5 2. Bb2"),
+        lichess_game("2000", "300+0", "Time forfeit", "1. f4 d5 2. Nf3"),
+        lichess_game("2000", "300+0", "Time forfeit", long),
+    ]
+    .concat();
+    let input = harness.write("games.pgn", &games);
+    let output = harness.path_str("positions.txt");
+    let args = ["pgn-extract", "-i", &input, "-o", &output];
+
+    let result = harness.run(&args, None);
+
+    assert!(String::from_utf8_lossy(&result.stdout).contains("0 filtered out"));
+
+    let result = harness.run(&[&args[..], &["--preset", "lichess"]].concat(), None);
+
+    // The low-rated game, both bullet games and the short time forfeit are
+    // filtered out.
+    assert!(String::from_utf8_lossy(&result.stdout).contains("4 filtered out"));
+}
+
+#[test]
+fn filters_games_on_headers() {
+    let harness = Harness::new();
+    let header_game = |event: &str, site: &str, elo: &str, tc: &str, termination: &str| {
+        format!(
+            "[Event \"{}\"]\n[Site \"{}\"]\n[Result \"0-1\"]\n[WhiteElo \"{}\"]\n\
+             [BlackElo \"2500\"]\n[TimeControl \"{}\"]\n[Termination \"{}\"]\n\n\
+             1. f3 e5 2. g4 Qh4# 0-1\n\n",
+            event, site, elo, tc, termination
+        )
+    };
+    let games = [
+        header_game(
+            "Rated Blitz",
+            "https://lichess.org/a",
+            "2400",
+            "300+0",
+            "Normal",
+        ),
+        header_game(
+            "Rated Blitz",
+            "https://lichess.org/b",
+            "1400",
+            "300+0",
+            "Normal",
+        ),
+        header_game(
+            "Rated Rapid",
+            "https://lichess.org/c",
+            "2400",
+            "600+5",
+            "Normal",
+        ),
+        header_game(
+            "Casual Blitz",
+            "https://lichess.org/d",
+            "2400",
+            "180+2",
+            "Normal",
+        ),
+        header_game("TCEC", "tcec-chess.com", "3500", "3600+10", "Normal"),
+        header_game(
+            "Rated Blitz",
+            "https://lichess.org/e",
+            "2400",
+            "300+3",
+            "Abandoned",
+        ),
+    ]
+    .concat();
+    let input = harness.write("games.pgn", &games);
+    let output = harness.path_str("positions.txt");
+    let filtered = |extra_args: &[&str]| {
+        let args = [
+            &["pgn-extract", "-i", &input, "-o", &output][..],
+            extra_args,
+        ]
+        .concat();
+        let stdout = String::from_utf8(harness.run(&args, None).stdout).unwrap();
+
+        stdout
+            .split(", ")
+            .find_map(|part| part.strip_suffix(" filtered out"))
+            .unwrap()
+            .parse::<usize>()
+            .unwrap()
+    };
+
+    assert_eq!(filtered(&[]), 0);
+    assert_eq!(filtered(&["--min-elo", "2000"]), 1);
+    assert_eq!(filtered(&["--time-control", "300\\+[0-3]"]), 3);
+    assert_eq!(
+        filtered(&["--time-control", "300", "--time-control", "600\\+5"]),
+        5
+    );
+    assert_eq!(filtered(&["--time-control", "300\\+0|600\\+5"]), 3);
+    assert_eq!(filtered(&["--termination", "abandoned"]), 1);
+    assert_eq!(filtered(&["--event", "^Rated"]), 2);
+    assert_eq!(filtered(&["--site", "lichess\\.org"]), 1);
+    assert_eq!(filtered(&["--preset", "lichess", "--min-elo", "1000"]), 0);
+}
+
+#[test]
+fn drops_opening_positions() {
+    let harness = Harness::new();
+    let mut book = Vec::new();
+
+    // The starting position, and the one after 1. e4.
+    for key in [0x463b96181691fc9c_u64, 0x823c9b50fd114196] {
+        book.extend_from_slice(&key.to_be_bytes());
+        book.extend_from_slice(&[0; 8]);
+    }
+
+    std::fs::write(harness.path("book.bin"), &book).unwrap();
+
+    let book = harness.path_str("book.bin");
+    let lines = extract(&harness, &["--exclude-book", &book]).unwrap();
+
+    assert_eq!(lines.len(), 18 - 2);
+    assert!(!lines.iter().any(|line| line.starts_with(STARTPOS)));
+
+    // The Chess960 game starts at move 9, beyond the skipped plies.
+    let lines = extract(&harness, &["--skip-plies", "4"]).unwrap();
+
+    assert_eq!(lines.len(), 18 - 4);
+    assert!(lines[0].contains(" w KQkq - 2 3 "));
+}
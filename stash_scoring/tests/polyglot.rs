@@ -0,0 +1,100 @@
+mod common;
+
+use std::fs;
+
+use stash_scoring::board::Position;
+use stash_scoring::polyglot::{polyglot_key, polyglot_move, PolyglotBook};
+
+use common::{Harness, KIWIPETE, STARTPOS};
+
+fn play(moves: &[&str]) -> Position {
+    let mut pos = Position::from_fen(STARTPOS, false).unwrap();
+
+    for uci in moves {
+        let mv = pos.parse_uci(uci).unwrap();
+
+        pos.play(mv);
+    }
+
+    pos
+}
+
+#[test]
+fn computes_polyglot_keys() {
+    // The reference keys of the Polyglot book format specification.
+    let cases: [(&[&str], u64); 9] = [
+        (&[], 0x463b96181691fc9c),
+        (&["e2e4"], 0x823c9b50fd114196),
+        (&["e2e4", "d7d5"], 0x0756b94461c50fb0),
+        (&["e2e4", "d7d5", "e4e5"], 0x662fafb965db29d4),
+        (&["e2e4", "d7d5", "e4e5", "f7f5"], 0x22a48b5a8e47ff78),
+        (
+            &["e2e4", "d7d5", "e4e5", "f7f5", "e1e2"],
+            0x652a607ca3f242c1,
+        ),
+        (
+            &["e2e4", "d7d5", "e4e5", "f7f5", "e1e2", "e8f7"],
+            0x00fdd303c946bdd9,
+        ),
+        (
+            &["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"],
+            0x3c8123ea7b067637,
+        ),
+        (
+            &["a2a4", "b7b5", "h2h4", "b5b4", "c2c4", "b4c3", "a1a3"],
+            0x5c3f9b829b279560,
+        ),
+    ];
+
+    for (moves, key) in cases {
+        assert_eq!(polyglot_key(&play(moves)), key, "{:?}", moves);
+    }
+}
+
+#[test]
+fn encodes_polyglot_moves() {
+    // Castling moves are written as the king capturing its rook.
+    for (fen, uci, encoded) in [
+        (STARTPOS, "e2e4", 0x031c),
+        (KIWIPETE, "e1g1", 0x0107),
+        (KIWIPETE, "e1c1", 0x0100),
+    ] {
+        let pos = Position::from_fen(fen, false).unwrap();
+
+        assert_eq!(
+            polyglot_move(pos.parse_uci(uci).unwrap()),
+            encoded,
+            "{}",
+            uci
+        );
+    }
+}
+
+#[test]
+fn reads_books() {
+    let harness = Harness::new();
+    let mut book = Vec::new();
+
+    // Two moves from the starting position, and one after 1. e4.
+    for (key, mv) in [
+        (0x463b96181691fc9c_u64, 0x031cu16),
+        (0x463b96181691fc9c, 0x0293),
+        (0x823c9b50fd114196, 0x0d2c),
+    ] {
+        book.extend_from_slice(&key.to_be_bytes());
+        book.extend_from_slice(&mv.to_be_bytes());
+        book.extend_from_slice(&[0, 1, 0, 0, 0, 0]);
+    }
+
+    fs::write(harness.path("book.bin"), &book).unwrap();
+
+    let book = PolyglotBook::open(&harness.path_str("book.bin")).unwrap();
+
+    assert_eq!(book.len(), 2);
+    assert!(book.contains(&play(&[])));
+    assert!(book.contains(&play(&["e2e4"])));
+    assert!(!book.contains(&play(&["d2d4"])));
+
+    fs::write(harness.path("truncated.bin"), [0; 20]).unwrap();
+    assert!(PolyglotBook::open(&harness.path_str("truncated.bin")).is_err());
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::{EngineConfig, Score, SearchLimit};
+use stash_scoring::pool::EnginePool;
+
+use common::*;
+
+const ENDGAME: &str = "8/5k2/8/8/8/8/2R5/4K3 w - - 0 1";
+
+/// Runs the mock engine through a wrapper, replaying the first script for
+/// the first engine started, and the second one for all the others.
+fn engine(harness: &Harness, first: &str, others: &str) -> EngineConfig {
+    let first = harness.write("first.txt", first);
+    let others = harness.write("others.txt", others);
+    let marker = harness.path_str("started");
+    let wrapper = harness.write(
+        "engine.sh",
+        &format!(
+            "#!/bin/sh\nif [ -e {marker} ]; then\n\
+             MOCK_ENGINE_SCRIPT={others} exec {engine}\nfi\n\
+             touch {marker}\nMOCK_ENGINE_SCRIPT={first} exec {engine}\n",
+            marker = marker,
+            first = first,
+            others = others,
+            engine = MOCK_ENGINE
+        ),
+    );
+
+    fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).unwrap();
+
+    EngineConfig {
+        name: String::from("mock"),
+        command: wrapper,
+        ..Default::default()
+    }
+}
+
+fn positions(fens: &[&str]) -> Vec<Position> {
+    fens.iter()
+        .map(|fen| Position::from_fen(fen, false).unwrap())
+        .collect()
+}
+
+const DEPTH_1: SearchLimit = SearchLimit {
+    depth: Some(1),
+    nodes: None,
+    go_template: None,
+    searchmoves: Vec::new(),
+};
+
+#[test]
+fn returns_results_in_order() {
+    let harness = Harness::new();
+    let script = "[go]\n!legal\n";
+    let pool = EnginePool::new(&engine(&harness, script, script), 2).unwrap();
+    let positions = positions(&[STARTPOS, ENDGAME, KIWIPETE, ENDGAME, STARTPOS]);
+    let results = pool.search_batch(&positions, &DEPTH_1);
+
+    assert_eq!(results.len(), positions.len());
+
+    for (pos, result) in positions.iter().zip(results) {
+        assert!(pos.parse_uci(&result.unwrap().best_move).is_ok());
+    }
+
+    assert!(pool.score_batch(&[], &DEPTH_1).is_empty());
+}
+
+#[test]
+fn retries_failed_searches() {
+    let harness = Harness::new();
+    let pool = EnginePool::new(
+        &engine(
+            &harness,
+            "[go]\n!exit 1\n",
+            &search_script("info depth 1 score cp 21 pv e2e4"),
+        ),
+        1,
+    )
+    .unwrap();
+
+    assert_eq!(
+        pool.score_batch(&positions(&[STARTPOS, KIWIPETE]), &DEPTH_1)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>(),
+        [Score::Cp(21), Score::Cp(21)]
+    );
+}
+
+#[test]
+fn reports_persistent_failures() {
+    let harness = Harness::new();
+    let script = "[go]\n!exit 1\n";
+    let pool = EnginePool::new(&engine(&harness, script, script), 1).unwrap();
+    let results = pool.score_batch(&positions(&[STARTPOS]), &DEPTH_1);
+
+    assert!(results[0].is_err());
+    // The pool keeps working after a failure.
+    assert!(pool.score_batch(&positions(&[ENDGAME]), &DEPTH_1)[0].is_err());
+}
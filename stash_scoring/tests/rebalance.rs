@@ -0,0 +1,100 @@
+mod common;
+
+use common::*;
+
+fn dataset(wins: usize, draws: usize, losses: usize) -> String {
+    let mut content = String::new();
+
+    for (count, wdl) in [(wins, "1.0"), (draws, "0.5"), (losses, "0.0")] {
+        for i in 0..count {
+            content.push_str(&format!("{} {} {}\n", STARTPOS, wdl, i));
+        }
+    }
+
+    content
+}
+
+fn rebalance(harness: &Harness, content: &str, extra_args: &[&str]) -> Option<Vec<String>> {
+    let input = harness.write("dataset.txt", content);
+    let output = harness.path_str("rebalanced.txt");
+    let mut args = vec![
+        "rebalance",
+        "-i",
+        &input,
+        "-o",
+        &output,
+        "--input-columns",
+        "fen,wdl,extra",
+    ];
+
+    args.extend_from_slice(extra_args);
+
+    match harness.run(&args, None).status.success() {
+        true => harness
+            .read("rebalanced.txt")
+            .map(|content| content.lines().map(String::from).collect()),
+        false => None,
+    }
+}
+
+fn count(lines: &[String], wdl: &str) -> usize {
+    lines
+        .iter()
+        .filter(|line| line.contains(&format!(" {} ", wdl)))
+        .count()
+}
+
+#[test]
+fn downsamples_draws() {
+    let harness = Harness::new();
+    let lines = rebalance(&harness, &dataset(1000, 6000, 1000), &[]).unwrap();
+    let draws = count(&lines, "0.5");
+
+    assert_eq!(count(&lines, "1.0"), 1000);
+    assert_eq!(count(&lines, "0.0"), 1000);
+    assert!((900..1100).contains(&draws), "{} draws kept", draws);
+}
+
+#[test]
+fn follows_target_ratios() {
+    let harness = Harness::new();
+    let args = ["--target-ratios", "2:1:1"];
+    let lines = rebalance(&harness, &dataset(3000, 3000, 3000), &args).unwrap();
+    let (wins, draws, losses) = (
+        count(&lines, "1.0"),
+        count(&lines, "0.5"),
+        count(&lines, "0.0"),
+    );
+
+    assert_eq!(wins, 3000);
+    assert!((1350..1650).contains(&draws), "{} draws kept", draws);
+    assert!((1350..1650).contains(&losses), "{} losses kept", losses);
+}
+
+#[test]
+fn is_reproducible_with_a_seed() {
+    let harness = Harness::new();
+    let content = dataset(100, 500, 100);
+    let first = rebalance(&harness, &content, &["--seed", "7"]).unwrap();
+
+    assert_eq!(
+        rebalance(&harness, &content, &["--seed", "7"]),
+        Some(first.clone())
+    );
+    assert_ne!(rebalance(&harness, &content, &["--seed", "8"]), Some(first));
+}
+
+#[test]
+fn rejects_invalid_ratios() {
+    let harness = Harness::new();
+    let content = dataset(1, 1, 1);
+
+    assert_eq!(
+        rebalance(&harness, &content, &["--target-ratios", "1:1"]),
+        None
+    );
+    assert_eq!(
+        rebalance(&harness, &content, &["--target-ratios", "0:0:0"]),
+        None
+    );
+}
@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+use std::io;
+
+use stash_scoring::engine::{Score, ScoreFormat};
+use stash_scoring::reference::{ReferenceDiff, ReferenceStats};
+
+#[test]
+fn counts_disagreements() {
+    let scores = HashMap::from([
+        (String::from("a"), Score::Cp(10)),
+        (String::from("b"), Score::Cp(-20)),
+        (String::from("c"), Score::Mate(2)),
+    ]);
+    let reference = ReferenceDiff::new(scores, 30, ScoreFormat::Pound, Box::new(io::sink()));
+
+    reference.compare("a", "a", Score::Cp(39)).unwrap();
+    reference.compare("b", "b", Score::Cp(-50)).unwrap();
+    reference.compare("c", "c", Score::Mate(2)).unwrap();
+    reference.compare("d", "d", Score::Cp(0)).unwrap();
+
+    assert_eq!(
+        reference.stats(),
+        ReferenceStats {
+            compared: 3,
+            missing: 1,
+            disagreements: 1,
+            total_difference: 59,
+            max_difference: 30,
+        }
+    );
+    assert_eq!(
+        reference.stats().to_string(),
+        "3 positions compared, 1 disagreements, score differences: mean 19.7, max 30, \
+         1 positions missing from the reference"
+    );
+}
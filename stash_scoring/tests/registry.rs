@@ -0,0 +1,75 @@
+use stash_scoring::engine::EngineProfile;
+use stash_scoring::registry::EngineRegistry;
+
+#[test]
+fn parses_engine_registries() {
+    let registry = EngineRegistry::parse(
+        r#"
+[stash-dev]
+cmd = "./stash"
+args = ["--uci", 2]
+dir = "/opt/stash"
+sync = ["EvalFile"]
+errors = ["^info string NNUE evaluation failed"]
+
+[stash-dev.options]
+Threads = 1
+Hash = 64
+EvalFile = "nets/latest.nnue"
+
+[lc0]
+cmd = "lc0"
+profile = "lc0"
+clearenv = true
+env = { LC_ALL = "C" }
+"#,
+    )
+    .unwrap();
+
+    let stash = registry.get("stash-dev").unwrap();
+
+    assert_eq!(stash.command, "./stash");
+    assert_eq!(stash.args, ["--uci", "2"]);
+    assert_eq!(stash.working_dir.as_deref(), Some("/opt/stash"));
+    assert_eq!(stash.sync_options, ["EvalFile"]);
+    assert_eq!(
+        stash.startup_errors,
+        ["^info string NNUE evaluation failed"]
+    );
+    // Options keep the order they are written in.
+    assert_eq!(
+        stash.options,
+        ["Threads=1", "Hash=64", "EvalFile=nets/latest.nnue"]
+    );
+    assert_eq!(stash.profile, EngineProfile::Generic);
+    assert!(!stash.clear_env);
+
+    let lc0 = registry.get("lc0").unwrap();
+
+    assert_eq!(lc0.profile, EngineProfile::Lc0);
+    assert!(lc0.clear_env);
+    assert_eq!(lc0.env, [(String::from("LC_ALL"), String::from("C"))]);
+    assert!(registry.get("stash").is_none());
+}
+
+#[test]
+fn rejects_invalid_registries() {
+    for (content, error) in [
+        ("[a]\nargs = []\n", "engine 'a': missing 'cmd'"),
+        (
+            "[a]\ncmd = 'x'\nhash = 16\n",
+            "engine 'a': unknown setting 'hash'",
+        ),
+        ("[a]\ncmd = 'x'\nprofile = 'sf'\n", "engine 'a': 'profile'"),
+        (
+            "[a]\ncmd = 'x'\nclearenv = 'yes'\n",
+            "engine 'a': 'clearenv'",
+        ),
+        ("a = 1\n", "engine 'a': expected a table"),
+        ("[a\n", ""),
+    ] {
+        let err = EngineRegistry::parse(content).err().unwrap();
+
+        assert!(err.starts_with(error), "{}", err);
+    }
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::fs;
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::{Score, SearchLimit};
+use stash_scoring::score_cache::{cache_context, ScoreCache};
+
+use common::{Harness, KIWIPETE, MOCK_ENGINE, STARTPOS};
+
+fn pos(fen: &str) -> Position {
+    Position::from_fen(fen, false).unwrap()
+}
+
+#[test]
+fn persists_scores_by_context() {
+    let harness = Harness::new();
+    let path = harness.path_str("cache.txt");
+    let cache = ScoreCache::open(&path, "ctx1").unwrap();
+
+    cache.insert(&pos(STARTPOS), None, Score::Cp(42)).unwrap();
+    cache
+        .insert(&pos(STARTPOS), Some("e2e4"), Score::Mate(-3))
+        .unwrap();
+    cache.insert(&pos(STARTPOS), None, Score::Cp(10)).unwrap();
+
+    // Move counters are not part of the key.
+    let moved = STARTPOS.replace(" 0 1", " 12 40");
+
+    assert_eq!(cache.get(&pos(&moved), None), Some(Score::Cp(10)));
+    assert_eq!(cache.get(&pos(STARTPOS), Some("d2d4")), None);
+    cache.flush().unwrap();
+    drop(cache);
+
+    let content = harness.read("cache.txt").unwrap();
+
+    assert_eq!(content.lines().count(), 2);
+    fs::write(
+        &path,
+        format!(
+            "{}ctx2 {} 5\nctx1 {}",
+            content,
+            KIWIPETE.replace(" 0 1", ""),
+            &KIWIPETE[..20]
+        ),
+    )
+    .unwrap();
+
+    // The truncated last line is ignored, as are the entries of other
+    // contexts.
+    let cache = ScoreCache::open(&path, "ctx1").unwrap();
+
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&pos(STARTPOS), None), Some(Score::Cp(42)));
+    assert_eq!(
+        cache.get(&pos(STARTPOS), Some("e2e4")),
+        Some(Score::Mate(-3))
+    );
+    assert_eq!(cache.get(&pos(KIWIPETE), None), None);
+    assert_eq!(ScoreCache::open(&path, "ctx2").unwrap().len(), 1);
+
+    // New entries do not extend the truncated line.
+    cache.insert(&pos(KIWIPETE), None, Score::Cp(-5)).unwrap();
+    cache.flush().unwrap();
+    drop(cache);
+
+    let cache = ScoreCache::open(&path, "ctx1").unwrap();
+
+    assert_eq!(cache.get(&pos(KIWIPETE), None), Some(Score::Cp(-5)));
+}
+
+#[test]
+fn identifies_configurations() {
+    let engine = std::path::Path::new(MOCK_ENGINE);
+    let depth = |depth| SearchLimit {
+        depth: Some(depth),
+        ..Default::default()
+    };
+    let options = vec![String::from("Hash=16")];
+    let context = cache_context(engine, &options, &depth(5)).unwrap();
+
+    assert_eq!(context.len(), 16);
+    assert_eq!(cache_context(engine, &options, &depth(5)).unwrap(), context);
+    assert_ne!(cache_context(engine, &options, &depth(6)).unwrap(), context);
+    assert_ne!(cache_context(engine, &[], &depth(5)).unwrap(), context);
+}
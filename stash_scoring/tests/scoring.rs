@@ -0,0 +1,1682 @@
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use stash_scoring::manifest::sha256_file;
+
+use common::*;
+
+#[test]
+fn scores_positions() {
+    let harness = Harness::new();
+    let script = search_script("info depth 1 score cp 42 pv e2e4");
+    let input = format!("{} 0.5\n{} 1.0\n", STARTPOS, KIWIPETE);
+    let output = harness.score(&input, Some(&script), &[]).unwrap();
+
+    assert_eq!(output, format!("{} 0.5 42\n{} 1 42\n", STARTPOS, KIWIPETE));
+}
+
+#[test]
+fn keeps_the_last_reported_score() {
+    let harness = Harness::new();
+    let script = "[go]\n\
+        info depth 1 score cp 10 pv e2e4\n\
+        info string some engine chatter score cp 999\n\
+        info depth 2 score cp 20 lowerbound pv e2e4\n\
+        \n\
+        info depth 3 score cp -35 pv e2e4 e7e5\n\
+        bestmove e2e4\n";
+    let output = harness
+        .score(&format!("{} 0.5\n", STARTPOS), Some(script), &[])
+        .unwrap();
+
+    assert_eq!(output, format!("{} 0.5 -35\n", STARTPOS));
+}
+
+#[test]
+fn parses_extreme_scores() {
+    let cases = [
+        ("cp 100000", "100000"),
+        ("cp -2147483648", "-2147483648"),
+        ("cp 2147483647", "2147483647"),
+        ("mate 0", "#0"),
+        ("mate -1", "#-1"),
+        ("mate 40000", "#40000"),
+    ];
+
+    for (score, expected) in cases {
+        let harness = Harness::new();
+        let script = search_script(&format!("info depth 1 score {} pv e2e4", score));
+        let output = harness.score(&format!("{} 0.5\n", STARTPOS), Some(&script), &[]);
+
+        assert_eq!(output, Some(format!("{} 0.5 {}\n", STARTPOS, expected)));
+    }
+}
+
+#[test]
+fn rejects_out_of_range_scores() {
+    let harness = Harness::new();
+    let script = search_script("info depth 1 score cp 2147483648 pv e2e4");
+
+    assert_eq!(
+        harness.score(&format!("{} 0.5\n", STARTPOS), Some(&script), &[]),
+        None
+    );
+}
+
+#[test]
+fn writes_mate_scores_in_the_chosen_format() {
+    let script = search_script("info depth 1 score mate -3 pv e2e4");
+    let input = format!("{} 0.5\n", STARTPOS);
+
+    for (format, expected) in [("pound", "#-3"), ("letter", "-M3"), ("folded", "-32003")] {
+        let harness = Harness::new();
+        let output = harness.score(&input, Some(&script), &["-s", format]);
+
+        assert_eq!(output, Some(format!("{} 0.5 {}\n", STARTPOS, expected)));
+    }
+}
+
+#[test]
+fn carries_extra_columns() {
+    let harness = Harness::new();
+    let input = format!("{} 0.5 game42 17\n{} 0 game43\n", STARTPOS, KIWIPETE);
+    let output = harness
+        .score(&input, None, &["--input-columns", "fen,wdl,extra*"])
+        .unwrap();
+
+    assert_eq!(
+        output,
+        format!("{} 0.5 0 game42 17\n{} 0 0 game43\n", STARTPOS, KIWIPETE)
+    );
+}
+
+#[test]
+fn detects_the_input_format() {
+    let harness = Harness::new();
+    let short = KIWIPETE.replace(" 0 1", "");
+    let output = harness
+        .score(&format!("{}\n{}\n", STARTPOS, KIWIPETE), None, &[])
+        .unwrap();
+
+    assert_eq!(output, format!("{} 0\n{} 0\n", STARTPOS, KIWIPETE));
+
+    let input = format!(
+        "{} hmvc 0; fmvn 1; c9 \"0-1\";\n{} id \"a\";\n",
+        short, short
+    );
+    let output = harness.score(&input, None, &[]).unwrap();
+
+    // Records without a result keep an empty WDL column.
+    assert_eq!(output, format!("{} 0 0\n{} - 0\n", KIWIPETE, KIWIPETE));
+
+    // Lines which do not match an explicit format are skipped.
+    let output = harness
+        .score(&input, None, &["--input-format", "fen-wdl"])
+        .unwrap();
+
+    assert_eq!(output, "");
+}
+
+#[test]
+fn scores_bare_fens() {
+    let harness = Harness::new();
+    let short = KIWIPETE.replace(" 0 1", "");
+    let script = search_script("info depth 1 score cp 42 pv e2e4");
+    let input = format!("{}\n{}\n", STARTPOS, short);
+    let args = ["--input-format", "fen", "--deterministic"];
+    let output = harness.score(&input, Some(&script), &args).unwrap();
+
+    assert_eq!(output, format!("{} 42\n{} 42\n", STARTPOS, short));
+
+    // Such outputs can be reused as well.
+    let old_path = harness.write("old.txt", &output);
+    let input = format!("{}\n{}\n", KIWIPETE, "8/8/8/8/8/8/8/K1k5 w - -");
+    let args = [
+        "--input-format",
+        "fen",
+        "--reuse-scores",
+        &old_path,
+        "--deterministic",
+    ];
+    let output = harness.score(&input, None, &args).unwrap();
+
+    assert_eq!(
+        output,
+        format!("{} 42\n{} 0\n", KIWIPETE, "8/8/8/8/8/8/8/K1k5 w - -")
+    );
+    assert_eq!(
+        harness.score(
+            &input,
+            None,
+            &["--input-format", "fen", "--blend-lambda", "0.5"]
+        ),
+        None
+    );
+}
+
+#[test]
+fn scores_moves_from_the_mover_point_of_view() {
+    let harness = Harness::new();
+    let script = search_script("info depth 1 score mate -2 pv e7e5");
+    let output = harness
+        .score(
+            &format!("{} e2e4\n", STARTPOS),
+            Some(&script),
+            &["--input-columns", "fen,move"],
+        )
+        .unwrap();
+
+    assert_eq!(output, format!("{} e2e4 #3\n", STARTPOS));
+
+    // Extreme scores saturate instead of overflowing.
+    for (score, expected) in [
+        ("cp -2147483648", "2147483647"),
+        ("mate -2147483648", "#2147483647"),
+    ] {
+        let script = search_script(&format!("info depth 1 score {} pv e7e5", score));
+        let output = harness
+            .score(
+                &format!("{} e2e4\n", STARTPOS),
+                Some(&script),
+                &["--input-columns", "fen,move"],
+            )
+            .unwrap();
+
+        assert_eq!(output, format!("{} e2e4 {}\n", STARTPOS, expected));
+    }
+}
+
+#[test]
+fn writes_multipv_policy_targets() {
+    let harness = Harness::new();
+    let script = "[go]\n\
+        info depth 1 multipv 1 score cp 30 pv e2e4\n\
+        info depth 1 multipv 2 score cp 20 pv d2d4\n\
+        info depth 2 multipv 1 score cp 35 pv d2d4\n\
+        info depth 2 multipv 2 score cp 25 pv e2e4 e7e5\n\
+        bestmove d2d4\n";
+    let output = harness
+        .score(
+            &format!("{} 0.5\n", STARTPOS),
+            Some(script),
+            &["--multipv", "2"],
+        )
+        .unwrap();
+
+    assert_eq!(output, format!("{} 0.5 d2d4:35 e2e4:25\n", STARTPOS));
+}
+
+#[test]
+fn skips_invalid_fens() {
+    let harness = Harness::new();
+    let input = format!("{} 0.5\n8/8/8/8 w - - 0 1 0.5\n", STARTPOS);
+    let output = harness.score(&input, None, &[]).unwrap();
+
+    assert_eq!(output, format!("{} 0.5 0\n", STARTPOS));
+}
+
+#[test]
+fn accepts_messy_whitespace() {
+    let harness = Harness::new();
+    let input = format!(
+        "\u{feff}{}\t0.5\r\n  {}  1.0 \n",
+        STARTPOS.replace(' ', "\t"),
+        KIWIPETE.replace(' ', "  ")
+    );
+    let output = harness.score(&input, None, &[]).unwrap();
+
+    assert_eq!(output, format!("{} 0.5 0\n{} 1 0\n", STARTPOS, KIWIPETE));
+}
+
+#[test]
+fn accepts_chess960_castling_rights() {
+    let harness = Harness::new();
+    let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+    let output = harness
+        .score(&format!("{} 0.5\n", fen), None, &["--chess960"])
+        .unwrap();
+
+    assert_eq!(output, format!("{} 0.5 0\n", fen));
+}
+
+#[test]
+fn writes_run_files_to_a_workspace() {
+    let harness = Harness::new();
+    let workspace = harness.path_str("runs");
+    let script = "[go]\n!stderr searching\ninfo depth 1 score cp 42 pv e2e4\nbestmove e2e4\n";
+    let input = format!("{} 0.5\n8/8/8/8 w - - 0 1 0.5\n", STARTPOS);
+    let args = ["--workspace", &workspace, "--workspace-cleanup", "never"];
+
+    assert!(harness.score(&input, Some(script), &args).is_some());
+
+    let runs: Vec<_> = fs::read_dir(&workspace).unwrap().flatten().collect();
+
+    assert_eq!(runs.len(), 1);
+
+    let run = runs[0].path();
+    let file = |name: &str| fs::read_to_string(run.join(name)).unwrap();
+
+    assert!(runs[0]
+        .file_name()
+        .to_str()
+        .unwrap()
+        .starts_with("output.txt-"));
+    assert!(file("engine-1.stderr").contains("searching"));
+    assert!(file("rejects.tsv").ends_with("\t8/8/8/8 w - - 0 1 0.5\n"));
+    assert!(file("run.log").contains("1 positions written out of 2 read"));
+    assert!(file("manifest.json").contains("output_layout"));
+
+    // By default, the workspace is only kept when the run fails.
+    let args = ["--workspace", &workspace];
+
+    assert!(harness.score(&input, Some(script), &args).is_some());
+    assert_eq!(fs::read_dir(&workspace).unwrap().count(), 1);
+
+    let script = "[go]\n!exit 1\n";
+
+    assert_eq!(harness.score(&input, Some(script), &args), None);
+    assert_eq!(fs::read_dir(&workspace).unwrap().count(), 2);
+}
+
+#[test]
+fn throttles_the_searches() {
+    let harness = Harness::new();
+    let input = format!("{} 0.5\n{} 0.5\n{} 0.5\n", STARTPOS, KIWIPETE, STARTPOS);
+    let script = "[go]\n!sleep 50\ninfo depth 1 nodes 2000 score cp 7 pv e2e4\nbestmove e2e4\n";
+    let expected = format!(
+        "{} 0.5 7\n{} 0.5 7\n{} 0.5 7\n",
+        STARTPOS, KIWIPETE, STARTPOS
+    );
+
+    // Each search then takes at least 200ms.
+    for args in [["--duty-cycle", "0.25"], ["--max-nps", "10000"]] {
+        let start = Instant::now();
+
+        assert_eq!(
+            harness.score(&input, Some(script), &args),
+            Some(expected.clone())
+        );
+        assert!(start.elapsed() >= Duration::from_millis(600), "{:?}", args);
+    }
+
+    assert_eq!(
+        harness.score(&input, Some(script), &["--duty-cycle", "0"]),
+        None
+    );
+}
+
+#[test]
+fn fails_cleanly_on_engine_crash() {
+    let harness = Harness::new();
+    let script = "[go]\ninfo depth 1 score cp 1 pv e2e4\nbestmove e2e4\n[go]\n!exit 1\n";
+    let input = format!("{} 0.5\n{} 0.5\n{} 0.5\n", STARTPOS, KIWIPETE, STARTPOS);
+
+    assert_eq!(harness.score(&input, Some(script), &[]), None);
+
+    // The partial results must not be left under the final name.
+    assert!(!harness.path("output.txt").exists());
+    assert!(harness.path("output.txt.tmp").exists());
+}
+
+#[test]
+fn keeps_input_order_in_deterministic_mode() {
+    let harness = Harness::new();
+    // The first searches take longer, so that later positions complete first
+    // when scored in parallel.
+    let script = "[go]\n!sleep 50\ninfo depth 1 score cp 1 pv e2e4\nbestmove e2e4\n\
+        [go]\ninfo depth 1 score cp 1 pv e2e4\nbestmove e2e4\n";
+    let input: String = (0..8)
+        .map(|i| format!("{} 0.5 {}\n", STARTPOS, i))
+        .collect();
+    let expected: String = (0..8)
+        .map(|i| format!("{} 0.5 1 {}\n", STARTPOS, i))
+        .collect();
+    let args = [
+        "--input-columns",
+        "fen,wdl,extra",
+        "-t",
+        "4",
+        "--deterministic",
+    ];
+
+    assert_eq!(harness.score(&input, Some(script), &args), Some(expected));
+}
+
+#[test]
+fn rejects_multithreaded_engines_in_deterministic_mode() {
+    let harness = Harness::new();
+    let args = ["--deterministic", "-c", "Threads=4"];
+
+    assert_eq!(
+        harness.score(&format!("{} 0.5\n", STARTPOS), None, &args),
+        None
+    );
+}
+
+#[test]
+fn preserves_soft_labels() {
+    let harness = Harness::new();
+    let input = format!("{} 0.73\n{} 0.123456789012\n", STARTPOS, KIWIPETE);
+    let output = harness.score(&input, None, &[]).unwrap();
+
+    assert_eq!(
+        output,
+        format!("{} 0.73 0\n{} 0.123456789012 0\n", STARTPOS, KIWIPETE)
+    );
+}
+
+#[test]
+fn rounds_wdl_values_to_the_requested_precision() {
+    let harness = Harness::new();
+    let input = format!("{} 0.7351\n{} 1\n", STARTPOS, KIWIPETE);
+    let output = harness
+        .score(&input, None, &["--wdl-precision", "2"])
+        .unwrap();
+
+    assert_eq!(
+        output,
+        format!("{} 0.74 0\n{} 1.00 0\n", STARTPOS, KIWIPETE)
+    );
+}
+
+#[test]
+fn skips_out_of_range_wdl_values() {
+    let harness = Harness::new();
+    let input = format!("{} 1.5\n{} 0.25\n", STARTPOS, KIWIPETE);
+    let output = harness.score(&input, None, &[]).unwrap();
+
+    assert_eq!(output, format!("{} 0.25 0\n", KIWIPETE));
+}
+
+#[test]
+fn writes_blended_targets() {
+    let harness = Harness::new();
+    let script = search_script("info depth 1 score cp 400 pv e2e4");
+    let black_to_move = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+    let input = format!("{} 1\n{} 0\n", STARTPOS, black_to_move);
+    let args = [
+        "--blend-lambda",
+        "0.5",
+        "--sigmoid-k",
+        "400",
+        "--wdl-precision",
+        "4",
+    ];
+    let output = harness.score(&input, Some(&script), &args).unwrap();
+
+    // sigmoid(1) = 0.7311, seen from White's point of view.
+    assert_eq!(
+        output,
+        format!(
+            "{} 1.0000 400 0.8655\n{} 0.0000 400 0.1345\n",
+            STARTPOS, black_to_move
+        )
+    );
+
+    // With all the material on the board, 100 centipawns give a 50% win
+    // probability and no losses.
+    let script = search_script("info depth 1 score cp 100 pv e2e4");
+    let args = [
+        "--blend-lambda",
+        "0.5",
+        "--wdl-model",
+        "material",
+        "--wdl-precision",
+        "4",
+    ];
+    let output = harness.score(&input, Some(&script), &args).unwrap();
+
+    assert_eq!(
+        output,
+        format!(
+            "{} 1.0000 100 0.8750\n{} 0.0000 100 0.1250\n",
+            STARTPOS, black_to_move
+        )
+    );
+}
+
+#[test]
+fn requires_results_for_blended_targets() {
+    let harness = Harness::new();
+    let input = format!("{} e2e4\n", STARTPOS);
+    let args = ["--input-columns", "fen,move", "--blend-lambda", "0.5"];
+
+    assert_eq!(harness.score(&input, None, &args), None);
+}
+
+#[test]
+fn skips_shallow_searches() {
+    let harness = Harness::new();
+    let script = "[go]\ninfo depth 2 score cp 5 pv e2e4\nbestmove e2e4\n\
+                  [go]\ninfo depth 9 score cp 17 pv e2e4\nbestmove e2e4\n";
+    let input = format!("{} 0.5\n", STARTPOS);
+    let args = ["-n", "1000", "--min-depth", "6"];
+
+    assert_eq!(harness.score(&input, Some(script), &args).unwrap(), "");
+
+    let args = ["-n", "1000", "--min-depth", "6", "--shallow-retries", "1"];
+
+    assert_eq!(
+        harness.score(&input, Some(script), &args).unwrap(),
+        format!("{} 0.5 17\n", STARTPOS)
+    );
+
+    let searches: Vec<String> = harness
+        .read("engine.log")
+        .unwrap()
+        .lines()
+        .filter(|line| line.starts_with("go"))
+        .map(String::from)
+        .collect();
+
+    assert_eq!(
+        searches[searches.len() - 2..],
+        ["go depth 1 nodes 1000", "go depth 6 nodes 2000"]
+    );
+
+    // Depth limits alone are raised as well.
+    let script = "[go]\ninfo depth 1 score cp 5 pv e2e4\nbestmove e2e4\n\
+                  [go]\ninfo depth 6 score cp 17 pv e2e4\nbestmove e2e4\n\
+                  [go]\ninfo depth 8 score cp 23 pv e2e4\nbestmove e2e4\n";
+    let args = ["--min-depth", "7", "--shallow-retries", "2"];
+
+    assert_eq!(
+        harness.score(&input, Some(script), &args).unwrap(),
+        format!("{} 0.5 23\n", STARTPOS)
+    );
+
+    let searches: Vec<String> = harness
+        .read("engine.log")
+        .unwrap()
+        .lines()
+        .filter(|line| line.starts_with("go"))
+        .map(String::from)
+        .collect();
+
+    assert_eq!(
+        searches[searches.len() - 3..],
+        ["go depth 1", "go depth 7", "go depth 9"]
+    );
+}
+
+#[test]
+fn searches_with_go_templates() {
+    let harness = Harness::new();
+    let script = search_script("info depth 1 score cp 5 pv e2e4");
+    let input = format!("{} 0.5\n", STARTPOS);
+    let args = [
+        "-n",
+        "1000",
+        "--go-template",
+        "go nodes {nodes} movetime 50",
+    ];
+
+    assert_eq!(
+        harness.score(&input, Some(&script), &args).unwrap(),
+        format!("{} 0.5 5\n", STARTPOS)
+    );
+    assert!(harness
+        .read("engine.log")
+        .unwrap()
+        .lines()
+        .any(|line| line == "go nodes 1000 movetime 50"));
+
+    // -d 1 is always given by the harness, but '{wtime}' has no value.
+    let args = ["--go-template", "go wtime {wtime}"];
+
+    assert_eq!(harness.score(&input, Some(&script), &args), None);
+}
+
+#[test]
+fn writes_run_manifests() {
+    let harness = Harness::new();
+    let script = search_script("info depth 1 score cp 42 pv e2e4");
+    let input = format!("{} 0.5\n", STARTPOS);
+    let output = harness.score(&input, Some(&script), &[]).unwrap();
+    let manifest: serde_json::Value =
+        serde_json::from_str(&harness.read("output.txt.manifest.json").unwrap()).unwrap();
+    let files = &manifest["files"];
+
+    assert_eq!(files["engine"]["path"], MOCK_ENGINE);
+    assert_eq!(files["input"]["path"], harness.path_str("input.txt"));
+    assert_eq!(files["output"]["path"], harness.path_str("output.txt"));
+    assert_eq!(
+        files["output"]["sha256"].as_str().unwrap(),
+        sha256_file(&harness.path("output.txt")).unwrap()
+    );
+    assert_eq!(output, format!("{} 0.5 42\n", STARTPOS));
+    assert!(manifest["command_line"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|arg| arg == "-d"));
+    assert!(manifest["tool"]["git_describe"].is_string());
+    assert!(manifest["finished_at"].as_str().unwrap().ends_with('Z'));
+
+    let harness = Harness::new();
+
+    assert!(harness
+        .score(&input, Some(&script), &["--no-manifest"])
+        .is_some());
+    assert!(!harness.path("output.txt.manifest.json").exists());
+}
+
+#[test]
+fn appends_to_compatible_outputs() {
+    let harness = Harness::new();
+    let script = search_script("info depth 1 score cp 42 pv e2e4");
+    let first = harness
+        .score(&format!("{} 0.5\n", STARTPOS), Some(&script), &["--append"])
+        .unwrap();
+
+    assert_eq!(first, format!("{} 0.5 42\n", STARTPOS));
+
+    let input = format!("{} 1\n", KIWIPETE);
+    let output = harness.score(&input, Some(&script), &["--append"]).unwrap();
+
+    assert_eq!(output, format!("{} 0.5 42\n{} 1 42\n", STARTPOS, KIWIPETE));
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&harness.read("output.txt.manifest.json").unwrap()).unwrap();
+
+    assert_eq!(manifest["output_layout"]["columns"], "fen,wdl,eval,extra*");
+
+    // The layout recorded in the manifest must match.
+    let args = ["--append", "--score-format", "folded"];
+
+    assert_eq!(harness.score(&input, Some(&script), &args), None);
+    assert_eq!(
+        harness.score(
+            &input,
+            Some(&script),
+            &["--append", "--input-format", "fen"]
+        ),
+        None
+    );
+
+    // Without a manifest, the first line is checked instead. Its move
+    // counters could pass for a score and an extra column, so only layouts
+    // which cannot read the line, like one with a move column, are caught.
+    std::fs::remove_file(harness.path("output.txt.manifest.json")).unwrap();
+
+    let args = [
+        "--append",
+        "--input-columns",
+        "fen,move,wdl",
+        "--no-manifest",
+    ];
+
+    assert_eq!(harness.score(&input, Some(&script), &args), None);
+    assert_eq!(
+        harness.score(&input, Some(&script), &["--append", "--no-manifest"]),
+        Some(format!(
+            "{} 0.5 42\n{} 1 42\n{} 1 42\n",
+            STARTPOS, KIWIPETE, KIWIPETE
+        ))
+    );
+}
+
+#[test]
+fn keeps_the_wdl_column_of_epd_records_without_results() {
+    let harness = Harness::new();
+    let script = search_script("info depth 1 score cp 42 pv e2e4");
+    let epd = |fen: &str| {
+        let fields: Vec<&str> = fen.split(' ').collect();
+
+        format!("{} id \"test\";\n", fields[..4].join(" "))
+    };
+    let args = ["--append", "--input-format", "epd"];
+    let first = harness.score(&epd(STARTPOS), Some(&script), &args).unwrap();
+
+    assert_eq!(first, format!("{} - 42\n", STARTPOS));
+
+    // The output can be appended to, and read back, with its own layout.
+    std::fs::remove_file(harness.path("output.txt.manifest.json")).unwrap();
+
+    let args = ["--append", "--input-format", "epd", "--no-manifest"];
+    let output = harness.score(&epd(KIWIPETE), Some(&script), &args).unwrap();
+
+    assert_eq!(output, format!("{} - 42\n{} - 42\n", STARTPOS, KIWIPETE));
+
+    let previous = harness.write("previous.txt", &output);
+    let args = ["--input-format", "epd", "--reuse-scores", &previous];
+    let script = search_script("info depth 1 score cp 7 pv e2e4");
+
+    assert_eq!(
+        harness.score(&epd(KIWIPETE), Some(&script), &args),
+        Some(format!("{} - 42\n", KIWIPETE))
+    );
+}
+
+#[test]
+fn pins_the_engine_build() {
+    let harness = Harness::new();
+    let input = format!("{} 0.5\n", STARTPOS);
+    let hash = sha256_file(std::path::Path::new(MOCK_ENGINE)).unwrap();
+
+    assert!(harness
+        .score(&input, None, &["--engine-sha256", &hash.to_uppercase()])
+        .is_some());
+    assert_eq!(
+        harness.score(&input, None, &["--engine-sha256", &"0".repeat(64)]),
+        None
+    );
+
+    // Appending scores from another build of the engine is refused.
+    let other_build = harness.path_str("other_engine");
+
+    fs::copy(MOCK_ENGINE, &other_build).unwrap();
+    fs::OpenOptions::new()
+        .append(true)
+        .open(&other_build)
+        .unwrap()
+        .write_all(b"rebuilt")
+        .unwrap();
+
+    let input = harness.path_str("input.txt");
+    let output = harness.path_str("output.txt");
+    let run = |engine: &str| {
+        let args = [
+            "-e", engine, "-i", &input, "-o", &output, "-d", "1", "--append",
+        ];
+
+        harness.run(&args, None).status.success()
+    };
+
+    assert!(run(MOCK_ENGINE));
+    assert!(run(MOCK_ENGINE));
+    assert!(!run(&other_build));
+    assert_eq!(harness.read("output.txt").unwrap().lines().count(), 3);
+}
+
+#[test]
+fn tags_the_source_of_scores() {
+    let harness = Harness::new();
+    let script = search_script("info depth 1 score cp 42 pv e2e4");
+    let old_path = harness.write("old.txt", &format!("{} 0.5 7\n", KIWIPETE));
+    let input = format!("{} 0.5 game1\n{} 1 game2\n", STARTPOS, KIWIPETE);
+    let args = [
+        "--tag-engine",
+        "--reuse-scores",
+        &old_path,
+        "--input-columns",
+        "fen,wdl,extra",
+        "--deterministic",
+    ];
+    let output = harness.score(&input, Some(&script), &args).unwrap();
+    let tags: Vec<Vec<&str>> = output
+        .lines()
+        .map(|line| line.split(' ').collect())
+        .collect();
+
+    // Tags come after the score, and before the extra columns.
+    assert_eq!(tags[0][6..8], ["0.5", "42"]);
+    assert_eq!(tags[0][9], "game1");
+    assert!(tags[0][8].ends_with(&format!(":{}:1", MOCK_ENGINE)));
+    assert_eq!(tags[1][7], "7");
+    assert!(tags[1][8].ends_with(&format!(":{}:reused", MOCK_ENGINE)));
+    assert_eq!(tags[0][8].split(':').next(), tags[1][8].split(':').next());
+}
+
+#[test]
+fn audits_sampled_positions() {
+    let harness = Harness::new();
+    let input = harness.write("input.txt", &format!("{} 0.5\n{} 1\n", STARTPOS, KIWIPETE));
+    let output = harness.path_str("output.txt");
+    // With a single engine, the positions are audited once the input is
+    // exhausted.
+    let script = [10, 20, 40, 40]
+        .map(|cp| {
+            format!(
+                "[go]\ninfo depth 1 score cp {} pv e2e4\nbestmove e2e4\n",
+                cp
+            )
+        })
+        .concat();
+    let run = |rate: &str| {
+        let args = [
+            "-e",
+            MOCK_ENGINE,
+            "-i",
+            &input,
+            "-o",
+            &output,
+            "-d",
+            "1",
+            "--audit-rate",
+            rate,
+        ];
+
+        harness.run(&args, Some(&script))
+    };
+    let result = run("1");
+
+    assert!(result.status.success());
+    assert_eq!(
+        harness.read("output.txt").unwrap(),
+        format!("{} 0.5 10\n{} 1 20\n", STARTPOS, KIWIPETE)
+    );
+    assert!(String::from_utf8(result.stdout).unwrap().contains(
+        "Audit: 2 positions audited, 0 with identical scores, score differences: \
+         median 20, 90th percentile 20, 99th percentile 20, max 30"
+    ));
+    assert!(String::from_utf8(run("0").stdout)
+        .unwrap()
+        .contains("Audit: 0 positions audited"));
+    assert!(!run("1.5").status.success());
+}
+
+#[test]
+fn compares_with_a_reference() {
+    let harness = Harness::new();
+    let reference = harness.write(
+        "reference.txt",
+        &format!("{} 0.5 40\n{} 1 -300\n", STARTPOS, KIWIPETE),
+    );
+    let input = harness.write(
+        "input.txt",
+        &format!(
+            "{} 0.5\n{} 1\n8/8/8/8/8/8/8/K6k w - - 0 1 0.5\n",
+            STARTPOS, KIWIPETE
+        ),
+    );
+    let output = harness.path_str("output.txt");
+    let script = search_script("info depth 1 score cp 7 pv e2e4");
+    let args = [
+        "-e",
+        MOCK_ENGINE,
+        "-i",
+        &input,
+        "-o",
+        &output,
+        "-d",
+        "1",
+        "--reference",
+        &reference,
+    ];
+    let result = harness.run(&args, Some(&script));
+
+    assert!(result.status.success());
+    // Only the second position moved by more than 50 centipawns, and the
+    // last one is missing from the reference.
+    assert_eq!(
+        harness.read("output.txt.disagreements").unwrap(),
+        format!("{} -300 7 +307\n", KIWIPETE)
+    );
+    assert!(String::from_utf8(result.stdout).unwrap().contains(
+        "2 positions compared, 1 disagreements, score differences: mean 170.0, max 307, \
+         1 positions missing from the reference"
+    ));
+    assert_eq!(
+        harness.score("", None, &["--disagreements-file", "x"]),
+        None
+    );
+}
+
+#[test]
+fn checks_principal_variations() {
+    let harness = Harness::new();
+    let script = "[go]\ninfo depth 2 score cp 12 pv e2e4 e7e5 g1f3\nbestmove e2e4\n\
+                  [go]\ninfo depth 2 score cp 30 pv e2e4 e2e4\nbestmove e2e4\n";
+    let input = format!("{} 0.5\n{} 1\n", STARTPOS, STARTPOS);
+    let args = ["--with-pv", "--deterministic"];
+
+    assert_eq!(
+        harness.score(&input, Some(script), &args).unwrap(),
+        format!("{} 0.5 12 e2e4,e7e5,g1f3\n", STARTPOS)
+    );
+}
+
+#[test]
+fn writes_node_counts() {
+    let harness = Harness::new();
+    let script = "[go]\ninfo depth 1 nodes 1500 score cp 12 pv e2e4\nbestmove e2e4\n\
+                  [go]\ninfo depth 1 score cp 30 pv a2a3\nbestmove a2a3\n";
+    let input = format!("{} 0.5 game1\n{} 1 game2\n", STARTPOS, KIWIPETE);
+    let args = [
+        "--with-nodes",
+        "--with-pv",
+        "--input-columns",
+        "fen,wdl,extra",
+        "--deterministic",
+    ];
+
+    // The second search does not report its node count.
+    assert_eq!(
+        harness.score(&input, Some(script), &args).unwrap(),
+        format!(
+            "{} 0.5 12 e2e4 1500 game1\n{} 1 30 a2a3 - game2\n",
+            STARTPOS, KIWIPETE
+        )
+    );
+}
+
+#[test]
+fn reuses_previous_scores() {
+    let harness = Harness::new();
+    let old_script = search_script("info depth 1 score cp 42 pv e2e4");
+    let old_output = harness
+        .score(&format!("{} 0.5\n", STARTPOS), Some(&old_script), &[])
+        .unwrap();
+    let old_path = harness.write("old.txt", &old_output);
+
+    // Only the new position is searched, while the results come from the new
+    // input.
+    let script = search_script("info depth 1 score cp 7 pv e2e4");
+    let input = format!("{} 1\n{} 0\n", STARTPOS, KIWIPETE);
+    let args = ["--reuse-scores", &old_path, "--deterministic"];
+    let output = harness.score(&input, Some(&script), &args).unwrap();
+
+    assert_eq!(output, format!("{} 1 42\n{} 0 7\n", STARTPOS, KIWIPETE));
+
+    let args = ["--reuse-scores", &old_path, "--multipv", "2"];
+
+    assert_eq!(harness.score(&input, Some(&script), &args), None);
+}
+
+#[test]
+fn caches_scores_across_runs() {
+    let harness = Harness::new();
+    let cache = harness.path_str("cache.txt");
+    let script = "[go]\ninfo depth 1 score cp 42 pv e2e4\nbestmove e2e4\n\
+        [go]\ninfo depth 1 score cp 7 pv e2e4\nbestmove e2e4\n";
+    let input = format!("{} 0.5\n{} 1\n", STARTPOS, STARTPOS);
+    let args = ["--score-cache", &cache, "--deterministic"];
+
+    // Duplicate positions are only searched once.
+    let output = harness.score(&input, Some(script), &args).unwrap();
+
+    assert_eq!(output, format!("{} 0.5 42\n{} 1 42\n", STARTPOS, STARTPOS));
+
+    let input = format!("{} 0\n{} 0\n", KIWIPETE, STARTPOS);
+    let output = harness.score(&input, Some(script), &args).unwrap();
+
+    assert_eq!(output, format!("{} 0 42\n{} 0 42\n", KIWIPETE, STARTPOS));
+    assert_eq!(harness.read("cache.txt").unwrap().lines().count(), 2);
+
+    // Other search limits do not share the cached scores.
+    let args = ["--score-cache", &cache, "--deterministic", "-n", "1000"];
+    let output = harness
+        .score(&format!("{} 0\n", STARTPOS), Some(script), &args)
+        .unwrap();
+
+    assert_eq!(output, format!("{} 0 42\n", STARTPOS));
+    assert_eq!(harness.read("cache.txt").unwrap().lines().count(), 3);
+}
+
+#[test]
+fn reports_progress_over_the_whole_input() {
+    let harness = Harness::new();
+    // Large enough for the input to be read in several chunks.
+    let input = harness.write("input.txt", &format!("{} 0.5\n", STARTPOS).repeat(2000));
+    let output = harness.path_str("output.txt");
+    let args = [
+        "-e",
+        MOCK_ENGINE,
+        "-i",
+        &input,
+        "-o",
+        &output,
+        "-d",
+        "1",
+        "--report-every",
+        "100",
+    ];
+    let result = harness.run(&args, None);
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    let reports: Vec<&str> = stdout
+        .split('\r')
+        .filter(|report| report.contains("queries done"))
+        .collect();
+
+    assert!(result.status.success());
+    assert_eq!(reports.len(), 20);
+    assert!(reports[0].starts_with("100/"));
+    assert!(reports[0].contains("(5.0% of the input)"), "{}", reports[0]);
+    assert!(reports[19].starts_with("2000/2000 queries done (100.0% of the input)"));
+}
+
+#[test]
+fn batches_engine_options() {
+    let harness = Harness::new();
+    let input = format!("{} 0.5\n", STARTPOS);
+    let handshake = |harness: &Harness| -> Vec<String> {
+        let log = harness.read("engine.log").unwrap();
+
+        log.lines()
+            .take_while(|line| *line != "ucinewgame")
+            .map(String::from)
+            .collect()
+    };
+
+    // Values are sent as declared by the engine, then waited for at once.
+    let args = ["-c", "hash=64", "-c", "Ponder=on"];
+
+    assert!(harness.score(&input, None, &args).is_some());
+    assert_eq!(
+        handshake(&harness),
+        [
+            "uci",
+            "setoption name Hash value 64",
+            "setoption name Ponder value true",
+            "isready"
+        ]
+    );
+
+    let harness = Harness::new();
+    let args = ["-c", "Hash=64", "-c", "Ponder=on", "--sync-option", "Hash"];
+
+    assert!(harness.score(&input, None, &args).is_some());
+    assert_eq!(
+        handshake(&harness),
+        [
+            "uci",
+            "setoption name Hash value 64",
+            "isready",
+            "setoption name Ponder value true",
+            "isready"
+        ]
+    );
+
+    // Invalid values are rejected before starting.
+    let harness = Harness::new();
+
+    assert_eq!(harness.score(&input, None, &["-c", "Hash=big"]), None);
+}
+
+#[test]
+fn sets_canonical_options() {
+    let harness = Harness::new();
+    let script = "[uci]\n\
+                  option name MultiPV type spin default 1 min 1 max 256\n\
+                  option name Contempt type spin default 20 min -100 max 100\n\
+                  option name Skill Level type spin default 10 min 0 max 20\n\
+                  uciok\n\
+                  [go]\ninfo depth 1 score cp 3 pv e2e4\nbestmove e2e4\n";
+    let input = format!("{} 0.5\n", STARTPOS);
+    let options = |args: &[&str]| {
+        let output = harness.score(&input, Some(script), args);
+
+        assert_eq!(output.unwrap(), format!("{} 0.5 3\n", STARTPOS));
+
+        let options: Vec<String> = harness
+            .read("engine.log")
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.strip_prefix("setoption name "))
+            .map(String::from)
+            .collect();
+
+        fs::remove_file(harness.path("engine.log")).unwrap();
+        options
+    };
+
+    // Options already at their neutral value are not sent.
+    assert_eq!(options(&[]), ["Skill Level value 20", "Contempt value 0"]);
+    assert_eq!(
+        options(&["-c", "contempt=5"]),
+        ["Skill Level value 20", "Contempt value 5"]
+    );
+    assert!(options(&["--no-canonical-options"]).is_empty());
+
+    let output = harness.run(
+        &[
+            "-e",
+            MOCK_ENGINE,
+            "-i",
+            &harness.path_str("input.txt"),
+            "-o",
+            &harness.path_str("output.txt"),
+            "-d",
+            "1",
+            "-c",
+            "Contempt=5",
+        ],
+        Some(script),
+    );
+
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("'Contempt=5' overrides the neutral value"));
+
+    // The maximal skill level is the neutral one.
+    let output = harness.run(
+        &[
+            "-e",
+            MOCK_ENGINE,
+            "-i",
+            &harness.path_str("input.txt"),
+            "-o",
+            &harness.path_str("output.txt"),
+            "-d",
+            "1",
+            "-c",
+            "Skill Level=20",
+            "-c",
+            "Contempt=0",
+        ],
+        Some(script),
+    );
+
+    assert!(output.status.success());
+    assert!(!String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("overrides the neutral value"));
+}
+
+#[test]
+fn aborts_on_startup_errors() {
+    let harness = Harness::new();
+    let input = format!("{} 0.5\n", STARTPOS);
+    let args = [
+        "-e",
+        MOCK_ENGINE,
+        "-i",
+        &harness.write("input.txt", &input),
+        "-o",
+        &harness.path_str("output.txt"),
+        "-d",
+        "1",
+    ];
+    let stderr = |script: &str, extra: &[&str]| {
+        let output = harness.run(&[&args[..], extra].concat(), Some(script));
+
+        assert!(!output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    assert!(stderr(
+        "[isready]\ninfo string ERROR: network file not loaded\nreadyok\n",
+        &[]
+    )
+    .contains("the engine reported an error: 'info string ERROR: network file not loaded'"));
+    assert!(stderr(
+        "[isready]\ninfo string NNUE evaluation disabled\nreadyok\n",
+        &["--startup-error", "NNUE .* disabled"]
+    )
+    .contains("'info string NNUE evaluation disabled'"));
+
+    // Other messages are harmless.
+    let script = "[isready]\ninfo string NNUE evaluation enabled\nreadyok\n";
+
+    assert_eq!(
+        harness.score(
+            &input,
+            Some(script),
+            &["--startup-error", "NNUE .* disabled"]
+        ),
+        Some(format!("{} 0.5 0\n", STARTPOS))
+    );
+    assert!(!harness
+        .run(&[&args[..], &["--startup-error", "("]].concat(), None)
+        .status
+        .success());
+}
+
+#[test]
+fn smoke_tests_the_first_positions() {
+    let harness = Harness::new();
+    let input = harness.write(
+        "input.txt",
+        &format!(
+            "{} 0.5\ninvalid\n{} 1\n{} 0\n",
+            STARTPOS, KIWIPETE, STARTPOS
+        ),
+    );
+    let output = harness.run(
+        &["-e", MOCK_ENGINE, "-i", &input, "-d", "1", "--smoke", "2"],
+        Some(&search_script(
+            "info depth 7 seldepth 9 nodes 1234 score cp 15 pv e2e4",
+        )),
+    );
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "{} 0.5\n    score 15 depth 7 nodes 1234 bestmove e2e4\n\
+             Invalid line 'invalid': not enough columns in line 'invalid'\n\
+             {} 1\n    score 15 depth 7 nodes 1234 bestmove e2e4\n\
+             2 positions scored\n",
+            STARTPOS, KIWIPETE
+        )
+    );
+    assert!(!harness.path("output.txt").exists());
+}
+
+#[test]
+fn slows_down_above_the_memory_limit() {
+    let harness = Harness::new();
+    let input: String = (0..2000).map(|_| format!("{} 0.5\n", STARTPOS)).collect();
+    let output = harness.run(
+        &[
+            "-e",
+            MOCK_ENGINE,
+            "-i",
+            &harness.write("input.txt", &input),
+            "-o",
+            &harness.path_str("output.txt"),
+            "-d",
+            "1",
+            "-t",
+            "2",
+            "--max-rss-gb",
+            "0.001",
+        ],
+        None,
+    );
+
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("more than --max-rss-gb"));
+    assert_eq!(harness.read("output.txt").unwrap().lines().count(), 2000);
+}
+
+#[test]
+fn drops_duplicate_positions() {
+    let harness = Harness::new();
+    let startpos = STARTPOS.replace(" 0 1", " 3 7");
+    let input = format!(
+        "{} 0.5\n{} 1\n{} 0\n{} 0.5\n",
+        STARTPOS, KIWIPETE, startpos, KIWIPETE
+    );
+    let dedup_file = harness.path_str("seen.bin");
+    let args = ["--dedup-output", "--dedup-file", &dedup_file];
+
+    assert_eq!(
+        harness.score(&input, None, &args[..1]),
+        Some(format!("{} 0.5 0\n{} 1 0\n", STARTPOS, KIWIPETE))
+    );
+    // Positions written by previous runs are dropped too.
+    assert_eq!(
+        harness.score(&format!("{} 0.5\n", KIWIPETE), None, &args),
+        Some(format!("{} 0.5 0\n", KIWIPETE))
+    );
+    assert_eq!(
+        harness.score(&input, None, &args),
+        Some(format!("{} 0.5 0\n", STARTPOS))
+    );
+    assert_eq!(harness.score(&input, None, &args), Some(String::new()));
+}
+
+#[test]
+fn starts_the_engine_from_its_directory() {
+    let harness = Harness::new();
+    let input = harness.write("input.txt", &format!("{} 0.5\n", STARTPOS));
+    let output = harness.path_str("output.txt");
+    let engine_dir = harness.path_str("engine");
+
+    fs::create_dir(&engine_dir).unwrap();
+
+    let wrapper = harness.write(
+        "engine/engine.sh",
+        &format!(
+            "#!/bin/sh\necho \"$PWD $*\" > ../launch.txt\nexec {}\n",
+            MOCK_ENGINE
+        ),
+    );
+
+    fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let args = [
+        "-e",
+        "./engine.sh",
+        "--engine-cwd",
+        &engine_dir,
+        "--engine-arg",
+        "--uci",
+        "--engine-arg",
+        "net.bin",
+        "-i",
+        &input,
+        "-o",
+        &output,
+        "-d",
+        "1",
+    ];
+
+    assert!(harness.run(&args, None).status.success());
+    assert_eq!(
+        harness.read("launch.txt").unwrap(),
+        format!("{} --uci net.bin\n", engine_dir)
+    );
+
+    // The engine binary is looked up in its directory for the manifest.
+    assert!(harness.read("output.txt.manifest.json").is_some());
+}
+
+#[test]
+fn scores_with_registered_engines() {
+    let harness = Harness::new();
+    let registry = harness.write(
+        "engines.toml",
+        &format!(
+            "[mock]\ncmd = \"{}\"\n\n[mock.options]\nHash = 64\n",
+            MOCK_ENGINE
+        ),
+    );
+    let input = harness.write("input.txt", &format!("{} 0.5\n", STARTPOS));
+    let output = harness.path_str("output.txt");
+    let args = [
+        "--engine-registry",
+        &registry,
+        "-e",
+        "mock",
+        "-c",
+        "Ponder=true",
+        "-i",
+        &input,
+        "-o",
+        &output,
+        "-d",
+        "1",
+    ];
+
+    assert!(harness.run(&args, None).status.success());
+
+    let log = harness.read("engine.log").unwrap();
+
+    assert!(log.contains("setoption name Hash value 64\nsetoption name Ponder value true\n"));
+
+    // An explicit registry must exist.
+    let missing = harness.path_str("missing.toml");
+    let args = [
+        "--engine-registry",
+        &missing,
+        "-e",
+        "mock",
+        "-i",
+        &input,
+        "-o",
+        &output,
+    ];
+
+    assert!(!harness.run(&args, None).status.success());
+}
+
+#[test]
+fn isolates_the_engine_environment() {
+    let harness = Harness::new();
+    let input = harness.write("input.txt", &format!("{} 0.5\n", STARTPOS));
+    let output = harness.path_str("output.txt");
+    let env_file = harness.path_str("env.txt");
+    let wrapper = harness.write(
+        "engine.sh",
+        &format!("#!/bin/sh\nenv > {}\nexec {}\n", env_file, MOCK_ENGINE),
+    );
+
+    fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let run = |extra_args: &[&str]| {
+        let mut args = vec!["-e", &wrapper, "-i", &input, "-o", &output, "-d", "1"];
+
+        args.extend_from_slice(extra_args);
+        assert!(harness.run(&args, None).status.success());
+        harness.read("env.txt").unwrap()
+    };
+
+    let env = run(&["--engine-env", "OMP_NUM_THREADS=1"]);
+
+    assert!(env.lines().any(|line| line == "OMP_NUM_THREADS=1"));
+    assert!(env.lines().any(|line| line.starts_with("MOCK_ENGINE_LOG=")));
+
+    let env = run(&["--clear-engine-env", "--engine-env", "LC_ALL=C"]);
+
+    assert!(env.lines().any(|line| line == "LC_ALL=C"));
+    assert!(!env.lines().any(|line| line.starts_with("MOCK_ENGINE_LOG=")));
+
+    // Variables must have a name.
+    let args = [
+        "-e",
+        &wrapper,
+        "-i",
+        &input,
+        "-o",
+        &output,
+        "--engine-env",
+        "=1",
+    ];
+
+    assert!(!harness.run(&args, None).status.success());
+}
+
+#[test]
+fn echoes_uci_traffic() {
+    let harness = Harness::new();
+    let input = harness.write("input.txt", &format!("{} 0.5\n", STARTPOS));
+    let output = harness.path_str("output.txt");
+    let result = harness.run(
+        &[
+            "-e",
+            MOCK_ENGINE,
+            "-i",
+            &input,
+            "-o",
+            &output,
+            "-d",
+            "1",
+            "--debug-uci",
+        ],
+        None,
+    );
+    let stderr = String::from_utf8(result.stderr).unwrap();
+
+    assert!(result.status.success());
+    assert!(stderr.lines().any(|line| line.ends_with("] #1 > uci")));
+    assert!(stderr.lines().any(|line| line.ends_with("] #1 < uciok")));
+    assert!(stderr.contains(&format!("#1 > position fen {}\n", STARTPOS)));
+}
+
+#[test]
+fn toggles_uci_traffic_echo_on_signal() {
+    let harness = Harness::new();
+    let input = harness.write("input.txt", &format!("{} 0.5\n", STARTPOS).repeat(4));
+    let script = harness.write(
+        "script.txt",
+        "[go]\n!sleep 200\ninfo depth 1 score cp 0 pv e2e4\nbestmove e2e4\n",
+    );
+    let output = harness.path_str("output.txt");
+    let child = Command::new(TOOL)
+        .args(["-e", MOCK_ENGINE, "-i", &input, "-o", &output, "-d", "1"])
+        .env("MOCK_ENGINE_SCRIPT", script)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(300));
+
+    let status = Command::new("kill")
+        .args(["-USR1", &child.id().to_string()])
+        .status()
+        .unwrap();
+    let result = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(result.stderr).unwrap();
+
+    assert!(status.success());
+    assert!(result.status.success());
+    assert!(!stderr.contains("> uci\n"));
+    assert!(stderr.contains("> go depth 1\n"));
+}
+
+#[test]
+fn keeps_scored_positions_on_interrupt() {
+    let harness = Harness::new();
+    let input = harness.write("input.txt", &format!("{} 0.5\n", STARTPOS).repeat(3));
+    let script = harness.write(
+        "script.txt",
+        "[go]\ninfo depth 1 score cp 7 pv e2e4\nbestmove e2e4\n\
+         [go]\n!sleep 1500\ninfo depth 1 score cp 0 pv e2e4\nbestmove e2e4\n",
+    );
+    let output = harness.path_str("output.txt");
+    let start = Instant::now();
+    let child = Command::new(TOOL)
+        .args(["-e", MOCK_ENGINE, "-i", &input, "-o", &output, "-d", "1"])
+        .env("MOCK_ENGINE_SCRIPT", script)
+        .env("MOCK_ENGINE_LOG", harness.path("engine.log"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(500));
+
+    let status = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    let result = child.wait_with_output().unwrap();
+
+    assert!(status.success());
+    assert!(!result.status.success());
+    // The remaining position is not searched.
+    assert!(start.elapsed() < Duration::from_millis(2500));
+    // The scored positions are kept under the temporary name only.
+    assert!(harness.read("output.txt").is_none());
+    assert_eq!(
+        harness.read("output.txt.tmp").unwrap(),
+        format!("{} 0.5 7\n", STARTPOS)
+    );
+    // The engine may outlive the tool for a moment, before it reads the
+    // 'stop' command.
+    let stopped = || {
+        harness
+            .read("engine.log")
+            .is_some_and(|log| log.lines().any(|line| line == "stop"))
+    };
+    let deadline = Instant::now() + Duration::from_secs(2);
+
+    while !stopped() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(stopped());
+    assert!(harness.read("output.txt.manifest.json").is_none());
+}
+
+#[test]
+fn leaves_previous_outputs_untouched_on_interrupt() {
+    let harness = Harness::new();
+    let input = harness.write("input.txt", &format!("{} 0.5\n", STARTPOS).repeat(3));
+    let script = harness.write(
+        "script.txt",
+        "[go]\ninfo depth 1 score cp 7 pv e2e4\nbestmove e2e4\n\
+         [go]\n!sleep 1500\ninfo depth 1 score cp 0 pv e2e4\nbestmove e2e4\n",
+    );
+    let previous = format!("{} 0.5 3\n", KIWIPETE);
+    let output = harness.write("output.txt", &previous);
+    let child = Command::new(TOOL)
+        .args(["-e", MOCK_ENGINE, "-i", &input, "-o", &output, "-d", "1"])
+        .env("MOCK_ENGINE_SCRIPT", script)
+        .env("MOCK_ENGINE_LOG", harness.path("engine.log"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(500));
+
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+
+    let result = child.wait_with_output().unwrap();
+
+    assert!(!result.status.success());
+    // The output of the previous run is only replaced by a complete one.
+    assert_eq!(harness.read("output.txt").unwrap(), previous);
+    assert_eq!(
+        harness.read("output.txt.tmp").unwrap(),
+        format!("{} 0.5 7\n", STARTPOS)
+    );
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn compresses_the_output() {
+    let harness = Harness::new();
+    let input = format!("{}\n{}\n", STARTPOS, KIWIPETE);
+    let script = search_script("info depth 1 score cp 12 pv e2e4");
+    let expected = harness.score(&input, Some(&script), &[]).unwrap();
+
+    harness.score(&input, Some(&script), &["--compress-threads", "2"]);
+
+    let compressed = std::fs::read(harness.path("output.txt")).unwrap();
+
+    assert_eq!(
+        zstd::decode_all(compressed.as_slice()).unwrap(),
+        expected.into_bytes()
+    );
+}
+
+#[cfg(not(feature = "zstd"))]
+#[test]
+fn requires_the_zstd_feature_to_compress() {
+    let harness = Harness::new();
+    let script = search_script("info depth 1 score cp 12 pv e2e4");
+
+    assert!(harness
+        .score(STARTPOS, Some(&script), &["--compress-threads", "2"])
+        .is_none());
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn reads_compressed_input() {
+    let harness = Harness::new();
+    let mut rng = stash_scoring::rng::Rng::new(3);
+    let mut plain = String::new();
+    let mut expected = String::new();
+
+    // Random extra columns keep the input large once compressed, so that it
+    // is read in several blocks.
+    for _ in 0..2000 {
+        let extra: String = (0..16)
+            .map(|_| format!("{:016x}", rng.next_u64()))
+            .collect();
+
+        plain.push_str(&format!("{} 0.5 {}\n", STARTPOS, extra));
+        expected.push_str(&format!("{} 0.5 12 {}\n", STARTPOS, extra));
+    }
+
+    let input = harness.path_str("input.txt.zst");
+    let output = harness.path_str("output.txt");
+
+    fs::write(&input, zstd::encode_all(plain.as_bytes(), 3).unwrap()).unwrap();
+
+    let args = [
+        "-e",
+        MOCK_ENGINE,
+        "-i",
+        &input,
+        "-o",
+        &output,
+        "-d",
+        "1",
+        "--input-columns",
+        "fen,wdl,extra",
+        "--report-every",
+        "1000",
+    ];
+    let result = harness.run(
+        &args,
+        Some(&search_script("info depth 1 score cp 12 pv e2e4")),
+    );
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    let reports: Vec<&str> = stdout
+        .split('\r')
+        .filter(|report| report.contains("queries done"))
+        .collect();
+
+    assert!(result.status.success());
+    assert_eq!(harness.read("output.txt").unwrap(), expected);
+
+    // Progress is measured in compressed bytes.
+    assert_eq!(reports.len(), 2);
+    assert!(
+        !reports[0].contains("(100.0% of the input)"),
+        "{}",
+        reports[0]
+    );
+    assert!(
+        reports[1].contains("(100.0% of the input)"),
+        "{}",
+        reports[1]
+    );
+}
+
+#[cfg(not(feature = "zstd"))]
+#[test]
+fn requires_the_zstd_feature_to_decompress() {
+    let harness = Harness::new();
+    let input = harness.write("input.txt.zst", &format!("{} 0.5\n", STARTPOS));
+    let output = harness.path_str("output.txt");
+    let args = ["-e", MOCK_ENGINE, "-i", &input, "-o", &output, "-d", "1"];
+
+    assert!(!harness.run(&args, None).status.success());
+}
+
+#[test]
+fn scores_watched_chunks() {
+    let harness = Harness::new();
+    let (games, positions, shards) = (
+        harness.path_str("games"),
+        harness.path_str("positions"),
+        harness.path_str("shards"),
+    );
+    let engine = format!("cmd={}", MOCK_ENGINE);
+    let args = [
+        "match",
+        "-n",
+        "1",
+        "--rounds",
+        "2",
+        "--engine",
+        &engine,
+        "--engine",
+        &engine,
+        "--chunk-dir",
+        &games,
+        "--chunk-games",
+        "3",
+    ];
+
+    assert!(harness.run(&args, Some("[go]\n!legal\n")).status.success());
+    assert!(harness.read("games/games-000001.pgn").is_some());
+    assert!(harness.read("games/games-000002.pgn").is_some());
+    assert!(harness.read("games/END").is_some());
+
+    // The chunks of a finished match are not overwritten by another one.
+    let games_1 = harness.read("games/games-000001.pgn");
+
+    assert!(!harness.run(&args, Some("[go]\n!legal\n")).status.success());
+    assert_eq!(harness.read("games/games-000001.pgn"), games_1);
+
+    // Each stage stops once the previous one is done.
+    let args = ["pgn-extract", "--watch", "-i", &games, "-o", &positions];
+
+    assert!(harness.run(&args, None).status.success());
+    assert!(harness.read("positions/END").is_some());
+
+    let script = search_script("info depth 1 score cp 12 pv e2e4");
+    let args = [
+        "-e",
+        MOCK_ENGINE,
+        "-d",
+        "1",
+        "--watch",
+        "-i",
+        &positions,
+        "-o",
+        &shards,
+    ];
+
+    assert!(harness.run(&args, Some(&script)).status.success());
+    assert!(harness.read("shards/END").is_some());
+
+    for chunk in ["games-000001.txt", "games-000002.txt"] {
+        let positions = harness.read(&format!("positions/{}", chunk)).unwrap();
+        let shard = harness.read(&format!("shards/{}", chunk)).unwrap();
+
+        assert!(!positions.is_empty());
+        assert_eq!(shard.lines().count(), positions.lines().count());
+        assert!(shard.lines().all(|line| line.ends_with(" 12")));
+    }
+
+    // Shards already written are not scored again, and the end marker is
+    // written again once the others are.
+    harness.write("shards/games-000001.txt", "");
+    std::fs::remove_file(harness.path("shards/games-000002.txt")).unwrap();
+    assert!(harness.run(&args, Some(&script)).status.success());
+    assert_eq!(harness.read("shards/games-000001.txt").unwrap(), "");
+    assert!(harness.read("shards/games-000002.txt").is_some());
+    assert!(harness.read("shards/END").is_some());
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+
+use serde_json::{json, Value};
+
+use common::*;
+
+/// A running scoring service, killed when dropped.
+struct Server {
+    child: Child,
+    address: String,
+}
+
+impl Server {
+    fn start(harness: &Harness, script: &str) -> Self {
+        let mut child = Command::new(TOOL)
+            .args(["serve-http", "-e", MOCK_ENGINE, "-d", "1"])
+            .args(["--listen", "127.0.0.1:0", "--max-positions", "2"])
+            .env("MOCK_ENGINE_SCRIPT", harness.write("script.txt", script))
+            .env("MOCK_ENGINE_LOG", harness.path("engine.log"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+
+        BufReader::new(child.stdout.as_mut().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+
+        let address = line
+            .trim()
+            .strip_prefix("Listening on http://")
+            .unwrap()
+            .to_string();
+
+        Self { child, address }
+    }
+
+    /// Sends a request, returning the status and the body of the reply.
+    fn request(&self, method: &str, path: &str, body: &str) -> (u16, Value) {
+        self.send(&format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        ))
+    }
+
+    /// Sends a raw request, returning the status and the body of the reply.
+    fn send(&self, request: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(&self.address).unwrap();
+        let mut reply = String::new();
+
+        stream.write_all(request.as_bytes()).unwrap();
+        stream.read_to_string(&mut reply).unwrap();
+
+        let (head, body) = reply.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+
+        (status, serde_json::from_str(body).unwrap())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn scores_positions_over_http() {
+    let harness = Harness::new();
+    let server = Server::start(
+        &harness,
+        "[go]\ninfo depth 1 score cp 35 pv e2e4\nbestmove e2e4\n\
+         [go]\ninfo depth 1 score mate 2 pv d1h5\nbestmove d1h5\n",
+    );
+    let body = json!({ "fens": [STARTPOS, KIWIPETE], "nodes": 500 }).to_string();
+
+    assert_eq!(
+        server.request("POST", "/score", &body),
+        (
+            200,
+            json!({ "scores": [
+                { "fen": STARTPOS, "score": "35", "bestmove": "e2e4" },
+                { "fen": KIWIPETE, "score": "#2", "bestmove": "d1h5" },
+            ] })
+        )
+    );
+    assert!(harness
+        .read("engine.log")
+        .unwrap()
+        .lines()
+        .any(|line| line == "go nodes 500"));
+    assert_eq!(
+        server.request("GET", "/health", ""),
+        (200, json!({ "status": "ok" }))
+    );
+}
+
+#[test]
+fn rejects_invalid_requests() {
+    let harness = Harness::new();
+    let server = Server::start(&harness, &search_script("info depth 1 score cp 0"));
+    let status = |method, path, body: &str| server.request(method, path, body).0;
+
+    assert_eq!(status("POST", "/score", "{\"fens\": [\"8/8/8\"]}"), 400);
+    assert_eq!(status("POST", "/score", "[]"), 400);
+    assert_eq!(status("POST", "/score", "{\"fens\": [1]}"), 400);
+    assert_eq!(
+        status(
+            "POST",
+            "/score",
+            &json!({ "fens": [STARTPOS], "depth": 0 }).to_string()
+        ),
+        400
+    );
+    assert_eq!(
+        status(
+            "POST",
+            "/score",
+            &json!({ "fens": [STARTPOS, STARTPOS, STARTPOS] }).to_string()
+        ),
+        413
+    );
+    assert_eq!(status("GET", "/score", ""), 405);
+    assert_eq!(status("GET", "/unknown", ""), 404);
+
+    let (status, body) =
+        server.request("POST", "/score", &json!({ "fens": [STARTPOS] }).to_string());
+
+    assert_eq!(status, 200);
+    assert_eq!(body["scores"][0]["score"], "0");
+}
+
+#[test]
+fn rejects_oversized_headers() {
+    let harness = Harness::new();
+    let server = Server::start(&harness, &search_script("info depth 1 score cp 0"));
+    let padding = "a".repeat(10_000);
+
+    assert_eq!(
+        server.send(&format!("GET /{} HTTP/1.1\r\n\r\n", padding)).0,
+        431
+    );
+    assert_eq!(
+        server
+            .send(&format!(
+                "GET /health HTTP/1.1\r\nX-Padding: {}\r\n\r\n",
+                padding
+            ))
+            .0,
+        431
+    );
+
+    // Idle connections do not keep the others from being served.
+    let _idle = TcpStream::connect(&server.address).unwrap();
+
+    assert_eq!(
+        server.request("GET", "/health", ""),
+        (200, json!({ "status": "ok" }))
+    );
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn scores_positions_at_each_limit() {
+    let harness = Harness::new();
+    let input = harness.write(
+        "input.txt",
+        &format!("{} 0.5 game1\ninvalid line\n", STARTPOS),
+    );
+    let output = harness.path_str("output.txt");
+    let script = [10, 20, 30]
+        .map(|cp| {
+            format!(
+                "[go]\ninfo depth 1 score cp {} pv e2e4\nbestmove e2e4\n",
+                cp
+            )
+        })
+        .concat();
+    let args = [
+        "sweep",
+        "-e",
+        MOCK_ENGINE,
+        "-i",
+        &input,
+        "--input-columns",
+        "fen,wdl,extra",
+        "-o",
+        &output,
+        "-d",
+        "4,8,12",
+    ];
+    let result = harness.run(&args, Some(&script));
+
+    assert!(result.status.success());
+    assert_eq!(
+        harness.read("output.txt").unwrap(),
+        format!("{} 0.5 game1 10 20 30\n", STARTPOS)
+    );
+
+    let searches: Vec<String> = harness
+        .read("engine.log")
+        .unwrap()
+        .lines()
+        .filter(|line| line.starts_with("go"))
+        .map(String::from)
+        .collect();
+
+    assert_eq!(searches, ["go depth 4", "go depth 8", "go depth 12"]);
+}
+
+#[test]
+fn scores_moves_at_each_limit() {
+    let harness = Harness::new();
+    let input = harness.write("input.txt", &format!("{} e2e4\n", STARTPOS));
+    let script = search_script("info depth 1 score cp 25 pv e7e5");
+    let args = [
+        "sweep",
+        "-e",
+        MOCK_ENGINE,
+        "-i",
+        &input,
+        "--input-columns",
+        "fen,move",
+        "-n",
+        "100,1000",
+    ];
+    let result = harness.run(&args, Some(&script));
+
+    // The move scores are given from the point of view of the side to move in
+    // the FEN.
+    assert!(result.status.success());
+    assert_eq!(
+        String::from_utf8(result.stdout).unwrap(),
+        format!("{} e2e4 -25 -25\n", STARTPOS)
+    );
+}
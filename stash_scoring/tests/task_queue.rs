@@ -0,0 +1,249 @@
+mod common;
+
+use stash_scoring::engine::EngineConfig;
+use stash_scoring::events::Event;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::thread;
+use std::time::Duration;
+
+use stash_scoring::task_queue::{Lane, TaskClient, TaskWorker, Workload};
+
+use common::*;
+
+fn mock_engine() -> EngineConfig {
+    EngineConfig {
+        name: String::from("mock"),
+        command: String::from(MOCK_ENGINE),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn serves_the_priority_lane_first() {
+    let mut client: TaskClient = TaskClient::new();
+
+    client.add_workload(Workload::from(String::from("a")));
+    client.add_workload(Workload::from(String::from("b")));
+    client.add_workload_to(Lane::Priority, Workload::from(String::from("c")));
+
+    let queue = client.queue_ref();
+    let order: Vec<String> = std::iter::from_fn(|| queue.query_workload())
+        .map(|workload| workload.to_string())
+        .collect();
+
+    assert_eq!(order, ["c", "a", "b"]);
+}
+
+#[test]
+fn retries_requeued_workloads_in_order() {
+    let mut client = TaskClient::ordered();
+    let mut worker = TaskWorker::new(client.queue_ref(), &mock_engine());
+
+    client.add_workloads(["a", "b", "c"].map(|line| Workload::from(String::from(line))));
+    client.stop_workload();
+
+    // The second workload fails once, and is retried before the third one.
+    let first = worker.query_workload().unwrap().unwrap();
+
+    worker.fill_response(&first, first.to_uppercase());
+
+    let second = worker.query_workload().unwrap().unwrap();
+
+    worker.requeue_workload(second);
+
+    let mut attempts = Vec::new();
+
+    while let Some(workload) = worker.query_workload().unwrap() {
+        attempts.push(workload.to_string());
+        worker.fill_response(&workload, workload.to_uppercase());
+    }
+
+    drop(worker);
+    assert_eq!(attempts, ["b", "c"]);
+
+    let responses: Vec<String> = std::iter::from_fn(|| client.query_response(true)).collect();
+
+    assert_eq!(responses, ["A", "B", "C"]);
+}
+
+#[test]
+fn carries_typed_payloads() {
+    let mut client: TaskClient<(u64, u64), u64> = TaskClient::ordered();
+    let handles: Vec<_> = (0..3)
+        .map(|_| {
+            let queue = client.queue_ref().clone();
+            let local = queue.add_worker();
+
+            thread::spawn(move || loop {
+                let finished = queue.is_workload_finished();
+
+                if let Some(task) = queue.query_workload_for(&local) {
+                    let (a, b) = *task;
+
+                    queue.add_response(task.index(), (b != 0).then(|| a / b));
+                } else if finished {
+                    queue.remove_worker();
+                    break;
+                }
+            })
+        })
+        .collect();
+
+    client.add_workloads((0..100).map(|i| (i * 10, i % 4)));
+    client.stop_workload();
+
+    let responses: Vec<u64> = std::iter::from_fn(|| client.query_response(true)).collect();
+    let expected: Vec<u64> = (0..100)
+        .filter(|i| i % 4 != 0)
+        .map(|i| i * 10 / (i % 4))
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(responses, expected);
+}
+
+#[test]
+fn cancels_queued_and_running_searches() {
+    let mut client: TaskClient = TaskClient::new();
+    let mut worker = TaskWorker::new(client.queue_ref(), &mock_engine());
+    let token = client.queue_ref().cancellation_token().clone();
+
+    client.add_workloads(["a", "b"].map(|line| Workload::from(String::from(line))));
+
+    let workload = worker.query_workload().unwrap().unwrap();
+
+    worker.engine_mut().write(b"go infinite\n").unwrap();
+
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        token.cancel();
+    });
+
+    // The infinite search only ends once the engine is told to stop.
+    while !worker
+        .engine_mut()
+        .read_line()
+        .unwrap()
+        .starts_with("bestmove")
+    {}
+
+    canceller.join().unwrap();
+    assert!(worker.is_cancelled());
+
+    // The response of the stopped search and the queued workload are dropped.
+    worker.fill_response(&workload, String::from("A"));
+    assert!(worker.query_workload().unwrap().is_none());
+    drop(worker);
+    assert_eq!(client.query_response(true), None);
+}
+
+#[test]
+fn reports_worker_events() {
+    let client: TaskClient = TaskClient::new();
+    let events = client.subscribe();
+    let mut engine = mock_engine();
+
+    engine.options.push(String::from("Contempt=10"));
+
+    let mut worker = TaskWorker::new(client.queue_ref(), &engine);
+    let first = worker.engine_mut().id();
+    let warning = |engine| Event::EngineWarning {
+        engine,
+        message: String::from("the engine did not declare the option 'Contempt'"),
+    };
+
+    assert_eq!(events.try_recv(), Ok(warning(first)));
+
+    worker.restart_engine("crashed").unwrap();
+
+    let second = worker.engine_mut().id();
+
+    assert_ne!(first, second);
+    assert_eq!(events.try_recv(), Ok(warning(second)));
+    assert_eq!(
+        events.try_recv(),
+        Ok(Event::WorkerRestarted {
+            previous: first,
+            engine: second,
+            reason: String::from("crashed"),
+        })
+    );
+    assert!(events.try_recv().is_err());
+}
+
+#[test]
+fn restarts_idle_engines_which_stop_answering() {
+    let harness = Harness::new();
+    let mut engine = mock_engine();
+    // The engine answers the startup 'isready', and dies on the next one.
+    let script = harness.write("script.txt", "[isready]\nreadyok\n[isready]\n!exit 1\n");
+
+    engine
+        .env
+        .push((String::from("MOCK_ENGINE_SCRIPT"), script));
+
+    let mut client: TaskClient = TaskClient::new();
+    let events = client.subscribe();
+    let mut worker = TaskWorker::new(client.queue_ref(), &engine);
+    let first = worker.engine_mut().id();
+
+    worker.set_heartbeat(Some(Duration::from_millis(50)));
+
+    let waiting = thread::spawn(move || {
+        worker
+            .query_workload()
+            .unwrap()
+            .map(|task| task.to_string())
+    });
+
+    thread::sleep(Duration::from_millis(300));
+    client.add_workload(Workload::from(String::from("a")));
+    client.stop_workload();
+
+    // The worker still gets the workload, with a new engine.
+    assert_eq!(waiting.join().unwrap().as_deref(), Some("a"));
+
+    match events.try_recv() {
+        Ok(Event::WorkerRestarted {
+            previous, reason, ..
+        }) => {
+            assert_eq!(previous, first);
+            assert!(reason.starts_with("the idle engine stopped answering"));
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn fails_when_idle_engines_cannot_be_restarted() {
+    let harness = Harness::new();
+    let script = harness.write("script.txt", "[isready]\nreadyok\n[isready]\n!exit 1\n");
+    let wrapper = harness.write(
+        "engine.sh",
+        &format!(
+            "#!/bin/sh\nMOCK_ENGINE_SCRIPT={} exec {}\n",
+            script, MOCK_ENGINE
+        ),
+    );
+
+    fs::set_permissions(&wrapper, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let engine = EngineConfig {
+        name: String::from("mock"),
+        command: wrapper.clone(),
+        ..Default::default()
+    };
+    let client: TaskClient = TaskClient::new();
+    let mut worker = TaskWorker::new(client.queue_ref(), &engine);
+
+    // The engine dies on the first heartbeat, and cannot be started again.
+    fs::remove_file(&wrapper).unwrap();
+    worker.set_heartbeat(Some(Duration::from_millis(50)));
+
+    // The failure is not mistaken for the end of the workloads.
+    assert!(worker.query_workload().is_err());
+}
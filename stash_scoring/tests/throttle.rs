@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+use stash_scoring::throttle::{idle, Throttle};
+
+const MS: Duration = Duration::from_millis(1);
+
+#[test]
+fn idles_for_the_duty_cycle() {
+    let throttle = Throttle {
+        duty_cycle: Some(0.25),
+        max_nps: None,
+    };
+
+    assert_eq!(throttle.idle_time(100 * MS, Some(1000)), 300 * MS);
+    assert_eq!(throttle.idle_time(Duration::ZERO, None), Duration::ZERO);
+    assert!(Throttle::default().is_unbounded());
+    assert_eq!(
+        Throttle::default().idle_time(100 * MS, Some(1000)),
+        Duration::ZERO
+    );
+}
+
+#[test]
+fn idles_for_the_node_rate() {
+    let throttle = Throttle {
+        duty_cycle: None,
+        max_nps: Some(10_000.0),
+    };
+
+    // 5000 nodes take at least half a second at this rate.
+    assert_eq!(throttle.idle_time(100 * MS, Some(5000)), 400 * MS);
+    assert_eq!(throttle.idle_time(600 * MS, Some(5000)), Duration::ZERO);
+    // Searches without a node count are not throttled.
+    assert_eq!(throttle.idle_time(100 * MS, None), Duration::ZERO);
+
+    // The strictest bound wins.
+    let throttle = Throttle {
+        duty_cycle: Some(0.5),
+        ..throttle
+    };
+
+    assert_eq!(throttle.idle_time(100 * MS, Some(5000)), 400 * MS);
+    assert_eq!(throttle.idle_time(100 * MS, Some(100)), 100 * MS);
+}
+
+#[test]
+fn shares_the_node_rate_between_workers() {
+    let throttle = Throttle {
+        duty_cycle: Some(0.5),
+        max_nps: Some(10_000.0),
+    };
+    let shared = throttle.shared_by(4);
+
+    assert_eq!(shared.max_nps, Some(2500.0));
+    assert_eq!(shared.duty_cycle, Some(0.5));
+    assert_eq!(shared.idle_time(100 * MS, Some(1000)), 300 * MS);
+    // Without running workers, the whole rate is kept.
+    assert_eq!(throttle.shared_by(0).max_nps, Some(10_000.0));
+    assert!(Throttle::default().shared_by(4).is_unbounded());
+}
+
+#[test]
+fn stops_idling_when_interrupted() {
+    let start = Instant::now();
+
+    idle(Duration::from_secs(10), || start.elapsed() > 50 * MS);
+
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
@@ -0,0 +1,71 @@
+use stash_scoring::game::GameResult;
+use stash_scoring::tournament::{Pentanomial, Sprt, SprtResult, Standings};
+
+#[test]
+fn pentanomial_statistics() {
+    let ptnml = Pentanomial([0, 1, 2, 3, 0]);
+    let reversed = Pentanomial([0, 3, 2, 1, 0]);
+    let (elo, margin) = ptnml.elo().unwrap();
+
+    assert!((elo - 58.5).abs() < 0.1, "{}", elo);
+    assert!(margin > elo);
+    assert!((reversed.elo().unwrap().0 + elo).abs() < 1e-9);
+    assert!((ptnml.los().unwrap() + reversed.los().unwrap() - 1.0).abs() < 1e-6);
+    assert!(ptnml.los().unwrap() > 0.8);
+    // (1/6 + 3/6) / 2 + 2/6 of the games are drawn.
+    assert!((ptnml.draw_ratio().unwrap() - 4.0 / 6.0).abs() < 1e-9);
+    assert!((Pentanomial([1, 2, 0, 4, 3]).draw_ratio().unwrap() - 0.3).abs() < 1e-9);
+
+    // Without any variance, neither the Elo nor the LOS can be estimated.
+    assert_eq!(Pentanomial([0, 0, 0, 0, 4]).elo(), None);
+    assert_eq!(Pentanomial([0, 0, 4, 0, 0]).los(), None);
+    assert_eq!(Pentanomial::default().draw_ratio(), None);
+}
+
+#[test]
+fn standings_track_game_pairs() {
+    let mut standings = Standings::new(vec![String::from("A"), String::from("B")]);
+
+    standings.add_game_pair(0, 1, [GameResult::WhiteWin, GameResult::Draw]);
+    standings.add_game_pair(1, 0, [GameResult::WhiteWin, GameResult::BlackWin]);
+    standings.add_game_pair(0, 1, [GameResult::BlackWin, GameResult::Draw]);
+
+    assert_eq!(standings.pentanomial(0, 1), Pentanomial([1, 1, 0, 1, 0]));
+    assert_eq!(standings.pentanomial(1, 0), Pentanomial([0, 1, 0, 1, 1]));
+
+    let wdl = standings.pair(0, 1);
+
+    assert_eq!((wdl.wins, wdl.draws, wdl.losses), (1, 2, 3));
+}
+
+#[test]
+fn sprt_log_likelihood_ratio() {
+    let sprt: Sprt = "elo0=0,elo1=5".parse().unwrap();
+    let (lower, upper) = sprt.bounds();
+
+    assert!((lower + 2.944).abs() < 1e-3 && (upper - 2.944).abs() < 1e-3);
+    assert_eq!(sprt.llr(&Pentanomial::default()), None);
+
+    let llr = sprt.llr(&Pentanomial([0, 1, 2, 3, 0])).unwrap();
+
+    assert!((llr - 0.099).abs() < 1e-3, "{}", llr);
+    assert_eq!(sprt.result(&Pentanomial([0, 1, 2, 3, 0])), None);
+    assert_eq!(
+        sprt.result(&Pentanomial([100, 2000, 4000, 2300, 150])),
+        Some(SprtResult::H1)
+    );
+    assert_eq!(
+        sprt.result(&Pentanomial([150, 2300, 4000, 2000, 100])),
+        Some(SprtResult::H0)
+    );
+
+    // One-sided results still lead to a conclusion.
+    assert_eq!(
+        sprt.result(&Pentanomial([0, 0, 0, 0, 5])),
+        Some(SprtResult::H1)
+    );
+
+    assert!("elo0=5,elo1=0".parse::<Sprt>().is_err());
+    assert!("elo0=0,elo1=5,alpha=0".parse::<Sprt>().is_err());
+    assert!("elo0=0".parse::<Sprt>().is_err());
+}
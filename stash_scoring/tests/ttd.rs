@@ -0,0 +1,57 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn measures_time_to_depth() {
+    let harness = Harness::new();
+    let script = "[go]\n!sleep 20\ninfo depth 5 score cp 12 pv e2e4\nbestmove e2e4\n";
+    let engine = format!("cmd={},name=mock", MOCK_ENGINE);
+    let args = ["ttd", "--engine", &engine, "--threads", "1,2", "-d", "5"];
+    let result = harness.run(&args, Some(script));
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    let rows: Vec<Vec<&str>> = stdout
+        .lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+
+    assert!(result.status.success());
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0][..2], ["mock", "1"]);
+    assert_eq!(rows[1][..2], ["mock", "2"]);
+    assert_eq!(rows[0][3], "1.00");
+    assert!(rows[1][2].parse::<f64>().unwrap() >= 20.0);
+
+    // The built-in suite is searched once per thread count.
+    let log = harness.read("engine.log").unwrap();
+
+    assert_eq!(log.matches("go depth 5\n").count(), 16);
+    assert!(log.contains("setoption name Threads value 2\n"));
+}
+
+#[test]
+fn reads_position_suites() {
+    let harness = Harness::new();
+    let suite = harness.write("suite.epd", &format!("{}\n\n{}\n", STARTPOS, KIWIPETE));
+    let engine = format!("cmd={}", MOCK_ENGINE);
+    let args = ["ttd", "--engine", &engine, "--suite", &suite, "-d", "3"];
+    let result = harness.run(&args, None);
+
+    assert!(result.status.success());
+    assert_eq!(
+        harness
+            .read("engine.log")
+            .unwrap()
+            .matches("go depth 3\n")
+            .count(),
+        2
+    );
+    assert!(!harness
+        .run(
+            &["ttd", "--engine", &engine, "--threads", "0", "-d", "3"],
+            None
+        )
+        .status
+        .success());
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::*;
+
+fn verify(harness: &Harness, content: &str, extra_args: &[&str]) -> (bool, serde_json::Value) {
+    let input = harness.write("dataset.txt", content);
+    let mut args = vec!["verify", "-i", &input];
+
+    args.extend_from_slice(extra_args);
+
+    let output = harness.run(&args, None);
+    let report = serde_json::from_slice(&output.stdout).unwrap();
+
+    (output.status.success(), report)
+}
+
+#[test]
+fn accepts_valid_datasets() {
+    let harness = Harness::new();
+    let content = format!("{} 0.5 12\n{} 1 #-4\n", STARTPOS, KIWIPETE);
+    let (valid, report) = verify(&harness, &content, &[]);
+
+    assert!(valid);
+    assert_eq!(report["lines"], 2);
+}
+
+#[test]
+fn reports_corrupted_lines() {
+    let harness = Harness::new();
+    let content = format!(
+        "{} 0.7 12\n{} 1 abc\n8/8/8/8/8/8/8/K5kQ w - - 0 1 0.5 0\n{} 0.5",
+        STARTPOS, KIWIPETE, STARTPOS
+    );
+    let (valid, report) = verify(&harness, &content, &[]);
+    let counts = &report["error_counts"];
+
+    assert!(!valid);
+    assert_eq!(counts["invalid_wdl"], 1);
+    assert_eq!(counts["invalid_eval"], 1);
+    assert_eq!(counts["invalid_fen"], 1);
+    assert_eq!(counts["truncated"], 1);
+}
+
+#[test]
+fn accepts_soft_labels_on_request() {
+    let harness = Harness::new();
+    let content = format!("{} 0.73 12\n", STARTPOS);
+
+    assert!(!verify(&harness, &content, &[]).0);
+    assert!(verify(&harness, &content, &["--soft-labels"]).0);
+}
+
+#[test]
+fn compares_line_counts_with_the_source() {
+    let harness = Harness::new();
+    let source = harness.write(
+        "source.txt",
+        &format!("{} 0.5\n{} 0.5\n", STARTPOS, KIWIPETE),
+    );
+    let (valid, report) = verify(&harness, &format!("{} 0.5 3\n", STARTPOS), &["-s", &source]);
+
+    assert!(!valid);
+    assert_eq!(report["source_lines"], 2);
+    assert_eq!(report["line_count_matches"], false);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::Score;
+use stash_scoring::wdl::WdlModel;
+
+use common::*;
+
+const ENDGAME: &str = "8/5k2/8/8/8/8/2R5/4K3 w - - 0 1";
+
+fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+}
+
+#[test]
+fn converts_scores_with_models() {
+    let startpos = Position::from_fen(STARTPOS, false).unwrap();
+    let endgame = Position::from_fen(ENDGAME, false).unwrap();
+    let logistic = WdlModel::Logistic { k: 400.0 };
+    let material = WdlModel::STOCKFISH;
+
+    assert_close(logistic.expected_result(Score::Cp(0), &startpos), 0.5);
+    assert_close(
+        logistic.expected_result(Score::Cp(400), &startpos),
+        1.0 / (1.0 + (-1.0f64).exp()),
+    );
+    assert_eq!(logistic.wdl(Score::Cp(250), &startpos).draw, 0.0);
+
+    // Normalized scores of 100 centipawns give a 50% win probability.
+    for pos in [&startpos, &endgame] {
+        let wdl = material.wdl(Score::Cp(100), pos);
+
+        assert_close(wdl.win, 0.5);
+        assert_close(wdl.win + wdl.draw + wdl.loss, 1.0);
+        assert_close(material.expected_result(Score::Cp(0), pos), 0.5);
+        assert_close(
+            material.wdl(Score::Cp(-60), pos).loss,
+            material.wdl(Score::Cp(60), pos).win,
+        );
+    }
+
+    // Draws are less likely with less material.
+    assert!(
+        material.wdl(Score::Cp(50), &endgame).draw < material.wdl(Score::Cp(50), &startpos).draw
+    );
+    assert_eq!(material.wdl(Score::Mate(-3), &endgame).loss, 1.0);
+
+    for model in [logistic, material] {
+        for cp in [-250, -20, 0, 35, 120] {
+            let expected = model.expected_result(Score::Cp(cp), &startpos);
+
+            assert!((model.centipawns(expected, &startpos) - cp as f64).abs() < 0.01);
+        }
+    }
+}
+
+#[test]
+fn prints_conversions() {
+    let harness = Harness::new();
+    let output = harness.run(&["cp2wdl", "0", "-400", "#2"], None);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0 0.5000 0.0000 0.5000 0.5000\n\
+         -400 0.2689 0.0000 0.7311 0.2689\n\
+         #2 1.0000 0.0000 0.0000 1.0000\n"
+    );
+
+    let output = harness.run(
+        &["cp2wdl", "--model", "material", "--inverse", "0.75"],
+        None,
+    );
+
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0.75 100.0\n");
+    assert!(!harness
+        .run(&["cp2wdl", "1.5", "--inverse"], None)
+        .status
+        .success());
+    assert!(!harness.run(&["cp2wdl", "abc"], None).status.success());
+}
@@ -0,0 +1,72 @@
+/*
+ * A C API for scoring chess positions with a pool of UCI engines.
+ *
+ * Positions are submitted to the pool, which searches them in the
+ * background, and their results are polled in completion order:
+ *
+ *     StashPool *pool = stash_pool_create("./stash", 4, 0, 10000, 0);
+ *     int64_t id = stash_pool_submit(pool, fen);
+ *     StashResult result;
+ *
+ *     while (stash_pool_poll(pool, 1, &result) == 1)
+ *         ...
+ *
+ *     stash_pool_destroy(pool);
+ *
+ * All the functions can be called from any thread.
+ */
+
+#ifndef STASH_SCORING_H
+#define STASH_SCORING_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct StashPool StashPool;
+
+typedef struct StashResult
+{
+    /* The identifier returned when submitting the position. */
+    int64_t id;
+    /* Non-zero if the search succeeded, in which case the fields below are
+     * set. */
+    int32_t ok;
+    /* Non-zero if the score is a mate score. */
+    int32_t is_mate;
+    /* The score from the side to move's point of view: in centipawns, or in
+     * moves for mate scores (negative when the side to move gets mated). */
+    int32_t score;
+    /* The best move in UCI notation, NUL-terminated. */
+    char best_move[8];
+} StashResult;
+
+/* Starts a pool of `threads` instances of the engine, given by its path or by
+ * its name in the engine registry. Searches are limited to the given depth
+ * and/or node count, 0 meaning no limit, but at least one limit must be set.
+ * When `chess960` is non-zero, the UCI_Chess960 option of the engine is
+ * enabled and submitted FENs are parsed as Chess960 positions, with
+ * Shredder-FEN or X-FEN castling rights. Returns NULL on failure. */
+StashPool *stash_pool_create(const char *engine, size_t threads, uint16_t depth, uint64_t nodes,
+                             int chess960);
+
+/* Queues the search of a position, returning its identifier, or -1 if the FEN
+ * is invalid. */
+int64_t stash_pool_submit(StashPool *pool, const char *fen);
+
+/* Gets the result of a finished search. When `wait` is non-zero, blocks until
+ * a submitted search is done. Returns 1 if a result was written, 0 if none is
+ * available (or none is pending), and -1 if all the engines failed. */
+int stash_pool_poll(StashPool *pool, int wait, StashResult *result);
+
+/* Stops the pool, after its pending searches are done. */
+void stash_pool_destroy(StashPool *pool);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,220 @@
+//! A C API for the engine pool of the scoring library, so that training
+//! frameworks written in other languages can embed the scorer instead of
+//! running the tool and parsing its output. The declarations are in
+//! `include/stash_scoring.h`.
+
+use std::ffi::{c_char, c_int, CStr};
+use std::io;
+use std::ptr;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::{EngineConfig, Score, SearchLimit, SearchResult};
+use stash_scoring::pool::{EnginePool, ResultSender};
+use stash_scoring::registry::EngineRegistry;
+
+pub struct StashPool {
+    pool: EnginePool,
+    limit: SearchLimit,
+    chess960: bool,
+    next_id: AtomicI64,
+    pending: AtomicUsize,
+    reply: Mutex<ResultSender>,
+    results: Mutex<Receiver<(usize, io::Result<SearchResult>)>>,
+}
+
+#[repr(C)]
+pub struct StashResult {
+    id: i64,
+    ok: i32,
+    is_mate: i32,
+    score: i32,
+    best_move: [c_char; 8],
+}
+
+/// Builds the configuration of an engine given by its path, or by its name
+/// in the default engine registry.
+fn engine_config(engine: &str, chess960: bool) -> io::Result<EngineConfig> {
+    let registry = EngineRegistry::load(None)?;
+    let mut config = match registry.get(engine) {
+        Some(config) if !engine.contains('/') => config.clone(),
+        _ => EngineConfig {
+            name: engine.to_string(),
+            command: engine.to_string(),
+            ..Default::default()
+        },
+    };
+
+    // Scores are used as labels, so they should only depend on the position.
+    config.canonical_options = true;
+
+    if chess960 {
+        config.options.insert(0, String::from("UCI_Chess960=true"));
+    }
+
+    Ok(config)
+}
+
+/// Starts a pool of engines, returning a null pointer on failure. A non-zero
+/// `chess960` enables the UCI_Chess960 option of the engines and the parsing
+/// of submitted FENs as Chess960 positions.
+///
+/// # Safety
+///
+/// `engine` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn stash_pool_create(
+    engine: *const c_char,
+    threads: usize,
+    depth: u16,
+    nodes: u64,
+    chess960: c_int,
+) -> *mut StashPool {
+    if engine.is_null() || (depth == 0 && nodes == 0) {
+        return ptr::null_mut();
+    }
+
+    let Ok(engine) = CStr::from_ptr(engine).to_str() else {
+        return ptr::null_mut();
+    };
+    let chess960 = chess960 != 0;
+    let Ok(pool) =
+        engine_config(engine, chess960).and_then(|config| EnginePool::new(&config, threads.max(1)))
+    else {
+        return ptr::null_mut();
+    };
+    let (reply, results) = mpsc::channel();
+
+    Box::into_raw(Box::new(StashPool {
+        pool,
+        limit: SearchLimit {
+            depth: (depth != 0).then_some(depth),
+            nodes: (nodes != 0).then_some(nodes),
+            ..Default::default()
+        },
+        chess960,
+        next_id: AtomicI64::new(0),
+        pending: AtomicUsize::new(0),
+        reply: Mutex::new(reply),
+        results: Mutex::new(results),
+    }))
+}
+
+/// Queues the search of a position, returning its identifier, or -1 if the
+/// FEN is invalid.
+///
+/// # Safety
+///
+/// `pool` must come from `stash_pool_create`, and `fen` must be a valid
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn stash_pool_submit(pool: *mut StashPool, fen: *const c_char) -> i64 {
+    let Some(pool) = pool.as_ref() else {
+        return -1;
+    };
+
+    if fen.is_null() {
+        return -1;
+    }
+
+    let Some(pos) = CStr::from_ptr(fen)
+        .to_str()
+        .ok()
+        .and_then(|fen| Position::from_fen(fen, pool.chess960).ok())
+    else {
+        return -1;
+    };
+    let id = pool.next_id.fetch_add(1, Ordering::Relaxed);
+
+    pool.pending.fetch_add(1, Ordering::AcqRel);
+    pool.pool
+        .submit(id as usize, &pos, &pool.limit, &pool.reply.lock().unwrap());
+    id
+}
+
+/// Gets the result of a finished search, returning 1 if one was written, 0
+/// if none is available, and -1 if all the engines failed.
+///
+/// # Safety
+///
+/// `pool` must come from `stash_pool_create`, and `result` must point to a
+/// writable `StashResult`.
+#[no_mangle]
+pub unsafe extern "C" fn stash_pool_poll(
+    pool: *mut StashPool,
+    wait: c_int,
+    result: *mut StashResult,
+) -> c_int {
+    let (Some(pool), Some(result)) = (pool.as_ref(), result.as_mut()) else {
+        return -1;
+    };
+    let results = pool.results.lock().unwrap();
+    let (id, search) = loop {
+        if pool.pending.load(Ordering::Acquire) == 0 {
+            return 0;
+        }
+
+        let received = match wait {
+            0 => results.try_recv().map_err(|err| match err {
+                TryRecvError::Empty => RecvTimeoutError::Timeout,
+                TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+            }),
+            _ => results.recv_timeout(Duration::from_millis(100)),
+        };
+
+        match received {
+            Ok(received) => break received,
+            Err(RecvTimeoutError::Timeout) if !pool.pool.has_engines() => return -1,
+            Err(RecvTimeoutError::Timeout) if wait == 0 => return 0,
+            Err(_) => continue,
+        }
+    };
+
+    pool.pending.fetch_sub(1, Ordering::AcqRel);
+    *result = StashResult {
+        id: id as i64,
+        ok: 0,
+        is_mate: 0,
+        score: 0,
+        best_move: [0; 8],
+    };
+
+    if let Ok(search) = search {
+        let (is_mate, score) = match search.score {
+            Score::Cp(cp) => (0, cp),
+            Score::Mate(mate) => (1, mate),
+        };
+
+        result.ok = 1;
+        result.is_mate = is_mate;
+        result.score = score;
+
+        // UCI moves are at most 5 characters long.
+        for (dst, &src) in result
+            .best_move
+            .iter_mut()
+            .zip(search.best_move.as_bytes())
+            .take(7)
+        {
+            *dst = src as c_char;
+        }
+    }
+
+    1
+}
+
+/// Stops the pool, after its pending searches are done.
+///
+/// # Safety
+///
+/// `pool` must come from `stash_pool_create`, and must not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn stash_pool_destroy(pool: *mut StashPool) {
+    if !pool.is_null() {
+        drop(Box::from_raw(pool));
+    }
+}
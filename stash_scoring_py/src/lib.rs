@@ -0,0 +1,81 @@
+//! Python bindings for the scoring library, for driving scoring runs from
+//! notebooks and Python scripts:
+//!
+//! ```python
+//! import stash_scoring_py
+//!
+//! scores = stash_scoring_py.score_positions("./stash", fens, nodes=10000, threads=4)
+//! ```
+
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+
+use stash_scoring::board::Position;
+use stash_scoring::engine::{EngineConfig, SearchLimit};
+use stash_scoring::pool::EnginePool;
+use stash_scoring::registry::EngineRegistry;
+
+/// Builds the configuration of an engine given by its path, or by its name
+/// in the default engine registry.
+fn engine_config(engine: &str) -> PyResult<EngineConfig> {
+    let registry = EngineRegistry::load(None).map_err(|err| PyOSError::new_err(err.to_string()))?;
+    let mut config = match registry.get(engine) {
+        Some(config) if !engine.contains('/') => config.clone(),
+        _ => EngineConfig {
+            name: engine.to_string(),
+            command: engine.to_string(),
+            ..Default::default()
+        },
+    };
+
+    // Scores are used as labels, so they should only depend on the position.
+    config.canonical_options = true;
+    Ok(config)
+}
+
+/// Scores the positions with the given number of engine instances, each
+/// search being limited to the given node count.
+///
+/// Returns the scores in centipawns from the side to move's point of view,
+/// in the order of the FENs. Mates are folded into the score as an offset
+/// from +/-32000, and positions whose search failed have a score of None.
+/// Invalid FENs raise a ValueError before any search is started.
+#[pyfunction]
+#[pyo3(signature = (engine, fens, nodes, threads = 1))]
+fn score_positions(
+    py: Python<'_>,
+    engine: &str,
+    fens: Vec<String>,
+    nodes: u64,
+    threads: usize,
+) -> PyResult<Vec<Option<i32>>> {
+    let positions = fens
+        .iter()
+        .map(|fen| {
+            Position::from_fen(fen, false)
+                .map_err(|err| PyValueError::new_err(format!("invalid FEN '{}': {}", fen, err)))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    let config = engine_config(engine)?;
+    let limit = SearchLimit {
+        nodes: Some(nodes),
+        ..Default::default()
+    };
+
+    // Other Python threads can run during the searches.
+    py.detach(|| {
+        let pool = EnginePool::new(&config, threads.max(1))?;
+
+        Ok(pool
+            .score_batch(&positions, &limit)
+            .into_iter()
+            .map(|score| score.ok().map(|score| score.folded()))
+            .collect())
+    })
+    .map_err(|err: std::io::Error| PyOSError::new_err(err.to_string()))
+}
+
+#[pymodule]
+fn stash_scoring_py(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(score_positions, module)?)
+}